@@ -1,7 +1,7 @@
 use std::net::Ipv4Addr;
 use std::os::unix::io::AsRawFd;
 use std::sync::Arc;
-use tokio_tun::Tun;
+use tokio_tun::{PacketDump, Tun};
 
 #[tokio::main]
 async fn main() {
@@ -98,7 +98,7 @@ async fn main() {
         println!("Starting to listen on 1 queue...");
         loop {
             match tun0.recv(&mut buf0).await {
-                Ok(n) => println!("reading {} bytes from tun: {:?}", n, &buf0[..n]),
+                Ok(n) => println!("tun: {}", PacketDump(&buf0[..n])),
                 Err(e) => println!("Error reading: {:?}", e),
             }
         }
@@ -122,7 +122,7 @@ async fn main() {
                 Ok(n) = tun1.recv(&mut buf1) => (&buf1[..n], 1),
                 Ok(n) = tun2.recv(&mut buf2) => (&buf2[..n], 2),
             };
-            println!("reading {} bytes from tuns[{}]: {:?}", buf.len(), id, buf);
+            println!("tuns[{}]: {}", id, PacketDump(buf));
         }
     }
 }
@@ -1,5 +1,4 @@
 use std::net::Ipv4Addr;
-use std::os::unix::io::AsRawFd;
 use std::sync::Arc;
 use tokio_tun::Tun;
 
@@ -26,17 +25,7 @@ async fn main() {
     println!("tap created");
     println!("-----------");
 
-    println!(
-        "┌ name: {}\n├ fd: {}\n├ mtu: {}\n├ flags: {}\n├ address: {}\n├ destination: {}\n├ broadcast: {}\n└ netmask: {}",
-        tun.name(),
-        tun.as_raw_fd(),
-        tun.mtu().unwrap(),
-        tun.flags().unwrap(),
-        tun.address().unwrap(),
-        tun.destination().unwrap(),
-        tun.broadcast().unwrap(),
-        tun.netmask().unwrap(),
-    );
+    println!("{}", tun);
 
     println!("---------------------");
     println!("ping 10.1.0.2 to test");
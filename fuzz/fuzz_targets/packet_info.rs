@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_tun::{PACKET_INFO_LEN, PacketInfo};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(info) = PacketInfo::parse(data) else {
+        assert!(data.len() < PACKET_INFO_LEN);
+        return;
+    };
+    let mut encoded = [0u8; PACKET_INFO_LEN];
+    info.encode(&mut encoded);
+    assert_eq!(encoded, data[..PACKET_INFO_LEN]);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_tun::fuzzing::strip_utun_header;
+
+fuzz_target!(|input: (u16, &[u8])| {
+    let (len, frame) = input;
+    let mut buf = vec![0u8; len as usize];
+    match strip_utun_header(frame, &mut buf) {
+        Ok(n) => {
+            assert_eq!(n, (frame.len() - 4).min(buf.len()));
+            assert_eq!(buf[..n], frame[4..4 + n]);
+        }
+        Err(_) => assert!(frame.len() < 4),
+    }
+});
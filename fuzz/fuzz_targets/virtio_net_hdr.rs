@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_tun::gso::{self, VIRTIO_NET_HDR_LEN, VirtioNetHdr};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(hdr) = VirtioNetHdr::parse(data) {
+        let mut encoded = [0u8; VIRTIO_NET_HDR_LEN];
+        hdr.encode(&mut encoded);
+        assert_eq!(encoded, data[..VIRTIO_NET_HDR_LEN]);
+    }
+    // Splitting a coalesced packet must fail cleanly on malformed input, never panic
+    let mut segments = Vec::new();
+    if let Ok(count) = gso::split(data, &mut segments) {
+        assert_eq!(count, segments.len());
+    }
+});
@@ -0,0 +1,49 @@
+/*
+ * C interface of tokio-tun, built with:
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * Functions returning an int or an ssize_t return -1 on failure, and those returning a pointer
+ * return NULL, with errno set to the cause.
+ */
+
+#ifndef TOKIO_TUN_H
+#define TOKIO_TUN_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct TokioTunBuilder TokioTunBuilder;
+typedef struct TokioTun TokioTun;
+
+TokioTunBuilder *tokio_tun_builder_new(void);
+void tokio_tun_builder_free(TokioTunBuilder *builder);
+int tokio_tun_builder_set_name(TokioTunBuilder *builder, const char *name);
+void tokio_tun_builder_set_tap(TokioTunBuilder *builder);
+void tokio_tun_builder_set_mtu(TokioTunBuilder *builder, int mtu);
+int tokio_tun_builder_set_address(TokioTunBuilder *builder, const char *address);
+int tokio_tun_builder_set_netmask(TokioTunBuilder *builder, const char *netmask);
+int tokio_tun_builder_set_destination(TokioTunBuilder *builder, const char *destination);
+void tokio_tun_builder_set_persist(TokioTunBuilder *builder);
+void tokio_tun_builder_set_up(TokioTunBuilder *builder);
+
+/* Consumes builder, even on failure. */
+TokioTun *tokio_tun_create(TokioTunBuilder *builder);
+const char *tokio_tun_name(const TokioTun *tun);
+int tokio_tun_fd(const TokioTun *tun);
+int tokio_tun_mtu(const TokioTun *tun);
+int tokio_tun_set_nonblocking(const TokioTun *tun, int nonblocking);
+ssize_t tokio_tun_recv(const TokioTun *tun, unsigned char *buf, size_t len);
+ssize_t tokio_tun_send(const TokioTun *tun, const unsigned char *buf, size_t len);
+/* Frees tun, even on failure. */
+int tokio_tun_close(TokioTun *tun);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
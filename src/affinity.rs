@@ -0,0 +1,37 @@
+//! Helpers to process each queue of a multi-queue device on its own CPU (Linux only).
+//!
+//! Combined with [`Tun::steer_by_cpu`](../struct.Tun.html#method.steer_by_cpu), packets sent
+//! by a CPU are queued on the queue of the same index, so pinning the worker of queue `N` to
+//! CPU `N` avoids bouncing packets between cores.
+
+use std::io;
+use std::mem;
+use std::thread;
+
+/// Returns the CPU recommended for the worker of each queue of a multi-queue device (e.g. the
+/// `Vec<Tun>` returned by the builder): queue `N` is assigned to CPU `N`, wrapping around if
+/// there are more queues than CPUs.
+pub fn recommended_cpus<T>(queues: &[T]) -> Vec<usize> {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    (0..queues.len()).map(|queue| queue % cpus).collect()
+}
+
+/// Pins the calling thread to `cpu`.
+///
+/// Call it at the start of the worker thread of a queue, e.g. in a thread running a
+/// current-thread runtime. Fails with `io::ErrorKind::InvalidInput` if `cpu` does not fit in a
+/// CPU set (`CPU_SETSIZE`).
+pub fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CPU is out of the range of a CPU set",
+        ));
+    }
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
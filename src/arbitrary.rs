@@ -0,0 +1,145 @@
+//! Implementations of `proptest::arbitrary::Arbitrary` (feature `proptest`).
+//!
+//! The configurations are those a device can be created with: names of up to 15 characters,
+//! MTUs between 68 and 65535, and up to 8 queues. The headers take any value, as they do on the
+//! wire.
+
+use crate::TunBuilder;
+use crate::TunConfig;
+use crate::gso::VirtioNetHdr;
+use crate::packet_info::PacketInfo;
+use proptest::prelude::*;
+use std::net::Ipv4Addr;
+
+/// Returns a strategy of the names of devices, empty to let the kernel choose one.
+fn name() -> impl Strategy<Value = String> {
+    prop_oneof![Just(String::new()), "[a-z][a-z0-9]{0,14}"]
+}
+
+fn mtu() -> impl Strategy<Value = i32> {
+    68..=65521
+}
+
+fn ipv4() -> impl Strategy<Value = Ipv4Addr> {
+    any::<u32>().prop_map(Ipv4Addr::from)
+}
+
+impl Arbitrary for TunBuilder {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            (name(), any::<bool>(), any::<bool>(), any::<bool>()),
+            (any::<bool>(), proptest::option::of(mtu()), 1..=8usize),
+            (
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+            ),
+        )
+            .prop_map(
+                |(
+                    (name, tap, packet_info, persist),
+                    (up, mtu, queues),
+                    (address, destination, broadcast, netmask),
+                )| {
+                    let mut builder = TunBuilder::new().name(&name).queues(queues);
+                    if tap {
+                        builder = builder.tap();
+                    }
+                    if packet_info {
+                        builder = builder.packet_info();
+                    }
+                    if persist {
+                        builder = builder.persist();
+                    }
+                    if up {
+                        builder = builder.up();
+                    }
+                    if let Some(mtu) = mtu {
+                        builder = builder.mtu(mtu);
+                    }
+                    if let Some(address) = address {
+                        builder = builder.address(address);
+                    }
+                    if let Some(destination) = destination {
+                        builder = builder.destination(destination);
+                    }
+                    if let Some(broadcast) = broadcast {
+                        builder = builder.broadcast(broadcast);
+                    }
+                    if let Some(netmask) = netmask {
+                        builder = builder.netmask(netmask);
+                    }
+                    builder
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for TunConfig {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            (name(), any::<bool>(), mtu(), any::<i16>(), 1..=8usize),
+            (
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+                proptest::option::of(ipv4()),
+            ),
+        )
+            .prop_map(
+                |((name, tap, mtu, flags, queues), (address, destination, broadcast, netmask))| {
+                    TunConfig {
+                        name,
+                        tap,
+                        mtu,
+                        flags,
+                        address,
+                        destination,
+                        broadcast,
+                        netmask,
+                        queues,
+                    }
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for VirtioNetHdr {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<(u8, u8, u16, u16, u16, u16)>()
+            .prop_map(
+                |(flags, gso_type, hdr_len, gso_size, csum_start, csum_offset)| VirtioNetHdr {
+                    flags,
+                    gso_type,
+                    hdr_len,
+                    gso_size,
+                    csum_start,
+                    csum_offset,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for PacketInfo {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<(u16, u16)>()
+            .prop_map(|(flags, proto)| PacketInfo { flags, proto })
+            .boxed()
+    }
+}
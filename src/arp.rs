@@ -0,0 +1,234 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::dot1q;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+/// Length of the ARP packet of an Ethernet and IPv4 mapping.
+const ARP_LEN: usize = 28;
+
+/// Broadcast MAC address.
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// Represents a TAP device which answers the ARP requests for a set of IPv4 addresses with
+/// their MAC addresses, as the L2 gateway or the hosts behind it would, so that the kernel (or
+/// the peers bridged to the device) can reach them.
+///
+/// The requests it answers are not returned by [`recv`](#method.recv); the other frames,
+/// including the requests for other addresses, are. A request tagged with VLAN tags is
+/// answered with the same tags. Adding or changing a mapping with [`insert`](#method.insert)
+/// announces it with a gratuitous ARP, so that the neighbors update their caches.
+///
+/// The device must be created without packet information and offload, whose headers would not
+/// be understood.
+///
+/// ```no_run
+/// use tokio_tun::{ArpResponder, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tap = TunBuilder::new().tap().up().build()?.pop().unwrap();
+/// let tap = ArpResponder::new(tap)?.entry("10.0.0.1".parse()?, [0x02, 0, 0, 0, 0, 1]);
+/// tap.announce_all().await?;
+/// let mut buf = [0u8; 1514];
+/// let n = tap.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ArpResponder<T> {
+    inner: T,
+    entries: Mutex<HashMap<Ipv4Addr, [u8; 6]>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArpResponder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArpResponder")
+            .field("inner", &self.inner)
+            .field("entries", &self.entries.lock().unwrap())
+            .finish()
+    }
+}
+
+impl<T: AsyncTunDevice> ArpResponder<T> {
+    /// Wraps `inner`, without mappings. Fails with `io::ErrorKind::InvalidInput` if it is not a
+    /// TAP device.
+    pub fn new(inner: T) -> Result<Self> {
+        if !inner.config()?.tap {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "answering ARP requests needs a TAP device",
+            )
+            .into());
+        }
+        Ok(Self {
+            inner,
+            entries: Mutex::default(),
+        })
+    }
+
+    /// Adds the mapping of `ip` to `mac`, without announcing it.
+    pub fn entry(self, ip: Ipv4Addr, mac: [u8; 6]) -> Self {
+        self.entries.lock().unwrap().insert(ip, mac);
+        self
+    }
+
+    /// Adds or changes the mapping of `ip` to `mac`, and announces it with a gratuitous ARP if
+    /// it is new. Returns the previous MAC address of `ip`, if any.
+    pub async fn insert(&self, ip: Ipv4Addr, mac: [u8; 6]) -> io::Result<Option<[u8; 6]>> {
+        let previous = self.entries.lock().unwrap().insert(ip, mac);
+        if previous != Some(mac) {
+            self.inner.send_all(&announcement(ip, mac)).await?;
+        }
+        Ok(previous)
+    }
+
+    /// Removes the mapping of `ip`. Returns its MAC address, if any.
+    pub fn remove(&self, ip: Ipv4Addr) -> Option<[u8; 6]> {
+        self.entries.lock().unwrap().remove(&ip)
+    }
+
+    /// Returns the MAC address which `ip` is mapped to, if any.
+    pub fn get(&self, ip: Ipv4Addr) -> Option<[u8; 6]> {
+        self.entries.lock().unwrap().get(&ip).copied()
+    }
+
+    /// Announces every mapping with a gratuitous ARP, e.g. once the device is up.
+    pub async fn announce_all(&self) -> io::Result<()> {
+        let entries: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&ip, &mac)| (ip, mac))
+            .collect();
+        for (ip, mac) in entries {
+            self.inner.send_all(&announcement(ip, mac)).await?;
+        }
+        Ok(())
+    }
+
+    /// Receives a frame from the device, answering the ARP requests for the mapped addresses
+    /// in the meantime.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.recv(buf).await?;
+            match self.reply(&buf[..n]) {
+                Some(reply) => self.inner.send_all(&reply).await?,
+                None => return Ok(n),
+            }
+        }
+    }
+
+    /// Sends a frame to the device.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf).await
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the reply to `frame` if it is an ARP request for a mapped address.
+    fn reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let tags = dot1q::parse(frame).ok()?;
+        let arp = frame.get(tags.payload..tags.payload + ARP_LEN)?;
+        let is_request = tags.ether_type == ETHERTYPE_ARP
+            && arp[0..8] == [0, 1, 0x08, 0x00, 6, 4, 0, ARP_REQUEST as u8];
+        if !is_request {
+            return None;
+        }
+        let target_ip = Ipv4Addr::from(<[u8; 4]>::try_from(&arp[24..28]).unwrap());
+        let mac = self.get(target_ip)?;
+        let sender_mac = <[u8; 6]>::try_from(&arp[8..14]).unwrap();
+        let sender_ip = Ipv4Addr::from(<[u8; 4]>::try_from(&arp[14..18]).unwrap());
+        let mut reply = Vec::with_capacity(tags.payload + ARP_LEN);
+        reply.extend_from_slice(&sender_mac);
+        reply.extend_from_slice(&mac);
+        // The tags and the EtherType of the request
+        reply.extend_from_slice(&frame[12..tags.payload]);
+        reply.extend_from_slice(&packet(ARP_REPLY, mac, target_ip, sender_mac, sender_ip));
+        Some(reply)
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for ArpResponder<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        ArpResponder::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        ArpResponder::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
+
+/// Returns the frame of the gratuitous ARP announcing that `ip` is at `mac`: a broadcast
+/// request for `ip` itself (RFC 5227).
+fn announcement(ip: Ipv4Addr, mac: [u8; 6]) -> Vec<u8> {
+    frame(
+        BROADCAST_MAC,
+        mac,
+        &packet(ARP_REQUEST, mac, ip, [0; 6], ip),
+    )
+}
+
+/// Returns the Ethernet frame of the ARP packet `arp` from `source` to `destination`.
+fn frame(destination: [u8; 6], source: [u8; 6], arp: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + arp.len());
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    frame.extend_from_slice(arp);
+    frame
+}
+
+/// Returns the ARP packet of `operation` for an Ethernet and IPv4 mapping.
+fn packet(
+    operation: u16,
+    sender_mac: [u8; 6],
+    sender_ip: Ipv4Addr,
+    target_mac: [u8; 6],
+    target_ip: Ipv4Addr,
+) -> [u8; ARP_LEN] {
+    let mut arp = [0u8; ARP_LEN];
+    arp[0..2].copy_from_slice(&1u16.to_be_bytes()); // Ethernet
+    arp[2..4].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    arp[4..6].copy_from_slice(&[6, 4]);
+    arp[6..8].copy_from_slice(&operation.to_be_bytes());
+    arp[8..14].copy_from_slice(&sender_mac);
+    arp[14..18].copy_from_slice(&sender_ip.octets());
+    arp[18..24].copy_from_slice(&target_mac);
+    arp[24..28].copy_from_slice(&target_ip.octets());
+    arp
+}
@@ -0,0 +1,54 @@
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::mem;
+
+/// Represents the runtime-agnostic surface of a Tun/Tap device.
+///
+/// It is implemented by [`Tun`](struct.Tun.html), [`SyncTun`](struct.SyncTun.html) and
+/// [`AsyncIoTun`](struct.AsyncIoTun.html), so that libraries can be written against any device,
+/// and tested against a mock instead of a real device.
+pub trait AsyncTunDevice: Send + Sync {
+    /// Receives a packet from the Tun/Tap interface.
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send;
+
+    /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send;
+
+    /// Sends all of a buffer to the Tun/Tap interface.
+    fn send_all(&self, buf: &[u8]) -> impl Future<Output = io::Result<()>> + Send {
+        async move {
+            let mut remaining = buf;
+            while !remaining.is_empty() {
+                match self.send(remaining).await? {
+                    0 => return Err(ErrorKind::WriteZero.into()),
+                    n => {
+                        let (_, rest) = mem::take(&mut remaining).split_at(n);
+                        remaining = rest;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the name of Tun/Tap device.
+    fn name(&self) -> &str;
+
+    /// Returns the value of MTU.
+    fn mtu(&self) -> Result<i32>;
+
+    /// Returns a snapshot of the whole configuration of device.
+    fn config(&self) -> Result<TunConfig>;
+
+    /// Returns the type of device, to pick the framing of its packets.
+    fn device_type(&self) -> Result<DeviceType> {
+        Ok(if self.config()?.tap {
+            DeviceType::Tap
+        } else {
+            DeviceType::Tun
+        })
+    }
+}
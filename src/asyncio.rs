@@ -0,0 +1,272 @@
+use crate::AsyncTunDevice;
+use crate::CloseOptions;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunBuilder;
+use crate::TunConfig;
+use crate::device;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::io::TunIo;
+#[cfg(target_os = "linux")]
+use crate::linux::params::Params;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::io::TunIo;
+#[cfg(target_os = "macos")]
+use crate::macos::params::Params;
+use async_io::Async;
+use std::future::Future;
+use std::io::{self, ErrorKind, IoSlice};
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+/// Represents a Tun/Tap device driven by the `async-io` reactor, for use with `smol`,
+/// `async-std` and other runtimes which do not run on tokio.
+///
+/// Use [`TunBuilder::build_async_io`](struct.TunBuilder.html#method.build_async_io) to create a
+/// new instance. It provides the same receive and send surface as [`Tun`](struct.Tun.html).
+pub struct AsyncIoTun {
+    iface: Arc<Interface>,
+    io: Async<TunIo>,
+    /// Index of the queue among the queues of device.
+    queue: usize,
+}
+
+impl AsRawFd for AsyncIoTun {
+    fn as_raw_fd(&self) -> RawFd {
+        self.io.as_raw_fd()
+    }
+}
+
+impl AsyncIoTun {
+    /// Creates new instances of Tun/Tap device, one per queue.
+    pub(crate) fn new(params: Params, queues: usize) -> Result<Vec<Self>> {
+        let (iface, fds) = device::allocate(params, queues)?;
+        let iface = Arc::new(iface);
+        let mut tuns = Vec::with_capacity(queues);
+        for (queue, fd) in fds.into_iter().enumerate() {
+            tuns.push(Self {
+                iface: iface.clone(),
+                io: Async::new(TunIo::from(fd))?,
+                queue,
+            });
+        }
+        Ok(tuns)
+    }
+
+    /// Creates a new instance of Tun/Tap device from an already configured file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor of a Tun/Tap device (a `utun` control socket on
+    /// macOS). The ownership of `fd` is transferred to the returned
+    /// [`AsyncIoTun`](struct.AsyncIoTun.html), which closes it on drop.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let iface = Interface::from_fd(fd.as_fd())?;
+        Ok(Self {
+            iface: Arc::new(iface),
+            io: Async::new(TunIo::from(fd))?,
+            queue: 0,
+        })
+    }
+
+    /// Closes the Tun/Tap device and reports any error, unlike dropping it.
+    ///
+    /// The device keeps its state and its persistent flag, so that the kernel removes it once
+    /// its last file descriptor is closed unless it is persistent. See
+    /// [`close_with`](#method.close_with) to tear it down.
+    pub fn close(self) -> Result<()> {
+        self.close_with(CloseOptions::default())
+    }
+
+    /// Closes the Tun/Tap device and reports any error, tearing it down as `options` tell if
+    /// this is the last handle of the device, before its file descriptor and control socket are
+    /// closed. Otherwise only the file descriptor of this queue is closed.
+    pub fn close_with(self, options: CloseOptions) -> Result<()> {
+        let io = self.io.into_inner()?;
+        device::close(self.iface, io, options)
+    }
+
+    /// Receives a packet from the Tun/Tap interface.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.read_with(|inner| inner.recv(buf)).await
+    }
+
+    /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write_with(|inner| inner.send(buf)).await
+    }
+
+    /// Sends all of a buffer to the Tun/Tap interface.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        let mut remaining = buf;
+        while !remaining.is_empty() {
+            match self.send(remaining).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => {
+                    let (_, rest) = mem::take(&mut remaining).split_at(n);
+                    remaining = rest;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends several different buffers to the Tun/Tap interface. Returns the number of bytes written to the device.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.io.write_with(|inner| inner.sendv(bufs)).await
+    }
+
+    /// Tries to receive a packet from the Tun/Tap interface without waiting.
+    pub fn try_recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.get_ref().recv(buf)
+    }
+
+    /// Tries to send a buffer to the Tun/Tap interface without waiting.
+    pub fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.get_ref().send(buf)
+    }
+
+    /// Waits for the Tun/Tap interface to become readable.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.io.readable().await
+    }
+
+    /// Waits for the Tun/Tap interface to become writable.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.io.writable().await
+    }
+
+    /// Returns the minimum size of a receive buffer which can hold any packet of the device
+    /// without truncation.
+    pub fn recommended_buffer_size(&self) -> Result<usize> {
+        device::recommended_buffer_size(&self.iface)
+    }
+
+    /// Applies the options of `builder` which differ from the current configuration of device,
+    /// and returns the names of the options which changed (see
+    /// [`Tun::reconfigure`](struct.Tun.html#method.reconfigure)).
+    pub fn reconfigure(&self, builder: TunBuilder) -> Result<Vec<&'static str>> {
+        device::reconfigure(&self.iface, builder.params()?)
+    }
+
+    /// Returns the index of this queue among the queues of device, in the order in which
+    /// [`TunBuilder::build_async_io`](struct.TunBuilder.html#method.build_async_io) returned them. A device
+    /// created from a file descriptor is a single queue, of index `0`.
+    pub fn queue_index(&self) -> usize {
+        self.queue
+    }
+
+    /// Returns the number of queues of device, as built.
+    pub fn total_queues(&self) -> usize {
+        self.iface.queues()
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    ///
+    /// Addresses which are not assigned to the device are reported as `None`.
+    pub fn config(&self) -> Result<TunConfig> {
+        device::config(&self.iface)
+    }
+
+    /// Returns the value of MTU.
+    pub fn mtu(&self) -> Result<i32> {
+        self.iface.mtu(None)
+    }
+
+    /// Returns the IPv4 address of device.
+    pub fn address(&self) -> Result<Ipv4Addr> {
+        self.iface.address(None)
+    }
+
+    /// Returns the IPv4 destination address of device.
+    pub fn destination(&self) -> Result<Ipv4Addr> {
+        self.iface.destination(None)
+    }
+
+    /// Returns the IPv4 broadcast address of device.
+    pub fn broadcast(&self) -> Result<Ipv4Addr> {
+        self.iface.broadcast(None)
+    }
+
+    /// Returns the IPv4 netmask address of device.
+    pub fn netmask(&self) -> Result<Ipv4Addr> {
+        self.iface.netmask(None)
+    }
+
+    /// Returns the type of device, known since its creation and checked against the kernel on
+    /// Linux, to pick the framing of its packets.
+    pub fn device_type(&self) -> Result<DeviceType> {
+        device::device_type(&self.iface, self.io.as_fd())
+    }
+
+    /// Returns `true` if the device is administratively up.
+    pub fn is_up(&self) -> Result<bool> {
+        device::is_up(&self.iface)
+    }
+
+    /// Brings the device up, so that it services incoming and outgoing packets.
+    pub fn set_up(&self) -> Result<()> {
+        device::set_up(&self.iface, true)
+    }
+
+    /// Brings the device down. Its configuration is kept, and the packets sent to it are
+    /// dropped until it is brought up again.
+    pub fn set_down(&self) -> Result<()> {
+        device::set_up(&self.iface, false)
+    }
+
+    /// Returns the flags of device.
+    pub fn flags(&self) -> Result<i16> {
+        self.iface.flags(None)
+    }
+}
+
+impl AsyncTunDevice for AsyncIoTun {
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        AsyncIoTun::recv(self, buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        AsyncIoTun::send(self, buf)
+    }
+
+    fn send_all(&self, buf: &[u8]) -> impl Future<Output = io::Result<()>> + Send {
+        AsyncIoTun::send_all(self, buf)
+    }
+
+    fn name(&self) -> &str {
+        AsyncIoTun::name(self)
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        AsyncIoTun::mtu(self)
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        AsyncIoTun::config(self)
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        AsyncIoTun::device_type(self)
+    }
+}
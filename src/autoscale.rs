@@ -0,0 +1,283 @@
+use crate::Result;
+use crate::Tun;
+use crate::Workers;
+use bytes::{Bytes, BytesMut};
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+/// Represents the limits and the thresholds of the autoscaling of the workers spawned by
+/// [`spawn_autoscaled_workers`](fn.spawn_autoscaled_workers.html).
+///
+/// Every [`interval`](#method.interval), the controller computes the average rate of packets
+/// received per attached queue over the interval: above [`scale_up`](#method.scale_up), it
+/// attaches one more queue, and below [`scale_down`](#method.scale_down), it detaches one, as
+/// long as the number of attached queues stays within `min..=max`. Scaling one queue at a time
+/// lets the rates settle before the next decision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Autoscale {
+    min: usize,
+    max: usize,
+    interval: Duration,
+    scale_up: f64,
+    scale_down: f64,
+}
+
+impl Autoscale {
+    /// Creates the autoscaling of `min` to `max` queues, sampled every second, scaling up above
+    /// 50,000 packets per second and queue and down below 10,000.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is zero or larger than `max`.
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(min > 0, "at least one queue must stay attached");
+        assert!(
+            min <= max,
+            "the minimum number of queues exceeds the maximum"
+        );
+        Self {
+            min,
+            max,
+            interval: Duration::from_secs(1),
+            scale_up: 50_000.0,
+            scale_down: 10_000.0,
+        }
+    }
+
+    /// Sets the interval between two decisions of the controller.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the average rate per attached queue, in packets per second, above which a queue is
+    /// attached.
+    pub fn scale_up(mut self, pps: f64) -> Self {
+        self.scale_up = pps;
+        self
+    }
+
+    /// Sets the average rate per attached queue, in packets per second, below which a queue is
+    /// detached. It should be well below the rate of scaling up divided by the number of queues
+    /// which share it once one is attached, lest the controller oscillate.
+    pub fn scale_down(mut self, pps: f64) -> Self {
+        self.scale_down = pps;
+        self
+    }
+}
+
+/// Represents the load of a queue, and whether it is attached to its device.
+#[derive(Debug, Default)]
+struct QueueLoad {
+    /// Number of packets received by the worker of the queue.
+    packets: AtomicU64,
+    attached: AtomicBool,
+}
+
+/// Represents the workers spawned by
+/// [`spawn_autoscaled_workers`](fn.spawn_autoscaled_workers.html), one per queue, and the
+/// controller attaching and detaching their queues.
+///
+/// Dropping it detaches the workers and the controller; use [`shutdown`](#method.shutdown) to
+/// stop them.
+pub struct AutoscaledWorkers {
+    workers: Workers,
+    active: Arc<AtomicUsize>,
+}
+
+impl AutoscaledWorkers {
+    /// Returns the number of workers, attached or not.
+    pub fn len(&self) -> usize {
+        // The controller is not a worker
+        self.workers.len() - 1
+    }
+
+    /// Returns `true` if there is no worker.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of queues currently attached.
+    pub fn active_queues(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Stops the workers and the controller and waits for them to finish. The queues are left
+    /// attached or detached as they are. Returns the first error, if any.
+    pub async fn shutdown(self) -> io::Result<()> {
+        self.workers.shutdown().await
+    }
+
+    /// Waits for the workers and the controller to finish, which happens once they fail or
+    /// their cancellation token is cancelled. Returns the first error, if any.
+    pub async fn join(self) -> io::Result<()> {
+        self.workers.join().await
+    }
+}
+
+/// Spawns a worker per queue of a multi-queue device like
+/// [`spawn_workers`](fn.spawn_workers.html), and a controller which attaches and detaches the
+/// queues according to the load, within the limits of `autoscale` (Linux only).
+///
+/// The device should be built with the largest number of queues wanted; the queues beyond the
+/// minimum are detached first, and their workers wait until their queue is attached. A queue
+/// is detached with [`Tun::detach_queue`](struct.Tun.html#method.detach_queue), which drops
+/// the packets waiting in it, and the replies to the packets being handled by its worker.
+///
+/// ```no_run
+/// use tokio_tun::{Autoscale, TunBuilder, spawn_autoscaled_workers};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let queues = TunBuilder::new().queues(8).up().build()?;
+/// let workers = spawn_autoscaled_workers(queues, Autoscale::new(1, 8), |packet| async move {
+///     Some(packet)
+/// })?;
+/// println!("{} queues attached", workers.active_queues());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime, or if `autoscale.interval` is zero.
+pub fn spawn_autoscaled_workers<F, Fut>(
+    queues: Vec<Tun>,
+    autoscale: Autoscale,
+    handler: F,
+) -> Result<AutoscaledWorkers>
+where
+    F: Fn(Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<Bytes>> + Send,
+{
+    spawn_autoscaled_workers_with_cancel(queues, autoscale, CancellationToken::new(), handler)
+}
+
+/// Spawns autoscaled workers like
+/// [`spawn_autoscaled_workers`](fn.spawn_autoscaled_workers.html), which also stop once
+/// `cancel` is cancelled.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime, or if `autoscale.interval` is zero.
+pub fn spawn_autoscaled_workers_with_cancel<F, Fut>(
+    queues: Vec<Tun>,
+    autoscale: Autoscale,
+    cancel: CancellationToken,
+    handler: F,
+) -> Result<AutoscaledWorkers>
+where
+    F: Fn(Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<Bytes>> + Send,
+{
+    let max = autoscale.max.min(queues.len());
+    let min = autoscale.min.min(max);
+    let queues: Vec<_> = queues.into_iter().map(Arc::new).collect();
+    let loads: Arc<[QueueLoad]> = queues.iter().map(|_| QueueLoad::default()).collect();
+    for (index, tun) in queues.iter().enumerate() {
+        if index < min {
+            loads[index].attached.store(true, Ordering::Relaxed);
+        } else {
+            tun.detach_queue()?;
+        }
+    }
+    let active = Arc::new(AtomicUsize::new(min));
+
+    let handler = Arc::new(handler);
+    // A child token, so that shutting the workers down does not cancel the caller's token
+    let cancel = cancel.child_token();
+    let mut handles: Vec<_> = queues
+        .iter()
+        .enumerate()
+        .map(|(index, tun)| {
+            let tun = tun.clone();
+            let handler = handler.clone();
+            let cancel = cancel.clone();
+            let loads = loads.clone();
+            tokio::spawn(async move {
+                let load = &loads[index];
+                let buffer_size = Tun::buffer_size(&tun.iface);
+                loop {
+                    let mut buf = BytesMut::zeroed(buffer_size);
+                    let n = tokio::select! {
+                        res = tun.recv(&mut buf) => res?,
+                        _ = cancel.cancelled() => return Ok(()),
+                    };
+                    buf.truncate(n);
+                    load.packets.fetch_add(1, Ordering::Relaxed);
+                    if let Some(reply) = handler(buf.freeze()).await {
+                        match tun.send_all(&reply).await {
+                            // The queue was detached while the packet was handled
+                            Err(_) if !load.attached.load(Ordering::Relaxed) => {}
+                            res => res?,
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    handles.push(tokio::spawn(control(
+        queues,
+        loads,
+        active.clone(),
+        Autoscale {
+            min,
+            max,
+            ..autoscale
+        },
+        cancel.clone(),
+    )));
+    Ok(AutoscaledWorkers {
+        workers: Workers { handles, cancel },
+        active,
+    })
+}
+
+/// Attaches and detaches `queues`, the first `active` of which are attached, according to
+/// their `loads`, until `cancel` is cancelled or attaching or detaching a queue fails.
+async fn control(
+    queues: Vec<Arc<Tun>>,
+    loads: Arc<[QueueLoad]>,
+    active: Arc<AtomicUsize>,
+    autoscale: Autoscale,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let packets = || -> Vec<u64> {
+        loads
+            .iter()
+            .map(|load| load.packets.load(Ordering::Relaxed))
+            .collect()
+    };
+    let mut ticks = time::interval_at(Instant::now() + autoscale.interval, autoscale.interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let (mut before, mut sampled) = (packets(), Instant::now());
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = cancel.cancelled() => return Ok(()),
+        }
+        let (after, now) = (packets(), Instant::now());
+        let secs = (now - sampled).as_secs_f64();
+        let attached = active.load(Ordering::Relaxed);
+        // A queue detached meanwhile may still have counted the packets being handled
+        let received: u64 = (0..attached).map(|i| after[i] - before[i]).sum();
+        let rate = received as f64 / secs / attached as f64;
+        (before, sampled) = (after, now);
+
+        if rate > autoscale.scale_up && attached < autoscale.max {
+            queues[attached].attach_queue()?;
+            loads[attached].attached.store(true, Ordering::Relaxed);
+            active.store(attached + 1, Ordering::Relaxed);
+        } else if rate < autoscale.scale_down && attached > autoscale.min {
+            let last = attached - 1;
+            // Marked first, so that its worker ignores the failure of a reply in flight
+            loads[last].attached.store(false, Ordering::Relaxed);
+            queues[last].detach_queue()?;
+            active.store(last, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Load generator and sink to measure the throughput and latency of a device (feature
+//! `bench-tools`).
+//!
+//! The generator writes UDP packets from the peer address of the device into it, and the sink
+//! receives them on a UDP socket bound to the address of the device after they traversed the
+//! kernel. Each packet carries its send time, so the sink reports latencies along with rates.
+//! The `tun-bench` binary wraps both.
+
+use crate::Tun;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// Port the sink listens on.
+pub const SINK_PORT: u16 = 9001;
+
+/// Port the generated packets are sent from.
+pub const SOURCE_PORT: u16 = 9000;
+
+const HEADERS_LEN: usize = 28;
+
+/// Represents the parameters of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Address of device, where the sink listens.
+    pub address: Ipv4Addr,
+    /// Address of the simulated peer, from which packets are sent.
+    pub peer: Ipv4Addr,
+    /// Size of the generated IP packets, headers included (between 36 and 65535 bytes).
+    pub size: usize,
+    /// Packets per second, or `None` to send as fast as possible.
+    pub rate: Option<u64>,
+    /// Duration of the run.
+    pub duration: Duration,
+}
+
+impl BenchConfig {
+    /// Fails with `io::ErrorKind::InvalidInput` if the packets cannot be generated.
+    fn validate(&self) -> io::Result<()> {
+        if !(HEADERS_LEN + 8..=u16::MAX as usize).contains(&self.size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packets must be between 36 and 65535 bytes long",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Represents the results of a benchmark run.
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    /// Number of packets sent by the generator.
+    pub sent: u64,
+    /// Number of packets received by the sink.
+    pub received: u64,
+    /// Number of bytes (IP packets) received by the sink.
+    pub bytes: u64,
+    /// Time elapsed between the first and the last packet.
+    pub elapsed: Duration,
+    /// Sorted one-way latencies of the received packets.
+    pub latencies: Vec<Duration>,
+}
+
+impl BenchReport {
+    /// Returns the number of packets received per second, `0.0` unless packets were received
+    /// over a measurable time.
+    pub fn pps(&self) -> f64 {
+        self.per_second(self.received as f64)
+    }
+
+    /// Returns the received throughput in gigabits per second, `0.0` unless packets were
+    /// received over a measurable time.
+    pub fn gbps(&self) -> f64 {
+        self.per_second(self.bytes as f64 * 8.0) / 1e9
+    }
+
+    fn per_second(&self, count: f64) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => count / secs,
+        }
+    }
+
+    /// Returns the latency at `percentile` (between 0 and 100), if any packet was received.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let index = (self.latencies.len() - 1) as f64 * percentile.clamp(0.0, 100.0) / 100.0;
+        Some(self.latencies[index.round() as usize])
+    }
+}
+
+/// Runs the generator and the sink concurrently on `tun`, which must be up with
+/// `config.address` assigned.
+pub async fn run(tun: &Tun, config: &BenchConfig) -> io::Result<BenchReport> {
+    config.validate()?;
+    let socket = UdpSocket::bind(SocketAddrV4::new(config.address, SINK_PORT)).await?;
+    let start = Instant::now();
+    let deadline = start + config.duration;
+    let (sent, mut report) = tokio::try_join!(
+        generate(tun, config, start, deadline),
+        sink(&socket, start, deadline + Duration::from_millis(100)),
+    )?;
+    report.sent = sent;
+    Ok(report)
+}
+
+/// Writes packets into `tun` until `deadline`. Returns the number of sent packets.
+pub async fn generate(
+    tun: &Tun,
+    config: &BenchConfig,
+    start: Instant,
+    deadline: Instant,
+) -> io::Result<u64> {
+    config.validate()?;
+    let mut packet = udp_packet(config);
+    let mut sent = 0u64;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(sent);
+        }
+        if let Some(rate) = config.rate {
+            let due = start + Duration::from_secs_f64(sent as f64 / rate as f64);
+            if due > now {
+                tokio::time::sleep_until(due.into()).await;
+            }
+        }
+        let timestamp = start.elapsed().as_nanos() as u64;
+        packet[HEADERS_LEN..HEADERS_LEN + 8].copy_from_slice(&timestamp.to_be_bytes());
+        tun.send(&packet).await?;
+        sent += 1;
+    }
+}
+
+/// Receives packets on `socket` until `deadline` and measures their latencies.
+pub async fn sink(
+    socket: &UdpSocket,
+    start: Instant,
+    deadline: Instant,
+) -> io::Result<BenchReport> {
+    let mut report = BenchReport::default();
+    let mut buf = vec![0u8; u16::MAX as usize];
+    let mut first = None;
+    let mut last = start;
+    loop {
+        let n = match tokio::time::timeout_at(deadline.into(), socket.recv(&mut buf)).await {
+            Ok(res) => res?,
+            Err(_) => break,
+        };
+        let now = Instant::now();
+        first.get_or_insert(now);
+        last = now;
+        report.received += 1;
+        report.bytes += (n + HEADERS_LEN) as u64;
+        if n >= 8 {
+            let timestamp = u64::from_be_bytes(buf[..8].try_into().unwrap());
+            let sent = start + Duration::from_nanos(timestamp);
+            report.latencies.push(now.saturating_duration_since(sent));
+        }
+    }
+    report.elapsed = last.saturating_duration_since(first.unwrap_or(start));
+    report.latencies.sort_unstable();
+    Ok(report)
+}
+
+fn udp_packet(config: &BenchConfig) -> Vec<u8> {
+    let mut packet = vec![0u8; config.size];
+    let total = config.size as u16;
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&total.to_be_bytes());
+    packet[6] = 0x40; // Don't fragment
+    packet[8] = 64;
+    packet[9] = 17;
+    packet[12..16].copy_from_slice(&config.peer.octets());
+    packet[16..20].copy_from_slice(&config.address.octets());
+    let csum = crate::checksum::fold(crate::checksum::sum(&packet[..20], 0));
+    packet[10..12].copy_from_slice(&csum.to_be_bytes());
+    packet[20..22].copy_from_slice(&SOURCE_PORT.to_be_bytes());
+    packet[22..24].copy_from_slice(&SINK_PORT.to_be_bytes());
+    packet[24..26].copy_from_slice(&(total - 20).to_be_bytes());
+    // The UDP checksum is optional over IPv4 and left empty
+    packet
+}
@@ -0,0 +1,225 @@
+//! Command-line tool to manage the devices of tokio-tun.
+//!
+//! It creates persistent devices, configures, lists and deletes them, and dumps the packets
+//! which the host sends through a device:
+//!
+//! ```text
+//! tokio-tun create tun0 --address 10.0.0.1 --netmask 255.255.255.0 --up
+//! tokio-tun configure tun0 --mtu 1400
+//! tokio-tun list
+//! tokio-tun dump tun0 --count 10
+//! tokio-tun delete tun0
+//! ```
+//!
+//! It also serves as the privileged helper of the [`tokio_tun::helper`] module.
+
+use std::env;
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+use std::process;
+use tokio_tun::{CloseOptions, DeviceInfo, Tun, TunBuilder, list_devices};
+
+const USAGE: &str = "usage: tokio-tun create NAME [--tap] [--owner UID] [--group GID] [OPTIONS]
+       tokio-tun configure NAME [OPTIONS] [--down]
+       tokio-tun list
+       tokio-tun delete NAME
+       tokio-tun dump NAME [--count N]
+
+options: [--address ADDR] [--netmask MASK] [--destination ADDR] [--mtu MTU] [--up]";
+
+#[derive(Default)]
+struct Args {
+    command: String,
+    name: String,
+    tap: bool,
+    owner: Option<i32>,
+    group: Option<i32>,
+    address: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+    destination: Option<Ipv4Addr>,
+    mtu: Option<i32>,
+    up: bool,
+    down: bool,
+    count: Option<usize>,
+}
+
+fn parse_args() -> Args {
+    let mut parsed = Args::default();
+    let mut args = env::args().skip(1);
+    parsed.command = args.next().unwrap_or_else(|| exit(USAGE));
+    if parsed.command != "list" {
+        parsed.name = args.next().unwrap_or_else(|| exit(USAGE));
+    }
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| exit(USAGE));
+        match arg.as_str() {
+            "--tap" => parsed.tap = true,
+            "--owner" => parsed.owner = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--group" => parsed.group = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--address" => parsed.address = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--netmask" => parsed.netmask = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--destination" => {
+                parsed.destination = Some(value().parse().unwrap_or_else(|_| exit(USAGE)))
+            }
+            "--mtu" => parsed.mtu = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--up" => parsed.up = true,
+            "--down" => parsed.down = true,
+            "--count" => parsed.count = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            _ => exit(USAGE),
+        }
+    }
+    parsed
+}
+
+fn exit(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+/// Returns a builder with the options of `args` which configure a device.
+fn configure(mut builder: TunBuilder, args: &Args) -> TunBuilder {
+    if let Some(address) = args.address {
+        builder = builder.address(address);
+    }
+    if let Some(netmask) = args.netmask {
+        builder = builder.netmask(netmask);
+    }
+    if let Some(destination) = args.destination {
+        builder = builder.destination(destination);
+    }
+    if let Some(mtu) = args.mtu {
+        builder = builder.mtu(mtu);
+    }
+    if args.up {
+        builder = builder.up();
+    }
+    if args.down {
+        builder = builder.down();
+    }
+    builder
+}
+
+/// Returns the existing device `name`, or exits.
+fn find(name: &str) -> DeviceInfo {
+    list_devices()
+        .unwrap_or_else(|err| exit(&format!("failed to list the devices: {}", err)))
+        .into_iter()
+        .find(|device| device.name == name)
+        .unwrap_or_else(|| exit(&format!("no Tun/Tap device named {}", name)))
+}
+
+/// Returns a builder of the existing device `device`.
+fn builder_of(device: &DeviceInfo) -> TunBuilder {
+    let builder = TunBuilder::new().name(&device.name);
+    if device.tap { builder.tap() } else { builder }
+}
+
+/// Attaches to the existing device `device`, or exits.
+fn attach(device: &DeviceInfo) -> Tun {
+    builder_of(device)
+        .build()
+        .unwrap_or_else(|err| exit(&format!("failed to attach to {}: {}", device.name, err)))
+        .pop()
+        .unwrap()
+}
+
+fn create(args: &Args) {
+    let mut builder = configure(TunBuilder::new().name(&args.name).persist(), args);
+    if args.tap {
+        builder = builder.tap();
+    }
+    if let Some(owner) = args.owner {
+        builder = builder.owner(owner);
+    }
+    if let Some(group) = args.group {
+        builder = builder.group(group);
+    }
+    let tun = builder
+        .build()
+        .unwrap_or_else(|err| exit(&format!("failed to create {}: {}", args.name, err)))
+        .pop()
+        .unwrap();
+    println!("{}", tun.name());
+}
+
+fn reconfigure(args: &Args) {
+    let device = find(&args.name);
+    let tun = attach(&device);
+    let changed = tun
+        .reconfigure(configure(builder_of(&device), args))
+        .unwrap_or_else(|err| exit(&format!("failed to configure {}: {}", args.name, err)));
+    println!("changed: {}", changed.join(", "));
+}
+
+fn list() {
+    let devices =
+        list_devices().unwrap_or_else(|err| exit(&format!("failed to list the devices: {}", err)));
+    for device in devices {
+        let mut line = format!(
+            "{}: {}",
+            device.name,
+            if device.tap { "tap" } else { "tun" }
+        );
+        if device.persistent {
+            line.push_str(" persistent");
+        }
+        if device.multi_queue {
+            line.push_str(" multi-queue");
+        }
+        if let Some(owner) = device.owner {
+            let _ = write!(line, " owner {}", owner);
+        }
+        if let Some(group) = device.group {
+            let _ = write!(line, " group {}", group);
+        }
+        for address in device.addresses {
+            let _ = write!(line, " {}", address);
+        }
+        println!("{}", line);
+    }
+}
+
+async fn delete(args: &Args) {
+    let device = find(&args.name);
+    if !device.persistent {
+        exit(&format!("{} is not persistent", args.name));
+    }
+    // Clearing the persistent flag of the last handle removes the device once closed
+    attach(&device)
+        .close_with(CloseOptions::new().down().clear_persist())
+        .await
+        .unwrap_or_else(|err| exit(&format!("failed to delete {}: {}", args.name, err)));
+}
+
+async fn dump(args: &Args) {
+    let tun = attach(&find(&args.name));
+    let buffer_size = tun
+        .recommended_buffer_size()
+        .unwrap_or_else(|err| exit(&format!("failed to get the MTU: {}", err)));
+    let mut buf = vec![0; buffer_size];
+    for _ in 0..args.count.unwrap_or(usize::MAX) {
+        let n = tun
+            .recv(&mut buf)
+            .await
+            .unwrap_or_else(|err| exit(&format!("failed to receive a packet: {}", err)));
+        let mut line = format!("{} bytes:", n);
+        for byte in &buf[..n] {
+            let _ = write!(line, " {:02x}", byte);
+        }
+        println!("{}", line);
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    tokio_tun::helper::run_if_requested();
+    let args = parse_args();
+    match args.command.as_str() {
+        "create" => create(&args),
+        "configure" => reconfigure(&args),
+        "list" => list(),
+        "delete" => delete(&args).await,
+        "dump" => dump(&args).await,
+        _ => exit(USAGE),
+    }
+}
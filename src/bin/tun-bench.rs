@@ -0,0 +1,77 @@
+use std::env;
+use std::net::Ipv4Addr;
+use std::process;
+use std::time::Duration;
+use tokio_tun::Tun;
+use tokio_tun::bench::{self, BenchConfig};
+
+const USAGE: &str = "usage: tun-bench [--size BYTES] [--rate PPS] [--duration SECS]";
+
+fn parse_args() -> BenchConfig {
+    let mut config = BenchConfig {
+        address: Ipv4Addr::new(10, 77, 0, 1),
+        peer: Ipv4Addr::new(10, 77, 0, 2),
+        size: 1400,
+        rate: None,
+        duration: Duration::from_secs(5),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit(USAGE));
+        match arg.as_str() {
+            "--size" => config.size = value.parse().unwrap_or_else(|_| exit(USAGE)),
+            "--rate" => config.rate = Some(value.parse().unwrap_or_else(|_| exit(USAGE))),
+            "--duration" => {
+                config.duration =
+                    Duration::from_secs_f64(value.parse().unwrap_or_else(|_| exit(USAGE)))
+            }
+            _ => exit(USAGE),
+        }
+    }
+    config
+}
+
+fn exit(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+#[tokio::main]
+async fn main() {
+    let config = parse_args();
+    let tun = Tun::builder()
+        .mtu(config.size.max(1500) as i32)
+        .address(config.address)
+        .netmask(Ipv4Addr::new(255, 255, 255, 0))
+        .up()
+        .build()
+        .unwrap_or_else(|err| exit(&format!("failed to create device: {}", err)))
+        .pop()
+        .unwrap();
+
+    println!(
+        "benchmarking {} with {} byte packets for {:?}",
+        tun.name(),
+        config.size,
+        config.duration
+    );
+    let report = bench::run(&tun, &config)
+        .await
+        .unwrap_or_else(|err| exit(&format!("benchmark failed: {}", err)));
+
+    let ms = |latency: Option<Duration>| latency.map_or(f64::NAN, |l| l.as_secs_f64() * 1e3);
+    println!("sent:     {} packets", report.sent);
+    println!("received: {} packets", report.received);
+    println!(
+        "rate:     {:.0} pps, {:.3} Gbps",
+        report.pps(),
+        report.gbps()
+    );
+    println!(
+        "latency:  p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        ms(report.latency(50.0)),
+        ms(report.latency(90.0)),
+        ms(report.latency(99.0)),
+        ms(report.latency(100.0)),
+    );
+}
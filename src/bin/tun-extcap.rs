@@ -0,0 +1,203 @@
+//! Wireshark extcap of the devices of tokio-tun.
+//!
+//! Installed in the extcap directory of Wireshark (see "About Wireshark" > "Folders"), it adds
+//! the `tokio-tun` and `tokio-tap` interfaces, whose capture opens the device named in their
+//! options, or attaches to it if it exists and is persistent, and streams the packets it
+//! receives to Wireshark. A persistent device owned by the user (e.g. created with
+//! `ip tuntap add mode tun user $USER`) can thus be inspected without root privileges.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio_tun::{PcapRecorder, TunBuilder};
+
+const USAGE: &str = "usage: tun-extcap --extcap-interfaces | --extcap-interface IFACE \
+                     (--extcap-dlts | --extcap-config | --capture --fifo PATH \
+                     [--device NAME] [--address ADDR] [--netmask MASK])";
+
+const TUN_INTERFACE: &str = "tokio-tun";
+const TAP_INTERFACE: &str = "tokio-tap";
+
+#[derive(Default)]
+struct Args {
+    interfaces: bool,
+    interface: Option<String>,
+    dlts: bool,
+    config: bool,
+    capture: bool,
+    fifo: Option<String>,
+    device: String,
+    address: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+}
+
+fn parse_args() -> Args {
+    let mut parsed = Args::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // Wireshark passes some options as `--option=value`
+        let (arg, inline) = match arg.split_once('=') {
+            Some((arg, value)) => (arg.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .unwrap_or_else(|| exit(USAGE))
+        };
+        match arg.as_str() {
+            "--extcap-interfaces" => parsed.interfaces = true,
+            "--extcap-interface" => parsed.interface = Some(value()),
+            "--extcap-dlts" => parsed.dlts = true,
+            "--extcap-config" => parsed.config = true,
+            "--capture" => parsed.capture = true,
+            "--fifo" => parsed.fifo = Some(value()),
+            "--device" => parsed.device = value(),
+            "--address" => parsed.address = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            "--netmask" => parsed.netmask = Some(value().parse().unwrap_or_else(|_| exit(USAGE))),
+            // Options of Wireshark which do not apply to devices
+            "--extcap-version"
+            | "--extcap-capture-filter"
+            | "--extcap-control-in"
+            | "--extcap-control-out" => {
+                value();
+            }
+            _ => exit(USAGE),
+        }
+    }
+    parsed
+}
+
+fn exit(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+/// Returns `true` if `interface` is the Tap interface of the extcap, `false` if it is its Tun
+/// interface.
+fn is_tap(interface: Option<&str>) -> bool {
+    match interface {
+        Some(TUN_INTERFACE) => false,
+        Some(TAP_INTERFACE) => true,
+        _ => exit(USAGE),
+    }
+}
+
+fn print_interfaces() {
+    println!(
+        "extcap {{version={}}}{{help={}}}",
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_REPOSITORY")
+    );
+    println!("interface {{value={TUN_INTERFACE}}}{{display=tokio-tun Tun device}}");
+    println!("interface {{value={TAP_INTERFACE}}}{{display=tokio-tun Tap device}}");
+}
+
+fn print_dlts(tap: bool) {
+    if tap {
+        println!("dlt {{number=1}}{{name=EN10MB}}{{display=Ethernet}}");
+    } else {
+        println!("dlt {{number=101}}{{name=RAW}}{{display=Raw IP}}");
+    }
+}
+
+fn print_config() {
+    println!(
+        "arg {{number=0}}{{call=--device}}{{display=Device}}{{type=string}}\
+         {{tooltip=Name of the device to create or attach to, empty to create a new one}}"
+    );
+    println!(
+        "arg {{number=1}}{{call=--address}}{{display=Address}}{{type=string}}\
+         {{tooltip=IPv4 address of a created device}}\
+         {{validation=^([0-9]{{1,3}}\\.){{3}}[0-9]{{1,3}}$}}"
+    );
+    println!(
+        "arg {{number=2}}{{call=--netmask}}{{display=Netmask}}{{type=string}}\
+         {{tooltip=IPv4 netmask of a created device}}\
+         {{validation=^([0-9]{{1,3}}\\.){{3}}[0-9]{{1,3}}$}}"
+    );
+}
+
+/// Writes to the fifo of Wireshark, and reports when it is closed.
+struct Fifo {
+    file: std::fs::File,
+    closed: Arc<AtomicBool>,
+}
+
+impl Write for Fifo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf).inspect_err(|_| {
+            self.closed.store(true, Ordering::Relaxed);
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+async fn capture(args: &Args, tap: bool) {
+    let fifo = args.fifo.as_deref().unwrap_or_else(|| exit(USAGE));
+    let mut builder = TunBuilder::new().name(&args.device).up();
+    if tap {
+        builder = builder.tap();
+    }
+    if let Some(address) = args.address {
+        builder = builder.address(address);
+    }
+    if let Some(netmask) = args.netmask {
+        builder = builder.netmask(netmask);
+    }
+    let tun = builder
+        .build()
+        .unwrap_or_else(|err| exit(&format!("failed to open device: {}", err)))
+        .pop()
+        .unwrap();
+    eprintln!("capturing on {}", tun.name());
+
+    let buffer_size = tun
+        .recommended_buffer_size()
+        .unwrap_or_else(|err| exit(&format!("failed to get the MTU: {}", err)));
+    let tun = PcapRecorder::new(tun)
+        .unwrap_or_else(|err| exit(&format!("failed to get the device flags: {}", err)));
+    let file = OpenOptions::new()
+        .write(true)
+        .open(fifo)
+        .unwrap_or_else(|err| exit(&format!("failed to open {}: {}", fifo, err)));
+    // The fifo is not buffered, so that Wireshark displays each packet as soon as it is received
+    let closed = Arc::new(AtomicBool::new(false));
+    tun.record_to(Fifo {
+        file,
+        closed: closed.clone(),
+    })
+    .unwrap_or_else(|err| exit(&format!("failed to write to {}: {}", fifo, err)));
+
+    let mut buf = vec![0; buffer_size];
+    while !closed.load(Ordering::Relaxed) {
+        if let Err(err) = tun.recv(&mut buf).await {
+            exit(&format!("failed to receive a packet: {}", err));
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = parse_args();
+    if args.interfaces {
+        print_interfaces();
+    } else if args.dlts {
+        print_dlts(is_tap(args.interface.as_deref()));
+    } else if args.config {
+        is_tap(args.interface.as_deref());
+        print_config();
+    } else if args.capture {
+        capture(&args, is_tap(args.interface.as_deref())).await;
+    } else {
+        exit(USAGE);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::Tun;
+use std::io::{self, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Length of the prefix of each frame.
+const PREFIX_LEN: usize = 2;
+
+/// Moves packets between `tun` and a byte stream (TCP, TLS, unix socket...) until the stream is
+/// closed, each packet being framed with its length as a 16-bit big-endian prefix in both
+/// directions: the framing of OpenVPN over TCP and of many other tunnels.
+///
+/// A direction waits for the stream (or the device) to accept a packet before it reads the next
+/// one, so a slow peer throttles the device and its kernel queue absorbs (and eventually drops)
+/// the excess, as it does for any slow reader. The stream is flushed once no more packets are
+/// ready on the device, so that buffered streams such as TLS send them in batches.
+///
+/// Returns `Ok(())` once the peer closes the stream between two frames, and an
+/// `io::ErrorKind::InvalidData` error if a packet does not fit in a frame. Dropping the
+/// returned future stops the bridge.
+///
+/// ```no_run
+/// use tokio::net::TcpStream;
+/// use tokio_tun::{TunBuilder, bridge_stream};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+/// let stream = TcpStream::connect("192.0.2.1:1194").await?;
+/// stream.set_nodelay(true)?;
+/// bridge_stream(&tun, stream).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn bridge_stream<S>(tun: &Tun, stream: S) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let to_stream = async {
+        let mut buf = vec![0; PREFIX_LEN + tun.recommended_buffer_size()?];
+        loop {
+            let mut n = tun.recv(&mut buf[PREFIX_LEN..]).await?;
+            loop {
+                write_frame(&mut writer, &mut buf, n).await?;
+                match tun.try_recv(&mut buf[PREFIX_LEN..]) {
+                    Ok(m) => n = m,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            writer.flush().await?;
+        }
+    };
+    let from_stream = async {
+        let mut buf = vec![0; u16::MAX as usize];
+        loop {
+            let mut prefix = [0; PREFIX_LEN];
+            match reader.read_exact(&mut prefix).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            let len = u16::from_be_bytes(prefix) as usize;
+            reader.read_exact(&mut buf[..len]).await?;
+            // Empty frames carry no packet, they may be used as keepalives
+            if len > 0 {
+                tun.send_all(&buf[..len]).await?;
+            }
+        }
+    };
+    tokio::select! {
+        res = to_stream => res,
+        res = from_stream => res,
+    }
+}
+
+/// Writes the packet stored in `buf` after the room of the prefix, and of length `len`.
+async fn write_frame<W>(writer: &mut W, buf: &mut [u8], len: usize) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let prefix = u16::try_from(len).map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            "packet too large for a length-prefixed frame",
+        )
+    })?;
+    buf[..PREFIX_LEN].copy_from_slice(&prefix.to_be_bytes());
+    writer.write_all(&buf[..PREFIX_LEN + len]).await
+}
+
+/// Represents the task forwarding packets between two devices, spawned by
+/// [`bridge`](fn.bridge.html).
+///
+/// Dropping it detaches the task; use [`shutdown`](#method.shutdown) to stop it.
+pub struct Bridge {
+    handle: JoinHandle<io::Result<()>>,
+    cancel: CancellationToken,
+}
+
+impl Bridge {
+    /// Returns `true` if the task has finished, after a failure or a cancellation.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops the task and waits for it to finish. The packets already received from a device
+    /// are sent to the other one before the task stops. Returns the error which stopped the
+    /// task before, if any.
+    pub async fn shutdown(self) -> io::Result<()> {
+        self.cancel.cancel();
+        self.join().await
+    }
+
+    /// Waits for the task to finish, which happens once a device fails or the cancellation
+    /// token is cancelled. Returns the error of the device, if any.
+    pub async fn join(self) -> io::Result<()> {
+        self.handle
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)))
+    }
+}
+
+/// Spawns a task forwarding the packets received from `a` to `b`, and from `b` to `a`, each
+/// packet being sent as a whole, e.g. to join two network namespaces or to translate between
+/// two protocols with a device on each side.
+///
+/// Each direction receives as many packets as are queued, up to the budget of the device (see
+/// [`TunBuilder::budget`](struct.TunBuilder.html#method.budget)), and sends them before it
+/// receives the next batch, so a slow device throttles the other one. The packets are
+/// forwarded unchanged, so both devices should have the same framing, i.e. both Tun or both Tap
+/// devices, with or without packet information. The task stops on the first error of either
+/// device.
+///
+/// ```no_run
+/// use tokio_tun::{TunBuilder, bridge};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let a = TunBuilder::new().name("tun-a").up().build()?.pop().unwrap();
+/// let b = TunBuilder::new().name("tun-b").up().build()?.pop().unwrap();
+/// let bridge = bridge(a, b);
+/// tokio::signal::ctrl_c().await?;
+/// bridge.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+pub fn bridge(a: Tun, b: Tun) -> Bridge {
+    bridge_with_cancel(a, b, CancellationToken::new())
+}
+
+/// Spawns a task forwarding packets between two devices like [`bridge`](fn.bridge.html), which
+/// also stops once `cancel` is cancelled.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+pub fn bridge_with_cancel(a: Tun, b: Tun, cancel: CancellationToken) -> Bridge {
+    // A child token, so that shutting the bridge down does not cancel the caller's token
+    let cancel = cancel.child_token();
+    let token = cancel.clone();
+    let handle = tokio::spawn(async move {
+        tokio::try_join!(forward(&a, &b, &token), forward(&b, &a, &token)).map(|_| ())
+    });
+    Bridge { handle, cancel }
+}
+
+/// Forwards the packets received from `from` to `to`, in batches, until `cancel` is cancelled.
+async fn forward(from: &Tun, to: &Tun, cancel: &CancellationToken) -> io::Result<()> {
+    let mut packets = Vec::new();
+    loop {
+        tokio::select! {
+            res = from.recv_many(&mut packets) => res?,
+            _ = cancel.cancelled() => return Ok(()),
+        };
+        for packet in packets.drain(..) {
+            to.send_all(&packet).await?;
+        }
+    }
+}
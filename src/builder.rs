@@ -1,12 +1,45 @@
+use crate::Error;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::Qdisc;
 use crate::Result;
+use crate::SyncTun;
+#[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
+use crate::TunPool;
+#[cfg(all(feature = "vmnet", target_os = "macos"))]
+use crate::VmnetMode;
+#[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
+use crate::WatchedTun;
+#[cfg(all(feature = "async-io", any(target_os = "linux", target_os = "macos")))]
+use crate::asyncio::AsyncIoTun;
 #[cfg(target_os = "linux")]
 use crate::linux::params::Params;
 #[cfg(target_os = "macos")]
 use crate::macos::params::Params;
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg(all(feature = "monoio", target_os = "linux"))]
+use crate::percore::TunQueue;
+#[cfg(target_os = "linux")]
+use crate::qemu::QemuTap;
+use crate::report::BuildReport;
+#[cfg(target_os = "macos")]
+use crate::report::OptionStatus;
+#[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
 use crate::tun::Tun;
 use core::convert::From;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use ipnet::IpNet;
+use std::ffi::OsString;
 use std::net::Ipv4Addr;
+use std::time::Duration;
+
+/// Default maximum number of packets received per readiness event by `Tun::recv_many`.
+pub(crate) const DEFAULT_BUDGET: usize = 32;
+
+/// Smallest MTU of device, which IPv4 requires of every link.
+pub(crate) const MIN_MTU: i32 = 68;
+
+/// Largest MTU of device, so that the frames of a Tap device, with their Ethernet header, are
+/// no larger than an IP packet may be.
+pub(crate) const MAX_DEVICE_MTU: i32 = 65521;
 
 // Define the constants for macOS since they're not available in libc for macOS
 #[cfg(target_os = "macos")]
@@ -17,18 +50,27 @@ mod constants {
 }
 
 #[cfg(target_os = "linux")]
-use libc::{IFF_NO_PI, IFF_TAP, IFF_TUN};
+use libc::IFF_TUN;
+#[cfg(target_os = "linux")]
+pub(crate) use libc::{IFF_NO_PI, IFF_TAP};
 
 #[cfg(target_os = "macos")]
-use constants::{IFF_NO_PI, IFF_TAP, IFF_TUN};
+use constants::IFF_TUN;
+#[cfg(target_os = "macos")]
+pub(crate) use constants::{IFF_NO_PI, IFF_TAP};
 
-/// Represents a factory to build new instances of [`Tun`](struct.Tun.html).
+/// Represents a factory to build new instances of [`Tun`](struct.Tun.html) (or
+/// [`SyncTun`](struct.SyncTun.html)).
+#[derive(Debug, Clone)]
 pub struct TunBuilder {
     name: String,
     is_tap: bool,
     packet_info: bool,
     persist: bool,
-    up: bool,
+    offload: bool,
+    up: Option<bool>,
+    set_flags: i16,
+    unset_flags: i16,
     mtu: Option<i32>,
     owner: Option<i32>,
     group: Option<i32>,
@@ -36,7 +78,19 @@ pub struct TunBuilder {
     destination: Option<Ipv4Addr>,
     broadcast: Option<Ipv4Addr>,
     netmask: Option<Ipv4Addr>,
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    addresses: Vec<IpNet>,
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    qdisc: Option<Qdisc>,
+    #[cfg(all(feature = "vmnet", target_os = "macos"))]
+    vmnet_mode: VmnetMode,
     queues: Option<usize>,
+    budget: usize,
+    counters: bool,
+    busy_poll: Option<Duration>,
+    strict: bool,
+    auto_suffix: bool,
+    helper: Option<Vec<OsString>>,
 }
 
 impl Default for TunBuilder {
@@ -47,14 +101,29 @@ impl Default for TunBuilder {
             group: None,
             is_tap: false,
             persist: false,
-            up: false,
+            offload: false,
+            up: None,
+            set_flags: 0,
+            unset_flags: 0,
             mtu: None,
             packet_info: false,
             address: None,
             destination: None,
             broadcast: None,
             netmask: None,
+            #[cfg(all(feature = "netlink", target_os = "linux"))]
+            addresses: Vec::new(),
+            #[cfg(all(feature = "netlink", target_os = "linux"))]
+            qdisc: None,
+            #[cfg(all(feature = "vmnet", target_os = "macos"))]
+            vmnet_mode: VmnetMode::default(),
             queues: None,
+            budget: DEFAULT_BUDGET,
+            counters: false,
+            busy_poll: None,
+            strict: false,
+            auto_suffix: false,
+            helper: None,
         }
     }
 }
@@ -78,11 +147,27 @@ impl TunBuilder {
     ///
     /// In contrast, *TUN* devices are layer 3 devices which means that IP packets are transmitted
     /// over it.
+    ///
+    /// On macOS, TAP devices are backed by a vmnet interface (feature `vmnet`), see
+    /// [`vmnet_mode`](#method.vmnet_mode).
     pub fn tap(mut self) -> Self {
         self.is_tap = true;
         self
     }
 
+    /// Sets the mode of the vmnet interface which backs a TAP device on macOS (feature `vmnet`).
+    /// Default value is [`VmnetMode::Shared`](enum.VmnetMode.html#variant.Shared).
+    ///
+    /// Starting a vmnet interface requires root privileges or the `com.apple.vm.networking`
+    /// entitlement. The interface has a single queue and no address on the host: its MTU is set
+    /// by vmnet and its addresses by the network stack running on it, e.g. through the DHCP server
+    /// of the shared mode.
+    #[cfg(all(feature = "vmnet", target_os = "macos"))]
+    pub fn vmnet_mode(mut self, mode: VmnetMode) -> Self {
+        self.vmnet_mode = mode;
+        self
+    }
+
     /// Builds multiple instances of [`Tun`](struct.Tun.html) with `IFF_MULTI_QUEUE` flag.
     ///
     /// Internally this creates multiple file descriptors to parallelize packet sending and receiving.
@@ -92,6 +177,80 @@ impl TunBuilder {
         self
     }
 
+    /// Sets the maximum number of packets received per readiness event by
+    /// [`Tun::recv_many`](struct.Tun.html#method.recv_many). Default value is `32`.
+    ///
+    /// A larger budget reduces the number of wakeups under load, but a busy queue may then
+    /// delay the other tasks of the runtime for longer.
+    pub fn budget(mut self, budget: usize) -> Self {
+        self.budget = budget.max(1);
+        self
+    }
+
+    /// Enables the software counters of each queue, see
+    /// [`Tun::counters`](struct.Tun.html#method.counters).
+    pub fn counters(mut self) -> Self {
+        self.counters = true;
+        self
+    }
+
+    /// Makes receive methods spin for up to `duration` retrying the read before waiting for
+    /// readiness.
+    ///
+    /// For latency-sensitive tunnels, this avoids the cost of parking the task and being woken
+    /// up by the reactor when packets follow each other closely, at the expense of burning CPU
+    /// while the device is idle. Keep it in the order of microseconds.
+    ///
+    /// The spin does not yield to the runtime, so the other tasks of the same worker thread do
+    /// not run meanwhile: use it for queues served by dedicated threads, e.g. one
+    /// current-thread runtime per queue, not on a shared multi-threaded runtime.
+    pub fn busy_poll(mut self, duration: Duration) -> Self {
+        self.busy_poll = Some(duration);
+        self
+    }
+
+    /// Creates the device under the next free name if its name is in use by a device which
+    /// cannot be attached to, instead of failing with
+    /// [`Error::NameInUse`](enum.Error.html#variant.NameInUse).
+    ///
+    /// The next free name is made of the name without its trailing digits and the smallest
+    /// number which is free, e.g. `tun1` if `tun0` is in use (on macOS, the next available
+    /// utun device). [`Tun::name`](struct.Tun.html#method.name) returns the name which was
+    /// picked.
+    pub fn auto_suffix(mut self) -> Self {
+        self.auto_suffix = true;
+        self
+    }
+
+    /// Creates the device in a privileged helper process, which sends its file descriptors back
+    /// to this process, so that this process needs no privileges at all (see the
+    /// [`helper`](helper/index.html) module).
+    ///
+    /// `command` is the program and leading arguments of the helper, e.g.
+    /// `["sudo", "-n", "/usr/local/bin/tokio-tun"]`, or the path of the current executable if it
+    /// calls [`helper::run_if_requested`](helper/fn.run_if_requested.html) first thing in `main`.
+    pub fn helper<I, S>(mut self, command: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.helper = Some(command.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Makes send methods fail with
+    /// [`Error::PacketTooLarge`](enum.Error.html#variant.PacketTooLarge) when a packet is larger
+    /// than the device accepts, instead of handing it to the kernel.
+    ///
+    /// The kernel otherwise drops such packets, or fragments them, which only shows once
+    /// packets grow beyond those of the first tests. The limit is the MTU of device at creation
+    /// time plus its framing (packet information, virtio-net and Ethernet headers), as reported
+    /// by [`Tun::recommended_buffer_size`](struct.Tun.html#method.recommended_buffer_size).
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
     /// Set `packet_info` to `true` (default is `false`), thereby unsetting the `IFF_NO_PI` flag on
     /// allocation.
     ///
@@ -113,6 +272,9 @@ impl TunBuilder {
     ///
     /// It can also be used as an estimate to size input buffers although the exact packet size
     /// may vary to account for some protocol overhead.
+    ///
+    /// The MTU must be between 68 and 65521, jumbo frames included, or building the device
+    /// fails with [`Error::InvalidParam`](enum.Error.html#variant.InvalidParam).
     pub fn mtu(mut self, mtu: i32) -> Self {
         self.mtu = Some(mtu);
         self
@@ -167,6 +329,26 @@ impl TunBuilder {
         self
     }
 
+    /// Adds an IPv4 or IPv6 address with its prefix length to the device (Linux only, feature
+    /// `netlink`).
+    ///
+    /// It can be called several times to assign several addresses. The addresses are set over
+    /// netlink, after the address set by [`address`](#method.address), if any.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn ip(mut self, net: IpNet) -> Self {
+        self.addresses.push(net);
+        self
+    }
+
+    /// Sets the queueing discipline of the device (Linux only, feature `netlink`).
+    ///
+    /// If not set, the device keeps the default of the system, usually `pfifo_fast`.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn qdisc(mut self, qdisc: Qdisc) -> Self {
+        self.qdisc = Some(qdisc);
+        self
+    }
+
     /// Makes the device persistent.
     ///
     /// Persistent devices stay registered as long as the computer is not restarted.
@@ -180,22 +362,230 @@ impl TunBuilder {
         self
     }
 
+    /// Enables checksum and segmentation offloads (Linux only).
+    ///
+    /// This sets the `IFF_VNET_HDR` flag, so every packet read from or written to the device is
+    /// preceded by a virtio-net header, and allows the kernel to hand up coalesced TCP packets of
+    /// up to 64KB. See the [`gso`](gso/index.html) module to split them into regular packets.
+    pub fn offload(mut self) -> Self {
+        self.offload = true;
+        self
+    }
+
     /// Sets up the device.
     ///
     /// This means the interface is immediately put into the *up* state.
     /// It is thus immediately able to service incoming and outgoing packets.
     pub fn up(mut self) -> Self {
-        self.up = true;
+        self.up = Some(true);
+        self
+    }
+
+    /// Sets the device down, the opposite of [`up`](#method.up).
+    ///
+    /// A new device is down unless it is set up, so this is meant to bring an existing device
+    /// down with [`Tun::reconfigure`](struct.Tun.html#method.reconfigure).
+    pub fn down(mut self) -> Self {
+        self.up = Some(false);
+        self
+    }
+
+    /// Enables multicast on the device (`IFF_MULTICAST`), or disables it. Unless set, the
+    /// default of the kernel is kept.
+    pub fn multicast(self, enabled: bool) -> Self {
+        self.link_flags(libc::IFF_MULTICAST as i16, enabled)
+    }
+
+    /// Enables ARP on the device, or disables it (`IFF_NOARP`). Unless set, the default of the
+    /// kernel is kept.
+    pub fn arp(self, enabled: bool) -> Self {
+        self.link_flags(libc::IFF_NOARP as i16, !enabled)
+    }
+
+    /// Puts the device in promiscuous mode (`IFF_PROMISC`), or out of it. Unless set, the
+    /// default of the kernel is kept.
+    pub fn promisc(self, enabled: bool) -> Self {
+        self.link_flags(libc::IFF_PROMISC as i16, enabled)
+    }
+
+    /// Sets the link flags `flags` of the device if `set`, else clears them.
+    pub(crate) fn link_flags(mut self, flags: i16, set: bool) -> Self {
+        if set {
+            self.set_flags |= flags;
+            self.unset_flags &= !flags;
+        } else {
+            self.unset_flags |= flags;
+            self.set_flags &= !flags;
+        }
         self
     }
 
     /// Builds a new instance of [`Tun`](struct.Tun.html).
-    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
     pub fn build(self) -> Result<Vec<Tun>> {
         match self.queues {
-            Some(queues) if queues > 1 => Tun::new_mq(self.into(), queues),
-            _ => Tun::new(self.into()).map(|tun| vec![tun]),
+            Some(queues) if queues > 1 => Tun::new_mq(self.params()?, queues),
+            _ => Tun::new(self.params()?).map(|tun| vec![tun]),
+        }
+    }
+
+    /// Builds a new instance of [`Tun`](struct.Tun.html), along with the report of the options
+    /// which the platform does not honor as requested (see [`report`](#method.report)).
+    #[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
+    pub fn build_with_report(self) -> Result<(Vec<Tun>, BuildReport)> {
+        let report = self.report();
+        Ok((self.build()?, report))
+    }
+
+    /// Returns the options of the builder which the platform skips, approximates or does not
+    /// support, e.g. the owner, group and persistence of device on macOS. The report is empty
+    /// when every option is honored as requested.
+    ///
+    /// Options which cannot be approximated, such as an MTU out of range, make building the
+    /// device fail instead.
+    pub fn report(&self) -> BuildReport {
+        #[allow(unused_mut)]
+        let mut report = BuildReport::default();
+        #[cfg(target_os = "macos")]
+        self.report_macos(&mut report);
+        report
+    }
+
+    #[cfg(target_os = "macos")]
+    fn report_macos(&self, report: &mut BuildReport) {
+        let vmnet = cfg!(feature = "vmnet") && self.is_tap;
+        if self.is_tap && !vmnet {
+            report.push(
+                "tap",
+                OptionStatus::Approximated,
+                "a layer 3 utun device is created, Tap devices require the `vmnet` feature",
+            );
+        }
+        let utun = self
+            .name
+            .strip_prefix("utun")
+            .is_some_and(|unit| unit.parse::<i32>().is_ok());
+        if !vmnet && !self.name.is_empty() && !utun {
+            report.push(
+                "name",
+                OptionStatus::Approximated,
+                "only `utun[0-9]+` names can be requested, the next available utun device is used",
+            );
+        }
+        if !vmnet && self.queues.is_some_and(|queues| queues > 1) {
+            report.push(
+                "queues",
+                OptionStatus::Approximated,
+                "utun devices have a single queue",
+            );
+        }
+        if self.owner.is_some() {
+            report.push(
+                "owner",
+                OptionStatus::Ignored,
+                "devices have no owner on macOS",
+            );
+        }
+        if self.group.is_some() {
+            report.push(
+                "group",
+                OptionStatus::Ignored,
+                "devices have no group on macOS",
+            );
+        }
+        if self.packet_info {
+            report.push(
+                "packet_info",
+                OptionStatus::Ignored,
+                "packets carry no packet information header on macOS",
+            );
+        }
+        if self.persist {
+            report.push(
+                "persist",
+                OptionStatus::Unsupported,
+                "devices are removed once closed on macOS",
+            );
         }
+        if self.offload {
+            report.push(
+                "offload",
+                OptionStatus::Unsupported,
+                "devices have no virtio-net header on macOS",
+            );
+        }
+    }
+
+    /// Builds the queues of a new device into a [`TunPool`](struct.TunPool.html), which receives
+    /// from and sends to all of them at once.
+    #[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
+    pub fn build_pool(self) -> Result<TunPool> {
+        self.build().map(TunPool::new)
+    }
+
+    /// Builds a new instance of [`WatchedTun`](struct.WatchedTun.html), a single-queue device
+    /// which is created again from the options of the builder, under the same name, when it is
+    /// removed or its file descriptor fails permanently.
+    #[cfg(all(feature = "tokio", any(target_os = "linux", target_os = "macos")))]
+    pub fn build_watched(mut self) -> Result<WatchedTun> {
+        if self.queues.is_some_and(|queues| queues > 1) {
+            return Err(Error::InvalidParam { field: "queues" });
+        }
+        let tun = Tun::new(self.clone().params()?)?;
+        // The device is created again under the name it was given, e.g. from a `%d` template
+        self.name = tun.name().to_owned();
+        self.auto_suffix = false;
+        Ok(WatchedTun::new(self, tun))
+    }
+
+    /// Builds a new instance of [`SyncTun`](struct.SyncTun.html), which performs blocking I/O
+    /// without any async runtime.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn build_sync(self) -> Result<Vec<SyncTun>> {
+        let queues = self.queues.unwrap_or(1).max(1);
+        SyncTun::new(self.params()?, queues)
+    }
+
+    /// Builds a new TAP device whose queues are handed over to a QEMU process (Linux only), see
+    /// [`QemuTap`](struct.QemuTap.html).
+    ///
+    /// The device is a TAP device even if [`tap`](#method.tap) was not called, without packet
+    /// information as QEMU expects.
+    #[cfg(target_os = "linux")]
+    pub fn build_qemu(mut self) -> Result<QemuTap> {
+        self.is_tap = true;
+        self.packet_info = false;
+        QemuTap::new(self.build_sync()?)
+    }
+
+    /// Builds a new instance of [`AsyncIoTun`](struct.AsyncIoTun.html), which is driven by the
+    /// `async-io` reactor instead of tokio.
+    #[cfg(all(feature = "async-io", any(target_os = "linux", target_os = "macos")))]
+    pub fn build_async_io(self) -> Result<Vec<AsyncIoTun>> {
+        let queues = self.queues.unwrap_or(1).max(1);
+        AsyncIoTun::new(self.params()?, queues)
+    }
+
+    /// Builds the queues of a new device for a thread-per-core runtime (Linux only), see
+    /// [`percore`](percore/index.html).
+    ///
+    /// Each [`TunQueue`](percore/struct.TunQueue.html) is assigned to a CPU and registered with
+    /// the ring of the thread serving that CPU.
+    #[cfg(all(feature = "monoio", target_os = "linux"))]
+    pub fn build_per_core(self) -> Result<Vec<TunQueue>> {
+        let queues = self.queues.unwrap_or(1).max(1);
+        TunQueue::new(self.params()?, queues)
+    }
+
+    /// Validates the parameters of device.
+    pub(crate) fn params(self) -> Result<Params> {
+        if self
+            .mtu
+            .is_some_and(|mtu| !(MIN_MTU..=MAX_DEVICE_MTU).contains(&mtu))
+        {
+            return Err(Error::InvalidParam { field: "mtu" });
+        }
+        Ok(self.into())
     }
 }
 
@@ -213,10 +603,16 @@ impl From<TunBuilder> for Params {
                 if !builder.packet_info {
                     flags |= IFF_NO_PI as i16;
                 }
+                if builder.offload {
+                    flags |= libc::IFF_VNET_HDR as i16;
+                }
                 flags
             },
             persist: builder.persist,
+            offload: builder.offload,
             up: builder.up,
+            set_flags: builder.set_flags,
+            unset_flags: builder.unset_flags,
             mtu: builder.mtu,
             owner: builder.owner,
             group: builder.group,
@@ -224,6 +620,16 @@ impl From<TunBuilder> for Params {
             destination: builder.destination,
             broadcast: builder.broadcast,
             netmask: builder.netmask,
+            #[cfg(feature = "netlink")]
+            addresses: builder.addresses,
+            #[cfg(feature = "netlink")]
+            qdisc: builder.qdisc,
+            budget: builder.budget,
+            counters: builder.counters,
+            busy_poll: builder.busy_poll,
+            strict: builder.strict,
+            auto_suffix: builder.auto_suffix,
+            helper: builder.helper,
         }
     }
 
@@ -242,15 +648,22 @@ impl From<TunBuilder> for Params {
                 }
                 flags
             },
-            persist: builder.persist,
             up: builder.up,
+            set_flags: builder.set_flags,
+            unset_flags: builder.unset_flags,
             mtu: builder.mtu,
-            owner: builder.owner,
-            group: builder.group,
             address: builder.address,
             destination: builder.destination,
             broadcast: builder.broadcast,
             netmask: builder.netmask,
+            #[cfg(feature = "vmnet")]
+            vmnet: builder.is_tap.then_some(builder.vmnet_mode),
+            budget: builder.budget,
+            counters: builder.counters,
+            busy_poll: builder.busy_poll,
+            strict: builder.strict,
+            auto_suffix: builder.auto_suffix,
+            helper: builder.helper,
         }
     }
 
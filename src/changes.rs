@@ -0,0 +1,206 @@
+use crate::Result;
+use crate::devices;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::linux::netlink::Netlink;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+use std::collections::BTreeSet;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+#[cfg(not(all(feature = "netlink", target_os = "linux")))]
+use std::time::Duration;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+#[cfg(not(all(feature = "netlink", target_os = "linux")))]
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+
+/// Interval between two reads of the configuration of a device, when the kernel does not
+/// notify its changes.
+#[cfg(not(all(feature = "netlink", target_os = "linux")))]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of changes which wait for the receiver before the task waits in turn.
+const CHANGES_CAPACITY: usize = 16;
+
+/// Represents a change of the addresses of a device, emitted by
+/// [`AddressChanges`](struct.AddressChanges.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressChange {
+    /// The address `address`, of prefix length `prefix_len`, was added to the device.
+    Added { address: IpAddr, prefix_len: u8 },
+    /// The address `address`, of prefix length `prefix_len`, was removed from the device.
+    Removed { address: IpAddr, prefix_len: u8 },
+}
+
+/// Represents the changes of the MTU of a device, returned by
+/// [`Tun::mtu_changes`](struct.Tun.html#method.mtu_changes). The task watching them stops when
+/// this value is dropped.
+#[derive(Debug)]
+pub struct MtuChanges {
+    rx: mpsc::Receiver<i32>,
+    task: JoinHandle<()>,
+}
+
+impl MtuChanges {
+    pub(crate) fn start(iface: Arc<Interface>) -> Result<Self> {
+        let (rx, task) = start(
+            iface,
+            |iface| iface.mtu(None),
+            |before, after| (before != after).then_some(*after).into_iter().collect(),
+        )?;
+        Ok(Self { rx, task })
+    }
+
+    /// Waits for the next change, and returns the new MTU. Returns `None` once the device was
+    /// removed, or watching it failed.
+    pub async fn next(&mut self) -> Option<i32> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for MtuChanges {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Represents the changes of the IPv4 and IPv6 addresses of a device, returned by
+/// [`Tun::address_changes`](struct.Tun.html#method.address_changes). The task watching them
+/// stops when this value is dropped.
+#[derive(Debug)]
+pub struct AddressChanges {
+    rx: mpsc::Receiver<AddressChange>,
+    task: JoinHandle<()>,
+}
+
+impl AddressChanges {
+    pub(crate) fn start(iface: Arc<Interface>) -> Result<Self> {
+        let (rx, task) = start(iface, read_addresses, |before, after| {
+            let removed =
+                before
+                    .difference(after)
+                    .map(|&(address, prefix_len)| AddressChange::Removed {
+                        address,
+                        prefix_len,
+                    });
+            let added =
+                after
+                    .difference(before)
+                    .map(|&(address, prefix_len)| AddressChange::Added {
+                        address,
+                        prefix_len,
+                    });
+            removed.chain(added).collect()
+        })?;
+        Ok(Self { rx, task })
+    }
+
+    /// Waits for the next change. Returns `None` once the device was removed, or watching it
+    /// failed.
+    pub async fn next(&mut self) -> Option<AddressChange> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for AddressChanges {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Returns the addresses of the device of `iface`, failing once it was removed.
+fn read_addresses(iface: &Interface) -> Result<BTreeSet<(IpAddr, u8)>> {
+    // Fails if the device does not exist anymore, which `getifaddrs` does not tell
+    iface.index()?;
+    Ok(devices::interface_addresses(iface.name())?
+        .into_iter()
+        .collect())
+}
+
+/// Reads the state of the device of `iface` with `read`, and spawns the task sending the
+/// changes which `diff` finds between a state and the next one, read again whenever the
+/// device may have changed.
+fn start<S, E>(
+    iface: Arc<Interface>,
+    read: impl Fn(&Interface) -> Result<S> + Send + 'static,
+    diff: impl Fn(&S, &S) -> Vec<E> + Send + 'static,
+) -> Result<(mpsc::Receiver<E>, JoinHandle<()>)>
+where
+    S: Send + 'static,
+    E: Send + 'static,
+{
+    // Watching before reading the state, so that no change is missed in between
+    let mut wakeups = Wakeups::new(&iface)?;
+    let mut state = read(&iface)?;
+    let (tx, rx) = mpsc::channel(CHANGES_CAPACITY);
+    let task = tokio::spawn(async move {
+        while wakeups.next().await.is_ok() {
+            let Ok(next) = read(&iface) else {
+                return;
+            };
+            for change in diff(&state, &next) {
+                if tx.send(change).await.is_err() {
+                    return;
+                }
+            }
+            state = next;
+        }
+    });
+    Ok((rx, task))
+}
+
+/// Represents the source of the wakeups of a watching task: the notifications of the kernel on
+/// Linux with the `netlink` feature, else a timer.
+enum Wakeups {
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    Netlink {
+        socket: AsyncFd<Netlink>,
+        index: u32,
+    },
+    #[cfg(not(all(feature = "netlink", target_os = "linux")))]
+    Poll(Interval),
+}
+
+impl Wakeups {
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    fn new(iface: &Interface) -> Result<Self> {
+        Ok(Wakeups::Netlink {
+            socket: AsyncFd::new(Netlink::monitor()?)?,
+            index: iface.index()?,
+        })
+    }
+
+    #[cfg(not(all(feature = "netlink", target_os = "linux")))]
+    fn new(_iface: &Interface) -> Result<Self> {
+        let mut ticks = time::interval_at(Instant::now() + POLL_INTERVAL, POLL_INTERVAL);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ok(Wakeups::Poll(ticks))
+    }
+
+    /// Waits until the device may have changed.
+    async fn next(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(all(feature = "netlink", target_os = "linux"))]
+            Wakeups::Netlink { socket, index } => loop {
+                let mut guard = socket.readable().await?;
+                match guard.try_io(|socket| socket.get_ref().notified(*index)) {
+                    Ok(Ok(true)) => return Ok(()),
+                    Ok(Ok(false)) | Err(_) => {}
+                    // Notifications were lost, any of which may concern the device
+                    Ok(Err(err)) if err.raw_os_error() == Some(libc::ENOBUFS) => return Ok(()),
+                    Ok(Err(err)) => return Err(err),
+                }
+            },
+            #[cfg(not(all(feature = "netlink", target_os = "linux")))]
+            Wakeups::Poll(ticks) => {
+                ticks.tick().await;
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Internet checksums of IPv4 headers and of TCP, UDP, ICMP and ICMPv6 packets (RFC 1071), and
+//! their incremental update when a field changes (RFC 1624).
+//!
+//! The functions take whole IP packets, as read from a Tun device, and locate the transport
+//! header themselves. IPv6 packets with extension headers and IPv4 fragments other than whole
+//! datagrams are not supported, since their transport checksum cannot be computed from the
+//! packet alone.
+//!
+//! Rewriting a field, e.g. the source address of a packet for NAT, updates the checksums which
+//! cover it without summing the whole packet again:
+//!
+//! ```
+//! use tokio_tun::checksum;
+//!
+//! // An IPv4 header from 10.0.0.1 to 10.0.0.2
+//! let mut packet = [
+//!     0x45, 0, 0, 20, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+//! ];
+//! checksum::fill(&mut packet);
+//! assert!(checksum::verify(&packet));
+//!
+//! let old = u16::from_be_bytes([packet[10], packet[11]]);
+//! packet[12..16].copy_from_slice(&[192, 168, 0, 1]);
+//! let new = checksum::update(old, &[10, 0, 0, 1], &[192, 168, 0, 1]);
+//! packet[10..12].copy_from_slice(&new.to_be_bytes());
+//! assert_eq!(checksum::ipv4_header(&packet), Some(new));
+//! ```
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Offset of the checksum in the IPv4 header.
+const IPV4_CHECKSUM: usize = 10;
+
+/// Represents where the transport checksum of a packet is, and what it covers.
+struct Transport {
+    /// Offset of the transport header.
+    start: usize,
+    /// Offset of the end of the transport packet, before any padding of the link layer.
+    end: usize,
+    /// Offset of the checksum.
+    checksum: usize,
+    /// Sum of the pseudo header, if the checksum covers one.
+    pseudo: u32,
+    /// Whether a zero checksum means that there is none (UDP over IPv4).
+    optional: bool,
+}
+
+/// Adds `data` to the one's complement sum `initial`, as 16-bit big-endian words.
+pub(crate) fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut sum = initial;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum = sum.wrapping_add(u16::from_be_bytes([chunk[0], chunk[1]]) as u32);
+    }
+    if let [last] = chunks.remainder() {
+        sum = sum.wrapping_add(u16::from_be_bytes([*last, 0]) as u32);
+    }
+    sum
+}
+
+/// Folds a one's complement sum into a 16-bit checksum.
+pub(crate) fn fold(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Returns the sum of the pseudo header of an IPv4 or IPv6 packet for a transport protocol.
+pub(crate) fn pseudo_header(packet: &[u8], protocol: u8, len: usize) -> u32 {
+    let addresses = match packet[0] >> 4 {
+        4 => &packet[12..20],
+        _ => &packet[8..40],
+    };
+    sum(addresses, 0)
+        .wrapping_add(protocol as u32)
+        .wrapping_add(len as u32)
+}
+
+/// Returns the Internet checksum of `data`.
+pub fn compute(data: &[u8]) -> u16 {
+    fold(sum(data, 0))
+}
+
+/// Returns the checksum of `data` whose checksum field, at the even offset `field`, is zero.
+fn compute_without(data: &[u8], field: usize, initial: u32) -> u16 {
+    fold(sum(&data[field + 2..], sum(&data[..field], initial)))
+}
+
+/// Returns the checksum of the header of the IPv4 packet `packet`, ignoring its current
+/// checksum, or `None` if it is not an IPv4 packet.
+pub fn ipv4_header(packet: &[u8]) -> Option<u16> {
+    let len = ipv4_header_len(packet)?;
+    Some(compute_without(&packet[..len], IPV4_CHECKSUM, 0))
+}
+
+/// Returns the checksum of the TCP, UDP, ICMP or ICMPv6 packet carried by the IP packet
+/// `packet`, ignoring its current checksum, or `None` if it carries another protocol or is not
+/// supported.
+pub fn transport(packet: &[u8]) -> Option<u16> {
+    let transport = locate(packet)?;
+    let csum = compute_without(
+        &packet[transport.start..transport.end],
+        transport.checksum - transport.start,
+        transport.pseudo,
+    );
+    // A computed zero is sent as all ones, zero meaning no checksum for UDP
+    Some(if csum == 0 && transport.optional {
+        0xffff
+    } else {
+        csum
+    })
+}
+
+/// Writes the checksums of the IP packet `packet`: the checksum of its header if it is an IPv4
+/// packet, and the checksum of the TCP, UDP, ICMP or ICMPv6 packet it carries. Returns `false`
+/// if it is not an IP packet, in which case it is left unchanged.
+pub fn fill(packet: &mut [u8]) -> bool {
+    if let Some(csum) = ipv4_header(packet) {
+        packet[IPV4_CHECKSUM..IPV4_CHECKSUM + 2].copy_from_slice(&csum.to_be_bytes());
+    } else if ipv6_payload(packet).is_none() {
+        return false;
+    }
+    if let (Some(csum), Some(transport)) = (transport(packet), locate(packet)) {
+        packet[transport.checksum..transport.checksum + 2].copy_from_slice(&csum.to_be_bytes());
+    }
+    true
+}
+
+/// Returns `true` if the checksums of the IP packet `packet` are correct: the checksum of its
+/// header if it is an IPv4 packet, and the checksum of the TCP, UDP, ICMP or ICMPv6 packet it
+/// carries, if any and if supported. A UDP packet over IPv4 without checksum is correct.
+pub fn verify(packet: &[u8]) -> bool {
+    if let Some(len) = ipv4_header_len(packet) {
+        if compute(&packet[..len]) != 0 {
+            return false;
+        }
+    } else if ipv6_payload(packet).is_none() {
+        return false;
+    }
+    match locate(packet) {
+        Some(transport) if transport.optional && packet[transport.checksum..][..2] == [0, 0] => {
+            true
+        }
+        Some(transport) => {
+            fold(sum(
+                &packet[transport.start..transport.end],
+                transport.pseudo,
+            )) == 0
+        }
+        None => true,
+    }
+}
+
+/// Returns the checksum `checksum` updated for the replacement of the bytes `old` by the bytes
+/// `new` in the data it covers (RFC 1624), e.g. of an address or a port.
+///
+/// The replaced bytes must start at an even offset of the covered data, which holds for the
+/// addresses and the ports of IP packets.
+///
+/// # Panics
+///
+/// Panics if `old` and `new` have different lengths.
+pub fn update(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    assert_eq!(old.len(), new.len(), "replaced bytes differ in length");
+    // HC' = ~(~HC + ~m + m')
+    let word = |chunk: &[u8]| u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]);
+    let mut sum = !checksum as u32;
+    for (old, new) in old.chunks(2).zip(new.chunks(2)) {
+        sum = sum
+            .wrapping_add(!word(old) as u32)
+            .wrapping_add(word(new) as u32);
+    }
+    fold(sum)
+}
+
+/// Returns the length of the header of the IPv4 packet `packet`, or `None` if it is not an IPv4
+/// packet.
+fn ipv4_header_len(packet: &[u8]) -> Option<usize> {
+    if packet.first()? >> 4 != 4 {
+        return None;
+    }
+    let len = (packet[0] & 0x0f) as usize * 4;
+    (len >= 20 && packet.len() >= len).then_some(len)
+}
+
+/// Returns the length of the payload of the IPv6 packet `packet`, or `None` if it is not an
+/// IPv6 packet.
+fn ipv6_payload(packet: &[u8]) -> Option<usize> {
+    if packet.first()? >> 4 != 6 || packet.len() < 40 {
+        return None;
+    }
+    let len = u16::from_be_bytes([packet[4], packet[5]]) as usize;
+    (packet.len() >= 40 + len).then_some(len)
+}
+
+/// Locates the transport checksum of the IP packet `packet`.
+fn locate(packet: &[u8]) -> Option<Transport> {
+    let (start, end, protocol, v4) = if let Some(len) = ipv4_header_len(packet) {
+        // Only whole datagrams carry the whole transport packet
+        if u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0 {
+            return None;
+        }
+        let total = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if total < len || total > packet.len() {
+            return None;
+        }
+        (len, total, packet[9], true)
+    } else {
+        (40, 40 + ipv6_payload(packet)?, packet[6], false)
+    };
+    let (offset, pseudo) = match (protocol, v4) {
+        (IPPROTO_TCP, _) => (16, true),
+        (IPPROTO_UDP, _) => (6, true),
+        (IPPROTO_ICMP, true) => (2, false),
+        (IPPROTO_ICMPV6, false) => (2, true),
+        _ => return None,
+    };
+    if end < start + offset + 2 {
+        return None;
+    }
+    Some(Transport {
+        start,
+        end,
+        checksum: start + offset,
+        pseudo: if pseudo {
+            pseudo_header(packet, protocol, end - start)
+        } else {
+            0
+        },
+        optional: protocol == IPPROTO_UDP && v4,
+    })
+}
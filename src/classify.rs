@@ -0,0 +1,230 @@
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_DSTOPTS: u8 = 60;
+const IPPROTO_SCTP: u8 = 132;
+const IPPROTO_UDPLITE: u8 = 136;
+
+/// Largest number of IPv6 extension headers skipped to find the transport protocol.
+const MAX_EXTENSION_HEADERS: usize = 8;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Represents the version of an IP packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    /// Returns the version of the IP packet `packet`, from its first nibble, or `None` if it is
+    /// not an IP packet.
+    pub fn of(packet: &[u8]) -> Option<Self> {
+        match packet.first()? >> 4 {
+            4 => Some(IpVersion::V4),
+            6 => Some(IpVersion::V6),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the class of an IP packet returned by [`classify`]: its version, its transport
+/// protocol and its 5-tuple, e.g. to route it or to look up its flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PacketClass {
+    /// Version of the packet.
+    pub version: IpVersion,
+    /// Transport protocol (`IPPROTO_*`), past the IPv6 extension headers.
+    pub protocol: u8,
+    /// Source address.
+    pub source: IpAddr,
+    /// Destination address.
+    pub destination: IpAddr,
+    /// Source and destination ports of a TCP, UDP, UDP-Lite or SCTP packet, or `None` for
+    /// other protocols and for the fragments which do not carry them.
+    pub ports: Option<(u16, u16)>,
+    /// Whether the packet is a fragment, the first one included.
+    pub fragment: bool,
+}
+
+impl PacketClass {
+    /// Returns the FNV-1a hash of the 5-tuple, equal for all the packets of a flow. It is the
+    /// same across processes, runs and platforms, e.g. to spread the flows over workers.
+    ///
+    /// Fragments are hashed by their version, protocol and addresses only, as only the first
+    /// one carries the ports, so that all the fragments of a packet have the same hash.
+    ///
+    /// ```
+    /// use tokio_tun::classify;
+    ///
+    /// // The first fragment of a UDP packet from 10.0.0.1:1234 to 10.0.0.2:53, and the next one
+    /// let first = [
+    ///     0x45, 0, 0, 28, 0, 1, 0x20, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0x04, 0xd2, 0, 53,
+    ///     0, 16, 0, 0,
+    /// ];
+    /// let next = [
+    ///     0x45, 0, 0, 28, 0, 1, 0, 1, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+    ///     0,
+    /// ];
+    /// let (first, next) = (classify(&first).unwrap(), classify(&next).unwrap());
+    /// assert_eq!(first.ports, Some((1234, 53)));
+    /// assert_eq!(first.flow_hash(), next.flow_hash());
+    /// ```
+    pub fn flow_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut write = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        let version = match self.version {
+            IpVersion::V4 => 4,
+            IpVersion::V6 => 6,
+        };
+        write(&[version, self.protocol]);
+        for address in [self.source, self.destination] {
+            match address {
+                IpAddr::V4(address) => write(&address.octets()),
+                IpAddr::V6(address) => write(&address.octets()),
+            }
+        }
+        if let Some((source, destination)) = self.ports.filter(|_| !self.fragment) {
+            write(&source.to_be_bytes());
+            write(&destination.to_be_bytes());
+        }
+        hash
+    }
+}
+
+/// Classifies the IPv4 or IPv6 packet `packet` without copying it, or returns `None` if it is
+/// not an IP packet or is shorter than its header.
+///
+/// ```
+/// use tokio_tun::{IpVersion, classify};
+///
+/// // A UDP packet from 10.0.0.1:1234 to 10.0.0.2:53
+/// let packet = [
+///     0x45, 0, 0, 28, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0x04, 0xd2, 0, 53,
+///     0, 8, 0, 0,
+/// ];
+/// let class = classify(&packet).unwrap();
+/// assert_eq!(class.version, IpVersion::V4);
+/// assert_eq!(class.protocol, 17);
+/// assert_eq!(class.ports, Some((1234, 53)));
+/// ```
+pub fn classify(packet: &[u8]) -> Option<PacketClass> {
+    classify_transport(packet).map(|(class, _)| class)
+}
+
+/// Classifies `packet` like [`classify`], and also returns the offset of its transport header,
+/// which is `None` for the fragments which do not carry it.
+pub(crate) fn classify_transport(packet: &[u8]) -> Option<(PacketClass, Option<usize>)> {
+    match IpVersion::of(packet)? {
+        IpVersion::V4 => {
+            let ihl = (packet[0] & 0x0f) as usize * 4;
+            if ihl < 20 || packet.len() < ihl {
+                return None;
+            }
+            let address = |at: usize| {
+                IpAddr::from(Ipv4Addr::from(
+                    <[u8; 4]>::try_from(&packet[at..at + 4]).unwrap(),
+                ))
+            };
+            // Only the first fragment carries the transport header
+            let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+            let start = (fragment & 0x1fff == 0).then_some(ihl);
+            let class = PacketClass {
+                version: IpVersion::V4,
+                protocol: packet[9],
+                source: address(12),
+                destination: address(16),
+                ports: start.and_then(|start| ports(packet, packet[9], start)),
+                // More fragments, or an offset
+                fragment: fragment & 0x3fff != 0,
+            };
+            Some((class, start))
+        }
+        IpVersion::V6 => {
+            if packet.len() < 40 {
+                return None;
+            }
+            let address = |at: usize| {
+                IpAddr::from(Ipv6Addr::from(
+                    <[u8; 16]>::try_from(&packet[at..at + 16]).unwrap(),
+                ))
+            };
+            let (protocol, start, fragment) = skip_extension_headers(packet);
+            let class = PacketClass {
+                version: IpVersion::V6,
+                protocol,
+                source: address(8),
+                destination: address(24),
+                ports: start.and_then(|start| ports(packet, protocol, start)),
+                fragment,
+            };
+            Some((class, start))
+        }
+    }
+}
+
+/// Returns the transport protocol of the IPv6 packet `packet`, the offset of its header, which
+/// is `None` if the packet is a fragment other than the first one or if the extension headers
+/// do not fit in it, and whether the packet is a fragment.
+fn skip_extension_headers(packet: &[u8]) -> (u8, Option<usize>, bool) {
+    let mut protocol = packet[6];
+    let mut start = 40;
+    let mut fragment = false;
+    let is_extension = |protocol| {
+        matches!(
+            protocol,
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_FRAGMENT | IPPROTO_DSTOPTS
+        )
+    };
+    for _ in 0..MAX_EXTENSION_HEADERS {
+        if !is_extension(protocol) {
+            break;
+        }
+        let Some(header) = packet.get(start..start + 8) else {
+            return (protocol, None, fragment);
+        };
+        if protocol == IPPROTO_FRAGMENT {
+            let offset = u16::from_be_bytes([header[2], header[3]]);
+            if offset & 0xfff8 != 0 {
+                return (header[0], None, true);
+            }
+            // More fragments, an atomic fragment is a whole packet
+            fragment = offset & 1 != 0;
+            start += 8;
+        } else {
+            start += (header[1] as usize + 1) * 8;
+        }
+        protocol = header[0];
+    }
+    (
+        protocol,
+        (!is_extension(protocol)).then_some(start),
+        fragment,
+    )
+}
+
+/// Returns the ports of the packet of `protocol` at `start` in `packet`, if it has some.
+fn ports(packet: &[u8], protocol: u8, start: usize) -> Option<(u16, u16)> {
+    if !matches!(
+        protocol,
+        IPPROTO_TCP | IPPROTO_UDP | IPPROTO_UDPLITE | IPPROTO_SCTP
+    ) {
+        return None;
+    }
+    let ports = packet.get(start..start + 4)?;
+    Some((
+        u16::from_be_bytes([ports[0], ports[1]]),
+        u16::from_be_bytes([ports[2], ports[3]]),
+    ))
+}
@@ -0,0 +1,34 @@
+use std::net::Ipv4Addr;
+
+/// Represents the type of a device, which determines the framing of its packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    /// Layer 3 device, whose packets are IP packets.
+    Tun,
+    /// Layer 2 device, whose packets are Ethernet frames.
+    Tap,
+}
+
+/// Represents a snapshot of the configuration of a [`Tun`](struct.Tun.html), returned by
+/// [`Tun::config`](struct.Tun.html#method.config).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TunConfig {
+    /// Name of device.
+    pub name: String,
+    /// Whether the device is a TAP (layer 2) device.
+    pub tap: bool,
+    /// MTU of device.
+    pub mtu: i32,
+    /// Interface flags of device (e.g. `IFF_UP`).
+    pub flags: i16,
+    /// IPv4 address of device, if set.
+    pub address: Option<Ipv4Addr>,
+    /// IPv4 destination address of device, if set.
+    pub destination: Option<Ipv4Addr>,
+    /// IPv4 broadcast address of device, if set.
+    pub broadcast: Option<Ipv4Addr>,
+    /// IPv4 netmask of device, if set.
+    pub netmask: Option<Ipv4Addr>,
+    /// Number of queues (file descriptors) of device.
+    pub queues: usize,
+}
@@ -0,0 +1,90 @@
+use std::io::{self, ErrorKind};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Represents a snapshot of the software counters of a queue, returned by
+/// [`Tun::counters`](struct.Tun.html#method.counters).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Number of received packets.
+    pub rx_packets: u64,
+    /// Number of received bytes.
+    pub rx_bytes: u64,
+    /// Number of sent packets.
+    pub tx_packets: u64,
+    /// Number of sent bytes.
+    pub tx_bytes: u64,
+    /// Number of I/O attempts which returned `WouldBlock` and had to wait for readiness.
+    pub would_block: u64,
+    /// Number of writes which did not write the whole buffer.
+    pub short_writes: u64,
+}
+
+#[derive(Default)]
+struct AtomicCounters {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    would_block: AtomicU64,
+    short_writes: AtomicU64,
+}
+
+/// Represents the optional software counters of a queue, updated with relaxed atomic operations.
+/// Its clones share the counters, e.g. with a [`StatsSampler`](struct.StatsSampler.html).
+#[derive(Clone)]
+pub(crate) struct QueueCounters(Option<Arc<AtomicCounters>>);
+
+impl QueueCounters {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled.then(Default::default))
+    }
+
+    /// Records the result of a read and passes it through.
+    pub fn recv(&self, res: io::Result<usize>) -> io::Result<usize> {
+        if let Some(counters) = &self.0 {
+            match &res {
+                Ok(n) => {
+                    counters.rx_packets.fetch_add(1, Ordering::Relaxed);
+                    counters.rx_bytes.fetch_add(*n as u64, Ordering::Relaxed);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    counters.would_block.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => {}
+            }
+        }
+        res
+    }
+
+    /// Records the result of a write of `len` bytes and passes it through.
+    pub fn send(&self, len: usize, res: io::Result<usize>) -> io::Result<usize> {
+        if let Some(counters) = &self.0 {
+            match &res {
+                Ok(n) => {
+                    counters.tx_packets.fetch_add(1, Ordering::Relaxed);
+                    counters.tx_bytes.fetch_add(*n as u64, Ordering::Relaxed);
+                    if *n < len {
+                        counters.short_writes.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    counters.would_block.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => {}
+            }
+        }
+        res
+    }
+
+    pub fn snapshot(&self) -> Option<Counters> {
+        self.0.as_ref().map(|counters| Counters {
+            rx_packets: counters.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: counters.rx_bytes.load(Ordering::Relaxed),
+            tx_packets: counters.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: counters.tx_bytes.load(Ordering::Relaxed),
+            would_block: counters.would_block.load(Ordering::Relaxed),
+            short_writes: counters.short_writes.load(Ordering::Relaxed),
+        })
+    }
+}
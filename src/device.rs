@@ -0,0 +1,560 @@
+use crate::DeviceType;
+use crate::Error;
+use crate::Result;
+use crate::TunConfig;
+use crate::builder::IFF_TAP;
+use crate::helper;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::io::TunIo;
+#[cfg(target_os = "linux")]
+use crate::linux::params::Params;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::io::TunIo;
+#[cfg(target_os = "macos")]
+use crate::macos::params::Params;
+#[cfg(all(target_os = "macos", feature = "vmnet"))]
+use crate::macos::vmnet::{Vmnet, VmnetMode};
+#[cfg(target_os = "linux")]
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::net::Ipv4Addr;
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "macos")]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+static TUN: &CStr = c"/dev/net/tun";
+
+/// Maximum MTU of device, bounded by the maximum size of an IP packet.
+pub(crate) const MAX_MTU: usize = 65535;
+
+/// Allocates a new Tun/Tap device with `queues` non-blocking file descriptors and configures it,
+/// in the helper process of `params` if it has one. Returns the device along with the file
+/// descriptors of its queues.
+pub(crate) fn allocate(mut params: Params, queues: usize) -> Result<(Interface, Vec<OwnedFd>)> {
+    match params.helper.take() {
+        Some(command) => helper::allocate(&command, params, queues),
+        None => allocate_local(params, queues),
+    }
+}
+
+/// Allocates a new Tun/Tap device in this process.
+#[cfg(target_os = "linux")]
+pub(crate) fn allocate_local(params: Params, queues: usize) -> Result<(Interface, Vec<OwnedFd>)> {
+    let fds = (0..queues)
+        .map(|_| unsafe {
+            match libc::open(TUN.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK) {
+                fd if fd >= 0 => Ok(OwnedFd::from_raw_fd(fd)),
+                _ => Err(io::Error::last_os_error().into()),
+            }
+        })
+        .collect::<Result<Vec<_>>>()
+        .map_err(missing_capability)?;
+
+    let name = params.name.as_deref().unwrap_or_default();
+    let iface = match Interface::new(&fds, name, params.flags) {
+        Err(err) if is_name_in_use(&err, name) => {
+            if !params.auto_suffix {
+                return Err(Error::NameInUse(name.to_owned()));
+            }
+            // The kernel replaces `%d` by the smallest number which makes a free name
+            let pattern = format!("{}%d", name.trim_end_matches(|c: char| c.is_ascii_digit()));
+            Interface::new(&fds, &pattern, params.flags)
+        }
+        res => res,
+    }
+    .map_err(missing_capability)?;
+    iface.init(&fds, params)?;
+    Ok((iface, fds))
+}
+
+/// Returns `true` if attaching to the device named `name` failed with `err` because a device
+/// which cannot be attached to has this name: a device which is not a Tun/Tap device, one of the
+/// other type, or one which another process uses.
+#[cfg(target_os = "linux")]
+fn is_name_in_use(err: &Error, name: &str) -> bool {
+    let refused = matches!(
+        err,
+        Error::NixError(nix::Error::EBUSY | nix::Error::EINVAL | nix::Error::EEXIST)
+    );
+    refused && !name.is_empty() && name_exists(name)
+}
+
+/// Returns `true` if a network interface is named `name`.
+#[cfg(target_os = "linux")]
+fn name_exists(name: &str) -> bool {
+    CString::new(name).is_ok_and(|name| unsafe { libc::if_nametoindex(name.as_ptr()) } != 0)
+}
+
+/// Reports a permission denied to open the device node or to create the device, with what the
+/// process lacks.
+#[cfg(target_os = "linux")]
+fn missing_capability(err: Error) -> Error {
+    match err {
+        Error::PermissionDenied => Error::MissingCapability {
+            capability: "CAP_NET_ADMIN",
+            device_node_exists: Path::new(OsStr::from_bytes(TUN.to_bytes())).exists(),
+        },
+        err => err,
+    }
+}
+
+/// Allocates a new Tun/Tap device in this process.
+#[cfg(target_os = "macos")]
+pub(crate) fn allocate_local(params: Params, queues: usize) -> Result<(Interface, Vec<OwnedFd>)> {
+    #[cfg(feature = "vmnet")]
+    if let Some(mode) = &params.vmnet {
+        return allocate_vmnet(&params, mode, queues);
+    }
+
+    // In macOS, we use the utun interface
+    let mut fds = Vec::with_capacity(queues);
+    let specified_unit = if let Some(name) = &params.name {
+        // Check if name is in utun format
+        if name.starts_with("utun") {
+            name[4..].parse::<i32>().ok()
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    // If a specific utun name was requested, try to open it
+    if let Some(unit) = specified_unit {
+        match Interface::open_utun(unit) {
+            Ok((fd, name)) => {
+                fds.push(fd);
+
+                // Create Interface instance
+                let iface = Interface::new(&fds, &name, params.flags)?;
+                iface.init(params)?;
+                return Ok((iface, fds));
+            }
+            // The unit is used by another control socket
+            Err(Error::IoError(err)) if err.raw_os_error() == Some(libc::EBUSY) => {
+                if !params.auto_suffix {
+                    return Err(Error::NameInUse(format!("utun{}", unit)));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    // Otherwise, try to open the next available utun device
+    for i in 0..16 {
+        // Try to open utun devices from 0 to 15
+        match Interface::open_utun(i) {
+            Ok((fd, name)) => {
+                // Set fd to non-blocking mode
+                set_nonblocking(fd.as_raw_fd(), true)?;
+                fds.push(fd);
+
+                // Create Interface instance
+                let iface = Interface::new(&fds, &name, params.flags)?;
+                iface.init(params)?;
+                return Ok((iface, fds));
+            }
+            Err(_) => continue,
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "No available utun device found").into())
+}
+
+/// Starts a vmnet interface backing a Tap device, which is configured by the network stack
+/// running on it rather than by the host.
+#[cfg(all(target_os = "macos", feature = "vmnet"))]
+fn allocate_vmnet(
+    params: &Params,
+    mode: &VmnetMode,
+    queues: usize,
+) -> Result<(Interface, Vec<OwnedFd>)> {
+    if queues > 1 {
+        return Err(Error::Unsupported {
+            feature: "multi-queue",
+            platform: "vmnet",
+        });
+    }
+    if params.mtu.is_some()
+        || params.address.is_some()
+        || params.netmask.is_some()
+        || params.destination.is_some()
+        || params.broadcast.is_some()
+    {
+        // The network stack running on the interface configures it
+        return Err(Error::Unsupported {
+            feature: "setting the MTU or the addresses",
+            platform: "vmnet",
+        });
+    }
+    let (vmnet, fd) = Vmnet::start(mode)?;
+    let iface = Interface::with_vmnet(vmnet)?;
+    set_nonblocking(fd.as_raw_fd(), true)?;
+    Ok((iface, vec![fd]))
+}
+
+/// Switches `fd` between blocking and non-blocking mode.
+pub(crate) fn set_nonblocking(fd: RawFd, nonblocking: bool) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let flags = if nonblocking {
+        flags | libc::O_NONBLOCK
+    } else {
+        flags & !libc::O_NONBLOCK
+    };
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the minimum size of a receive buffer which can hold any packet of the device.
+pub(crate) fn recommended_buffer_size(iface: &Interface) -> Result<usize> {
+    let mtu = if iface.vnet_hdr()? {
+        // Coalesced packets may be as large as an IP packet may be
+        MAX_MTU
+    } else {
+        iface.mtu(None)? as usize
+    };
+    Ok(mtu + iface.overhead()?)
+}
+
+/// Returns the type of `iface`, known since it was attached and checked against the kernel for
+/// the queue `fd` on Linux.
+pub(crate) fn device_type(iface: &Interface, fd: BorrowedFd<'_>) -> Result<DeviceType> {
+    let tap = iface.is_tap()?;
+    #[cfg(target_os = "linux")]
+    if iface.kernel_is_tap(fd)? != tap {
+        return Err(Error::IoError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the kernel reports a device of another type",
+        )));
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = fd;
+    Ok(if tap {
+        DeviceType::Tap
+    } else {
+        DeviceType::Tun
+    })
+}
+
+/// Returns a snapshot of the whole configuration of `iface`, where the addresses which are not
+/// assigned to it are `None`.
+pub(crate) fn config(iface: &Interface) -> Result<TunConfig> {
+    Ok(TunConfig {
+        name: iface.name().to_owned(),
+        tap: iface.is_tap()?,
+        mtu: iface.mtu(None)?,
+        flags: iface.flags(None)?,
+        address: iface.address(None).ok(),
+        destination: iface.destination(None).ok(),
+        broadcast: iface.broadcast(None).ok(),
+        netmask: iface.netmask(None).ok(),
+        queues: iface.queues(),
+    })
+}
+
+/// Returns `true` if `iface` is administratively up.
+pub(crate) fn is_up(iface: &Interface) -> Result<bool> {
+    Ok(iface.flags(None)? & libc::IFF_UP as i16 != 0)
+}
+
+/// Brings `iface` up, or down.
+pub(crate) fn set_up(iface: &Interface, up: bool) -> Result<()> {
+    if up {
+        iface.flags(Some((libc::IFF_UP | libc::IFF_RUNNING) as i16))?;
+    } else {
+        iface.unset_flags(libc::IFF_UP as i16)?;
+    }
+    Ok(())
+}
+
+/// Returns `true` if `iface` is administratively and operationally up, and none of its IPv6
+/// addresses is still tentative (Linux).
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(crate) fn is_ready(iface: &Interface) -> Result<bool> {
+    let up = (libc::IFF_UP | libc::IFF_RUNNING) as i16;
+    if iface.flags(None)? & up != up {
+        return Ok(false);
+    }
+    #[cfg(target_os = "linux")]
+    if has_tentative_address(iface.name())? {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Returns `true` if an IPv6 address of the interface `name` is undergoing duplicate address
+/// detection. Addresses whose detection failed are not waited for.
+#[cfg(target_os = "linux")]
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+fn has_tentative_address(name: &str) -> Result<bool> {
+    const IFA_F_DADFAILED: u32 = 0x08;
+    const IFA_F_TENTATIVE: u32 = 0x40;
+    let addresses = match std::fs::read_to_string("/proc/net/if_inet6") {
+        Ok(addresses) => addresses,
+        // IPv6 is disabled
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    // Each line holds the address, index, prefix length, scope, flags and name of interface
+    Ok(addresses.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let flags = fields
+            .get(4)
+            .and_then(|flags| u32::from_str_radix(flags, 16).ok())
+            .unwrap_or_default();
+        fields.get(5) == Some(&name) && flags & IFA_F_TENTATIVE != 0 && flags & IFA_F_DADFAILED == 0
+    }))
+}
+
+/// Applies the parameters of `params` which differ from the current configuration of `iface`
+/// (MTU, IPv4 addresses, additional addresses and up state), and returns the names of the
+/// options which changed. The name and mode of the device cannot change, and fail before
+/// anything is applied. If an option fails to apply, the ones already applied are restored.
+pub(crate) fn reconfigure(iface: &Interface, params: Params) -> Result<Vec<&'static str>> {
+    if params
+        .name
+        .as_deref()
+        .is_some_and(|name| name != iface.name())
+    {
+        return Err(Error::InvalidParam { field: "name" });
+    }
+    if (params.flags as i32 & IFF_TAP != 0) != iface.is_tap()? {
+        return Err(Error::InvalidParam { field: "tap" });
+    }
+    let old = Snapshot::of(iface)?;
+    let mut changed = Vec::new();
+    if let Err(err) = apply(iface, params, &old, &mut changed) {
+        // Best effort, the device is left as it is if it cannot be restored either
+        for option in changed.iter().rev() {
+            let _ = old.restore(iface, option);
+        }
+        return Err(err);
+    }
+    Ok(changed.into_iter().map(|option| option.name()).collect())
+}
+
+/// Applies the parameters of `params` which differ from `old` to `iface`, recording the options
+/// applied into `changed`.
+fn apply(
+    iface: &Interface,
+    params: Params,
+    old: &Snapshot,
+    changed: &mut Vec<Changed>,
+) -> Result<()> {
+    if let Some(address) = params.address.filter(|&addr| old.address != Some(addr)) {
+        iface.address(Some(address))?;
+        changed.push(Changed::Address);
+    }
+    if let Some(netmask) = params.netmask.filter(|&addr| old.netmask != Some(addr)) {
+        iface.netmask(Some(netmask))?;
+        changed.push(Changed::Netmask);
+    }
+    if let Some(destination) = params
+        .destination
+        .filter(|&addr| old.destination != Some(addr))
+    {
+        iface.destination(Some(destination))?;
+        changed.push(Changed::Destination);
+    }
+    if let Some(broadcast) = params.broadcast.filter(|&addr| old.broadcast != Some(addr)) {
+        iface.broadcast(Some(broadcast))?;
+        changed.push(Changed::Broadcast);
+    }
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    for net in params.addresses {
+        if !old.addresses.contains(&net.addr()) {
+            iface.add_address(net)?;
+            changed.push(Changed::Addresses(net));
+        }
+    }
+    if let Some(mtu) = params.mtu.filter(|&mtu| mtu != old.mtu) {
+        iface.mtu(Some(mtu))?;
+        changed.push(Changed::Mtu);
+    }
+    for (flag, name) in LINK_FLAGS {
+        if params.set_flags & flag != 0 && old.flags & flag == 0 {
+            iface.flags(Some(flag))?;
+        } else if params.unset_flags & flag != 0 && old.flags & flag != 0 {
+            iface.unset_flags(flag)?;
+        } else {
+            continue;
+        }
+        changed.push(Changed::Flag(flag, name));
+    }
+    if let Some(up) = params.up.filter(|&up| up != old.up) {
+        set_up(iface, up)?;
+        changed.push(Changed::Up(up));
+    }
+    Ok(())
+}
+
+/// Link flags which [`reconfigure`] applies, with the names of the methods of the builder which
+/// set them.
+const LINK_FLAGS: [(i16, &str); 3] = [
+    (libc::IFF_MULTICAST as i16, "multicast"),
+    (libc::IFF_NOARP as i16, "arp"),
+    (libc::IFF_PROMISC as i16, "promisc"),
+];
+
+/// Represents the configuration of a device which [`reconfigure`] may change.
+struct Snapshot {
+    mtu: i32,
+    flags: i16,
+    up: bool,
+    address: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+    destination: Option<Ipv4Addr>,
+    broadcast: Option<Ipv4Addr>,
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    addresses: Vec<std::net::IpAddr>,
+}
+
+impl Snapshot {
+    fn of(iface: &Interface) -> Result<Self> {
+        Ok(Self {
+            mtu: iface.mtu(None)?,
+            flags: iface.flags(None)?,
+            up: is_up(iface)?,
+            address: iface.address(None).ok(),
+            netmask: iface.netmask(None).ok(),
+            destination: iface.destination(None).ok(),
+            broadcast: iface.broadcast(None).ok(),
+            #[cfg(all(target_os = "linux", feature = "netlink"))]
+            addresses: crate::devices::addresses()?
+                .remove(iface.name())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Restores the option `option` of `iface` to its value in the snapshot. An IPv4 address
+    /// which was not set is left as it is.
+    fn restore(&self, iface: &Interface, option: &Changed) -> Result<()> {
+        match *option {
+            Changed::Address => {
+                if let Some(address) = self.address {
+                    iface.address(Some(address))?;
+                }
+            }
+            Changed::Netmask => {
+                if let Some(netmask) = self.netmask {
+                    iface.netmask(Some(netmask))?;
+                }
+            }
+            Changed::Destination => {
+                if let Some(destination) = self.destination {
+                    iface.destination(Some(destination))?;
+                }
+            }
+            Changed::Broadcast => {
+                if let Some(broadcast) = self.broadcast {
+                    iface.broadcast(Some(broadcast))?;
+                }
+            }
+            #[cfg(all(target_os = "linux", feature = "netlink"))]
+            Changed::Addresses(net) => iface.del_address(net)?,
+            Changed::Mtu => {
+                iface.mtu(Some(self.mtu))?;
+            }
+            Changed::Flag(flag, _) if self.flags & flag != 0 => {
+                iface.flags(Some(flag))?;
+            }
+            Changed::Flag(flag, _) => {
+                iface.unset_flags(flag)?;
+            }
+            Changed::Up(_) => set_up(iface, self.up)?,
+        }
+        Ok(())
+    }
+}
+
+/// Represents an option which [`reconfigure`] changed.
+enum Changed {
+    Address,
+    Netmask,
+    Destination,
+    Broadcast,
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    Addresses(ipnet::IpNet),
+    Mtu,
+    Flag(i16, &'static str),
+    Up(bool),
+}
+
+impl Changed {
+    /// Returns the name of the method of the builder which sets the option.
+    fn name(&self) -> &'static str {
+        match self {
+            Changed::Address => "address",
+            Changed::Netmask => "netmask",
+            Changed::Destination => "destination",
+            Changed::Broadcast => "broadcast",
+            #[cfg(all(target_os = "linux", feature = "netlink"))]
+            Changed::Addresses(_) => "ip",
+            Changed::Mtu => "mtu",
+            Changed::Flag(_, name) => name,
+            Changed::Up(true) => "up",
+            Changed::Up(false) => "down",
+        }
+    }
+}
+
+/// Represents what closing the last handle of a device tears down, e.g. with
+/// [`Tun::close_with`](struct.Tun.html#method.close_with).
+///
+/// By default, nothing is: the device keeps its state and its persistent flag, so that a
+/// persistent device survives, and a device which is not is removed by the kernel as usual.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CloseOptions {
+    pub(crate) down: bool,
+    pub(crate) clear_persist: bool,
+}
+
+impl CloseOptions {
+    /// Creates the default options, which tear nothing down.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the device down before closing it.
+    pub fn down(mut self) -> Self {
+        self.down = true;
+        self
+    }
+
+    /// Clears the persistent flag of the device before closing it, so that the kernel removes
+    /// it (Linux only).
+    pub fn clear_persist(mut self) -> Self {
+        self.clear_persist = true;
+        self
+    }
+}
+
+/// Closes the file descriptor of a queue, tearing the device down as `options` tell if it is
+/// the last one.
+pub(crate) fn close(iface: Arc<Interface>, io: TunIo, options: CloseOptions) -> Result<()> {
+    match Arc::try_unwrap(iface) {
+        Ok(iface) => {
+            iface.shutdown(io.as_fd(), options)?;
+            io.close()?;
+            iface.close()
+        }
+        Err(_) => Ok(io.close()?),
+    }
+}
@@ -0,0 +1,208 @@
+use crate::Result;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Represents a Tun/Tap device which exists on the system, returned by [`list_devices`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Name of device.
+    pub name: String,
+    /// Whether the device is a TAP (layer 2) device.
+    pub tap: bool,
+    /// Whether the device outlives its file descriptors, in which case it has to be deleted
+    /// explicitly. utun devices of macOS never are.
+    pub persistent: bool,
+    /// Whether the device was created with multiple queues.
+    pub multi_queue: bool,
+    /// UID of the user allowed to attach to the device, if restricted (Linux only).
+    pub owner: Option<u32>,
+    /// GID of the group allowed to attach to the device, if restricted (Linux only).
+    pub group: Option<u32>,
+    /// IPv4 and IPv6 addresses assigned to the device.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Lists the Tun/Tap devices which exist on the system, e.g. to adopt persistent devices or to
+/// clean up the ones left behind, sorted by name.
+///
+/// On Linux, the devices are found in sysfs, whatever created them. On macOS, the utun devices
+/// are listed, including the ones of other applications.
+///
+/// ```no_run
+/// for device in tokio_tun::list_devices()? {
+///     if device.persistent {
+///         println!("{} ({:?})", device.name, device.addresses);
+///     }
+/// }
+/// # Ok::<(), tokio_tun::Error>(())
+/// ```
+pub fn list_devices() -> Result<Vec<DeviceInfo>> {
+    let mut addresses = addresses()?;
+    let mut devices = Vec::new();
+    for name in names()? {
+        let addresses = addresses.remove(&name).unwrap_or_default();
+        #[cfg(target_os = "linux")]
+        if let Some(device) = sysfs_device(name, addresses)? {
+            devices.push(device);
+        }
+        #[cfg(target_os = "macos")]
+        if name.starts_with("utun") {
+            devices.push(DeviceInfo {
+                name,
+                tap: false,
+                persistent: false,
+                multi_queue: false,
+                owner: None,
+                group: None,
+                addresses,
+            });
+        }
+    }
+    Ok(devices)
+}
+
+/// Returns the names of the network interfaces of the system, sorted.
+#[cfg(target_os = "linux")]
+fn names() -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir("/sys/class/net")?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the names of the network interfaces of the system, sorted.
+#[cfg(target_os = "macos")]
+fn names() -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for_each_ifaddr(|name, _, _| names.push(name.to_owned()))?;
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Returns the device `name` from its attributes in sysfs, or `None` if it is not a Tun/Tap
+/// device, which are the only devices with `tun_flags`.
+#[cfg(target_os = "linux")]
+fn sysfs_device(name: String, addresses: Vec<IpAddr>) -> io::Result<Option<DeviceInfo>> {
+    let dir = format!("/sys/class/net/{}", name);
+    let flags = match fs::read_to_string(format!("{}/tun_flags", dir)) {
+        Ok(flags) => flags,
+        // The device was removed in the meantime, or is not a Tun/Tap device
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let flags = i32::from_str_radix(flags.trim().trim_start_matches("0x"), 16)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    // Either is -1 when the device is not restricted to a user or a group
+    let id = |attr: &str| -> io::Result<Option<u32>> {
+        Ok(fs::read_to_string(format!("{}/{}", dir, attr))?
+            .trim()
+            .parse()
+            .ok())
+    };
+    Ok(Some(DeviceInfo {
+        name,
+        tap: flags & libc::IFF_TAP != 0,
+        persistent: flags & libc::IFF_PERSIST != 0,
+        multi_queue: flags & libc::IFF_MULTI_QUEUE != 0,
+        owner: id("owner")?,
+        group: id("group")?,
+        addresses,
+    }))
+}
+
+/// Returns the IPv4 and IPv6 addresses of the network interfaces of the system, by name.
+pub(crate) fn addresses() -> io::Result<BTreeMap<String, Vec<IpAddr>>> {
+    let mut addresses = BTreeMap::<String, Vec<IpAddr>>::new();
+    for_each_ifaddr(|name, addr, _| {
+        if let Some(addr) = addr {
+            addresses.entry(name.to_owned()).or_default().push(addr);
+        }
+    })?;
+    Ok(addresses)
+}
+
+/// Returns the IPv4 and IPv6 addresses of the network interface `name`, with the length of
+/// their prefix.
+#[cfg(feature = "tokio")]
+pub(crate) fn interface_addresses(name: &str) -> io::Result<Vec<(IpAddr, u8)>> {
+    let mut addresses = Vec::new();
+    for_each_ifaddr(|entry_name, addr, prefix_len| {
+        if let Some(addr) = addr.filter(|_| entry_name == name) {
+            addresses.push((addr, prefix_len));
+        }
+    })?;
+    Ok(addresses)
+}
+
+/// Calls `f` with the name, and the IP address if any and the length of its prefix, of each
+/// entry of `getifaddrs`.
+fn for_each_ifaddr(mut f: impl FnMut(&str, Option<IpAddr>, u8)) -> io::Result<()> {
+    let mut ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut ifaddr = ifaddrs;
+    while let Some(entry) = unsafe { ifaddr.as_ref() } {
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }.to_string_lossy();
+        let addr = unsafe { ip_addr(entry.ifa_addr) };
+        let prefix_len = addr.map_or(0, |addr| unsafe { prefix_len(entry.ifa_netmask, addr) });
+        f(&name, addr, prefix_len);
+        ifaddr = entry.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(())
+}
+
+/// Returns the length of the prefix of the netmask `netmask` of the address `addr`, counted
+/// from the bytes the socket address holds: the netmasks of macOS may lack their family and
+/// their trailing zero bytes.
+///
+/// # Safety
+///
+/// `netmask` must be null or point to a socket address of the family of `addr`, or as long as
+/// its `sa_len` on macOS.
+unsafe fn prefix_len(netmask: *const libc::sockaddr, addr: IpAddr) -> u8 {
+    let Some(netmask) = (unsafe { netmask.as_ref() }) else {
+        return 0;
+    };
+    // Offset and length of `sin_addr` and `sin6_addr`
+    let (offset, len) = match addr {
+        IpAddr::V4(_) => (4, 4),
+        IpAddr::V6(_) => (8, 16),
+    };
+    #[cfg(target_os = "macos")]
+    let len = (netmask.sa_len as usize).saturating_sub(offset).min(len);
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            (netmask as *const libc::sockaddr as *const u8).add(offset),
+            len,
+        )
+    };
+    bytes.iter().map(|byte| byte.count_ones()).sum::<u32>() as u8
+}
+
+/// Returns the IP address of `addr`, if it is one.
+///
+/// # Safety
+///
+/// `addr` must be null or point to a socket address of the size of its family.
+unsafe fn ip_addr(addr: *const libc::sockaddr) -> Option<IpAddr> {
+    let addr = unsafe { addr.as_ref() }?;
+    match addr.sa_family as i32 {
+        libc::AF_INET => {
+            let addr = unsafe { &*(addr as *const _ as *const libc::sockaddr_in) };
+            Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into())
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(addr as *const _ as *const libc::sockaddr_in6) };
+            Some(Ipv6Addr::from(addr.sin6_addr.s6_addr).into())
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,525 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::checksum;
+use crate::dot1q;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const IPPROTO_UDP: u8 = 17;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+/// Length of the fixed part of a BOOTP message, before the magic cookie and the options.
+const BOOTP_LEN: usize = 236;
+/// Smallest BOOTP message, which some clients require of the replies.
+const BOOTP_MIN_LEN: usize = 300;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Broadcast bit of the flags, set by the clients which cannot receive unicast replies yet.
+const FLAG_BROADCAST: u16 = 0x8000;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const DECLINE: u8 = 4;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+const RELEASE: u8 = 7;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+/// Time for which an offered address is held for the client, waiting for its request.
+const OFFER_HOLD: Duration = Duration::from_secs(30);
+
+/// MAC address of the server when none is set.
+const DEFAULT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x67];
+
+/// Represents an address leased by a [`DhcpServer`](struct.DhcpServer.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// MAC address of the client.
+    pub mac: [u8; 6],
+    /// Address leased to the client.
+    pub ip: Ipv4Addr,
+    /// Time at which the lease expires unless the client renews it.
+    pub expires: Instant,
+}
+
+/// Represents the holder of an address of the pool.
+#[derive(Debug, Clone, Copy)]
+struct Binding {
+    /// MAC address of the client, or `None` for an address declined as already in use.
+    mac: Option<[u8; 6]>,
+    expires: Instant,
+    /// Whether the client acknowledged the address, or it was only offered.
+    bound: bool,
+}
+
+/// Represents the parts of a message of a client which the server uses.
+struct Message<'a> {
+    /// VLAN tags of the frame, and its EtherType.
+    tags: &'a [u8],
+    kind: u8,
+    xid: [u8; 4],
+    flags: u16,
+    ciaddr: Ipv4Addr,
+    chaddr: [u8; 6],
+    requested_ip: Option<Ipv4Addr>,
+    server_id: Option<Ipv4Addr>,
+}
+
+/// Represents a TAP device which hands out IPv4 addresses from a pool to the DHCP clients
+/// reaching it, e.g. the VMs or containers bridged to the device in integration tests, without
+/// running dnsmasq.
+///
+/// The server answers as the host `server_ip`, at the MAC address set with
+/// [`mac`](#method.mac). The messages of the clients, sent to UDP port 67, are not returned by
+/// [`recv`](#method.recv), whether they were answered or not; the other frames are. Only the
+/// clients on the link are served, not the ones behind a relay. The leases live as long as
+/// this value; the clients renewing their lease by unicast need the server address to be
+/// resolved, which wrapping the device in an [`ArpResponder`](struct.ArpResponder.html) mapping
+/// `server_ip` to the MAC address of the server provides.
+///
+/// The device must be created without packet information and offload, whose headers would not
+/// be understood.
+///
+/// ```no_run
+/// use std::net::Ipv4Addr;
+/// use tokio_tun::{DhcpServer, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tap = TunBuilder::new().tap().up().build()?.pop().unwrap();
+/// let server_ip = Ipv4Addr::new(10, 0, 0, 1);
+/// let pool = Ipv4Addr::new(10, 0, 0, 100)..=Ipv4Addr::new(10, 0, 0, 199);
+/// let dhcp = DhcpServer::new(tap, server_ip, pool)?
+///     .router(server_ip)
+///     .dns([server_ip]);
+/// let mut buf = [0u8; 1514];
+/// loop {
+///     let n = dhcp.recv(&mut buf).await?;
+///     // Handles the other frames of the clients
+/// }
+/// # }
+/// ```
+pub struct DhcpServer<T> {
+    inner: T,
+    server_ip: Ipv4Addr,
+    mac: [u8; 6],
+    pool: RangeInclusive<Ipv4Addr>,
+    netmask: Ipv4Addr,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_time: Duration,
+    bindings: Mutex<HashMap<Ipv4Addr, Binding>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for DhcpServer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DhcpServer")
+            .field("inner", &self.inner)
+            .field("server_ip", &self.server_ip)
+            .field("pool", &self.pool)
+            .field("netmask", &self.netmask)
+            .field("router", &self.router)
+            .field("dns", &self.dns)
+            .field("lease_time", &self.lease_time)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice> DhcpServer<T> {
+    /// Wraps `inner`, serving as `server_ip` the addresses of `pool` other than `server_ip`,
+    /// with a netmask of `255.255.255.0`, no router nor DNS server, and leases of an hour.
+    /// Fails with `io::ErrorKind::InvalidInput` if it is not a TAP device.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pool` is empty.
+    pub fn new(inner: T, server_ip: Ipv4Addr, pool: RangeInclusive<Ipv4Addr>) -> Result<Self> {
+        assert!(!pool.is_empty(), "the pool of addresses is empty");
+        if !inner.config()?.tap {
+            return Err(
+                io::Error::new(ErrorKind::InvalidInput, "serving DHCP needs a TAP device").into(),
+            );
+        }
+        Ok(Self {
+            inner,
+            server_ip,
+            mac: DEFAULT_MAC,
+            pool,
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            router: None,
+            dns: Vec::new(),
+            lease_time: Duration::from_secs(3600),
+            bindings: Mutex::default(),
+        })
+    }
+
+    /// Sets the MAC address of the server, `02:00:00:00:00:67` by default.
+    pub fn mac(mut self, mac: [u8; 6]) -> Self {
+        self.mac = mac;
+        self
+    }
+
+    /// Sets the netmask given to the clients.
+    pub fn netmask(mut self, netmask: Ipv4Addr) -> Self {
+        self.netmask = netmask;
+        self
+    }
+
+    /// Sets the default gateway given to the clients.
+    pub fn router(mut self, router: Ipv4Addr) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Sets the DNS servers given to the clients.
+    pub fn dns(mut self, servers: impl IntoIterator<Item = Ipv4Addr>) -> Self {
+        self.dns = servers.into_iter().collect();
+        self
+    }
+
+    /// Sets the duration of the leases.
+    pub fn lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = lease_time;
+        self
+    }
+
+    /// Returns the leases acknowledged to the clients which have not expired.
+    pub fn leases(&self) -> Vec<DhcpLease> {
+        let now = Instant::now();
+        let bindings = self.bindings.lock().unwrap();
+        let mut leases: Vec<_> = bindings
+            .iter()
+            .filter(|(_, binding)| binding.bound && binding.expires > now)
+            .filter_map(|(&ip, binding)| {
+                Some(DhcpLease {
+                    mac: binding.mac?,
+                    ip,
+                    expires: binding.expires,
+                })
+            })
+            .collect();
+        leases.sort_by_key(|lease| lease.ip);
+        leases
+    }
+
+    /// Receives a frame from the device, answering the messages of the DHCP clients in the
+    /// meantime.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.recv(buf).await?;
+            let Some(message) = parse(&buf[..n]) else {
+                return Ok(n);
+            };
+            if let Some(reply) = self.reply(&message) {
+                self.inner.send_all(&reply).await?;
+            }
+        }
+    }
+
+    /// Sends a frame to the device.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf).await
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Updates the bindings with the message `message`, and returns the frame of the reply, if
+    /// any.
+    fn reply(&self, message: &Message<'_>) -> Option<Vec<u8>> {
+        let now = Instant::now();
+        let mut bindings = self.bindings.lock().unwrap();
+        bindings.retain(|_, binding| binding.expires > now);
+        let for_other_server = message
+            .server_id
+            .is_some_and(|server_id| server_id != self.server_ip);
+        match message.kind {
+            DISCOVER => {
+                let ip = self.allocate(&bindings, message.chaddr, message.requested_ip)?;
+                // A lease the client holds is offered again as it is, not downgraded to an offer
+                if !bindings.get(&ip).is_some_and(|binding| binding.bound) {
+                    bindings.insert(
+                        ip,
+                        Binding {
+                            mac: Some(message.chaddr),
+                            expires: now + OFFER_HOLD,
+                            bound: false,
+                        },
+                    );
+                }
+                Some(self.build(message, OFFER, ip))
+            }
+            // The client chose the offer of another server
+            REQUEST if for_other_server => {
+                bindings.retain(|_, binding| binding.bound || binding.mac != Some(message.chaddr));
+                None
+            }
+            REQUEST => {
+                let ip = message.requested_ip.unwrap_or(message.ciaddr);
+                if !self.is_available(&bindings, ip, message.chaddr) {
+                    return Some(self.build(message, NAK, Ipv4Addr::UNSPECIFIED));
+                }
+                bindings.retain(|_, binding| binding.mac != Some(message.chaddr));
+                bindings.insert(
+                    ip,
+                    Binding {
+                        mac: Some(message.chaddr),
+                        expires: now + self.lease_time,
+                        bound: true,
+                    },
+                );
+                Some(self.build(message, ACK, ip))
+            }
+            DECLINE if !for_other_server => {
+                // Another host uses the address: it is not offered again for a lease time. Only
+                // the client the address was offered or leased to may decline it
+                let ip = message.requested_ip?;
+                if bindings.get(&ip)?.mac != Some(message.chaddr) {
+                    return None;
+                }
+                bindings.insert(
+                    ip,
+                    Binding {
+                        mac: None,
+                        expires: now + self.lease_time,
+                        bound: true,
+                    },
+                );
+                None
+            }
+            RELEASE if !for_other_server => {
+                bindings.retain(|&ip, binding| {
+                    ip != message.ciaddr || binding.mac != Some(message.chaddr)
+                });
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the address to offer to the client `mac`: the one it holds, else the one it
+    /// asked for if it is available, else the first available one of the pool.
+    fn allocate(
+        &self,
+        bindings: &HashMap<Ipv4Addr, Binding>,
+        mac: [u8; 6],
+        requested: Option<Ipv4Addr>,
+    ) -> Option<Ipv4Addr> {
+        let held = bindings
+            .iter()
+            .find(|(_, binding)| binding.mac == Some(mac))
+            .map(|(&ip, _)| ip);
+        held.or(requested.filter(|&ip| self.is_available(bindings, ip, mac)))
+            .or_else(|| {
+                let (start, end) = (u32::from(*self.pool.start()), u32::from(*self.pool.end()));
+                (start..=end)
+                    .map(Ipv4Addr::from)
+                    .find(|&ip| self.is_available(bindings, ip, mac))
+            })
+    }
+
+    /// Returns `true` if `ip` can be leased to the client `mac`.
+    fn is_available(
+        &self,
+        bindings: &HashMap<Ipv4Addr, Binding>,
+        ip: Ipv4Addr,
+        mac: [u8; 6],
+    ) -> bool {
+        self.pool.contains(&ip)
+            && ip != self.server_ip
+            && bindings
+                .get(&ip)
+                .is_none_or(|binding| binding.mac == Some(mac))
+    }
+
+    /// Returns the frame of the reply `kind` to `message`, leasing `yiaddr`.
+    fn build(&self, message: &Message<'_>, kind: u8, yiaddr: Ipv4Addr) -> Vec<u8> {
+        let mut bootp = vec![0u8; BOOTP_LEN];
+        bootp[0] = 2; // Reply
+        bootp[1] = 1; // Ethernet
+        bootp[2] = 6;
+        bootp[4..8].copy_from_slice(&message.xid);
+        bootp[10..12].copy_from_slice(&message.flags.to_be_bytes());
+        if kind == ACK {
+            bootp[12..16].copy_from_slice(&message.ciaddr.octets());
+        }
+        bootp[16..20].copy_from_slice(&yiaddr.octets());
+        bootp[28..34].copy_from_slice(&message.chaddr);
+        bootp.extend_from_slice(&MAGIC_COOKIE);
+
+        let mut option = |code: u8, value: &[u8]| {
+            bootp.push(code);
+            bootp.push(value.len() as u8);
+            bootp.extend_from_slice(value);
+        };
+        option(OPTION_MESSAGE_TYPE, &[kind]);
+        option(OPTION_SERVER_ID, &self.server_ip.octets());
+        if kind != NAK {
+            let secs = u32::try_from(self.lease_time.as_secs()).unwrap_or(u32::MAX);
+            option(OPTION_LEASE_TIME, &secs.to_be_bytes());
+            option(OPTION_SUBNET_MASK, &self.netmask.octets());
+            if let Some(router) = self.router {
+                option(OPTION_ROUTER, &router.octets());
+            }
+            if !self.dns.is_empty() {
+                let servers: Vec<u8> = self.dns.iter().flat_map(|dns| dns.octets()).collect();
+                option(OPTION_DNS, &servers);
+            }
+        }
+        bootp.push(OPTION_END);
+        if bootp.len() < BOOTP_MIN_LEN {
+            bootp.resize(BOOTP_MIN_LEN, OPTION_PAD);
+        }
+
+        // RFC 2131, 4.1: to the address of a bound client, else to the address offered to the
+        // client unless it asked for broadcast replies
+        let (destination_mac, destination_ip) = if kind == NAK {
+            ([0xff; 6], Ipv4Addr::BROADCAST)
+        } else if !message.ciaddr.is_unspecified() {
+            (message.chaddr, message.ciaddr)
+        } else if message.flags & FLAG_BROADCAST != 0 {
+            ([0xff; 6], Ipv4Addr::BROADCAST)
+        } else {
+            (message.chaddr, yiaddr)
+        };
+
+        let mut packet = vec![0u8; 28];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&((28 + bootp.len()) as u16).to_be_bytes());
+        packet[8] = 64;
+        packet[9] = IPPROTO_UDP;
+        packet[12..16].copy_from_slice(&self.server_ip.octets());
+        packet[16..20].copy_from_slice(&destination_ip.octets());
+        packet[20..22].copy_from_slice(&SERVER_PORT.to_be_bytes());
+        packet[22..24].copy_from_slice(&CLIENT_PORT.to_be_bytes());
+        packet[24..26].copy_from_slice(&((8 + bootp.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&bootp);
+        checksum::fill(&mut packet);
+
+        let mut frame = Vec::with_capacity(12 + message.tags.len() + packet.len());
+        frame.extend_from_slice(&destination_mac);
+        frame.extend_from_slice(&self.mac);
+        // The tags and the EtherType of the message
+        frame.extend_from_slice(message.tags);
+        frame.extend_from_slice(&packet);
+        frame
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for DhcpServer<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        DhcpServer::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        DhcpServer::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
+
+/// Returns the message of a client carried by `frame`, if it is an unfragmented IPv4 packet
+/// sent to UDP port 67 carrying a DHCP request.
+fn parse(frame: &[u8]) -> Option<Message<'_>> {
+    let tags = dot1q::parse(frame).ok()?;
+    if tags.ether_type != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let packet = &frame[tags.payload..];
+    let header_len = (*packet.first()? & 0x0f) as usize * 4;
+    let is_udp = packet[0] >> 4 == 4 && packet.get(9) == Some(&IPPROTO_UDP);
+    // Neither a later fragment nor a first one
+    let is_whole = packet.get(6..8)? == [0, 0] || packet.get(6..8)? == [0x40, 0];
+    let udp = packet.get(header_len..)?;
+    if !is_udp || !is_whole || udp.get(2..4)? != SERVER_PORT.to_be_bytes() {
+        return None;
+    }
+    let bootp = udp.get(8..)?;
+    if bootp.len() < BOOTP_LEN + MAGIC_COOKIE.len()
+        || bootp[0..3] != [1, 1, 6]
+        || bootp[BOOTP_LEN..BOOTP_LEN + 4] != MAGIC_COOKIE
+    {
+        return None;
+    }
+    let address = |at: usize| Ipv4Addr::new(bootp[at], bootp[at + 1], bootp[at + 2], bootp[at + 3]);
+    // Relayed messages are not served
+    if !address(24).is_unspecified() {
+        return None;
+    }
+
+    let mut kind = None;
+    let mut requested_ip = None;
+    let mut server_id = None;
+    let mut options = &bootp[BOOTP_LEN + 4..];
+    while let [code, rest @ ..] = options {
+        match *code {
+            OPTION_PAD => {
+                options = rest;
+                continue;
+            }
+            OPTION_END => break,
+            _ => {}
+        }
+        let [len, rest @ ..] = rest else { break };
+        let Some(value) = rest.get(..*len as usize) else {
+            break;
+        };
+        match (*code, value) {
+            (OPTION_MESSAGE_TYPE, &[value]) => kind = Some(value),
+            (OPTION_REQUESTED_IP, &[a, b, c, d]) => requested_ip = Some(Ipv4Addr::new(a, b, c, d)),
+            (OPTION_SERVER_ID, &[a, b, c, d]) => server_id = Some(Ipv4Addr::new(a, b, c, d)),
+            _ => {}
+        }
+        options = &rest[*len as usize..];
+    }
+
+    Some(Message {
+        tags: &frame[12..tags.payload],
+        kind: kind?,
+        xid: bootp[4..8].try_into().unwrap(),
+        flags: u16::from_be_bytes([bootp[10], bootp[11]]),
+        ciaddr: address(12),
+        chaddr: bootp[28..34].try_into().unwrap(),
+        requested_ip,
+        server_id,
+    })
+}
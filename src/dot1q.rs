@@ -0,0 +1,221 @@
+//! Helpers parsing, stripping and inserting the 802.1Q and 802.1ad VLAN tags of Ethernet
+//! frames, as read from and written to a Tap device.
+//!
+//! A frame carries at most two tags: a service tag (S-tag, TPID `0x88a8`, or the legacy
+//! `0x9100`) outside a customer tag (C-tag, TPID `0x8100`). In the legacy double tagging with
+//! two `0x8100` tags, the outer one is the service tag. [`insert`] puts a tag where it belongs
+//! whatever the tags already present, and [`strip`] removes the outermost tag in place.
+//!
+//! ```
+//! use tokio_tun::dot1q::{self, VlanTag};
+//!
+//! // An untagged IPv4 frame, without payload
+//! let mut frame = vec![0xff; 12];
+//! frame.extend_from_slice(&[0x08, 0x00]);
+//!
+//! dot1q::insert(&mut frame, VlanTag::customer(100))?;
+//! dot1q::insert(&mut frame, VlanTag::service(10))?;
+//! let tags = dot1q::parse(&frame)?;
+//! assert_eq!(tags.service.map(|tag| tag.vid), Some(10));
+//! assert_eq!(tags.customer.map(|tag| tag.vid), Some(100));
+//! assert_eq!(tags.ether_type, 0x0800);
+//!
+//! let (outer, frame) = dot1q::strip(&mut frame)?;
+//! assert_eq!(outer, Some(VlanTag::service(10)));
+//! assert_eq!(dot1q::parse(frame)?.service, None);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, ErrorKind};
+
+/// TPID of a customer tag (802.1Q).
+pub const TPID_CUSTOMER: u16 = 0x8100;
+
+/// TPID of a service tag (802.1ad).
+pub const TPID_SERVICE: u16 = 0x88a8;
+
+/// TPID of a service tag in the legacy double tagging which predates 802.1ad.
+pub const TPID_SERVICE_LEGACY: u16 = 0x9100;
+
+/// Offset of the first tag, or of the EtherType of an untagged frame, past the MAC addresses.
+const TAG_OFFSET: usize = 12;
+
+/// Length of a tag.
+const TAG_LEN: usize = 4;
+
+/// Largest VLAN id; `0` marks a frame tagged only for its priority.
+const MAX_VID: u16 = 4095;
+
+/// Represents a VLAN tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VlanTag {
+    /// Tag protocol identifier, which tells a service tag from a customer tag.
+    pub tpid: u16,
+    /// Priority code point, from 0 to 7.
+    pub pcp: u8,
+    /// Drop eligible indicator.
+    pub dei: bool,
+    /// VLAN id, from 0 to 4095.
+    pub vid: u16,
+}
+
+impl VlanTag {
+    /// Creates a customer tag (802.1Q) of VLAN `vid`, of priority 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vid` is larger than 4095.
+    pub fn customer(vid: u16) -> Self {
+        Self::with_tpid(TPID_CUSTOMER, vid)
+    }
+
+    /// Creates a service tag (802.1ad) of VLAN `vid`, of priority 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vid` is larger than 4095.
+    pub fn service(vid: u16) -> Self {
+        Self::with_tpid(TPID_SERVICE, vid)
+    }
+
+    fn with_tpid(tpid: u16, vid: u16) -> Self {
+        assert!(vid <= MAX_VID, "VLAN id {} is larger than 4095", vid);
+        Self {
+            tpid,
+            pcp: 0,
+            dei: false,
+            vid,
+        }
+    }
+
+    /// Sets the priority code point of the tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pcp` is larger than 7.
+    pub fn priority(mut self, pcp: u8) -> Self {
+        assert!(pcp <= 7, "priority code point {} is larger than 7", pcp);
+        self.pcp = pcp;
+        self
+    }
+
+    /// Returns `true` if the tag is a service tag, of either TPID.
+    pub fn is_service(&self) -> bool {
+        matches!(self.tpid, TPID_SERVICE | TPID_SERVICE_LEGACY)
+    }
+
+    /// Returns the tag control information: the priority, the drop eligibility and the id.
+    pub fn tci(&self) -> u16 {
+        (self.pcp as u16) << 13 | (self.dei as u16) << 12 | self.vid
+    }
+
+    /// Returns the tag from its TPID and TCI.
+    fn from_tci(tpid: u16, tci: u16) -> Self {
+        Self {
+            tpid,
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            vid: tci & 0x0fff,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; TAG_LEN] {
+        let [tpid0, tpid1] = self.tpid.to_be_bytes();
+        let [tci0, tci1] = self.tci().to_be_bytes();
+        [tpid0, tpid1, tci0, tci1]
+    }
+}
+
+/// Represents the VLAN tags of a frame, returned by [`parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VlanTags {
+    /// Service tag, the outer one of a double-tagged frame.
+    pub service: Option<VlanTag>,
+    /// Customer tag, the only one of a single-tagged 802.1Q frame.
+    pub customer: Option<VlanTag>,
+    /// EtherType of the payload, past the tags.
+    pub ether_type: u16,
+    /// Offset of the payload in the frame.
+    pub payload: usize,
+}
+
+/// Returns the VLAN tags of the Ethernet frame `frame`, of which at most two are read.
+pub fn parse(frame: &[u8]) -> io::Result<VlanTags> {
+    let mut tags = [None; 2];
+    let mut count = 0;
+    let mut at = TAG_OFFSET;
+    let ether_type = loop {
+        let Some(field) = frame.get(at..at + 2) else {
+            return Err(invalid("frame is shorter than its Ethernet header"));
+        };
+        let tpid = u16::from_be_bytes([field[0], field[1]]);
+        let is_tag = matches!(tpid, TPID_CUSTOMER | TPID_SERVICE | TPID_SERVICE_LEGACY);
+        if !is_tag || count == tags.len() {
+            break tpid;
+        }
+        let Some(tci) = frame.get(at + 2..at + 4) else {
+            return Err(invalid("frame is shorter than its VLAN tags"));
+        };
+        tags[count] = Some(VlanTag::from_tci(
+            tpid,
+            u16::from_be_bytes([tci[0], tci[1]]),
+        ));
+        count += 1;
+        at += TAG_LEN;
+    };
+    let (service, customer) = match tags {
+        [Some(tag), None] if tag.is_service() => (Some(tag), None),
+        [Some(tag), None] => (None, Some(tag)),
+        [outer, inner] => (outer, inner),
+    };
+    Ok(VlanTags {
+        service,
+        customer,
+        ether_type,
+        payload: at + 2,
+    })
+}
+
+/// Removes the outermost VLAN tag of the Ethernet frame `frame` in place, by moving its MAC
+/// addresses over the tag. Returns the tag, if the frame had one, and the frame without it,
+/// which starts 4 bytes further in `frame`.
+pub fn strip(frame: &mut [u8]) -> io::Result<(Option<VlanTag>, &mut [u8])> {
+    let tags = parse(frame)?;
+    let Some(tag) = tags.service.or(tags.customer) else {
+        return Ok((None, frame));
+    };
+    frame.copy_within(..TAG_OFFSET, TAG_LEN);
+    Ok((Some(tag), &mut frame[TAG_LEN..]))
+}
+
+/// Inserts the VLAN tag `tag` into the Ethernet frame `frame`: a service tag outside the
+/// customer tag, if any, and a customer tag inside the service tag, if any.
+///
+/// Fails with `io::ErrorKind::InvalidInput` if `tag` is invalid, or if the frame already has a
+/// tag of its kind.
+pub fn insert(frame: &mut Vec<u8>, tag: VlanTag) -> io::Result<()> {
+    let is_tag = matches!(tag.tpid, TPID_CUSTOMER | TPID_SERVICE | TPID_SERVICE_LEGACY);
+    if !is_tag || tag.pcp > 7 || tag.vid > MAX_VID {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "invalid VLAN tag"));
+    }
+    let tags = parse(frame)?;
+    let (existing, at) = if tag.is_service() {
+        (tags.service, TAG_OFFSET)
+    } else {
+        // Past the service tag, if any
+        let at = TAG_OFFSET + tags.service.map_or(0, |_| TAG_LEN);
+        (tags.customer, at)
+    };
+    if existing.is_some() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "frame already has a VLAN tag of this kind",
+        ));
+    }
+    frame.splice(at..at, tag.to_bytes());
+    Ok(())
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
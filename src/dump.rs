@@ -0,0 +1,203 @@
+use crate::classify::{IpVersion, PacketClass, classify_transport};
+use std::fmt;
+use std::net::IpAddr;
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Number of bytes per line of the hexdump.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Formats an IP packet as a one-line summary in the style of `tcpdump`, and with the alternate
+/// flag (`{:#}`) as the summary followed by a hexdump of the packet, in the style of
+/// `tcpdump -X`.
+///
+/// ```
+/// use tokio_tun::PacketDump;
+///
+/// // A UDP packet from 10.0.0.1:1234 to 10.0.0.2:53
+/// let packet = [
+///     0x45, 0, 0, 28, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0x04, 0xd2, 0, 53,
+///     0, 8, 0, 0,
+/// ];
+/// assert_eq!(
+///     PacketDump(&packet).to_string(),
+///     "IP 10.0.0.1.1234 > 10.0.0.2.53: UDP, length 28"
+/// );
+/// println!("{:#}", PacketDump(&packet));
+/// ```
+#[derive(Clone, Copy)]
+pub struct PacketDump<'a>(pub &'a [u8]);
+
+impl fmt::Display for PacketDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.0;
+        match classify_transport(packet) {
+            Some((class, start)) => summary(f, packet, &class, start)?,
+            None => write!(f, "unknown, length {}", packet.len())?,
+        }
+        if f.alternate() {
+            hexdump(f, packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PacketDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Writes the summary of the IP packet `packet` of class `class`, whose transport header is at
+/// `start`.
+fn summary(
+    f: &mut fmt::Formatter<'_>,
+    packet: &[u8],
+    class: &PacketClass,
+    start: Option<usize>,
+) -> fmt::Result {
+    let version = match class.version {
+        IpVersion::V4 => "IP",
+        IpVersion::V6 => "IP6",
+    };
+    write!(f, "{} ", version)?;
+    endpoint(f, class.source, class.ports.map(|(source, _)| source))?;
+    f.write_str(" > ")?;
+    endpoint(
+        f,
+        class.destination,
+        class.ports.map(|(_, destination)| destination),
+    )?;
+    f.write_str(": ")?;
+    let transport = start.and_then(|start| packet.get(start..));
+    match (class.protocol, transport) {
+        (protocol, None) => write!(f, "proto {} (fragment)", protocol)?,
+        (IPPROTO_TCP, Some(tcp)) if tcp.len() >= 14 => write!(f, "TCP [{}]", TcpFlags(tcp[13]))?,
+        (IPPROTO_TCP, _) => f.write_str("TCP")?,
+        (IPPROTO_UDP, _) => f.write_str("UDP")?,
+        (IPPROTO_ICMP, Some(icmp)) if icmp.len() >= 8 && class.version == IpVersion::V4 => {
+            icmp_summary(f, "ICMP", icmp, icmp_name(icmp[0]))?
+        }
+        (IPPROTO_ICMPV6, Some(icmp)) if icmp.len() >= 8 && class.version == IpVersion::V6 => {
+            icmp_summary(f, "ICMP6", icmp, icmpv6_name(icmp[0]))?
+        }
+        (protocol, _) => write!(f, "proto {}", protocol)?,
+    }
+    write!(f, ", length {}", packet.len())
+}
+
+/// Writes `addr`, followed by `port` if any, as `tcpdump` does.
+fn endpoint(f: &mut fmt::Formatter<'_>, addr: IpAddr, port: Option<u16>) -> fmt::Result {
+    match port {
+        Some(port) => write!(f, "{}.{}", addr, port),
+        None => write!(f, "{}", addr),
+    }
+}
+
+/// Writes the summary of the ICMP or ICMPv6 message `icmp`, named `name` if known.
+fn icmp_summary(
+    f: &mut fmt::Formatter<'_>,
+    protocol: &str,
+    icmp: &[u8],
+    name: Option<&str>,
+) -> fmt::Result {
+    match name {
+        Some(name) => write!(f, "{} {}", protocol, name)?,
+        None => write!(f, "{} type {} code {}", protocol, icmp[0], icmp[1])?,
+    }
+    if matches!(name, Some("echo request" | "echo reply")) {
+        let id = u16::from_be_bytes([icmp[4], icmp[5]]);
+        let seq = u16::from_be_bytes([icmp[6], icmp[7]]);
+        write!(f, ", id {}, seq {}", id, seq)?;
+    }
+    Ok(())
+}
+
+/// Returns the name of the ICMP message type `kind`, if known.
+fn icmp_name(kind: u8) -> Option<&'static str> {
+    Some(match kind {
+        0 => "echo reply",
+        3 => "unreachable",
+        5 => "redirect",
+        8 => "echo request",
+        11 => "time exceeded",
+        12 => "parameter problem",
+        _ => return None,
+    })
+}
+
+/// Returns the name of the ICMPv6 message type `kind`, if known.
+fn icmpv6_name(kind: u8) -> Option<&'static str> {
+    Some(match kind {
+        1 => "unreachable",
+        2 => "packet too big",
+        3 => "time exceeded",
+        4 => "parameter problem",
+        128 => "echo request",
+        129 => "echo reply",
+        133 => "router solicitation",
+        134 => "router advertisement",
+        135 => "neighbor solicitation",
+        136 => "neighbor advertisement",
+        137 => "redirect",
+        _ => return None,
+    })
+}
+
+/// Represents the flags of a TCP segment, written as `tcpdump` does (e.g. `S.` for SYN-ACK).
+struct TcpFlags(u8);
+
+impl fmt::Display for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const FLAGS: [(u8, char); 7] = [
+            (0x01, 'F'),
+            (0x02, 'S'),
+            (0x04, 'R'),
+            (0x08, 'P'),
+            (0x20, 'U'),
+            (0x40, 'E'),
+            (0x80, 'W'),
+        ];
+        for (bit, flag) in FLAGS {
+            if self.0 & bit != 0 {
+                write!(f, "{}", flag)?;
+            }
+        }
+        if self.0 & 0x10 != 0 {
+            f.write_str(".")?;
+        }
+        if self.0 == 0 {
+            f.write_str("none")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the hexdump of `packet`, a line of offset, bytes and characters per 16 bytes.
+fn hexdump(f: &mut fmt::Formatter<'_>, packet: &[u8]) -> fmt::Result {
+    for (line, bytes) in packet.chunks(HEXDUMP_WIDTH).enumerate() {
+        write!(f, "\n\t0x{:04x}:  ", line * HEXDUMP_WIDTH)?;
+        for i in 0..HEXDUMP_WIDTH {
+            match bytes.get(i) {
+                Some(byte) => write!(f, "{:02x}", byte)?,
+                None => f.write_str("  ")?,
+            }
+            if i % 2 == 1 {
+                f.write_str(" ")?;
+            }
+        }
+        f.write_str(" ")?;
+        for &byte in bytes {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            write!(f, "{}", c)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use crate::AsyncTunDevice;
+use crate::Result;
+use crate::TunConfig;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::sync::Mutex;
+
+/// Seed of the faults when none is set, so that runs are reproducible by default.
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Represents the probabilities of the faults, between `0.0` (never) and `1.0` (always).
+#[derive(Debug, Clone, Copy, Default)]
+struct Faults {
+    would_block: f64,
+    short_write: f64,
+    eio: f64,
+    drop: f64,
+    duplicate: f64,
+    reorder: f64,
+}
+
+/// Represents a xorshift64* pseudo-random generator.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// Creates a generator from `seed`, or from the default seed if `seed` is zero.
+    pub(crate) fn new(seed: u64) -> Self {
+        // Zero is the only state which xorshift never leaves
+        Self(if seed == 0 { DEFAULT_SEED } else { seed })
+    }
+
+    /// Returns the next random number.
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a random number between `0.0` (included) and `1.0` (excluded).
+    pub(crate) fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns `true` with probability `p`.
+    pub(crate) fn happens(&mut self, p: f64) -> bool {
+        p > 0.0 && self.uniform() < p
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self(DEFAULT_SEED)
+    }
+}
+
+/// Represents the state of the faults: the random generator, and the packets delayed by
+/// duplication or reordering.
+struct State {
+    rng: Rng,
+    /// Packets to return from `recv` before receiving from the device.
+    pending: VecDeque<Vec<u8>>,
+    /// Packet received from the device which is returned after the next one.
+    held_recv: Option<Vec<u8>>,
+    /// Packet sent to the device after the next one.
+    held_send: Option<Vec<u8>>,
+}
+
+/// Represents a device which injects faults into the I/O of the device it wraps, with
+/// configurable probabilities, to test how an application copes with what real devices do
+/// under load: spurious `WouldBlock`, short writes, `EIO`, and packets which are dropped,
+/// duplicated or reordered.
+///
+/// The faults are drawn from a pseudo-random generator, whose [`seed`](#method.seed) makes a
+/// run reproducible. Drops, duplicates and reorders apply in both directions: a packet sent to
+/// the device may not reach it, reach it twice, or reach it after the next one.
+///
+/// ```no_run
+/// use tokio_tun::{FaultyTun, TestTun};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let (tun, handle) = TestTun::new("tun0");
+/// let tun = FaultyTun::new(tun)
+///     .seed(42)
+///     .drop_packets(0.1)
+///     .reorder(0.05)
+///     .short_write(0.01);
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FaultyTun<T> {
+    inner: T,
+    faults: Faults,
+    state: Mutex<State>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for FaultyTun<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultyTun")
+            .field("inner", &self.inner)
+            .field("faults", &self.faults)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice> FaultyTun<T> {
+    /// Wraps `inner`, without faults.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            faults: Faults::default(),
+            state: Mutex::new(State {
+                rng: Rng::default(),
+                pending: VecDeque::new(),
+                held_recv: None,
+                held_send: None,
+            }),
+        }
+    }
+
+    /// Sets the seed of the faults, so that different runs inject different faults.
+    pub fn seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap().rng = Rng::new(seed);
+        self
+    }
+
+    /// Sets the probability that receiving or sending fails with `WouldBlock`.
+    pub fn would_block(mut self, p: f64) -> Self {
+        self.faults.would_block = p;
+        self
+    }
+
+    /// Sets the probability that sending only sends a prefix of the packet, whose length is
+    /// returned.
+    pub fn short_write(mut self, p: f64) -> Self {
+        self.faults.short_write = p;
+        self
+    }
+
+    /// Sets the probability that receiving or sending fails with `EIO`.
+    pub fn eio(mut self, p: f64) -> Self {
+        self.faults.eio = p;
+        self
+    }
+
+    /// Sets the probability that a packet is dropped: a received packet is skipped, and a sent
+    /// packet is reported as sent without reaching the device.
+    pub fn drop_packets(mut self, p: f64) -> Self {
+        self.faults.drop = p;
+        self
+    }
+
+    /// Sets the probability that a packet is received or sent twice.
+    pub fn duplicate(mut self, p: f64) -> Self {
+        self.faults.duplicate = p;
+        self
+    }
+
+    /// Sets the probability that a packet is received or sent after the next one.
+    pub fn reorder(mut self, p: f64) -> Self {
+        self.faults.reorder = p;
+        self
+    }
+
+    /// Receives a packet from the device, subject to the faults. A packet larger than `buf` is
+    /// truncated.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                self.fail(&mut state)?;
+                if let Some(packet) = state.pending.pop_front() {
+                    let n = packet.len().min(buf.len());
+                    buf[..n].copy_from_slice(&packet[..n]);
+                    return Ok(n);
+                }
+            }
+            let n = self.inner.recv(buf).await?;
+            let mut state = self.state.lock().unwrap();
+            if state.rng.happens(self.faults.drop) {
+                continue;
+            }
+            if state.rng.happens(self.faults.reorder) && state.held_recv.is_none() {
+                state.held_recv = Some(buf[..n].to_vec());
+                continue;
+            }
+            if state.rng.happens(self.faults.duplicate) {
+                state.pending.push_back(buf[..n].to_vec());
+            }
+            if let Some(held) = state.held_recv.take() {
+                state.pending.push_back(held);
+            }
+            return Ok(n);
+        }
+    }
+
+    /// Sends a packet to the device, subject to the faults. Returns the number of bytes which
+    /// are reported as sent.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let (len, duplicate) = {
+            let mut state = self.state.lock().unwrap();
+            self.fail(&mut state)?;
+            if state.rng.happens(self.faults.drop) {
+                return Ok(buf.len());
+            }
+            let len = if buf.len() > 1 && state.rng.happens(self.faults.short_write) {
+                1 + (state.rng.next() % (buf.len() as u64 - 1)) as usize
+            } else {
+                buf.len()
+            };
+            if state.rng.happens(self.faults.reorder) && state.held_send.is_none() {
+                state.held_send = Some(buf[..len].to_vec());
+                return Ok(len);
+            }
+            (len, state.rng.happens(self.faults.duplicate))
+        };
+        let n = self.inner.send(&buf[..len]).await?;
+        if duplicate {
+            self.inner.send(&buf[..n]).await?;
+        }
+        let held = self.state.lock().unwrap().held_send.take();
+        if let Some(held) = held {
+            self.inner.send(&held).await?;
+        }
+        Ok(n)
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device, dropping the packets delayed by the faults.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Fails with `WouldBlock` or `EIO`, according to their probabilities.
+    fn fail(&self, state: &mut State) -> io::Result<()> {
+        if state.rng.happens(self.faults.would_block) {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        if state.rng.happens(self.faults.eio) {
+            return Err(io::Error::from_raw_os_error(libc::EIO));
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for FaultyTun<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        FaultyTun::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        FaultyTun::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+}
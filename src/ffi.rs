@@ -0,0 +1,350 @@
+//! C interface (feature `ffi`).
+//!
+//! The functions drive a [`SyncTun`](../struct.SyncTun.html) through opaque handles, so that C,
+//! C++ or Go programs can create and use devices without reimplementing the quirks of each
+//! platform. Their declarations are in `include/tokio_tun.h`, and the shared library is built
+//! with:
+//!
+//! ```sh
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! Functions returning an `int` or an `ssize_t` return `-1` on failure, and a null pointer for
+//! those returning a pointer, with `errno` set to the cause. A device reports its removal with
+//! `EBADFD` on Linux and `ENXIO` on macOS.
+
+use crate::Error;
+use crate::SyncTun;
+use crate::TunBuilder;
+use nix::errno::Errno;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+
+/// Represents the configuration of a device to create, see
+/// [`tokio_tun_builder_new`](fn.tokio_tun_builder_new.html).
+pub struct TokioTunBuilder(TunBuilder);
+
+/// Represents a device, see [`tokio_tun_create`](fn.tokio_tun_create.html).
+pub struct TokioTun {
+    tun: SyncTun,
+    name: CString,
+}
+
+/// Sets `errno` to the cause of `err` and returns `-1`.
+fn fail(err: Error) -> c_int {
+    #[cfg(target_os = "linux")]
+    let removed = libc::EBADFD;
+    #[cfg(not(target_os = "linux"))]
+    let removed = libc::ENXIO;
+    let errno = match err {
+        Error::NixError(err) => err as i32,
+        Error::IoError(err) => err.raw_os_error().unwrap_or(match err.kind() {
+            io::ErrorKind::InvalidInput => libc::EINVAL,
+            _ => libc::EIO,
+        }),
+        Error::DeviceRemoved => removed,
+        Error::PermissionDenied | Error::MissingCapability { .. } => libc::EPERM,
+        Error::DeviceNotFound => libc::ENODEV,
+        Error::NameInUse(_) => libc::EBUSY,
+        Error::InvalidParam { .. } => libc::EINVAL,
+        Error::PacketTooLarge { .. } => libc::EMSGSIZE,
+        Error::Unsupported { .. } => libc::EOPNOTSUPP,
+    };
+    Errno::set_raw(errno);
+    -1
+}
+
+fn invalid() -> Error {
+    io::Error::from(io::ErrorKind::InvalidInput).into()
+}
+
+/// Returns the string `s`, which must be valid UTF-8.
+///
+/// # Safety
+///
+/// `s` must be a null-terminated string.
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(invalid());
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().map_err(|_| invalid())
+}
+
+/// Parses the IPv4 address `s` in dotted notation.
+///
+/// # Safety
+///
+/// `s` must be a null-terminated string.
+unsafe fn to_ipv4(s: *const c_char) -> Result<Ipv4Addr, Error> {
+    unsafe { to_str(s) }?.parse().map_err(|_| invalid())
+}
+
+/// Applies `f` to the configuration of `builder`.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+unsafe fn update(builder: *mut TokioTunBuilder, f: impl FnOnce(TunBuilder) -> TunBuilder) {
+    let builder = unsafe { &mut *builder };
+    builder.0 = f(mem::take(&mut builder.0));
+}
+
+/// Returns a new configuration of a Tun device, to be passed to `tokio_tun_create` or freed by
+/// `tokio_tun_builder_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tokio_tun_builder_new() -> *mut TokioTunBuilder {
+    Box::into_raw(Box::new(TokioTunBuilder(TunBuilder::new())))
+}
+
+/// Frees a configuration which was not passed to `tokio_tun_create`.
+///
+/// # Safety
+///
+/// `builder` must be null or a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_free(builder: *mut TokioTunBuilder) {
+    if !builder.is_null() {
+        drop(unsafe { Box::from_raw(builder) });
+    }
+}
+
+/// Sets the name of the device. Fails with `EINVAL` if it is not valid UTF-8.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`, and `name` a
+/// null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_name(
+    builder: *mut TokioTunBuilder,
+    name: *const c_char,
+) -> c_int {
+    match unsafe { to_str(name) } {
+        Ok(name) => {
+            unsafe { update(builder, |b| b.name(name)) };
+            0
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// Creates a Tap device instead of a Tun device.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_tap(builder: *mut TokioTunBuilder) {
+    unsafe { update(builder, TunBuilder::tap) };
+}
+
+/// Sets the MTU of the device.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_mtu(builder: *mut TokioTunBuilder, mtu: c_int) {
+    unsafe { update(builder, |b| b.mtu(mtu)) };
+}
+
+/// Sets the IPv4 address of the device, in dotted notation. Fails with `EINVAL` if it cannot be
+/// parsed.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`, and `address` a
+/// null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_address(
+    builder: *mut TokioTunBuilder,
+    address: *const c_char,
+) -> c_int {
+    match unsafe { to_ipv4(address) } {
+        Ok(address) => {
+            unsafe { update(builder, |b| b.address(address)) };
+            0
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// Sets the IPv4 netmask of the device, in dotted notation. Fails with `EINVAL` if it cannot be
+/// parsed.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`, and `netmask` a
+/// null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_netmask(
+    builder: *mut TokioTunBuilder,
+    netmask: *const c_char,
+) -> c_int {
+    match unsafe { to_ipv4(netmask) } {
+        Ok(netmask) => {
+            unsafe { update(builder, |b| b.netmask(netmask)) };
+            0
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// Sets the IPv4 destination address of the device, in dotted notation. Fails with `EINVAL` if
+/// it cannot be parsed.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`, and `destination` a
+/// null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_destination(
+    builder: *mut TokioTunBuilder,
+    destination: *const c_char,
+) -> c_int {
+    match unsafe { to_ipv4(destination) } {
+        Ok(destination) => {
+            unsafe { update(builder, |b| b.destination(destination)) };
+            0
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// Makes the device persistent, so that it outlives the process.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_persist(builder: *mut TokioTunBuilder) {
+    unsafe { update(builder, TunBuilder::persist) };
+}
+
+/// Sets the device up once it is created.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_builder_set_up(builder: *mut TokioTunBuilder) {
+    unsafe { update(builder, TunBuilder::up) };
+}
+
+/// Creates a device from the configuration `builder`, which is freed even if it fails. The
+/// device performs blocking I/O, and must be closed by `tokio_tun_close`.
+///
+/// # Safety
+///
+/// `builder` must be a configuration returned by `tokio_tun_builder_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_create(builder: *mut TokioTunBuilder) -> *mut TokioTun {
+    let builder = unsafe { Box::from_raw(builder) }.0;
+    let tun = match builder.build_sync() {
+        Ok(mut tuns) => tuns.remove(0),
+        Err(err) => {
+            fail(err);
+            return ptr::null_mut();
+        }
+    };
+    // The names of devices are made of ASCII characters
+    let name = CString::new(tun.name()).unwrap_or_default();
+    Box::into_raw(Box::new(TokioTun { tun, name }))
+}
+
+/// Returns the name of the device, which lives as long as the device.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_name(tun: *const TokioTun) -> *const c_char {
+    unsafe { &*tun }.name.as_ptr()
+}
+
+/// Returns the file descriptor of the device, to wait for it with `poll` or `epoll` once it is
+/// non-blocking. It is owned by the device.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_fd(tun: *const TokioTun) -> c_int {
+    unsafe { &*tun }.tun.as_raw_fd()
+}
+
+/// Returns the MTU of the device.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_mtu(tun: *const TokioTun) -> c_int {
+    unsafe { &*tun }.tun.mtu().unwrap_or_else(fail)
+}
+
+/// Switches the device between blocking (the default) and non-blocking mode, in which I/O fails
+/// with `EAGAIN` instead of waiting.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_set_nonblocking(
+    tun: *const TokioTun,
+    nonblocking: c_int,
+) -> c_int {
+    match unsafe { &*tun }.tun.set_nonblocking(nonblocking != 0) {
+        Ok(()) => 0,
+        Err(err) => fail(err.into()),
+    }
+}
+
+/// Receives a packet into `buf`, of length `len`, and returns its length.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`, and `buf` valid for writes of `len`
+/// bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_recv(tun: *const TokioTun, buf: *mut u8, len: usize) -> isize {
+    let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+    match unsafe { &*tun }.tun.recv(buf) {
+        Ok(n) => n as isize,
+        Err(err) => fail(err.into()) as isize,
+    }
+}
+
+/// Sends the packet `buf`, of length `len`, and returns the number of bytes sent.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`, and `buf` valid for reads of `len`
+/// bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_send(tun: *const TokioTun, buf: *const u8, len: usize) -> isize {
+    let buf = unsafe { slice::from_raw_parts(buf, len) };
+    match unsafe { &*tun }.tun.send(buf) {
+        Ok(n) => n as isize,
+        Err(err) => fail(err.into()) as isize,
+    }
+}
+
+/// Closes the device and frees it, even if it fails. Unless it is persistent, the device is
+/// removed.
+///
+/// # Safety
+///
+/// `tun` must be a device returned by `tokio_tun_create`, which is not used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tokio_tun_close(tun: *mut TokioTun) -> c_int {
+    match unsafe { Box::from_raw(tun) }.tun.close() {
+        Ok(()) => 0,
+        Err(err) => fail(err),
+    }
+}
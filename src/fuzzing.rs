@@ -0,0 +1,10 @@
+//! Entry points of the fuzz targets in `fuzz/` into the private parsers of the crate (feature
+//! `fuzzing`). They are not part of the public API.
+
+use std::io;
+
+/// Copies the packet of `frame`, as read from a utun device on macOS, into `buf` without its
+/// header.
+pub fn strip_utun_header(frame: &[u8], buf: &mut [u8]) -> io::Result<usize> {
+    crate::utun::strip_header(frame, buf)
+}
@@ -0,0 +1,534 @@
+//! Helpers for devices created with [`TunBuilder::offload`](../struct.TunBuilder.html#method.offload).
+//!
+//! With offloads enabled, every packet is preceded by a [`VirtioNetHdr`] and the kernel may
+//! hand up coalesced TCP (or UDP) super-packets of up to 64KB, which have to be split into
+//! MTU-sized segments before being processed like regular packets.
+
+use crate::checksum;
+use std::io::{self, ErrorKind};
+
+/// Length of [`VirtioNetHdr`] on the wire.
+pub const VIRTIO_NET_HDR_LEN: usize = 10;
+
+/// The checksum of the packet is partial and has to be completed.
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+
+/// The packet is not a GSO packet.
+pub const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+/// The packet is a TCP over IPv4 GSO packet.
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+/// The packet is a UDP fragmentation offload packet.
+pub const VIRTIO_NET_HDR_GSO_UDP: u8 = 3;
+/// The packet is a TCP over IPv6 GSO packet.
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+/// The packet is a UDP segmentation offload packet.
+pub const VIRTIO_NET_HDR_GSO_UDP_L4: u8 = 5;
+/// The TCP packet has the ECN bit set.
+pub const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// Represents the `virtio_net_hdr` structure which precedes packets of devices with offloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VirtioNetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHdr {
+    /// Parses the header from the beginning of `buf`.
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        if buf.len() < VIRTIO_NET_HDR_LEN {
+            return Err(invalid("packet is shorter than the virtio-net header"));
+        }
+        let u16_at = |i: usize| u16::from_ne_bytes([buf[i], buf[i + 1]]);
+        Ok(Self {
+            flags: buf[0],
+            gso_type: buf[1],
+            hdr_len: u16_at(2),
+            gso_size: u16_at(4),
+            csum_start: u16_at(6),
+            csum_offset: u16_at(8),
+        })
+    }
+
+    /// Encodes the header into the beginning of `buf`, which must be at least
+    /// [`VIRTIO_NET_HDR_LEN`] bytes long.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.flags;
+        buf[1] = self.gso_type;
+        buf[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        buf[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        buf[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        buf[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+    }
+}
+
+/// Splits a packet received from a device with offloads into individual packets, which are
+/// appended to `out`. Returns the number of appended packets.
+///
+/// `packet` starts with a [`VirtioNetHdr`], directly followed by the IP header: it is read from a
+/// TUN device without packet information, not from a TAP device. Partial checksums are completed and the IP and
+/// transport headers of every segment are rewritten (lengths, IPv4 identification, TCP sequence
+/// number and flags, checksums).
+///
+/// A UDP segmentation offload packet is split into datagrams of `gso_size` bytes, the last
+/// one shorter:
+///
+/// ```
+/// use tokio_tun::checksum;
+/// use tokio_tun::gso::{self, VirtioNetHdr};
+///
+/// let payload = (0..2500).map(|i| i as u8).collect::<Vec<_>>();
+/// let mut packet = vec![0u8; gso::VIRTIO_NET_HDR_LEN];
+/// VirtioNetHdr {
+///     flags: gso::VIRTIO_NET_HDR_F_NEEDS_CSUM,
+///     gso_type: gso::VIRTIO_NET_HDR_GSO_UDP_L4,
+///     hdr_len: 28,
+///     gso_size: 1000,
+///     csum_start: 20,
+///     csum_offset: 6,
+/// }
+/// .encode(&mut packet);
+/// packet.extend_from_slice(&[0x45, 0, 0, 0, 0, 100, 0x40, 0, 64, 17, 0, 0]);
+/// packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x13, 0x88, 0, 53, 0, 0, 0, 0]);
+/// packet.extend_from_slice(&payload);
+///
+/// let mut segments = Vec::new();
+/// assert_eq!(gso::split(&packet, &mut segments).unwrap(), 3);
+/// for (i, (segment, chunk)) in segments.iter().zip(payload.chunks(1000)).enumerate() {
+///     // Total length, identification, UDP length, then the payload
+///     assert_eq!(segment[2..4], ((28 + chunk.len()) as u16).to_be_bytes());
+///     assert_eq!(segment[4..6], (100 + i as u16).to_be_bytes());
+///     assert_eq!(segment[24..26], ((8 + chunk.len()) as u16).to_be_bytes());
+///     assert_eq!(segment[28..], *chunk);
+///     assert!(checksum::verify(segment));
+/// }
+///
+/// // Datagrams are not coalesced again, but passed through with an empty header
+/// let segments = segments.iter().map(Vec::as_slice).collect::<Vec<_>>();
+/// let mut coalesced = Vec::new();
+/// assert_eq!(gso::coalesce(&segments, &mut coalesced), 3);
+/// assert_eq!(coalesced[2][..gso::VIRTIO_NET_HDR_LEN], [0; gso::VIRTIO_NET_HDR_LEN]);
+/// assert_eq!(coalesced[2][gso::VIRTIO_NET_HDR_LEN..], *segments[2]);
+/// ```
+///
+/// A header which does not describe the packet fails with `io::ErrorKind::InvalidData`, and an
+/// unknown GSO type with `io::ErrorKind::Unsupported`:
+///
+/// ```
+/// use std::io::ErrorKind;
+/// use tokio_tun::gso::{self, VirtioNetHdr};
+///
+/// // A TCP over IPv4 packet with 100 bytes of payload
+/// let tcp = |hdr: VirtioNetHdr, version: u8, data_offset: u8| {
+///     let mut packet = vec![0u8; gso::VIRTIO_NET_HDR_LEN];
+///     hdr.encode(&mut packet);
+///     packet.extend_from_slice(&[version << 4 | 5, 0, 0, 140, 0, 0, 0x40, 0, 64, 6, 0, 0]);
+///     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x1f, 0x90, 0xc3, 0x50]);
+///     packet.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, data_offset << 4, 0x10, 0xff, 0xff]);
+///     packet.extend_from_slice(&[0; 4]);
+///     packet.extend_from_slice(&[1; 100]);
+///     packet
+/// };
+/// let valid = VirtioNetHdr {
+///     flags: gso::VIRTIO_NET_HDR_F_NEEDS_CSUM,
+///     gso_type: gso::VIRTIO_NET_HDR_GSO_TCPV4,
+///     hdr_len: 40,
+///     gso_size: 50,
+///     csum_start: 20,
+///     csum_offset: 16,
+/// };
+/// let split = |packet: &[u8]| gso::split(packet, &mut Vec::new()).map_err(|err| err.kind());
+///
+/// assert_eq!(split(&tcp(valid, 4, 5)), Ok(2));
+/// assert_eq!(split(&[0; 4]), Err(ErrorKind::InvalidData));
+/// // Not an IP packet
+/// assert_eq!(split(&tcp(valid, 0, 5)), Err(ErrorKind::InvalidData));
+/// // TCP header shorter than 20 bytes
+/// assert_eq!(split(&tcp(valid, 4, 4)), Err(ErrorKind::InvalidData));
+/// // Transport header within the IP header, or beyond the packet
+/// let hdr = VirtioNetHdr { csum_start: 10, ..valid };
+/// assert_eq!(split(&tcp(hdr, 4, 5)), Err(ErrorKind::InvalidData));
+/// let hdr = VirtioNetHdr { csum_start: 200, ..valid };
+/// assert_eq!(split(&tcp(hdr, 4, 5)), Err(ErrorKind::InvalidData));
+/// // No segment size
+/// let hdr = VirtioNetHdr { gso_size: 0, ..valid };
+/// assert_eq!(split(&tcp(hdr, 4, 5)), Err(ErrorKind::InvalidData));
+/// // Partial checksum beyond the packet
+/// let hdr = VirtioNetHdr { gso_type: gso::VIRTIO_NET_HDR_GSO_NONE, csum_offset: 200, ..valid };
+/// assert_eq!(split(&tcp(hdr, 4, 5)), Err(ErrorKind::InvalidData));
+/// // UDP fragmentation offload, which is deprecated
+/// let hdr = VirtioNetHdr { gso_type: gso::VIRTIO_NET_HDR_GSO_UDP, ..valid };
+/// assert_eq!(split(&tcp(hdr, 4, 5)), Err(ErrorKind::Unsupported));
+/// ```
+pub fn split(packet: &[u8], out: &mut Vec<Vec<u8>>) -> io::Result<usize> {
+    let hdr = VirtioNetHdr::parse(packet)?;
+    let packet = &packet[VIRTIO_NET_HDR_LEN..];
+    match hdr.gso_type & !VIRTIO_NET_HDR_GSO_ECN {
+        VIRTIO_NET_HDR_GSO_NONE => {
+            let mut packet = packet.to_vec();
+            if hdr.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+                complete_checksum(&mut packet, &hdr)?;
+            }
+            out.push(packet);
+            Ok(1)
+        }
+        VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6 => {
+            segment(packet, &hdr, IPPROTO_TCP, out)
+        }
+        VIRTIO_NET_HDR_GSO_UDP_L4 => segment(packet, &hdr, IPPROTO_UDP, out),
+        _ => Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "unsupported virtio-net GSO type",
+        )),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn complete_checksum(packet: &mut [u8], hdr: &VirtioNetHdr) -> io::Result<()> {
+    let start = hdr.csum_start as usize;
+    let offset = start + hdr.csum_offset as usize;
+    if offset + 2 > packet.len() {
+        return Err(invalid("checksum offset is out of the packet"));
+    }
+    // The checksum field already holds the sum of the pseudo header
+    let csum = checksum::fold(checksum::sum(&packet[start..], 0));
+    packet[offset..offset + 2].copy_from_slice(&csum.to_be_bytes());
+    Ok(())
+}
+
+fn segment(
+    packet: &[u8],
+    hdr: &VirtioNetHdr,
+    protocol: u8,
+    out: &mut Vec<Vec<u8>>,
+) -> io::Result<usize> {
+    let version = packet.first().map(|b| b >> 4);
+    let ip_len = match version {
+        Some(4) if packet.len() >= 20 && packet[0] & 0x0f >= 5 => (packet[0] & 0x0f) as usize * 4,
+        Some(6) if packet.len() >= 40 => 40,
+        _ => return Err(invalid("GSO packet is not a valid IP packet")),
+    };
+    let l4 = hdr.csum_start as usize;
+    let min_l4_len = if protocol == IPPROTO_TCP { 20 } else { 8 };
+    if l4 < ip_len || l4 + min_l4_len > packet.len() {
+        return Err(invalid("transport header is out of the packet"));
+    }
+    let l4_len = match protocol {
+        IPPROTO_TCP => (packet[l4 + 12] >> 4) as usize * 4,
+        _ => 8,
+    };
+    let hdr_len = l4 + l4_len;
+    let mss = hdr.gso_size as usize;
+    if l4_len < min_l4_len || hdr_len > packet.len() || mss == 0 {
+        return Err(invalid("invalid GSO header or segment size"));
+    }
+
+    let payload = &packet[hdr_len..];
+    if payload.is_empty() {
+        let mut packet = packet.to_vec();
+        complete_checksum(&mut packet, hdr)?;
+        out.push(packet);
+        return Ok(1);
+    }
+    let id = u16::from_be_bytes([packet[4], packet[5]]);
+    let seq = u32::from_be_bytes([
+        packet[l4 + 4],
+        packet[l4 + 5],
+        packet[l4 + 6],
+        packet[l4 + 7],
+    ]);
+    let count = payload.len().div_ceil(mss);
+    for (i, chunk) in payload.chunks(mss).enumerate() {
+        let mut seg = Vec::with_capacity(hdr_len + chunk.len());
+        seg.extend_from_slice(&packet[..hdr_len]);
+        seg.extend_from_slice(chunk);
+        let total = seg.len();
+
+        if version == Some(4) {
+            seg[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+            seg[4..6].copy_from_slice(&id.wrapping_add(i as u16).to_be_bytes());
+            seg[10..12].copy_from_slice(&[0, 0]);
+            let csum = checksum::fold(checksum::sum(&seg[..ip_len], 0));
+            seg[10..12].copy_from_slice(&csum.to_be_bytes());
+        } else {
+            seg[4..6].copy_from_slice(&((total - 40) as u16).to_be_bytes());
+        }
+
+        let csum_offset = if protocol == IPPROTO_TCP {
+            let seq = seq.wrapping_add((i * mss) as u32);
+            seg[l4 + 4..l4 + 8].copy_from_slice(&seq.to_be_bytes());
+            if i + 1 < count {
+                // Clear FIN and PSH on all but the last segment
+                seg[l4 + 13] &= !0x09;
+            }
+            if i > 0 {
+                // Clear CWR on all but the first segment
+                seg[l4 + 13] &= !0x80;
+            }
+            l4 + 16
+        } else {
+            seg[l4 + 4..l4 + 6].copy_from_slice(&((total - l4) as u16).to_be_bytes());
+            l4 + 6
+        };
+        seg[csum_offset..csum_offset + 2].copy_from_slice(&[0, 0]);
+        let pseudo = checksum::pseudo_header(&seg, protocol, total - l4);
+        let mut csum = checksum::fold(checksum::sum(&seg[l4..], pseudo));
+        if protocol == IPPROTO_UDP && csum == 0 {
+            csum = 0xffff;
+        }
+        seg[csum_offset..csum_offset + 2].copy_from_slice(&csum.to_be_bytes());
+        out.push(seg);
+    }
+    Ok(count)
+}
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_PSH: u8 = 0x08;
+const TCP_URG: u8 = 0x20;
+const TCP_CWR: u8 = 0x80;
+
+/// Represents the headers of a TCP segment which may be coalesced with its neighbours.
+struct TcpSegment<'a> {
+    packet: &'a [u8],
+    ip_len: usize,
+    hdr_len: usize,
+    seq: u32,
+    flags: u8,
+}
+
+impl<'a> TcpSegment<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        let ip_len = match packet.first()? >> 4 {
+            4 if packet.len() >= 20 => {
+                let fragmented = u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0;
+                let total = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+                if packet[9] != IPPROTO_TCP || fragmented || total != packet.len() {
+                    return None;
+                }
+                (packet[0] & 0x0f) as usize * 4
+            }
+            6 if packet.len() >= 40 => {
+                let payload = u16::from_be_bytes([packet[4], packet[5]]) as usize;
+                if packet[6] != IPPROTO_TCP || payload + 40 != packet.len() {
+                    return None;
+                }
+                40
+            }
+            _ => return None,
+        };
+        if ip_len < 20 || ip_len + 20 > packet.len() {
+            return None;
+        }
+        let hdr_len = ip_len + (packet[ip_len + 12] >> 4) as usize * 4;
+        if hdr_len < ip_len + 20 || hdr_len > packet.len() {
+            return None;
+        }
+        let seq = &packet[ip_len + 4..ip_len + 8];
+        Some(Self {
+            packet,
+            ip_len,
+            hdr_len,
+            seq: u32::from_be_bytes([seq[0], seq[1], seq[2], seq[3]]),
+            flags: packet[ip_len + 13],
+        })
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        &self.packet[self.hdr_len..]
+    }
+
+    fn is_v4(&self) -> bool {
+        self.packet[0] >> 4 == 4
+    }
+
+    /// Returns whether `other` belongs to the same flow and carries the same header fields,
+    /// apart from the ones which differ between segments.
+    fn same_flow(&self, other: &TcpSegment<'_>) -> bool {
+        let (a, b) = (self.packet, other.packet);
+        let (l4, hdr_len) = (self.ip_len, self.hdr_len);
+        let same_ip = if self.is_v4() {
+            // TOS, flags (DF), TTL, addresses and options, but not the length, identification
+            // and checksum
+            a[1] == b[1] && a[6] == b[6] && a[8] == b[8] && a[12..l4] == b[12..l4]
+        } else {
+            // Traffic class, flow label, hop limit and addresses, but not the payload length
+            a[0..4] == b[0..4] && a[7..40] == b[7..40]
+        };
+        self.ip_len == other.ip_len
+            && self.hdr_len == other.hdr_len
+            && a[0] == b[0]
+            && same_ip
+            // Ports
+            && a[l4..l4 + 4] == b[l4..l4 + 4]
+            // Acknowledgment number
+            && a[l4 + 8..l4 + 12] == b[l4 + 8..l4 + 12]
+            // Options
+            && a[l4 + 20..hdr_len] == b[l4 + 20..hdr_len]
+    }
+}
+
+/// Merges consecutive TCP segments of the same flow into coalesced packets, which are appended
+/// to `out` with a [`VirtioNetHdr`] in front. Returns the number of appended packets.
+///
+/// The result is meant to be written to a device with offloads enabled, which segments the
+/// coalesced packets again in the kernel, so that many packets are sent with a single write.
+/// Packets which cannot be coalesced are passed through with an empty header. `packets` are IP
+/// packets, for a TUN device without packet information.
+///
+/// Splitting a coalesced packet again yields the original segments, headers and checksums
+/// included:
+///
+/// ```
+/// use tokio_tun::checksum;
+/// use tokio_tun::gso::{self, VirtioNetHdr};
+///
+/// const PSH: u8 = 0x08;
+///
+/// // An acknowledging TCP segment of `payload`, with the IPv4 identification `id`
+/// fn segment(v6: bool, id: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+///     let mut packet = if v6 {
+///         let mut ip = vec![0x60, 0, 0, 0, 0, 0, 6, 64];
+///         ip[4..6].copy_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+///         ip.extend_from_slice(&[0xfd; 16]);
+///         ip.extend_from_slice(&[0xfe; 16]);
+///         ip
+///     } else {
+///         let mut ip = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, 6, 0, 0];
+///         ip.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+///         ip[2..4].copy_from_slice(&((40 + payload.len()) as u16).to_be_bytes());
+///         ip[4..6].copy_from_slice(&id.to_be_bytes());
+///         ip
+///     };
+///     packet.extend_from_slice(&[0x1f, 0x90, 0xc3, 0x50]);
+///     packet.extend_from_slice(&seq.to_be_bytes());
+///     packet.extend_from_slice(&[0, 0, 0, 1, 0x50, 0x10 | flags, 0xff, 0xff, 0, 0, 0, 0]);
+///     packet.extend_from_slice(payload);
+///     checksum::fill(&mut packet);
+///     packet
+/// }
+///
+/// for v6 in [false, true] {
+///     let segments = [
+///         segment(v6, 7, u32::MAX - 99, 0, &[1; 100]),
+///         segment(v6, 8, 0, 0, &[2; 100]),
+///         segment(v6, 9, 100, PSH, &[3; 60]),
+///     ];
+///     let packets = segments.iter().map(Vec::as_slice).collect::<Vec<_>>();
+///     let mut coalesced = Vec::new();
+///     assert_eq!(gso::coalesce(&packets, &mut coalesced), 1);
+///     let hdr = VirtioNetHdr::parse(&coalesced[0]).unwrap();
+///     assert_eq!((hdr.gso_size, hdr.hdr_len), (100, if v6 { 60 } else { 40 }));
+///
+///     let mut split = Vec::new();
+///     assert_eq!(gso::split(&coalesced[0], &mut split).unwrap(), 3);
+///     assert_eq!(split, segments);
+/// }
+///
+/// // A gap in the sequence numbers ends the coalesced packet
+/// let segments = [segment(false, 1, 0, 0, &[1; 100]), segment(false, 2, 200, 0, &[2; 100])];
+/// let packets = segments.iter().map(Vec::as_slice).collect::<Vec<_>>();
+/// assert_eq!(gso::coalesce(&packets, &mut Vec::new()), 2);
+/// ```
+pub fn coalesce(packets: &[&[u8]], out: &mut Vec<Vec<u8>>) -> usize {
+    let start = out.len();
+    let mut i = 0;
+    while i < packets.len() {
+        let Some(first) = TcpSegment::parse(packets[i]) else {
+            out.push(with_empty_header(packets[i]));
+            i += 1;
+            continue;
+        };
+        let mss = first.payload().len();
+        let mut last = i;
+        let mut total = first.packet.len();
+        let mut next_seq = first.seq.wrapping_add(mss as u32);
+        let mut open =
+            mss > 0 && first.flags & (TCP_FIN | TCP_SYN | TCP_RST | TCP_PSH | TCP_URG) == 0;
+        while open && last + 1 < packets.len() {
+            let Some(next) = TcpSegment::parse(packets[last + 1]) else {
+                break;
+            };
+            let len = next.payload().len();
+            if !first.same_flow(&next)
+                || next.seq != next_seq
+                || len == 0
+                || len > mss
+                || total + len > u16::MAX as usize
+                || next.flags & (TCP_SYN | TCP_RST | TCP_URG | TCP_CWR) != 0
+            {
+                break;
+            }
+            last += 1;
+            total += len;
+            next_seq = next_seq.wrapping_add(len as u32);
+            // A shorter segment or a segment with PSH or FIN has to be the last one
+            open = len == mss && next.flags & (TCP_FIN | TCP_PSH) == 0;
+        }
+
+        if last == i {
+            out.push(with_empty_header(packets[i]));
+        } else {
+            out.push(merge(&first, &packets[i + 1..=last], mss, total));
+        }
+        i = last + 1;
+    }
+    out.len() - start
+}
+
+fn with_empty_header(packet: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0u8; VIRTIO_NET_HDR_LEN];
+    buf.extend_from_slice(packet);
+    buf
+}
+
+fn merge(first: &TcpSegment<'_>, rest: &[&[u8]], mss: usize, total: usize) -> Vec<u8> {
+    let (l4, hdr_len) = (first.ip_len, first.hdr_len);
+    let mut buf = Vec::with_capacity(VIRTIO_NET_HDR_LEN + total);
+    buf.resize(VIRTIO_NET_HDR_LEN, 0);
+    buf.extend_from_slice(first.packet);
+    for packet in rest {
+        buf.extend_from_slice(&packet[hdr_len..]);
+    }
+
+    let hdr = VirtioNetHdr {
+        flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+        gso_type: if first.is_v4() {
+            VIRTIO_NET_HDR_GSO_TCPV4
+        } else {
+            VIRTIO_NET_HDR_GSO_TCPV6
+        },
+        hdr_len: hdr_len as u16,
+        gso_size: mss as u16,
+        csum_start: l4 as u16,
+        csum_offset: 16,
+    };
+    hdr.encode(&mut buf);
+
+    let last_flags = rest.last().map_or(first.flags, |packet| packet[l4 + 13]);
+    let packet = &mut buf[VIRTIO_NET_HDR_LEN..];
+    if first.is_v4() {
+        packet[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+        packet[10..12].copy_from_slice(&[0, 0]);
+        let csum = checksum::fold(checksum::sum(&packet[..l4], 0));
+        packet[10..12].copy_from_slice(&csum.to_be_bytes());
+    } else {
+        packet[4..6].copy_from_slice(&((total - 40) as u16).to_be_bytes());
+    }
+    packet[l4 + 13] = first.flags | (last_flags & (TCP_FIN | TCP_PSH));
+    // The kernel completes the checksum, which has to hold the sum of the pseudo header
+    let pseudo = !checksum::fold(checksum::pseudo_header(packet, IPPROTO_TCP, total - l4));
+    packet[l4 + 16..l4 + 18].copy_from_slice(&pseudo.to_be_bytes());
+    buf
+}
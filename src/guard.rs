@@ -0,0 +1,48 @@
+use crate::Tun;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{Interest, Ready};
+
+/// Represents an owned readiness guard of a [`Tun`](struct.Tun.html), returned by
+/// [`Tun::readable_owned`](struct.Tun.html#method.readable_owned) and
+/// [`Tun::writable_owned`](struct.Tun.html#method.writable_owned).
+///
+/// Unlike the guards of `AsyncFd`, it holds an `Arc<Tun>` instead of a borrow, so it is `'static`
+/// and may be moved into spawned tasks.
+pub struct OwnedReadyGuard {
+    tun: Arc<Tun>,
+    interest: Interest,
+    ready: Ready,
+}
+
+impl OwnedReadyGuard {
+    pub(crate) fn new(tun: Arc<Tun>, interest: Interest, ready: Ready) -> Self {
+        Self {
+            tun,
+            interest,
+            ready,
+        }
+    }
+
+    /// Returns a reference to the Tun/Tap device.
+    pub fn get_ref(&self) -> &Arc<Tun> {
+        &self.tun
+    }
+
+    /// Consumes the guard and returns the Tun/Tap device.
+    pub fn into_inner(self) -> Arc<Tun> {
+        self.tun
+    }
+
+    /// Performs the I/O operation `f` if the device is still ready.
+    ///
+    /// If `f` returns `Err(io::ErrorKind::WouldBlock)`, the readiness is cleared and the caller
+    /// should wait for a new readiness event before retrying.
+    pub fn try_io<R>(&self, f: impl FnOnce(&Tun) -> io::Result<R>) -> io::Result<R> {
+        if self.interest.is_readable() {
+            self.tun.try_read(self.ready, |_| f(&self.tun))
+        } else {
+            self.tun.io.try_io(self.interest, |_| f(&self.tun))
+        }
+    }
+}
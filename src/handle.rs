@@ -0,0 +1,175 @@
+use crate::Result;
+use crate::Tun;
+use crate::TunConfig;
+use bytes::{Bytes, BytesMut};
+use std::io::{self, ErrorKind};
+use std::net::Ipv4Addr;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+
+pub(crate) enum Command {
+    Send(Bytes, Option<oneshot::Sender<io::Result<()>>>),
+    SetMtu(i32, oneshot::Sender<Result<()>>),
+    SetAddress(Ipv4Addr, oneshot::Sender<Result<()>>),
+    SetDestination(Ipv4Addr, oneshot::Sender<Result<()>>),
+    SetNetmask(Ipv4Addr, oneshot::Sender<Result<()>>),
+    Config(oneshot::Sender<Result<TunConfig>>),
+}
+
+/// Represents a cloneable handle of a Tun/Tap queue owned by a background task, created by
+/// [`Tun::into_handle`](struct.Tun.html#method.into_handle).
+///
+/// The packets and configuration changes of all the clones are executed one at a time by the
+/// owner task, so packets are never interleaved and no `Arc<Tun>` has to be shared.
+#[derive(Clone)]
+pub struct TunHandle {
+    tx: mpsc::Sender<Command>,
+}
+
+impl TunHandle {
+    pub(crate) fn channel(capacity: usize) -> (Self, mpsc::Receiver<Command>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Self { tx }, rx)
+    }
+
+    /// Sends a packet to the Tun/Tap interface, waiting until it is written to the device.
+    pub async fn send(&self, packet: Bytes) -> io::Result<()> {
+        let (reply, rx) = oneshot::channel();
+        self.command(Command::Send(packet, Some(reply))).await?;
+        rx.await.unwrap_or_else(|_| Err(closed()))
+    }
+
+    /// Queues a packet to be sent to the Tun/Tap interface without waiting.
+    ///
+    /// Fails with `WouldBlock` if the queue of the owner task is full. Write errors of queued
+    /// packets are not reported.
+    pub fn try_send(&self, packet: Bytes) -> io::Result<()> {
+        self.tx
+            .try_send(Command::Send(packet, None))
+            .map_err(|err| match err {
+                mpsc::error::TrySendError::Full(_) => ErrorKind::WouldBlock.into(),
+                mpsc::error::TrySendError::Closed(_) => closed(),
+            })
+    }
+
+    /// Sets the MTU of device.
+    pub async fn set_mtu(&self, mtu: i32) -> Result<()> {
+        self.request(|reply| Command::SetMtu(mtu, reply)).await
+    }
+
+    /// Sets the IPv4 address of device.
+    pub async fn set_address(&self, address: Ipv4Addr) -> Result<()> {
+        self.request(|reply| Command::SetAddress(address, reply))
+            .await
+    }
+
+    /// Sets the IPv4 destination address of device.
+    pub async fn set_destination(&self, dst: Ipv4Addr) -> Result<()> {
+        self.request(|reply| Command::SetDestination(dst, reply))
+            .await
+    }
+
+    /// Sets the IPv4 netmask of device.
+    pub async fn set_netmask(&self, netmask: Ipv4Addr) -> Result<()> {
+        self.request(|reply| Command::SetNetmask(netmask, reply))
+            .await
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    pub async fn config(&self) -> Result<TunConfig> {
+        self.request(Command::Config).await
+    }
+
+    /// Returns `true` once the owner task has stopped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    async fn command(&self, command: Command) -> io::Result<()> {
+        self.tx.send(command).await.map_err(|_| closed())
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<Result<T>>) -> Command,
+    ) -> Result<T> {
+        let (reply, rx) = oneshot::channel();
+        self.command(command(reply)).await?;
+        rx.await.unwrap_or_else(|_| Err(closed().into()))
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(ErrorKind::BrokenPipe, "the owner task of Tun has stopped")
+}
+
+/// Serves the commands of the handles of `tun` and forwards the received packets to `inbound`.
+///
+/// It stops once all the handles and the receiver are dropped, on the first read error, or once
+/// `cancel` is cancelled, after executing the commands which are already queued.
+pub(crate) async fn run(
+    tun: Tun,
+    mut commands: mpsc::Receiver<Command>,
+    inbound: mpsc::Sender<Bytes>,
+    buffer_size: usize,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let mut reading = true;
+    let mut serving = true;
+    while reading || serving {
+        tokio::select! {
+            res = read(&tun, &inbound, buffer_size), if reading => reading = res?,
+            command = commands.recv(), if serving => match command {
+                Some(command) => execute(&tun, command).await,
+                None => serving = false,
+            },
+            _ = cancel.cancelled() => {
+                commands.close();
+                while let Some(command) = commands.recv().await {
+                    execute(&tun, command).await;
+                }
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads a packet into `inbound` once it has room. Returns `false` if `inbound` is closed.
+async fn read(tun: &Tun, inbound: &mpsc::Sender<Bytes>, buffer_size: usize) -> io::Result<bool> {
+    let Ok(permit) = inbound.reserve().await else {
+        return Ok(false);
+    };
+    let mut buf = BytesMut::zeroed(buffer_size);
+    let n = tun.recv(&mut buf).await?;
+    buf.truncate(n);
+    permit.send(buf.freeze());
+    Ok(true)
+}
+
+async fn execute(tun: &Tun, command: Command) {
+    // A dropped reply only means that its handle is no longer interested in the result
+    match command {
+        Command::Send(packet, reply) => {
+            let res = tun.send_all(&packet).await;
+            if let Some(reply) = reply {
+                let _ = reply.send(res);
+            }
+        }
+        Command::SetMtu(mtu, reply) => {
+            let _ = reply.send(tun.iface.mtu(Some(mtu)).map(|_| ()));
+        }
+        Command::SetAddress(address, reply) => {
+            let _ = reply.send(tun.iface.address(Some(address)).map(|_| ()));
+        }
+        Command::SetDestination(dst, reply) => {
+            let _ = reply.send(tun.iface.destination(Some(dst)).map(|_| ()));
+        }
+        Command::SetNetmask(netmask, reply) => {
+            let _ = reply.send(tun.iface.netmask(Some(netmask)).map(|_| ()));
+        }
+        Command::Config(reply) => {
+            let _ = reply.send(tun.config());
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Privileged helper process creating devices on behalf of an unprivileged process.
+//!
+//! With [`TunBuilder::helper`](../struct.TunBuilder.html#method.helper), the builder does not
+//! create the device itself: it spawns the helper command with the options of the builder,
+//! and the helper creates and configures the device, sends the file descriptors of its queues
+//! back over a unix socket, and exits. The process thus needs no privileges at all, only the
+//! helper does (e.g. through `sudo`, a `setcap cap_net_admin+ep` binary or a setuid one).
+//!
+//! Unless it is run by root, e.g. through `sudo`, the helper only creates devices which belong
+//! to the user running it: it refuses persistent devices, and an owner or a group other than
+//! the user and group of the caller, which would otherwise let any user able to run the helper
+//! keep devices of other users.
+//!
+//! Any binary calling [`run_if_requested`] first thing in `main` can serve as helper, including
+//! the current executable and the `tokio-tun` binary of the `cli` feature:
+//!
+//! ```no_run
+//! use tokio_tun::TunBuilder;
+//!
+//! # async fn run() -> tokio_tun::Result<()> {
+//! // Returns only when the process was not spawned as a helper
+//! tokio_tun::helper::run_if_requested();
+//!
+//! let tun = TunBuilder::new()
+//!     .name("tun0")
+//!     .up()
+//!     .helper(["sudo", "-n", "/usr/local/bin/my-app"])
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The process receiving the device has no more privileges than before: the operations which
+//! require some, e.g. changing the MTU or the addresses of the device afterwards, fail with
+//! [`Error::PermissionDenied`](../enum.Error.html#variant.PermissionDenied). Devices backed by
+//! vmnet on macOS live in the process which starts them, and are not supported.
+
+use crate::Error;
+use crate::Result;
+use crate::TunBuilder;
+use crate::builder::{IFF_NO_PI, IFF_TAP};
+use crate::device;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::params::Params;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::params::Params;
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::process::{self, Command, Stdio};
+use std::ptr;
+
+/// First argument of the helper command, which tells [`run_if_requested`] to serve as helper.
+pub const HELPER_FLAG: &str = "--tokio-tun-helper";
+
+/// Largest number of file descriptors passed at once (`SCM_MAX_FD` of Linux).
+const MAX_FDS: usize = 253;
+
+/// Size of the largest message of the helper.
+const MAX_MESSAGE: usize = 4096;
+
+/// Serves as helper and exits if the process was spawned as one, i.e. if its first argument is
+/// [`HELPER_FLAG`], and returns otherwise.
+///
+/// The helper creates the device described by its other arguments and sends the file
+/// descriptors of its queues, or the error which prevented it, over its standard input. It
+/// exits with status `0` if the device was created, `1` otherwise.
+pub fn run_if_requested() {
+    if env::args_os().nth(1).as_deref() != Some(HELPER_FLAG.as_ref()) {
+        return;
+    }
+    // The standard input is the socket connected to the process which spawned the helper
+    let socket = unsafe { BorrowedFd::borrow_raw(0) };
+    let res = parse_args(env::args().skip(2))
+        .and_then(|(params, queues)| check_caller(&params).map(|_| (params, queues)))
+        .and_then(|(params, queues)| device::allocate_local(params, queues));
+    let sent = match &res {
+        Ok((_, fds)) => send(socket, &[0], fds),
+        Err(err) => send(socket, format!("\x01{}", err).as_bytes(), &[]),
+    };
+    process::exit(if res.is_ok() && sent.is_ok() { 0 } else { 1 })
+}
+
+/// Allocates a new Tun/Tap device with `queues` file descriptors in the helper process
+/// `command`, which creates and configures it from `params`.
+pub(crate) fn allocate(
+    command: &[OsString],
+    params: Params,
+    queues: usize,
+) -> Result<(Interface, Vec<OwnedFd>)> {
+    let (program, args) = command
+        .split_first()
+        .ok_or(Error::InvalidParam { field: "helper" })?;
+    if queues > MAX_FDS {
+        return Err(Error::InvalidParam { field: "queues" });
+    }
+    let (socket, remote) = socketpair()?;
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(HELPER_FLAG)
+        .args(to_args(&params, queues)?)
+        .stdin(Stdio::from(remote))
+        .spawn()?;
+    let received = recv(socket.as_fd());
+    let status = child.wait()?;
+    let (message, fds) = received?;
+    match message.split_first() {
+        Some((0, _)) if fds.len() == queues => {}
+        Some((1, err)) => {
+            return Err(Error::IoError(io::Error::other(format!(
+                "helper: {}",
+                String::from_utf8_lossy(err)
+            ))));
+        }
+        _ => {
+            return Err(Error::IoError(io::Error::other(format!(
+                "helper exited without sending the device ({})",
+                status
+            ))));
+        }
+    }
+    for fd in &fds {
+        device::set_nonblocking(fd.as_raw_fd(), true)?;
+    }
+    let mut iface = Interface::from_fd(fds[0].as_fd())?;
+    iface.set_queues(fds.len());
+    Ok((iface, fds))
+}
+
+/// Returns the arguments which describe the device of `params` to the helper.
+fn to_args(params: &Params, queues: usize) -> Result<Vec<String>> {
+    #[cfg(all(target_os = "macos", feature = "vmnet"))]
+    if params.vmnet.is_some() {
+        return Err(Error::Unsupported {
+            feature: "vmnet devices created by a helper",
+            platform: "macos",
+        });
+    }
+    let mut args = vec!["--queues".to_owned(), queues.to_string()];
+    let mut push = |arg: &str, value: Option<String>| {
+        args.push(arg.to_owned());
+        args.extend(value);
+    };
+    if let Some(name) = &params.name {
+        push("--name", Some(name.clone()));
+    }
+    if params.flags as i32 & IFF_TAP != 0 {
+        push("--tap", None);
+    }
+    if params.flags as i32 & IFF_NO_PI == 0 {
+        push("--packet-info", None);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if params.persist {
+            push("--persist", None);
+        }
+        if params.offload {
+            push("--offload", None);
+        }
+        if let Some(owner) = params.owner {
+            push("--owner", Some(owner.to_string()));
+        }
+        if let Some(group) = params.group {
+            push("--group", Some(group.to_string()));
+        }
+    }
+    match params.up {
+        Some(true) => push("--up", None),
+        Some(false) => push("--down", None),
+        None => {}
+    }
+    if params.set_flags != 0 {
+        push("--set-flags", Some(params.set_flags.to_string()));
+    }
+    if params.unset_flags != 0 {
+        push("--unset-flags", Some(params.unset_flags.to_string()));
+    }
+    if let Some(mtu) = params.mtu {
+        push("--mtu", Some(mtu.to_string()));
+    }
+    if let Some(address) = params.address {
+        push("--address", Some(address.to_string()));
+    }
+    if let Some(destination) = params.destination {
+        push("--destination", Some(destination.to_string()));
+    }
+    if let Some(broadcast) = params.broadcast {
+        push("--broadcast", Some(broadcast.to_string()));
+    }
+    if let Some(netmask) = params.netmask {
+        push("--netmask", Some(netmask.to_string()));
+    }
+    #[cfg(all(target_os = "linux", feature = "netlink"))]
+    {
+        for net in &params.addresses {
+            push("--ip", Some(net.to_string()));
+        }
+        if let Some(qdisc) = &params.qdisc {
+            push("--qdisc", Some(qdisc.kind().to_owned()));
+        }
+    }
+    if params.auto_suffix {
+        push("--auto-suffix", None);
+    }
+    Ok(args)
+}
+
+/// Returns the parameters and the number of queues of the device described by `args`.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Params, usize)> {
+    let mut builder = TunBuilder::new();
+    let mut queues = 1;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(Error::InvalidParam { field: "helper" });
+        builder = match arg.as_str() {
+            "--queues" => {
+                queues = value()?.parse().map_err(invalid_arg)?;
+                builder.queues(queues)
+            }
+            "--name" => builder.name(&value()?),
+            "--tap" => builder.tap(),
+            "--packet-info" => builder.packet_info(),
+            "--persist" => builder.persist(),
+            "--offload" => builder.offload(),
+            "--owner" => builder.owner(value()?.parse().map_err(invalid_arg)?),
+            "--group" => builder.group(value()?.parse().map_err(invalid_arg)?),
+            "--up" => builder.up(),
+            "--down" => builder.down(),
+            "--set-flags" => builder.link_flags(value()?.parse().map_err(invalid_arg)?, true),
+            "--unset-flags" => builder.link_flags(value()?.parse().map_err(invalid_arg)?, false),
+            "--mtu" => builder.mtu(value()?.parse().map_err(invalid_arg)?),
+            "--address" => builder.address(value()?.parse().map_err(invalid_arg)?),
+            "--destination" => builder.destination(value()?.parse().map_err(invalid_arg)?),
+            "--broadcast" => builder.broadcast(value()?.parse().map_err(invalid_arg)?),
+            "--netmask" => builder.netmask(value()?.parse().map_err(invalid_arg)?),
+            #[cfg(all(target_os = "linux", feature = "netlink"))]
+            "--ip" => builder.ip(value()?.parse().map_err(invalid_arg)?),
+            #[cfg(all(target_os = "linux", feature = "netlink"))]
+            "--qdisc" => {
+                let kind = value()?;
+                let qdisc = [
+                    crate::Qdisc::NoQueue,
+                    crate::Qdisc::Fq,
+                    crate::Qdisc::FqCodel,
+                ]
+                .into_iter()
+                .find(|qdisc| qdisc.kind() == kind)
+                .ok_or(Error::InvalidParam { field: "qdisc" })?;
+                builder.qdisc(qdisc)
+            }
+            "--auto-suffix" => builder.auto_suffix(),
+            _ => return Err(Error::InvalidParam { field: "helper" }),
+        };
+    }
+    Ok((builder.params()?, queues))
+}
+
+/// Checks that the caller of the helper may create the device of `params`: a caller other than
+/// root, e.g. running a `setcap` or setuid helper, may only create a device it owns and which
+/// does not outlive it.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+fn check_caller(params: &Params) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let foreign_owner = params
+            .owner
+            .is_some_and(|owner| owner as libc::uid_t != uid);
+        let foreign_group = params
+            .group
+            .is_some_and(|group| group as libc::gid_t != gid);
+        if uid != 0 && (params.persist || foreign_owner || foreign_group) {
+            return Err(Error::PermissionDenied);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the error of an argument of the helper which does not parse.
+fn invalid_arg<E>(_: E) -> Error {
+    Error::InvalidParam { field: "helper" }
+}
+
+/// Returns a pair of connected unix sockets, the first of which is not inherited by children.
+fn socketpair() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fds = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    set_cloexec(fds.0.as_raw_fd())?;
+    set_cloexec(fds.1.as_raw_fd())?;
+    Ok(fds)
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns a buffer suitably aligned for control messages holding up to `fds` file descriptors.
+fn control_buffer(fds: usize) -> Vec<u64> {
+    let space = unsafe { libc::CMSG_SPACE((fds * mem::size_of::<RawFd>()) as _) } as usize;
+    vec![0; space.div_ceil(mem::size_of::<u64>())]
+}
+
+/// Sends `data` along with the file descriptors `fds` over `socket`.
+fn send(socket: BorrowedFd<'_>, data: &[u8], fds: &[OwnedFd]) -> io::Result<()> {
+    let raw: Vec<RawFd> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+    let mut control = control_buffer(raw.len());
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if !raw.is_empty() {
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = (control.len() * mem::size_of::<u64>()) as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of_val(raw.as_slice()) as _) as _;
+            ptr::copy_nonoverlapping(raw.as_ptr(), libc::CMSG_DATA(cmsg).cast(), raw.len());
+        }
+    }
+    if unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receives a message and the file descriptors which come with it from `socket`.
+fn recv(socket: BorrowedFd<'_>) -> io::Result<(Vec<u8>, Vec<OwnedFd>)> {
+    let mut data = vec![0u8; MAX_MESSAGE];
+    let mut control = control_buffer(MAX_FDS);
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * mem::size_of::<u64>()) as _;
+    let n = Error::retry_io(|| unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) })?;
+    data.truncate(n);
+
+    let mut fds = Vec::new();
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while let Some(header) = unsafe { cmsg.as_ref() } {
+        if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_RIGHTS {
+            // The length is a `socklen_t` on macOS
+            #[allow(clippy::unnecessary_cast)]
+            let len = header.cmsg_len as usize - unsafe { libc::CMSG_LEN(0) } as usize;
+            let data = unsafe { libc::CMSG_DATA(cmsg) } as *const RawFd;
+            for i in 0..len / mem::size_of::<RawFd>() {
+                let fd = unsafe { OwnedFd::from_raw_fd(data.add(i).read_unaligned()) };
+                set_cloexec(fd.as_raw_fd())?;
+                fds.push(fd);
+            }
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::other(
+            "the helper sent too many file descriptors",
+        ));
+    }
+    Ok((data, fds))
+}
@@ -0,0 +1,169 @@
+use crate::AsyncTunDevice;
+use crate::Result;
+use crate::TunConfig;
+use std::fmt;
+use std::io;
+
+/// Represents the direction of an inspected packet, from the point of view of the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The packet was received from the device.
+    Received,
+    /// The packet was sent to the device.
+    Sent,
+}
+
+/// Represents a packet passed to the hooks of [`Inspected`](struct.Inspected.html).
+#[derive(Debug, Clone, Copy)]
+pub struct InspectedPacket<'a> {
+    /// Bytes of the packet, as read from or written to the device.
+    pub data: &'a [u8],
+    /// Direction of the packet.
+    pub direction: Direction,
+    /// Index of the queue of the device which carried the packet.
+    pub queue: usize,
+}
+
+impl InspectedPacket<'_> {
+    /// Returns the length of the packet.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the packet is empty.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+type Hook = Box<dyn Fn(&InspectedPacket<'_>) + Send + Sync>;
+
+/// Represents a device whose received and sent packets are passed to hooks, which observe them
+/// without changing them: the building block of logging, metrics and debugging layers.
+///
+/// The hooks are called synchronously on the task performing the I/O, after the packet was
+/// received or sent successfully, so they should be cheap.
+///
+/// ```no_run
+/// use tokio_tun::{Inspected, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+/// let tun = Inspected::new(tun, 0)
+///     .on_recv(|packet| println!("queue {}: received {} bytes", packet.queue, packet.len()))
+///     .on_send(|packet| println!("queue {}: sent {} bytes", packet.queue, packet.len()));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Inspected<T> {
+    inner: T,
+    queue: usize,
+    on_recv: Option<Hook>,
+    on_send: Option<Hook>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Inspected<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inspected")
+            .field("inner", &self.inner)
+            .field("queue", &self.queue)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice> Inspected<T> {
+    /// Wraps `inner`, the queue of index `queue` of a device, without hooks.
+    pub fn new(inner: T, queue: usize) -> Self {
+        Self {
+            inner,
+            queue,
+            on_recv: None,
+            on_send: None,
+        }
+    }
+
+    /// Sets the hook called with every packet received from the device.
+    pub fn on_recv(mut self, hook: impl Fn(&InspectedPacket<'_>) + Send + Sync + 'static) -> Self {
+        self.on_recv = Some(Box::new(hook));
+        self
+    }
+
+    /// Sets the hook called with every packet sent to the device. A packet partially sent by
+    /// [`send`](#method.send) is passed with the part which was sent.
+    pub fn on_send(mut self, hook: impl Fn(&InspectedPacket<'_>) + Send + Sync + 'static) -> Self {
+        self.on_send = Some(Box::new(hook));
+        self
+    }
+
+    /// Returns the index of the queue.
+    pub fn queue(&self) -> usize {
+        self.queue
+    }
+
+    /// Receives a packet from the device and passes it to the `on_recv` hook.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.recv(buf).await?;
+        self.observe(&self.on_recv, &buf[..n], Direction::Received);
+        Ok(n)
+    }
+
+    /// Sends a buffer to the device and passes the part which was sent to the `on_send` hook.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.send(buf).await?;
+        self.observe(&self.on_send, &buf[..n], Direction::Sent);
+        Ok(n)
+    }
+
+    /// Sends all of a buffer to the device and passes it to the `on_send` hook.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.inner.send_all(buf).await?;
+        self.observe(&self.on_send, buf, Direction::Sent);
+        Ok(())
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device, dropping the hooks.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn observe(&self, hook: &Option<Hook>, data: &[u8], direction: Direction) {
+        if let Some(hook) = hook {
+            hook(&InspectedPacket {
+                data,
+                direction,
+                queue: self.queue,
+            });
+        }
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for Inspected<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        Inspected::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        Inspected::send(self, buf).await
+    }
+
+    async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        Inspected::send_all(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+}
@@ -3,8 +3,11 @@ mod linux {
     pub mod address;
     pub mod interface;
     pub mod io;
+    #[cfg(feature = "netlink")]
+    pub mod netlink;
     pub mod params;
     pub mod request;
+    pub mod steering;
 }
 
 #[cfg(target_os = "macos")]
@@ -14,12 +17,200 @@ mod macos {
     pub mod io;
     pub mod params;
     pub mod request;
+    #[cfg(feature = "netlink")]
+    pub mod route;
+    #[cfg(feature = "vmnet")]
+    pub mod vmnet;
 }
 
+#[cfg(target_os = "linux")]
+pub mod affinity;
+#[cfg(feature = "proptest")]
+mod arbitrary;
+mod arp;
+mod async_device;
+#[cfg(feature = "async-io")]
+mod asyncio;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+mod autoscale;
+#[cfg(feature = "bench-tools")]
+pub mod bench;
+#[cfg(feature = "tokio")]
+mod bridge;
 mod builder;
+#[cfg(feature = "tokio")]
+mod changes;
+pub mod checksum;
+mod classify;
+mod config;
+#[cfg(feature = "tokio")]
+mod counters;
+mod device;
+mod devices;
+#[cfg(feature = "dhcp")]
+mod dhcp;
+pub mod dot1q;
+mod dump;
+mod faulty;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod gso;
+#[cfg(feature = "tokio")]
+mod guard;
+#[cfg(feature = "tokio")]
+mod handle;
+pub mod helper;
+mod inspect;
+#[cfg(feature = "tokio")]
+mod mirror;
+#[cfg(feature = "tokio")]
+mod mock;
+#[cfg(feature = "tokio")]
+mod multiqueue;
+pub mod nat;
+mod ndp;
+#[cfg(feature = "tokio")]
+mod netemu;
+mod packet_info;
+#[cfg(feature = "etherparse")]
+mod parsed;
+mod pcap;
+#[cfg(all(feature = "monoio", target_os = "linux"))]
+pub mod percore;
+#[cfg(feature = "smoltcp")]
+pub mod phy;
+#[cfg(feature = "tokio")]
+mod pool;
+#[cfg(feature = "tokio")]
+mod priority;
+#[cfg(feature = "tokio")]
+mod pump;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+mod qdisc;
+#[cfg(target_os = "linux")]
+mod qemu;
+#[cfg(feature = "tokio")]
+mod ratelimit;
+mod reassembly;
+mod report;
 mod result;
+#[cfg(feature = "tokio")]
+mod ring;
+#[cfg(feature = "netlink")]
+mod route;
+#[cfg(feature = "tokio")]
+mod stats;
+mod sync;
+#[cfg(target_os = "linux")]
+mod sysctl;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "tokio")]
+mod threaded;
+#[cfg(feature = "tokio")]
+mod timestamp;
+#[cfg(feature = "tokio")]
 mod tun;
+#[cfg(feature = "tokio")]
+mod udp;
+#[cfg(any(target_os = "macos", feature = "fuzzing"))]
+mod utun;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+mod vlan;
+#[cfg(feature = "netlink")]
+mod vpn;
+#[cfg(feature = "tokio")]
+mod watchdog;
+#[cfg(feature = "tokio")]
+mod workers;
 
+pub use self::arp::ArpResponder;
+pub use self::async_device::AsyncTunDevice;
+#[cfg(feature = "async-io")]
+pub use self::asyncio::AsyncIoTun;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+pub use self::autoscale::{
+    Autoscale, AutoscaledWorkers, spawn_autoscaled_workers, spawn_autoscaled_workers_with_cancel,
+};
+#[cfg(feature = "tokio")]
+pub use self::bridge::{Bridge, bridge, bridge_stream, bridge_with_cancel};
 pub use self::builder::TunBuilder;
+#[cfg(feature = "tokio")]
+pub use self::changes::{AddressChange, AddressChanges, MtuChanges};
+pub use self::classify::{IpVersion, PacketClass, classify};
+pub use self::config::{DeviceType, TunConfig};
+#[cfg(feature = "tokio")]
+pub use self::counters::Counters;
+pub use self::device::CloseOptions;
+pub use self::devices::{DeviceInfo, list_devices};
+#[cfg(feature = "dhcp")]
+pub use self::dhcp::{DhcpLease, DhcpServer};
+pub use self::dump::PacketDump;
+pub use self::faulty::FaultyTun;
+#[cfg(feature = "tokio")]
+pub use self::guard::OwnedReadyGuard;
+#[cfg(feature = "tokio")]
+pub use self::handle::TunHandle;
+pub use self::inspect::{Direction, Inspected, InspectedPacket};
+#[cfg(all(feature = "vmnet", target_os = "macos"))]
+pub use self::macos::vmnet::VmnetMode;
+#[cfg(feature = "tokio")]
+pub use self::mirror::Mirrored;
+#[cfg(feature = "tokio")]
+pub use self::mock::{TestTun, TestTunHandle};
+#[cfg(feature = "tokio")]
+pub use self::multiqueue::{Dispatch, TunPool};
+pub use self::ndp::NdpResponder;
+#[cfg(feature = "tokio")]
+pub use self::netemu::NetEmu;
+pub use self::packet_info::{PACKET_INFO_LEN, PacketInfo, TUN_PKT_STRIP};
+#[cfg(feature = "etherparse")]
+pub use self::parsed::ParsedPacket;
+pub use self::pcap::PcapRecorder;
+#[cfg(feature = "tokio")]
+pub use self::pool::PooledPacket;
+#[cfg(feature = "tokio")]
+pub use self::priority::PrioritizedSender;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+pub use self::qdisc::Qdisc;
+#[cfg(target_os = "linux")]
+pub use self::qemu::QemuTap;
+#[cfg(feature = "tokio")]
+pub use self::ratelimit::RateLimited;
+pub use self::reassembly::{Reassembled, Reassembler};
+pub use self::report::{BuildReport, OptionStatus, SkippedOption};
 pub use self::result::{Error, Result};
+#[cfg(feature = "tokio")]
+pub use self::ring::PacketRing;
+#[cfg(feature = "netlink")]
+pub use self::route::RouteOptions;
+#[cfg(feature = "tokio")]
+pub use self::stats::{InterfaceCounters, Rates, StatsSampler, StatsSnapshot};
+pub use self::sync::SyncTun;
+#[cfg(target_os = "linux")]
+pub use self::sysctl::{AcceptRa, RpFilter};
+#[cfg(feature = "tokio")]
+pub use self::threaded::ThreadedTun;
+#[cfg(feature = "tokio")]
+pub use self::timestamp::Timestamp;
+#[cfg(feature = "tokio")]
 pub use self::tun::Tun;
+#[cfg(feature = "tokio")]
+pub use self::udp::UdpTunnel;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+pub use self::vlan::VlanInterface;
+#[cfg(feature = "netlink")]
+pub use self::vpn::VpnRoutes;
+#[cfg(feature = "tokio")]
+pub use self::watchdog::{WatchdogEvent, WatchedTun};
+#[cfg(feature = "tokio")]
+pub use self::workers::{Workers, spawn_workers, spawn_workers_with_cancel};
+#[cfg(feature = "etherparse")]
+pub use etherparse;
+#[cfg(feature = "tokio")]
+pub use tokio_util::sync::CancellationToken;
@@ -1,14 +1,32 @@
+#[cfg(feature = "netlink")]
+use super::netlink::Netlink;
 use super::params::Params;
 use super::request::ifreq;
+use super::steering;
+use crate::CloseOptions;
+#[cfg(feature = "netlink")]
+use crate::Qdisc;
 use crate::Result;
+#[cfg(feature = "netlink")]
+use crate::RouteOptions;
 use crate::linux::address::Ipv4AddrExt;
+#[cfg(feature = "netlink")]
+use ipnet::IpNet;
+#[cfg(any(feature = "netlink", feature = "tokio"))]
+use std::io;
+#[cfg(feature = "netlink")]
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
 
 nix::ioctl_write_int!(tunsetiff, b'T', 202);
 nix::ioctl_write_int!(tunsetpersist, b'T', 203);
 nix::ioctl_write_int!(tunsetowner, b'T', 204);
 nix::ioctl_write_int!(tunsetgroup, b'T', 206);
+nix::ioctl_write_int!(tunsetoffload, b'T', 208);
+nix::ioctl_read!(tunsetsteeringebpf, b'T', 224, libc::c_int);
 
+nix::ioctl_write_ptr_bad!(tunsetqueue, libc::TUNSETQUEUE, ifreq);
 nix::ioctl_write_ptr_bad!(siocsifmtu, libc::SIOCSIFMTU, ifreq);
 nix::ioctl_write_ptr_bad!(siocsifflags, libc::SIOCSIFFLAGS, ifreq);
 nix::ioctl_write_ptr_bad!(siocsifaddr, libc::SIOCSIFADDR, ifreq);
@@ -16,6 +34,8 @@ nix::ioctl_write_ptr_bad!(siocsifdstaddr, libc::SIOCSIFDSTADDR, ifreq);
 nix::ioctl_write_ptr_bad!(siocsifbrdaddr, libc::SIOCSIFBRDADDR, ifreq);
 nix::ioctl_write_ptr_bad!(siocsifnetmask, libc::SIOCSIFNETMASK, ifreq);
 
+nix::ioctl_read_bad!(tungetiff, libc::TUNGETIFF, ifreq);
+nix::ioctl_read_bad!(tungetvnethdrsz, libc::TUNGETVNETHDRSZ, libc::c_int);
 nix::ioctl_read_bad!(siocgifmtu, libc::SIOCGIFMTU, ifreq);
 nix::ioctl_read_bad!(siocgifflags, libc::SIOCGIFFLAGS, ifreq);
 nix::ioctl_read_bad!(siocgifaddr, libc::SIOCGIFADDR, ifreq);
@@ -23,39 +43,84 @@ nix::ioctl_read_bad!(siocgifdstaddr, libc::SIOCGIFDSTADDR, ifreq);
 nix::ioctl_read_bad!(siocgifbrdaddr, libc::SIOCGIFBRDADDR, ifreq);
 nix::ioctl_read_bad!(siocgifnetmask, libc::SIOCGIFNETMASK, ifreq);
 
-#[derive(Clone)]
+/// Represents the configuration side of a device: the control socket of its ioctls, which it
+/// owns, and what is known of the device since it was attached.
+///
+/// The file descriptors of the queues are owned by the queues themselves, and borrowed by the
+/// few ioctls which need one.
 pub struct Interface {
-    fds: Vec<i32>,
-    socket: i32,
+    socket: OwnedFd,
     name: String,
+    tap: bool,
+    /// Flags of the device, as attached to.
+    tun_flags: i16,
+    /// Size of the virtio-net header of packets, if the device has one.
+    vnet_hdr_size: usize,
+    queues: usize,
 }
 
 impl Interface {
-    pub fn new(fds: Vec<i32>, name: &str, mut flags: i16) -> Result<Self> {
+    /// Attaches the queues `fds` to the device named `name`, creating it with `flags` if it does
+    /// not exist.
+    pub fn new(fds: &[OwnedFd], name: &str, mut flags: i16) -> Result<Self> {
         let mut req = ifreq::new(name);
         if fds.len() > 1 {
             flags |= libc::IFF_MULTI_QUEUE as i16;
         }
         req.ifr_ifru.ifru_flags = flags;
-        for &fd in &fds {
-            unsafe { tunsetiff(fd, &req as *const _ as _) }?;
+        for fd in fds {
+            unsafe { tunsetiff(fd.as_raw_fd(), &req as *const _ as _) }?;
         }
+        let vnet_hdr_size = match fds.first() {
+            Some(fd) => Self::vnet_hdr_size(fd.as_fd(), flags)?,
+            None => 0,
+        };
         Ok(Interface {
-            fds,
-            socket: unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) },
+            socket: control_socket()?,
             name: req.name().to_owned(),
+            tap: flags as libc::c_int & libc::IFF_TAP != 0,
+            tun_flags: flags,
+            vnet_hdr_size,
+            queues: fds.len(),
         })
     }
 
-    pub fn init(&self, params: Params) -> Result<()> {
+    pub fn from_fd(fd: BorrowedFd<'_>) -> Result<Self> {
+        let mut req = ifreq::new("");
+        unsafe { tungetiff(fd.as_raw_fd(), &mut req) }?;
+        let flags = unsafe { req.ifr_ifru.ifru_flags };
+        Ok(Interface {
+            socket: control_socket()?,
+            name: req.name().to_owned(),
+            tap: flags as libc::c_int & libc::IFF_TAP != 0,
+            tun_flags: flags,
+            vnet_hdr_size: Self::vnet_hdr_size(fd, flags)?,
+            queues: 1,
+        })
+    }
+
+    /// Returns the size of the virtio-net header of the packets of `fd`, `0` unless `flags`
+    /// enable it.
+    fn vnet_hdr_size(fd: BorrowedFd<'_>, flags: i16) -> Result<usize> {
+        if flags as libc::c_int & libc::IFF_VNET_HDR == 0 {
+            return Ok(0);
+        }
+        let mut size = 0;
+        unsafe { tungetvnethdrsz(fd.as_raw_fd(), &mut size) }?;
+        Ok(size as usize)
+    }
+
+    /// Configures the device, whose queues are `fds`, from `params`.
+    pub fn init(&self, fds: &[OwnedFd], params: Params) -> Result<()> {
+        #[cfg(not(feature = "netlink"))]
         if let Some(mtu) = params.mtu {
             self.mtu(Some(mtu))?;
         }
         if let Some(owner) = params.owner {
-            self.owner(owner)?;
+            self.owner(fds, owner)?;
         }
         if let Some(group) = params.group {
-            self.group(group)?;
+            self.group(fds, group)?;
         }
         if let Some(address) = params.address {
             self.address(Some(address))?;
@@ -69,30 +134,147 @@ impl Interface {
         if let Some(broadcast) = params.broadcast {
             self.broadcast(Some(broadcast))?;
         }
+        if params.set_flags != 0 {
+            self.flags(Some(params.set_flags))?;
+        }
+        if params.unset_flags != 0 {
+            self.unset_flags(params.unset_flags)?;
+        }
         if params.persist {
-            self.persist()?;
+            self.persist(fds)?;
+        }
+        if params.offload {
+            self.offload(fds)?;
         }
-        if params.up {
+        #[cfg(not(feature = "netlink"))]
+        if params.up == Some(true) {
             self.flags(Some(libc::IFF_UP as i16 | libc::IFF_RUNNING as i16))?;
         }
+        #[cfg(feature = "netlink")]
+        {
+            // The MTU and the state of the link are changed at once, after the addresses are set
+            let mut netlink = Netlink::new()?;
+            let index = self.index()?;
+            for net in params.addresses {
+                netlink.add_address(index, net)?;
+            }
+            if params.mtu.is_some() || params.up.is_some() {
+                netlink.set_link(index, params.mtu.map(|mtu| mtu as u32), params.up)?;
+            }
+            if let Some(qdisc) = params.qdisc {
+                netlink.set_qdisc(index, qdisc)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn files(&self) -> &[i32] {
-        &self.fds
+    /// Returns the index of the interface.
+    #[cfg(any(feature = "netlink", feature = "tokio"))]
+    pub fn index(&self) -> Result<u32> {
+        index_of(self.name())
+    }
+
+    /// Enslaves the interface to bridge `bridge`, or releases it from its bridge if `None`.
+    #[cfg(feature = "netlink")]
+    pub fn set_bridge(&self, bridge: Option<&str>) -> Result<()> {
+        let master = bridge.map_or(Ok(0), index_of)?;
+        Ok(Netlink::new()?.set_master(self.index()?, master)?)
+    }
+
+    /// Adds (or replaces) an IPv4 or IPv6 address of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn add_address(&self, net: IpNet) -> Result<()> {
+        Ok(Netlink::new()?.add_address(self.index()?, net)?)
+    }
+
+    /// Removes an IPv4 or IPv6 address of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn del_address(&self, net: IpNet) -> Result<()> {
+        Ok(Netlink::new()?.del_address(self.index()?, net)?)
+    }
+
+    /// Adds (or replaces) a static neighbor entry of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn add_neighbor(&self, addr: IpAddr, mac: [u8; 6]) -> Result<()> {
+        Ok(Netlink::new()?.add_neighbor(self.index()?, addr, mac)?)
+    }
+
+    /// Removes a neighbor entry of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn del_neighbor(&self, addr: IpAddr) -> Result<()> {
+        Ok(Netlink::new()?.del_neighbor(self.index()?, addr)?)
+    }
+
+    /// Replaces the root queueing discipline of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn set_qdisc(&self, qdisc: Qdisc) -> Result<()> {
+        Ok(Netlink::new()?.set_qdisc(self.index()?, qdisc)?)
+    }
+
+    /// Adds a route to `dest` through the interface.
+    #[cfg(feature = "netlink")]
+    pub fn add_route(&self, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+        Ok(Netlink::new()?.add_route(self.index()?, dest, opts)?)
+    }
+
+    /// Removes a route to `dest` through the interface.
+    #[cfg(feature = "netlink")]
+    pub fn del_route(&self, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+        Ok(Netlink::new()?.del_route(self.index()?, dest, opts)?)
+    }
+
+    /// Returns the number of queues which the device was attached with.
+    pub fn queues(&self) -> usize {
+        self.queues
+    }
+
+    /// Sets the number of queues of a device attached from the file descriptor of one of them.
+    pub fn set_queues(&mut self, queues: usize) {
+        self.queues = queues;
     }
 
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
 
+    pub fn tun_flags(&self) -> Result<i16> {
+        Ok(self.tun_flags)
+    }
+
+    pub fn is_tap(&self) -> Result<bool> {
+        // The type of a device never changes, so it is known since it was attached
+        Ok(self.tap)
+    }
+
+    /// Returns whether the kernel reports the device of the queue `fd` as a TAP device.
+    pub fn kernel_is_tap(&self, fd: BorrowedFd<'_>) -> Result<bool> {
+        let mut req = ifreq::new("");
+        unsafe { tungetiff(fd.as_raw_fd(), &mut req) }?;
+        Ok(unsafe { req.ifr_ifru.ifru_flags } as libc::c_int & libc::IFF_TAP != 0)
+    }
+
+    /// Returns the number of bytes added to each packet on top of the MTU.
+    pub fn overhead(&self) -> Result<usize> {
+        let flags = self.tun_flags()? as libc::c_int;
+        let mut overhead = 0;
+        if flags & libc::IFF_NO_PI == 0 {
+            overhead += 4;
+        }
+        overhead += self.vnet_hdr_size;
+        if flags & libc::IFF_TAP != 0 {
+            // Ethernet header including a VLAN tag
+            overhead += 18;
+        }
+        Ok(overhead)
+    }
+
     pub fn mtu(&self, mtu: Option<i32>) -> Result<i32> {
         let mut req = ifreq::new(self.name());
         if let Some(mtu) = mtu {
             req.ifr_ifru.ifru_mtu = mtu;
-            unsafe { siocsifmtu(self.socket, &req) }?;
+            unsafe { siocsifmtu(self.socket.as_raw_fd(), &req) }?;
         } else {
-            unsafe { siocgifmtu(self.socket, &mut req) }?;
+            unsafe { siocgifmtu(self.socket.as_raw_fd(), &mut req) }?;
         }
         Ok(unsafe { req.ifr_ifru.ifru_mtu })
     }
@@ -101,10 +283,10 @@ impl Interface {
         let mut req = ifreq::new(self.name());
         if let Some(netmask) = netmask {
             req.ifr_ifru.ifru_netmask = netmask.to_address();
-            unsafe { siocsifnetmask(self.socket, &req) }?;
+            unsafe { siocsifnetmask(self.socket.as_raw_fd(), &req) }?;
             return Ok(netmask);
         }
-        unsafe { siocgifnetmask(self.socket, &mut req) }?;
+        unsafe { siocgifnetmask(self.socket.as_raw_fd(), &mut req) }?;
         Ok(unsafe { Ipv4Addr::from_address(req.ifr_ifru.ifru_netmask) })
     }
 
@@ -112,10 +294,10 @@ impl Interface {
         let mut req = ifreq::new(self.name());
         if let Some(address) = address {
             req.ifr_ifru.ifru_addr = address.to_address();
-            unsafe { siocsifaddr(self.socket, &req) }?;
+            unsafe { siocsifaddr(self.socket.as_raw_fd(), &req) }?;
             return Ok(address);
         }
-        unsafe { siocgifaddr(self.socket, &mut req) }?;
+        unsafe { siocgifaddr(self.socket.as_raw_fd(), &mut req) }?;
         Ok(unsafe { Ipv4Addr::from_address(req.ifr_ifru.ifru_addr) })
     }
 
@@ -123,10 +305,10 @@ impl Interface {
         let mut req = ifreq::new(self.name());
         if let Some(dst) = dst {
             req.ifr_ifru.ifru_dstaddr = dst.to_address();
-            unsafe { siocsifdstaddr(self.socket, &req) }?;
+            unsafe { siocsifdstaddr(self.socket.as_raw_fd(), &req) }?;
             return Ok(dst);
         }
-        unsafe { siocgifdstaddr(self.socket, &mut req) }?;
+        unsafe { siocgifdstaddr(self.socket.as_raw_fd(), &mut req) }?;
         Ok(unsafe { Ipv4Addr::from_address(req.ifr_ifru.ifru_dstaddr) })
     }
 
@@ -134,47 +316,125 @@ impl Interface {
         let mut req = ifreq::new(self.name());
         if let Some(broadcast) = broadcast {
             req.ifr_ifru.ifru_broadaddr = broadcast.to_address();
-            unsafe { siocsifbrdaddr(self.socket, &req) }?;
+            unsafe { siocsifbrdaddr(self.socket.as_raw_fd(), &req) }?;
             return Ok(broadcast);
         }
-        unsafe { siocgifbrdaddr(self.socket, &mut req) }?;
+        unsafe { siocgifbrdaddr(self.socket.as_raw_fd(), &mut req) }?;
         Ok(unsafe { Ipv4Addr::from_address(req.ifr_ifru.ifru_broadaddr) })
     }
 
     pub fn flags(&self, flags: Option<i16>) -> Result<i16> {
         let mut req = ifreq::new(self.name());
-        unsafe { siocgifflags(self.socket, &mut req) }?;
+        unsafe { siocgifflags(self.socket.as_raw_fd(), &mut req) }?;
         if let Some(flags) = flags {
             unsafe { req.ifr_ifru.ifru_flags |= flags };
-            unsafe { siocsifflags(self.socket, &req) }?;
+            unsafe { siocsifflags(self.socket.as_raw_fd(), &req) }?;
         }
         Ok(unsafe { req.ifr_ifru.ifru_flags })
     }
 
-    pub fn owner(&self, owner: i32) -> Result<()> {
-        for fd in self.fds.iter() {
-            unsafe { tunsetowner(*fd, owner as _) }?;
+    pub fn unset_flags(&self, flags: i16) -> Result<i16> {
+        let mut req = ifreq::new(self.name());
+        unsafe { siocgifflags(self.socket.as_raw_fd(), &mut req) }?;
+        unsafe { req.ifr_ifru.ifru_flags &= !flags };
+        unsafe { siocsifflags(self.socket.as_raw_fd(), &req) }?;
+        Ok(unsafe { req.ifr_ifru.ifru_flags })
+    }
+
+    pub fn owner(&self, fds: &[OwnedFd], owner: i32) -> Result<()> {
+        for fd in fds {
+            unsafe { tunsetowner(fd.as_raw_fd(), owner as _) }?;
+        }
+        Ok(())
+    }
+
+    pub fn group(&self, fds: &[OwnedFd], group: i32) -> Result<()> {
+        for fd in fds {
+            unsafe { tunsetgroup(fd.as_raw_fd(), group as _) }?;
         }
         Ok(())
     }
 
-    pub fn group(&self, group: i32) -> Result<()> {
-        for fd in self.fds.iter() {
-            unsafe { tunsetgroup(*fd, group as _) }?;
+    pub fn persist(&self, fds: &[OwnedFd]) -> Result<()> {
+        for fd in fds {
+            unsafe { tunsetpersist(fd.as_raw_fd(), 1) }?;
         }
         Ok(())
     }
 
-    pub fn persist(&self) -> Result<()> {
-        for fd in self.fds.iter() {
-            unsafe { tunsetpersist(*fd, 1) }?;
+    pub fn offload(&self, fds: &[OwnedFd]) -> Result<()> {
+        let offloads = libc::TUN_F_CSUM | libc::TUN_F_TSO4 | libc::TUN_F_TSO6 | libc::TUN_F_TSO_ECN;
+        for fd in fds {
+            unsafe { tunsetoffload(fd.as_raw_fd(), offloads as _) }?;
         }
         Ok(())
     }
+
+    /// Steers the packets of the device to its queues by CPU, using `fd`, one of its queues.
+    pub fn steer_by_cpu(&self, fd: BorrowedFd<'_>) -> Result<()> {
+        let mut prog = steering::load_cpu_program()?;
+        // The device holds its own reference to the program
+        let res = unsafe { tunsetsteeringebpf(fd.as_raw_fd(), &mut prog) };
+        unsafe { libc::close(prog) };
+        res?;
+        Ok(())
+    }
+
+    /// Attaches the queue `fd` to the device, or detaches it so that the device stops steering
+    /// packets to it.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn set_queue_attached(&self, fd: BorrowedFd<'_>, attached: bool) -> Result<()> {
+        let mut req = ifreq::new("");
+        let flags = if attached {
+            libc::IFF_ATTACH_QUEUE
+        } else {
+            libc::IFF_DETACH_QUEUE
+        };
+        req.ifr_ifru.ifru_flags = flags as i16;
+        unsafe { tunsetqueue(fd.as_raw_fd(), &req) }?;
+        Ok(())
+    }
+
+    pub fn vnet_hdr(&self) -> Result<bool> {
+        Ok(self.tun_flags()? as libc::c_int & libc::IFF_VNET_HDR != 0)
+    }
+
+    /// Sets the device down and clears its persistent flag using `fd`, which must be the last
+    /// open file descriptor of the device, as far as `options` tell.
+    pub fn shutdown(&self, fd: BorrowedFd<'_>, options: CloseOptions) -> Result<()> {
+        if options.down {
+            self.unset_flags(libc::IFF_UP as i16)?;
+        }
+        if options.clear_persist {
+            unsafe { tunsetpersist(fd.as_raw_fd(), 0) }?;
+        }
+        Ok(())
+    }
+
+    /// Closes the control socket, reporting the error which would be swallowed on drop.
+    pub fn close(self) -> Result<()> {
+        if unsafe { libc::close(self.socket.into_raw_fd()) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+/// Opens a socket to perform the ioctls of the interface on, which child processes such as QEMU
+/// do not inherit.
+fn control_socket() -> Result<OwnedFd> {
+    match unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) } {
+        fd if fd >= 0 => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+        _ => Err(std::io::Error::last_os_error().into()),
+    }
 }
 
-impl Drop for Interface {
-    fn drop(&mut self) {
-        unsafe { libc::close(self.socket) };
+/// Returns the index of the interface named `name`.
+#[cfg(any(feature = "netlink", feature = "tokio"))]
+pub fn index_of(name: &str) -> Result<u32> {
+    let name = std::ffi::CString::new(name).map_err(io::Error::other)?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error().into()),
+        index => Ok(index),
     }
 }
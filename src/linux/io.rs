@@ -1,24 +1,44 @@
-use std::convert::From;
+use crate::Error;
 use std::io::{self, IoSlice, Read, Write};
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 
-pub struct TunIo(RawFd);
+/// Represents a queue of a device, which owns its file descriptor.
+pub struct TunIo(OwnedFd);
 
-impl From<RawFd> for TunIo {
-    fn from(fd: RawFd) -> Self {
+impl From<OwnedFd> for TunIo {
+    fn from(fd: OwnedFd) -> Self {
         Self(fd)
     }
 }
 
 impl FromRawFd for TunIo {
     unsafe fn from_raw_fd(fd: RawFd) -> Self {
-        Self(fd)
+        Self(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
 
 impl AsRawFd for TunIo {
     fn as_raw_fd(&self) -> RawFd {
-        self.0
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for TunIo {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl AsFd for TunIo {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<TunIo> for OwnedFd {
+    fn from(io: TunIo) -> Self {
+        io.0
     }
 }
 
@@ -38,7 +58,7 @@ impl Write for TunIo {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let ret = unsafe { libc::fsync(self.0) };
+        let ret = unsafe { libc::fsync(self.as_raw_fd()) };
         if ret < 0 {
             return Err(io::Error::last_os_error());
         }
@@ -48,19 +68,24 @@ impl Write for TunIo {
 
 impl TunIo {
     pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let n = unsafe { libc::read(self.0, buf.as_ptr() as *mut _, buf.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
-        Ok(n as _)
+        Error::retry_io(|| unsafe {
+            libc::read(self.as_raw_fd(), buf.as_ptr() as *mut _, buf.len() as _)
+        })
+    }
+
+    /// Receives a packet into `buf`, which may be uninitialized. Returns the number of bytes
+    /// which are initialized at the start of `buf`.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn recv_uninit(&self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        Error::retry_io(|| unsafe {
+            libc::read(self.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len() as _)
+        })
     }
 
     pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
-        let n = unsafe { libc::write(self.0, buf.as_ptr() as *const _, buf.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
-        Ok(n as _)
+        Error::retry_io(|| unsafe {
+            libc::write(self.as_raw_fd(), buf.as_ptr() as *const _, buf.len() as _)
+        })
     }
 
     pub fn sendv(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
@@ -71,16 +96,16 @@ impl TunIo {
                 iov_len: buf.len() as _,
             })
             .collect::<Vec<_>>();
-        let n = unsafe { libc::writev(self.0, iov.as_ptr() as *const _, iov.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
-        Ok(n as _)
+        Error::retry_io(|| unsafe {
+            libc::writev(self.as_raw_fd(), iov.as_ptr() as *const _, iov.len() as _)
+        })
     }
-}
 
-impl Drop for TunIo {
-    fn drop(&mut self) {
-        unsafe { libc::close(self.0) };
+    /// Closes the file descriptor, reporting the error which would be swallowed on drop.
+    pub fn close(self) -> io::Result<()> {
+        if unsafe { libc::close(self.into_raw_fd()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,511 @@
+//! A minimal synchronous rtnetlink client, used instead of ioctls to configure the links,
+//! addresses, routes and neighbors of devices.
+
+use crate::Qdisc;
+use crate::RouteOptions;
+use ipnet::IpNet;
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+
+const NLM_F_REQUEST: u16 = 0x01;
+const NLM_F_ACK: u16 = 0x04;
+const NLM_F_REPLACE: u16 = 0x100;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_CREATE: u16 = 0x400;
+
+const IFLA_IFNAME: u16 = 3;
+const IFLA_MTU: u16 = 4;
+const IFLA_LINK: u16 = 5;
+const IFLA_MASTER: u16 = 10;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_INFO_DATA: u16 = 2;
+const IFLA_VLAN_ID: u16 = 1;
+const NLA_F_NESTED: u16 = 0x8000;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_BROADCAST: u16 = 4;
+
+const NDA_DST: u16 = 1;
+const NDA_LLADDR: u16 = 2;
+const NUD_PERMANENT: u16 = 0x80;
+
+const TCA_KIND: u16 = 1;
+const TC_H_ROOT: u32 = 0xffffffff;
+
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PRIORITY: u16 = 6;
+const RTA_TABLE: u16 = 15;
+
+const RT_TABLE_UNSPEC: u8 = 0;
+const RT_TABLE_MAIN: u32 = 254;
+const RTPROT_STATIC: u8 = 4;
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RT_SCOPE_LINK: u8 = 253;
+const RT_SCOPE_NOWHERE: u8 = 255;
+const RTN_UNICAST: u8 = 1;
+
+/// Represents a `NETLINK_ROUTE` socket.
+pub struct Netlink {
+    fd: RawFd,
+    seq: u32,
+}
+
+impl AsRawFd for Netlink {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Netlink {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl Netlink {
+    pub fn new() -> io::Result<Self> {
+        Self::open(0, 0)
+    }
+
+    /// Opens a non-blocking socket notified of the changes of the links and of their
+    /// addresses.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn monitor() -> io::Result<Self> {
+        let groups = libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR;
+        Self::open(groups as u32, libc::SOCK_NONBLOCK)
+    }
+
+    /// Opens a socket bound to the multicast `groups`, of socket flags `flags`.
+    fn open(groups: u32, flags: i32) -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | flags,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let netlink = Self { fd, seq: 0 };
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as _;
+        addr.nl_groups = groups;
+        if unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as _,
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(netlink)
+    }
+
+    /// Receives the pending notifications of a socket opened by [`monitor`](#method.monitor),
+    /// and returns `true` if one concerns link `index` or its addresses. Fails with
+    /// `io::ErrorKind::WouldBlock` if there is none.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn notified(&self, index: u32) -> io::Result<bool> {
+        let mut buf = vec![0u8; 8192];
+        let n = self.recv(&mut buf)?;
+        // Both `ifinfomsg` and `ifaddrmsg` hold the index of the link at offset 4
+        Ok(messages(&buf[..n]).any(|(ty, _, payload)| {
+            matches!(
+                ty,
+                libc::RTM_NEWLINK | libc::RTM_DELLINK | libc::RTM_NEWADDR | libc::RTM_DELADDR
+            ) && payload.get(4..8) == Some(&index.to_ne_bytes())
+        }))
+    }
+
+    /// Sets the MTU and the administrative state of link `index` at once.
+    pub fn set_link(&mut self, index: u32, mtu: Option<u32>, up: Option<bool>) -> io::Result<()> {
+        let (flags, change) = match up {
+            Some(true) => (libc::IFF_UP as u32, libc::IFF_UP as u32),
+            Some(false) => (0, libc::IFF_UP as u32),
+            None => (0, 0),
+        };
+        let mut msg = Message::new(libc::RTM_NEWLINK, 0).header(&ifinfomsg(index, flags, change));
+        if let Some(mtu) = mtu {
+            msg = msg.attr(IFLA_MTU, &mtu.to_ne_bytes());
+        }
+        self.request(msg)
+    }
+
+    /// Creates the 802.1Q VLAN link `name` of VLAN `id` on top of link `parent`.
+    pub fn add_vlan(&mut self, parent: u32, name: &str, id: u16) -> io::Result<()> {
+        let mut kind = b"vlan".to_vec();
+        kind.push(0);
+        let mut data = Vec::new();
+        push_attr(&mut data, IFLA_VLAN_ID, &id.to_ne_bytes());
+        let mut info = Vec::new();
+        push_attr(&mut info, IFLA_INFO_KIND, &kind);
+        push_attr(&mut info, IFLA_INFO_DATA | NLA_F_NESTED, &data);
+        let mut ifname = name.as_bytes().to_vec();
+        ifname.push(0);
+        let msg = Message::new(libc::RTM_NEWLINK, NLM_F_CREATE | NLM_F_EXCL)
+            .header(&ifinfomsg(0, 0, 0))
+            .attr(IFLA_IFNAME, &ifname)
+            .attr(IFLA_LINK, &parent.to_ne_bytes())
+            .attr(IFLA_LINKINFO | NLA_F_NESTED, &info);
+        self.request(msg)
+    }
+
+    /// Deletes link `index`.
+    pub fn del_link(&mut self, index: u32) -> io::Result<()> {
+        let msg = Message::new(libc::RTM_DELLINK, 0).header(&ifinfomsg(index, 0, 0));
+        self.request(msg)
+    }
+
+    /// Enslaves link `index` to link `master`, such as a bridge, or releases it if `master` is 0.
+    pub fn set_master(&mut self, index: u32, master: u32) -> io::Result<()> {
+        let msg = Message::new(libc::RTM_NEWLINK, 0)
+            .header(&ifinfomsg(index, 0, 0))
+            .attr(IFLA_MASTER, &master.to_ne_bytes());
+        self.request(msg)
+    }
+
+    /// Adds (or replaces) address `net` on link `index`.
+    pub fn add_address(&mut self, index: u32, net: IpNet) -> io::Result<()> {
+        let msg = address_message(libc::RTM_NEWADDR, NLM_F_CREATE | NLM_F_REPLACE, index, net);
+        self.request(msg)
+    }
+
+    /// Removes address `net` from link `index`.
+    pub fn del_address(&mut self, index: u32, net: IpNet) -> io::Result<()> {
+        self.request(address_message(libc::RTM_DELADDR, 0, index, net))
+    }
+
+    /// Adds a route to `dest` through link `index`.
+    pub fn add_route(&mut self, index: u32, dest: IpNet, opts: &RouteOptions) -> io::Result<()> {
+        let msg = route_message(
+            libc::RTM_NEWROUTE,
+            NLM_F_CREATE | NLM_F_EXCL,
+            index,
+            dest,
+            opts,
+        );
+        self.request(msg)
+    }
+
+    /// Removes the route to `dest` through link `index`.
+    pub fn del_route(&mut self, index: u32, dest: IpNet, opts: &RouteOptions) -> io::Result<()> {
+        self.request(route_message(libc::RTM_DELROUTE, 0, index, dest, opts))
+    }
+
+    /// Adds (or replaces) a permanent neighbor entry mapping `addr` to `mac` on link `index`.
+    pub fn add_neighbor(&mut self, index: u32, addr: IpAddr, mac: [u8; 6]) -> io::Result<()> {
+        let msg = Message::new(libc::RTM_NEWNEIGH, NLM_F_CREATE | NLM_F_REPLACE)
+            .header(&ndmsg(addr, index, NUD_PERMANENT))
+            .attr(NDA_DST, &octets(addr))
+            .attr(NDA_LLADDR, &mac);
+        self.request(msg)
+    }
+
+    /// Removes the neighbor entry of `addr` on link `index`.
+    pub fn del_neighbor(&mut self, index: u32, addr: IpAddr) -> io::Result<()> {
+        let msg = Message::new(libc::RTM_DELNEIGH, 0)
+            .header(&ndmsg(addr, index, 0))
+            .attr(NDA_DST, &octets(addr));
+        self.request(msg)
+    }
+
+    /// Replaces the root queueing discipline of link `index`.
+    pub fn set_qdisc(&mut self, index: u32, qdisc: Qdisc) -> io::Result<()> {
+        let mut tcmsg = [0u8; 20];
+        tcmsg[0] = libc::AF_UNSPEC as u8;
+        tcmsg[4..8].copy_from_slice(&index.to_ne_bytes());
+        tcmsg[12..16].copy_from_slice(&TC_H_ROOT.to_ne_bytes());
+        let mut kind = qdisc.kind().as_bytes().to_vec();
+        kind.push(0);
+        let msg = Message::new(libc::RTM_NEWQDISC, NLM_F_CREATE | NLM_F_REPLACE)
+            .header(&tcmsg)
+            .attr(TCA_KIND, &kind);
+        self.request(msg)
+    }
+
+    /// Looks the route to `dest` up, returning the index of its link and its gateway, if any.
+    pub fn get_route(&mut self, dest: IpAddr) -> io::Result<(u32, Option<IpAddr>)> {
+        let (family, len) = match dest {
+            IpAddr::V4(_) => (libc::AF_INET as u8, 32),
+            IpAddr::V6(_) => (libc::AF_INET6 as u8, 128),
+        };
+        let msg = Message::new(libc::RTM_GETROUTE, 0)
+            .header(&[family, len, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+            .attr(RTA_DST, &octets(dest));
+        let reply = self.query(msg, libc::RTM_NEWROUTE)?;
+        let (mut index, mut gateway) = (None, None);
+        for (ty, value) in attrs(reply.get(12..).unwrap_or_default()) {
+            match (ty, value.len()) {
+                (RTA_OIF, 4) => index = Some(u32::from_ne_bytes(value.try_into().unwrap())),
+                (RTA_GATEWAY, 4) => {
+                    gateway = Some(IpAddr::from(<[u8; 4]>::try_from(value).unwrap()))
+                }
+                (RTA_GATEWAY, 16) => {
+                    gateway = Some(IpAddr::from(<[u8; 16]>::try_from(value).unwrap()))
+                }
+                _ => {}
+            }
+        }
+        let index = index.ok_or_else(|| io::Error::from_raw_os_error(libc::ENETUNREACH))?;
+        Ok((index, gateway))
+    }
+
+    /// Sends a request and waits for its acknowledgement.
+    fn request(&mut self, msg: Message) -> io::Result<()> {
+        let seq = self.send(msg)?;
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let n = self.recv(&mut buf)?;
+            for (ty, msg_seq, payload) in messages(&buf[..n]) {
+                if msg_seq == seq && ty == NLMSG_ERROR {
+                    return ack(payload);
+                }
+            }
+        }
+    }
+
+    /// Sends a request and returns the payload of its reply of type `ty`.
+    fn query(&mut self, msg: Message, ty: u16) -> io::Result<Vec<u8>> {
+        let seq = self.send(msg)?;
+        let mut buf = vec![0u8; 8192];
+        let mut reply = None;
+        loop {
+            let n = self.recv(&mut buf)?;
+            for (msg_ty, msg_seq, payload) in messages(&buf[..n]) {
+                if msg_seq != seq {
+                    continue;
+                }
+                if msg_ty == ty && reply.is_none() {
+                    reply = Some(payload.to_vec());
+                } else if msg_ty == NLMSG_ERROR {
+                    ack(payload)?;
+                    return reply.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, msg: Message) -> io::Result<u32> {
+        self.seq = self.seq.wrapping_add(1);
+        let buf = msg.finish(self.seq);
+        let n = unsafe { libc::send(self.fd, buf.as_ptr() as *const _, buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(self.seq)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+}
+
+/// Represents a netlink message under construction.
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(ty: u16, flags: u16) -> Self {
+        let mut buf = vec![0u8; NLMSG_HDRLEN];
+        buf[4..6].copy_from_slice(&ty.to_ne_bytes());
+        buf[6..8].copy_from_slice(&(NLM_F_REQUEST | NLM_F_ACK | flags).to_ne_bytes());
+        Self { buf }
+    }
+
+    /// Appends the fixed header of the message family.
+    fn header(mut self, header: &[u8]) -> Self {
+        self.buf.extend_from_slice(header);
+        self.align();
+        self
+    }
+
+    /// Appends an attribute.
+    fn attr(mut self, ty: u16, value: &[u8]) -> Self {
+        push_attr(&mut self.buf, ty, value);
+        self
+    }
+
+    fn align(&mut self) {
+        self.buf.resize(self.buf.len().next_multiple_of(4), 0);
+    }
+
+    fn finish(mut self, seq: u32) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// Appends an attribute to `buf`, such as the payload of a nested attribute.
+fn push_attr(buf: &mut Vec<u8>, ty: u16, value: &[u8]) {
+    buf.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+    buf.extend_from_slice(&ty.to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// Iterates over the (type, sequence, payload) of the messages in `buf`.
+fn messages(mut buf: &[u8]) -> impl Iterator<Item = (u16, u32, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < NLMSG_HDRLEN {
+            return None;
+        }
+        let len = u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return None;
+        }
+        let ty = u16::from_ne_bytes(buf[4..6].try_into().unwrap());
+        let seq = u32::from_ne_bytes(buf[8..12].try_into().unwrap());
+        let payload = &buf[NLMSG_HDRLEN..len];
+        buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+        Some((ty, seq, payload))
+    })
+}
+
+/// Iterates over the (type, value) of the attributes in `buf`.
+fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes(buf[0..2].try_into().unwrap()) as usize;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let ty = u16::from_ne_bytes(buf[2..4].try_into().unwrap());
+        let value = &buf[4..len];
+        buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+        Some((ty, value))
+    })
+}
+
+/// Converts the payload of an `NLMSG_ERROR` message into a result.
+fn ack(payload: &[u8]) -> io::Result<()> {
+    let errno = payload
+        .get(0..4)
+        .map_or(0, |errno| i32::from_ne_bytes(errno.try_into().unwrap()));
+    if errno < 0 {
+        return Err(io::Error::from_raw_os_error(-errno));
+    }
+    Ok(())
+}
+
+fn ifinfomsg(index: u32, flags: u32, change: u32) -> [u8; 16] {
+    let mut msg = [0u8; 16];
+    msg[0] = libc::AF_UNSPEC as u8;
+    msg[4..8].copy_from_slice(&index.to_ne_bytes());
+    msg[8..12].copy_from_slice(&flags.to_ne_bytes());
+    msg[12..16].copy_from_slice(&change.to_ne_bytes());
+    msg
+}
+
+fn ifaddrmsg(family: u8, prefix_len: u8, index: u32) -> [u8; 8] {
+    let mut msg = [0u8; 8];
+    msg[0] = family;
+    msg[1] = prefix_len;
+    msg[4..8].copy_from_slice(&index.to_ne_bytes());
+    msg
+}
+
+fn ndmsg(addr: IpAddr, index: u32, state: u16) -> [u8; 12] {
+    let mut msg = [0u8; 12];
+    msg[0] = match addr {
+        IpAddr::V4(_) => libc::AF_INET,
+        IpAddr::V6(_) => libc::AF_INET6,
+    } as u8;
+    msg[4..8].copy_from_slice(&index.to_ne_bytes());
+    msg[8..10].copy_from_slice(&state.to_ne_bytes());
+    msg
+}
+
+fn address_message(ty: u16, flags: u16, index: u32, net: IpNet) -> Message {
+    let msg = Message::new(ty, flags);
+    match net {
+        IpNet::V4(net) => {
+            let octets = net.addr().octets();
+            let msg = msg
+                .header(&ifaddrmsg(libc::AF_INET as u8, net.prefix_len(), index))
+                .attr(IFA_LOCAL, &octets)
+                .attr(IFA_ADDRESS, &octets);
+            if net.prefix_len() < 31 {
+                msg.attr(IFA_BROADCAST, &net.broadcast().octets())
+            } else {
+                msg
+            }
+        }
+        IpNet::V6(net) => msg
+            .header(&ifaddrmsg(libc::AF_INET6 as u8, net.prefix_len(), index))
+            .attr(IFA_ADDRESS, &net.addr().octets()),
+    }
+}
+
+fn route_message(ty: u16, flags: u16, index: u32, dest: IpNet, opts: &RouteOptions) -> Message {
+    let dest = dest.trunc();
+    let family = match dest {
+        IpNet::V4(_) => libc::AF_INET,
+        IpNet::V6(_) => libc::AF_INET6,
+    } as u8;
+    let table = opts.table.unwrap_or(RT_TABLE_MAIN);
+    // Deletions match any protocol, scope and type, as `ip route del` does
+    let (protocol, scope, kind) = if ty == libc::RTM_DELROUTE {
+        (0, RT_SCOPE_NOWHERE, 0)
+    } else if opts.gateway.is_some() {
+        (RTPROT_STATIC, RT_SCOPE_UNIVERSE, RTN_UNICAST)
+    } else {
+        (RTPROT_STATIC, RT_SCOPE_LINK, RTN_UNICAST)
+    };
+    let rtmsg = [
+        family,
+        dest.prefix_len(),
+        0,
+        0,
+        u8::try_from(table).unwrap_or(RT_TABLE_UNSPEC),
+        protocol,
+        scope,
+        kind,
+        0,
+        0,
+        0,
+        0,
+    ];
+    let mut msg = Message::new(ty, flags)
+        .header(&rtmsg)
+        .attr(RTA_TABLE, &table.to_ne_bytes())
+        .attr(RTA_OIF, &index.to_ne_bytes());
+    if dest.prefix_len() > 0 {
+        msg = msg.attr(RTA_DST, &octets(dest.addr()));
+    }
+    if let Some(gateway) = opts.gateway {
+        msg = msg.attr(RTA_GATEWAY, &octets(gateway));
+    }
+    if let Some(metric) = opts.metric {
+        msg = msg.attr(RTA_PRIORITY, &metric.to_ne_bytes());
+    }
+    msg
+}
+
+/// Returns the raw bytes of an IP address.
+fn octets(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().to_vec(),
+        IpAddr::V6(addr) => addr.octets().to_vec(),
+    }
+}
@@ -1,4 +1,10 @@
+#[cfg(feature = "netlink")]
+use crate::Qdisc;
+#[cfg(feature = "netlink")]
+use ipnet::IpNet;
+use std::ffi::OsString;
 use std::net::Ipv4Addr;
+use std::time::Duration;
 
 /// Represents parameters for creating a new Tun/Tap device on Linux.
 #[cfg(target_os = "linux")]
@@ -6,7 +12,10 @@ pub struct Params {
     pub name: Option<String>,
     pub flags: i16,
     pub persist: bool,
-    pub up: bool,
+    pub offload: bool,
+    pub up: Option<bool>,
+    pub set_flags: i16,
+    pub unset_flags: i16,
     pub mtu: Option<i32>,
     pub owner: Option<i32>,
     pub group: Option<i32>,
@@ -14,4 +23,18 @@ pub struct Params {
     pub destination: Option<Ipv4Addr>,
     pub broadcast: Option<Ipv4Addr>,
     pub netmask: Option<Ipv4Addr>,
+    #[cfg(feature = "netlink")]
+    pub addresses: Vec<IpNet>,
+    #[cfg(feature = "netlink")]
+    pub qdisc: Option<Qdisc>,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub budget: usize,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub counters: bool,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub busy_poll: Option<Duration>,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub strict: bool,
+    pub auto_suffix: bool,
+    pub helper: Option<Vec<OsString>>,
 }
@@ -0,0 +1,71 @@
+use crate::Result;
+use std::io;
+use std::mem;
+
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_PROG_TYPE_SOCKET_FILTER: u32 = 1;
+const BPF_FUNC_GET_SMP_PROCESSOR_ID: i32 = 8;
+const BPF_JMP_CALL: u8 = 0x85;
+const BPF_JMP_EXIT: u8 = 0x95;
+
+#[repr(C)]
+struct BpfInsn {
+    code: u8,
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+/// Leading fields of `union bpf_attr` used by `BPF_PROG_LOAD`.
+#[repr(C)]
+struct BpfProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+}
+
+/// Loads an eBPF steering program which selects the queue matching the CPU that processes the
+/// packet (the kernel takes the result modulo the number of queues). Returns its file descriptor.
+pub fn load_cpu_program() -> Result<i32> {
+    let insns = [
+        // r0 = bpf_get_smp_processor_id()
+        BpfInsn {
+            code: BPF_JMP_CALL,
+            regs: 0,
+            off: 0,
+            imm: BPF_FUNC_GET_SMP_PROCESSOR_ID,
+        },
+        // return r0
+        BpfInsn {
+            code: BPF_JMP_EXIT,
+            regs: 0,
+            off: 0,
+            imm: 0,
+        },
+    ];
+    let license = b"GPL\0";
+    let mut attr: BpfProgLoadAttr = unsafe { mem::zeroed() };
+    attr.prog_type = BPF_PROG_TYPE_SOCKET_FILTER;
+    attr.insn_cnt = insns.len() as u32;
+    attr.insns = insns.as_ptr() as u64;
+    attr.license = license.as_ptr() as u64;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_PROG_LOAD,
+            &attr as *const _,
+            mem::size_of::<BpfProgLoadAttr>(),
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(fd as i32)
+}
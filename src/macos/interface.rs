@@ -1,12 +1,27 @@
 use super::params::Params;
 use super::request::ifreq;
+#[cfg(feature = "netlink")]
+use super::route;
+#[cfg(feature = "vmnet")]
+use super::vmnet::Vmnet;
+use crate::CloseOptions;
+use crate::Error;
 use crate::Result;
+#[cfg(feature = "netlink")]
+use crate::RouteOptions;
 use crate::macos::address::Ipv4AddrExt;
+#[cfg(feature = "netlink")]
+use ipnet::IpNet;
 use std::ffi::CString;
+#[cfg(any(feature = "netlink", feature = "tokio"))]
+use std::io;
 use std::mem;
 use std::net::Ipv4Addr;
 use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd};
 use std::ptr;
+#[cfg(feature = "vmnet")]
+use std::sync::Arc;
 
 // Constants for macOS system calls
 const CTLIOCGINFO: u64 = 0xc0644e03;
@@ -28,6 +43,17 @@ const SIOCGIFNETMASK: u64 = 0xc0206925;
 const SIOCGIFADDR: u64 = 0xc0206921;
 const SIOCGIFDSTADDR: u64 = 0xc0206922;
 
+#[cfg(feature = "netlink")]
+const SIOCAIFADDR: u64 = 0x8040691a;
+#[cfg(feature = "netlink")]
+const SIOCDIFADDR: u64 = 0x80206919;
+#[cfg(feature = "netlink")]
+const SIOCAIFADDR_IN6: u64 = 0x8080691a;
+#[cfg(feature = "netlink")]
+const SIOCDIFADDR_IN6: u64 = 0x81206919;
+#[cfg(feature = "netlink")]
+const ND6_INFINITE_LIFETIME: u32 = 0xffffffff;
+
 // Define the control info struct
 #[repr(C)]
 struct CtlInfo {
@@ -46,22 +72,96 @@ struct SockaddrCtl {
     sc_reserved: [u32; 5],
 }
 
-#[derive(Clone)]
+// Define the request structs to add and remove the (alias) addresses of an interface
+#[cfg(feature = "netlink")]
+#[repr(C)]
+struct InAliasReq {
+    ifra_name: [c_char; 16],
+    ifra_addr: libc::sockaddr_in,
+    ifra_broadaddr: libc::sockaddr_in,
+    ifra_mask: libc::sockaddr_in,
+}
+
+#[cfg(feature = "netlink")]
+#[repr(C)]
+struct In6AddrLifetime {
+    ia6t_expire: libc::time_t,
+    ia6t_preferred: libc::time_t,
+    ia6t_vltime: u32,
+    ia6t_pltime: u32,
+}
+
+#[cfg(feature = "netlink")]
+#[repr(C)]
+struct In6AliasReq {
+    ifra_name: [c_char; 16],
+    ifra_addr: libc::sockaddr_in6,
+    ifra_dstaddr: libc::sockaddr_in6,
+    ifra_prefixmask: libc::sockaddr_in6,
+    ifra_flags: c_int,
+    ifra_lifetime: In6AddrLifetime,
+}
+
+// Only the address of the union of `struct in6_ifreq` is used, the padding keeps its size
+#[cfg(feature = "netlink")]
+#[repr(C)]
+struct In6IfReq {
+    ifr_name: [c_char; 16],
+    ifr_addr: libc::sockaddr_in6,
+    _pad: [u8; 244],
+}
+
+/// Represents the configuration side of a device: the control socket of its ioctls, which it
+/// owns. The file descriptors of the queues are owned by the queues themselves.
 pub struct Interface {
-    fds: Vec<i32>,
-    socket: i32,
+    socket: OwnedFd,
     name: String,
+    queues: usize,
+    #[cfg(feature = "vmnet")]
+    vmnet: Option<Arc<Vmnet>>,
 }
 
 impl Interface {
-    pub fn new(fds: Vec<i32>, name: &str, _flags: i16) -> Result<Self> {
+    pub fn new(fds: &[OwnedFd], name: &str, _flags: i16) -> Result<Self> {
+        let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+        if socket < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
         Ok(Interface {
-            fds,
-            socket: unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) },
+            socket: unsafe { OwnedFd::from_raw_fd(socket) },
             name: name.to_owned(),
+            queues: fds.len(),
+            #[cfg(feature = "vmnet")]
+            vmnet: None,
         })
     }
 
+    /// Creates the Tap device backed by the vmnet interface `vmnet`, whose packets are carried by
+    /// a single queue. It has no network interface of its own on the host.
+    #[cfg(feature = "vmnet")]
+    pub fn with_vmnet(vmnet: Vmnet) -> Result<Self> {
+        let mut iface = Self::new(&[], "vmnet", 0)?;
+        iface.queues = 1;
+        iface.vmnet = Some(Arc::new(vmnet));
+        Ok(iface)
+    }
+
+    /// Returns the MTU of the vmnet interface which backs the device, if any.
+    fn vmnet_mtu(&self) -> Option<i32> {
+        #[cfg(feature = "vmnet")]
+        if let Some(vmnet) = &self.vmnet {
+            return Some(vmnet.mtu());
+        }
+        None
+    }
+
+    pub fn from_fd(fd: BorrowedFd<'_>) -> Result<Self> {
+        let name = Self::utun_name(fd.as_raw_fd())?;
+        let mut iface = Self::new(&[], &name, 0)?;
+        iface.queues = 1;
+        Ok(iface)
+    }
+
     pub fn init(&self, params: Params) -> Result<()> {
         if let Some(mtu) = params.mtu {
             self.mtu(Some(mtu))?;
@@ -78,52 +178,170 @@ impl Interface {
         if let Some(broadcast) = params.broadcast {
             self.broadcast(Some(broadcast))?;
         }
-        if params.up {
+        if params.set_flags != 0 {
+            self.flags(Some(params.set_flags))?;
+        }
+        if params.unset_flags != 0 {
+            self.unset_flags(params.unset_flags)?;
+        }
+        if params.up == Some(true) {
             self.flags(Some(libc::IFF_UP as i16 | libc::IFF_RUNNING as i16))?;
         }
 
-        // Handle owner and group for macOS
-        if let Some(owner) = params.owner {
-            // On macOS, we would typically use chown for the device
-            // but it's not directly available like in Linux
-            // Log this information instead
-            eprintln!("Note: Setting owner to {} is not supported on macOS", owner);
+        // The owner, group, persistence and offloads of the device have no equivalent on macOS,
+        // `TunBuilder::report` lists them
+        Ok(())
+    }
+
+    /// Returns the index of the interface.
+    #[cfg(any(feature = "netlink", feature = "tokio"))]
+    pub fn index(&self) -> Result<u32> {
+        let name = CString::new(self.name()).map_err(io::Error::other)?;
+        match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+            0 => Err(io::Error::last_os_error().into()),
+            index => Ok(index),
         }
+    }
+
+    /// Adds a route to `dest` through the interface. The metric and the table are ignored.
+    #[cfg(feature = "netlink")]
+    pub fn add_route(&self, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+        Ok(route::add(self.index()?, dest, opts)?)
+    }
+
+    /// Removes a route to `dest` through the interface.
+    #[cfg(feature = "netlink")]
+    pub fn del_route(&self, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+        Ok(route::delete(self.index()?, dest, opts)?)
+    }
 
-        if let Some(group) = params.group {
-            // Similarly for group
-            eprintln!("Note: Setting group to {} is not supported on macOS", group);
+    /// Adds an IPv4 or IPv6 (alias) address to the interface.
+    #[cfg(feature = "netlink")]
+    pub fn add_address(&self, net: IpNet) -> Result<()> {
+        match net {
+            IpNet::V4(net) => {
+                let mut req: InAliasReq = unsafe { mem::zeroed() };
+                req.ifra_name = self.ifr_name();
+                req.ifra_addr = sockaddr_in(net.addr());
+                // utun devices are point-to-point, the address is its own destination
+                req.ifra_broadaddr = sockaddr_in(net.addr());
+                req.ifra_mask = sockaddr_in(net.netmask());
+                if unsafe { libc::ioctl(self.socket.as_raw_fd(), SIOCAIFADDR, &req) } < 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+            }
+            IpNet::V6(net) => {
+                let mut req: In6AliasReq = unsafe { mem::zeroed() };
+                req.ifra_name = self.ifr_name();
+                req.ifra_addr = sockaddr_in6(net.addr());
+                req.ifra_prefixmask = sockaddr_in6(net.netmask());
+                req.ifra_lifetime.ia6t_vltime = ND6_INFINITE_LIFETIME;
+                req.ifra_lifetime.ia6t_pltime = ND6_INFINITE_LIFETIME;
+                self.ioctl_in6(SIOCAIFADDR_IN6, &req)?;
+            }
         }
+        Ok(())
+    }
 
-        // Handle persistence
-        if params.persist {
-            // macOS utun devices are persistent by default but log this info
-            eprintln!("Note: macOS utun devices are already persistent by default");
+    /// Removes an IPv4 or IPv6 (alias) address of the interface.
+    #[cfg(feature = "netlink")]
+    pub fn del_address(&self, net: IpNet) -> Result<()> {
+        match net {
+            IpNet::V4(net) => {
+                let mut req = ifreq::new(self.name());
+                req.ifr_ifru.ifru_addr = net.addr().to_address();
+                if unsafe { libc::ioctl(self.socket.as_raw_fd(), SIOCDIFADDR, &req) } < 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+            }
+            IpNet::V6(net) => {
+                let mut req: In6IfReq = unsafe { mem::zeroed() };
+                req.ifr_name = self.ifr_name();
+                req.ifr_addr = sockaddr_in6(net.addr());
+                self.ioctl_in6(SIOCDIFADDR_IN6, &req)?;
+            }
         }
+        Ok(())
+    }
 
+    /// Performs an IPv6 ioctl, which requires an `AF_INET6` socket.
+    #[cfg(feature = "netlink")]
+    fn ioctl_in6<T>(&self, request: u64, req: &T) -> Result<()> {
+        let socket = unsafe { libc::socket(libc::AF_INET6, libc::SOCK_DGRAM, 0) };
+        if socket < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let ret = unsafe { libc::ioctl(socket, request, req as *const T) };
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(socket) };
+        if ret < 0 {
+            return Err(err.into());
+        }
         Ok(())
     }
 
-    pub fn files(&self) -> &[i32] {
-        &self.fds
+    #[cfg(feature = "netlink")]
+    fn ifr_name(&self) -> [c_char; 16] {
+        let mut name = [0; 16];
+        for (dst, src) in name.iter_mut().zip(self.name().bytes().take(15)) {
+            *dst = src as c_char;
+        }
+        name
+    }
+
+    /// Returns the number of queues which the device was attached with.
+    pub fn queues(&self) -> usize {
+        self.queues
+    }
+
+    /// Sets the number of queues of a device attached from the file descriptor of one of them.
+    pub fn set_queues(&mut self, queues: usize) {
+        self.queues = queues;
     }
 
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
 
+    /// utun devices are always layer 3 devices, only vmnet interfaces are layer 2 ones.
+    pub fn is_tap(&self) -> Result<bool> {
+        Ok(self.vmnet_mtu().is_some())
+    }
+
+    /// utun devices do not support virtio-net headers.
+    pub fn vnet_hdr(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Returns the number of bytes added to each packet on top of the MTU. The 4-byte utun
+    /// header is stripped on receive, so there is none but the Ethernet header of vmnet
+    /// interfaces.
+    pub fn overhead(&self) -> Result<usize> {
+        Ok(if self.vmnet_mtu().is_some() { 14 } else { 0 })
+    }
+
     pub fn mtu(&self, mtu: Option<i32>) -> Result<i32> {
+        if let Some(vmnet_mtu) = self.vmnet_mtu() {
+            if mtu.is_some() {
+                // The MTU of vmnet interfaces is set by vmnet
+                return Err(Error::Unsupported {
+                    feature: "setting the MTU",
+                    platform: "vmnet",
+                });
+            }
+            return Ok(vmnet_mtu);
+        }
         let mut req = ifreq::new(self.name());
         if let Some(mtu) = mtu {
             req.ifr_ifru.ifru_mtu = mtu;
             unsafe {
-                if libc::ioctl(self.socket, SIOCSIFMTU, &req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFMTU, &req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
         } else {
             unsafe {
-                if libc::ioctl(self.socket, SIOCGIFMTU, &mut req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFMTU, &mut req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
@@ -136,14 +354,14 @@ impl Interface {
         if let Some(netmask) = netmask {
             req.ifr_ifru.ifru_netmask = netmask.to_address();
             unsafe {
-                if libc::ioctl(self.socket, SIOCSIFNETMASK, &req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFNETMASK, &req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
             return Ok(netmask);
         }
         unsafe {
-            if libc::ioctl(self.socket, SIOCGIFNETMASK, &mut req) < 0 {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFNETMASK, &mut req) < 0 {
                 return Err(std::io::Error::last_os_error().into());
             }
         }
@@ -155,14 +373,14 @@ impl Interface {
         if let Some(address) = address {
             req.ifr_ifru.ifru_addr = address.to_address();
             unsafe {
-                if libc::ioctl(self.socket, SIOCSIFADDR, &req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFADDR, &req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
             return Ok(address);
         }
         unsafe {
-            if libc::ioctl(self.socket, SIOCGIFADDR, &mut req) < 0 {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFADDR, &mut req) < 0 {
                 return Err(std::io::Error::last_os_error().into());
             }
         }
@@ -174,14 +392,14 @@ impl Interface {
         if let Some(dst) = dst {
             req.ifr_ifru.ifru_dstaddr = dst.to_address();
             unsafe {
-                if libc::ioctl(self.socket, SIOCSIFDSTADDR, &req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFDSTADDR, &req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
             return Ok(dst);
         }
         unsafe {
-            if libc::ioctl(self.socket, SIOCGIFDSTADDR, &mut req) < 0 {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFDSTADDR, &mut req) < 0 {
                 return Err(std::io::Error::last_os_error().into());
             }
         }
@@ -214,14 +432,14 @@ impl Interface {
     pub fn flags(&self, flags: Option<i16>) -> Result<i16> {
         let mut req = ifreq::new(self.name());
         unsafe {
-            if libc::ioctl(self.socket, SIOCGIFFLAGS, &mut req) < 0 {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFFLAGS, &mut req) < 0 {
                 return Err(std::io::Error::last_os_error().into());
             }
         }
         if let Some(flags) = flags {
             unsafe { req.ifr_ifru.ifru_flags |= flags };
             unsafe {
-                if libc::ioctl(self.socket, SIOCSIFFLAGS, &req) < 0 {
+                if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFFLAGS, &req) < 0 {
                     return Err(std::io::Error::last_os_error().into());
                 }
             }
@@ -229,12 +447,48 @@ impl Interface {
         Ok(unsafe { req.ifr_ifru.ifru_flags })
     }
 
+    pub fn unset_flags(&self, flags: i16) -> Result<i16> {
+        let mut req = ifreq::new(self.name());
+        unsafe {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCGIFFLAGS, &mut req) < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+        unsafe { req.ifr_ifru.ifru_flags &= !flags };
+        unsafe {
+            if libc::ioctl(self.socket.as_raw_fd(), SIOCSIFFLAGS, &req) < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+        Ok(unsafe { req.ifr_ifru.ifru_flags })
+    }
+
+    /// Sets the device down if `options` tell so. utun devices have no persistent flag to
+    /// clear, and vmnet interfaces are stopped once dropped.
+    pub fn shutdown(&self, _fd: BorrowedFd<'_>, options: CloseOptions) -> Result<()> {
+        if !options.down || self.vmnet_mtu().is_some() {
+            return Ok(());
+        }
+        self.unset_flags(libc::IFF_UP as i16)?;
+        Ok(())
+    }
+
+    /// Closes the control socket, reporting the error which would be swallowed on drop.
+    pub fn close(self) -> Result<()> {
+        if unsafe { libc::close(self.socket.into_raw_fd()) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
     // Create a new utun device
-    pub fn open_utun(unit: i32) -> Result<(i32, String)> {
+    pub fn open_utun(unit: i32) -> Result<(OwnedFd, String)> {
         let fd = unsafe { libc::socket(PF_SYSTEM, SOCK_DGRAM, SYSPROTO_CONTROL) };
         if fd < 0 {
             return Err(std::io::Error::last_os_error().into());
         }
+        // Closes the socket on the failures below
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
 
         let control_name = CString::new(UTUN_CONTROL_NAME).unwrap();
         let mut info: CtlInfo = unsafe { mem::zeroed() };
@@ -248,7 +502,6 @@ impl Interface {
         }
 
         if unsafe { libc::ioctl(fd, CTLIOCGINFO, &mut info as *mut _ as *mut c_void) } < 0 {
-            unsafe { libc::close(fd) };
             return Err(std::io::Error::last_os_error().into());
         }
 
@@ -267,11 +520,15 @@ impl Interface {
             )
         } < 0
         {
-            unsafe { libc::close(fd) };
             return Err(std::io::Error::last_os_error().into());
         }
 
-        // Get the interface name
+        let name = Self::utun_name(fd)?;
+        Ok((socket, name))
+    }
+
+    // Get the interface name of a connected utun control socket
+    fn utun_name(fd: i32) -> Result<String> {
         let mut name_buf = [0u8; 64];
         let mut name_len: libc::socklen_t = name_buf.len() as libc::socklen_t;
         let name_ptr = name_buf.as_mut_ptr() as *mut c_void;
@@ -286,18 +543,28 @@ impl Interface {
             )
         } < 0
         {
-            unsafe { libc::close(fd) };
             return Err(std::io::Error::last_os_error().into());
         }
 
         // Extract the interface name (null-terminated C string)
-        let name = String::from_utf8_lossy(&name_buf[..name_len as usize - 1]).to_string();
-        Ok((fd, name))
+        Ok(String::from_utf8_lossy(&name_buf[..name_len as usize - 1]).to_string())
     }
 }
 
-impl Drop for Interface {
-    fn drop(&mut self) {
-        unsafe { libc::close(self.socket) };
-    }
+#[cfg(feature = "netlink")]
+fn sockaddr_in(addr: Ipv4Addr) -> libc::sockaddr_in {
+    let mut sin: libc::sockaddr_in = unsafe { mem::zeroed() };
+    sin.sin_len = mem::size_of::<libc::sockaddr_in>() as _;
+    sin.sin_family = libc::AF_INET as _;
+    sin.sin_addr.s_addr = u32::from_ne_bytes(addr.octets());
+    sin
+}
+
+#[cfg(feature = "netlink")]
+fn sockaddr_in6(addr: std::net::Ipv6Addr) -> libc::sockaddr_in6 {
+    let mut sin6: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+    sin6.sin6_len = mem::size_of::<libc::sockaddr_in6>() as _;
+    sin6.sin6_family = libc::AF_INET6 as _;
+    sin6.sin6_addr.s6_addr = addr.octets();
+    sin6
 }
@@ -1,24 +1,45 @@
-use std::convert::From;
+use crate::Error;
+use crate::utun::{self, UTUN_HEADER_LEN};
 use std::io::{self, IoSlice, Read, Write};
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 
-pub struct TunIo(RawFd);
+/// Represents a queue of a device, which owns its file descriptor.
+pub struct TunIo(OwnedFd);
 
-impl From<RawFd> for TunIo {
-    fn from(fd: RawFd) -> Self {
+impl From<OwnedFd> for TunIo {
+    fn from(fd: OwnedFd) -> Self {
         Self(fd)
     }
 }
 
 impl FromRawFd for TunIo {
     unsafe fn from_raw_fd(fd: RawFd) -> Self {
-        Self(fd)
+        Self(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
 
 impl AsRawFd for TunIo {
     fn as_raw_fd(&self) -> RawFd {
-        self.0
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for TunIo {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl AsFd for TunIo {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<TunIo> for OwnedFd {
+    fn from(io: TunIo) -> Self {
+        io.0
     }
 }
 
@@ -38,7 +59,7 @@ impl Write for TunIo {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let ret = unsafe { libc::fsync(self.0) };
+        let ret = unsafe { libc::fsync(self.as_raw_fd()) };
         if ret < 0 {
             return Err(io::Error::last_os_error());
         }
@@ -48,67 +69,75 @@ impl Write for TunIo {
 
 impl TunIo {
     pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        // macOS utun adds a 4-byte header to each packet
-        // First 4 bytes are family type (AF_INET, AF_INET6)
-        let mut vec = vec![0u8; buf.len() + 4];
-        let n = unsafe { libc::read(self.0, vec.as_mut_ptr() as *mut _, vec.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
+        let frame = self.recv_frame(buf.len())?;
+        utun::strip_header(&frame, buf)
+    }
 
-        if n < 4 {
-            return Ok(0);
-        }
+    /// Receives a packet into `buf`, which may be uninitialized. Returns the number of bytes
+    /// which are initialized at the start of `buf`.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub fn recv_uninit(&self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        let frame = self.recv_frame(buf.len())?;
+        let packet = utun::packet(&frame, buf.len())?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(packet.as_ptr(), buf.as_mut_ptr().cast(), packet.len())
+        };
+        Ok(packet.len())
+    }
 
-        let data_size = n as usize - 4;
-        buf[..data_size].copy_from_slice(&vec[4..n as usize]);
-        Ok(data_size)
+    /// Reads a frame holding a packet of up to `len` bytes, along with its header.
+    fn recv_frame(&self, len: usize) -> io::Result<Vec<u8>> {
+        // macOS utun adds a 4-byte header to each packet
+        // First 4 bytes are family type (AF_INET, AF_INET6)
+        // The kernel truncates packets which do not fit, as it does on Linux
+        let mut frame = vec![0u8; len + UTUN_HEADER_LEN];
+        let n = Error::retry_io(|| unsafe {
+            libc::read(
+                self.as_raw_fd(),
+                frame.as_mut_ptr() as *mut _,
+                frame.len() as _,
+            )
+        })?;
+        frame.truncate(n);
+        Ok(frame)
     }
 
     pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
-        // Prepend 4-byte header
-        // For IPv4, the value is 2 (AF_INET) in network byte order
-        let mut vec = vec![0, 0, 0, 2];
+        // Prepend the 4-byte header, the address family of the packet in network byte order
+        let mut vec = utun::header(buf).to_vec();
         vec.extend_from_slice(buf);
 
-        let n = unsafe { libc::write(self.0, vec.as_ptr() as *const _, vec.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
-        if n <= 4 {
-            return Ok(0);
-        }
+        let n = Error::retry_io(|| unsafe {
+            libc::write(self.as_raw_fd(), vec.as_ptr() as *const _, vec.len() as _)
+        })?;
 
-        Ok(n as usize - 4)
+        Ok(n.saturating_sub(UTUN_HEADER_LEN))
     }
 
     pub fn sendv(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
         // For the macOS implementation, we need to handle the 4-byte header
         // Since we can't easily modify IoSlice, we'll convert to a continuous buffer
         let mut data = Vec::new();
-        // Add the 4-byte protocol header (AF_INET = 2 in network byte order)
-        data.extend_from_slice(&[0, 0, 0, 2]);
+        // Add the 4-byte header, the address family of the packet in network byte order
+        let first = bufs.iter().find(|buf| !buf.is_empty());
+        data.extend_from_slice(&utun::header(first.map_or(&[], |buf| &buf[..])));
 
         for buf in bufs {
             data.extend_from_slice(buf);
         }
 
-        let n = unsafe { libc::write(self.0, data.as_ptr() as *const _, data.len() as _) };
-        if n < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
-        if n <= 4 {
-            return Ok(0);
-        }
+        let n = Error::retry_io(|| unsafe {
+            libc::write(self.as_raw_fd(), data.as_ptr() as *const _, data.len() as _)
+        })?;
 
-        Ok(n as usize - 4)
+        Ok(n.saturating_sub(UTUN_HEADER_LEN))
     }
-}
 
-impl Drop for TunIo {
-    fn drop(&mut self) {
-        unsafe { libc::close(self.0) };
+    /// Closes the file descriptor, reporting the error which would be swallowed on drop.
+    pub fn close(self) -> io::Result<()> {
+        if unsafe { libc::close(self.into_raw_fd()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
     }
 }
@@ -1,17 +1,32 @@
+#[cfg(feature = "vmnet")]
+use super::vmnet::VmnetMode;
+use std::ffi::OsString;
 use std::net::Ipv4Addr;
+use std::time::Duration;
 
 /// Represents parameters for creating a new Tun/Tap device on macOS.
 #[cfg(target_os = "macos")]
 pub struct Params {
     pub name: Option<String>,
     pub flags: i16,
-    pub persist: bool,
-    pub up: bool,
+    pub up: Option<bool>,
+    pub set_flags: i16,
+    pub unset_flags: i16,
     pub mtu: Option<i32>,
-    pub owner: Option<i32>,
-    pub group: Option<i32>,
     pub address: Option<Ipv4Addr>,
     pub destination: Option<Ipv4Addr>,
     pub broadcast: Option<Ipv4Addr>,
     pub netmask: Option<Ipv4Addr>,
+    #[cfg(feature = "vmnet")]
+    pub vmnet: Option<VmnetMode>,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub budget: usize,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub counters: bool,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub busy_poll: Option<Duration>,
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub strict: bool,
+    pub auto_suffix: bool,
+    pub helper: Option<Vec<OsString>>,
 }
@@ -0,0 +1,181 @@
+//! Routing table changes through a `PF_ROUTE` socket.
+
+use crate::RouteOptions;
+use ipnet::IpNet;
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+use std::ptr;
+use std::slice;
+
+/// Adds a route to `dest` through interface `index`.
+pub fn add(index: u32, dest: IpNet, opts: &RouteOptions) -> io::Result<()> {
+    change(libc::RTM_ADD, index, dest, opts)
+}
+
+/// Removes the route to `dest` through interface `index`.
+pub fn delete(index: u32, dest: IpNet, opts: &RouteOptions) -> io::Result<()> {
+    change(libc::RTM_DELETE, index, dest, opts)
+}
+
+/// Looks the route to `dest` up, returning the index of its interface and its gateway, if any.
+pub fn get(dest: IpAddr) -> io::Result<(u32, Option<IpAddr>)> {
+    let mut hdr: libc::rt_msghdr = unsafe { mem::zeroed() };
+    hdr.rtm_version = libc::RTM_VERSION as _;
+    hdr.rtm_type = libc::RTM_GET as _;
+    hdr.rtm_flags = libc::RTF_UP | libc::RTF_HOST;
+    hdr.rtm_addrs = libc::RTA_DST;
+    hdr.rtm_seq = 1;
+
+    let mut msg = bytes(&hdr).to_vec();
+    push_addr(&mut msg, dest);
+    let len = msg.len() as u16;
+    msg[0..2].copy_from_slice(&len.to_ne_bytes());
+
+    let fd = unsafe { libc::socket(libc::PF_ROUTE, libc::SOCK_RAW, libc::AF_UNSPEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let res = request(fd, &msg);
+    unsafe { libc::close(fd) };
+    let reply = res?;
+
+    // The reply holds the header, followed by the socket addresses set in `rtm_addrs`
+    let hdr: libc::rt_msghdr = unsafe { ptr::read_unaligned(reply.as_ptr() as *const _) };
+    let mut addrs = &reply[mem::size_of::<libc::rt_msghdr>().min(reply.len())..];
+    let mut gateway = None;
+    for bit in [libc::RTA_DST, libc::RTA_GATEWAY] {
+        if hdr.rtm_addrs & bit == 0 || addrs.len() < 2 {
+            continue;
+        }
+        let len = (addrs[0] as usize)
+            .max(1)
+            .next_multiple_of(4)
+            .min(addrs.len());
+        if bit == libc::RTA_GATEWAY && hdr.rtm_flags & libc::RTF_GATEWAY != 0 {
+            gateway = parse_addr(&addrs[..len]);
+        }
+        addrs = &addrs[len..];
+    }
+    Ok((hdr.rtm_index as u32, gateway))
+}
+
+/// Writes `msg` to the routing socket `fd` and returns its reply.
+fn request(fd: libc::c_int, msg: &[u8]) -> io::Result<Vec<u8>> {
+    if unsafe { libc::write(fd, msg.as_ptr() as *const _, msg.len()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let pid = unsafe { libc::getpid() };
+    let mut buf = vec![0u8; 2048];
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let n = n as usize;
+        if n < mem::size_of::<libc::rt_msghdr>() {
+            continue;
+        }
+        let hdr: libc::rt_msghdr = unsafe { ptr::read_unaligned(buf.as_ptr() as *const _) };
+        if hdr.rtm_pid != pid || hdr.rtm_seq != 1 || hdr.rtm_type != libc::RTM_GET as u8 {
+            continue;
+        }
+        if hdr.rtm_errno != 0 {
+            return Err(io::Error::from_raw_os_error(hdr.rtm_errno));
+        }
+        buf.truncate(n);
+        return Ok(buf);
+    }
+}
+
+/// Parses the IP address of a socket address, if any.
+fn parse_addr(addr: &[u8]) -> Option<IpAddr> {
+    match *addr.get(1)? as libc::c_int {
+        libc::AF_INET if addr.len() >= 8 => {
+            Some(IpAddr::from(<[u8; 4]>::try_from(&addr[4..8]).unwrap()))
+        }
+        libc::AF_INET6 if addr.len() >= 24 => {
+            Some(IpAddr::from(<[u8; 16]>::try_from(&addr[8..24]).unwrap()))
+        }
+        _ => None,
+    }
+}
+
+fn change(ty: libc::c_int, index: u32, dest: IpNet, opts: &RouteOptions) -> io::Result<()> {
+    let dest = dest.trunc();
+    let mut flags = libc::RTF_UP | libc::RTF_STATIC;
+    if opts.gateway.is_some() {
+        flags |= libc::RTF_GATEWAY;
+    }
+    if dest.prefix_len() == dest.max_prefix_len() {
+        flags |= libc::RTF_HOST;
+    }
+
+    let mut hdr: libc::rt_msghdr = unsafe { mem::zeroed() };
+    hdr.rtm_version = libc::RTM_VERSION as _;
+    hdr.rtm_type = ty as _;
+    hdr.rtm_index = index as _;
+    hdr.rtm_flags = flags;
+    hdr.rtm_addrs = libc::RTA_DST | libc::RTA_GATEWAY | libc::RTA_NETMASK;
+    hdr.rtm_seq = 1;
+
+    let mut msg = bytes(&hdr).to_vec();
+    push_addr(&mut msg, dest.addr());
+    match opts.gateway {
+        Some(gateway) => push_addr(&mut msg, gateway),
+        None => {
+            // Routes without a gateway point at the link of the interface
+            let mut link: libc::sockaddr_dl = unsafe { mem::zeroed() };
+            link.sdl_len = mem::size_of::<libc::sockaddr_dl>() as _;
+            link.sdl_family = libc::AF_LINK as _;
+            link.sdl_index = index as _;
+            push(&mut msg, bytes(&link));
+        }
+    }
+    push_addr(&mut msg, dest.netmask());
+    let len = msg.len() as u16;
+    msg[0..2].copy_from_slice(&len.to_ne_bytes());
+
+    let fd = unsafe { libc::socket(libc::PF_ROUTE, libc::SOCK_RAW, libc::AF_UNSPEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let n = unsafe { libc::write(fd, msg.as_ptr() as *const _, msg.len()) };
+    let res = if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    unsafe { libc::close(fd) };
+    res
+}
+
+/// Appends the socket address of `addr` to `msg`.
+fn push_addr(msg: &mut Vec<u8>, addr: IpAddr) {
+    match addr {
+        IpAddr::V4(addr) => {
+            let mut sin: libc::sockaddr_in = unsafe { mem::zeroed() };
+            sin.sin_len = mem::size_of::<libc::sockaddr_in>() as _;
+            sin.sin_family = libc::AF_INET as _;
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.octets());
+            push(msg, bytes(&sin));
+        }
+        IpAddr::V6(addr) => {
+            let mut sin6: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            sin6.sin6_len = mem::size_of::<libc::sockaddr_in6>() as _;
+            sin6.sin6_family = libc::AF_INET6 as _;
+            sin6.sin6_addr.s6_addr = addr.octets();
+            push(msg, bytes(&sin6));
+        }
+    }
+}
+
+/// Appends a socket address to `msg`, padded to a multiple of 4 bytes.
+fn push(msg: &mut Vec<u8>, addr: &[u8]) {
+    msg.extend_from_slice(addr);
+    msg.resize(msg.len().next_multiple_of(4), 0);
+}
+
+fn bytes<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
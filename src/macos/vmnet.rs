@@ -0,0 +1,345 @@
+use crate::Result;
+use block2::{Block, RcBlock};
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::{Arc, mpsc};
+use std::thread::{self, JoinHandle};
+
+type InterfaceRef = *mut c_void;
+type XpcObject = *mut c_void;
+type DispatchQueue = *mut c_void;
+
+const VMNET_SUCCESS: u32 = 1000;
+const VMNET_INVALID_ACCESS: u32 = 1005;
+
+const VMNET_HOST_MODE: u64 = 1000;
+const VMNET_SHARED_MODE: u64 = 1001;
+const VMNET_BRIDGED_MODE: u64 = 1002;
+
+const VMNET_INTERFACE_PACKETS_AVAILABLE: u32 = 1 << 0;
+
+/// Maximum number of packets read from the interface at once.
+const READ_BATCH: usize = 32;
+
+// Header of each packet on the socket pair, which mimics the one of utun devices so that the
+// packets can be read and written like those of a utun device
+const HEADER: [u8; 4] = [0, 0, 0, 2];
+
+#[repr(C)]
+struct VmPktDesc {
+    vm_pkt_size: usize,
+    vm_pkt_iov: *mut libc::iovec,
+    vm_pkt_iovcnt: u32,
+    vm_flags: u32,
+}
+
+#[link(name = "vmnet", kind = "framework")]
+unsafe extern "C" {
+    static vmnet_operation_mode_key: *const c_char;
+    static vmnet_shared_interface_name_key: *const c_char;
+    static vmnet_mtu_key: *const c_char;
+    static vmnet_max_packet_size_key: *const c_char;
+
+    fn vmnet_start_interface(
+        desc: XpcObject,
+        queue: DispatchQueue,
+        handler: &Block<dyn Fn(u32, XpcObject)>,
+    ) -> InterfaceRef;
+    fn vmnet_stop_interface(
+        iface: InterfaceRef,
+        queue: DispatchQueue,
+        handler: &Block<dyn Fn(u32)>,
+    ) -> u32;
+    fn vmnet_interface_set_event_callback(
+        iface: InterfaceRef,
+        mask: u32,
+        queue: DispatchQueue,
+        callback: Option<&Block<dyn Fn(u32, XpcObject)>>,
+    ) -> u32;
+    fn vmnet_read(iface: InterfaceRef, packets: *mut VmPktDesc, count: *mut c_int) -> u32;
+    fn vmnet_write(iface: InterfaceRef, packets: *mut VmPktDesc, count: *mut c_int) -> u32;
+}
+
+unsafe extern "C" {
+    fn xpc_dictionary_create(
+        keys: *const *const c_char,
+        values: *const XpcObject,
+        count: usize,
+    ) -> XpcObject;
+    fn xpc_dictionary_set_uint64(dict: XpcObject, key: *const c_char, value: u64);
+    fn xpc_dictionary_set_string(dict: XpcObject, key: *const c_char, value: *const c_char);
+    fn xpc_dictionary_get_uint64(dict: XpcObject, key: *const c_char) -> u64;
+    fn xpc_release(object: XpcObject);
+    fn dispatch_queue_create(label: *const c_char, attr: *mut c_void) -> DispatchQueue;
+    fn dispatch_release(object: DispatchQueue);
+}
+
+/// Represents the mode of a vmnet interface, which backs Tap devices on macOS (feature
+/// `vmnet`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum VmnetMode {
+    /// The interface reaches the network of the host through NAT, and is given an address by the
+    /// DHCP server of vmnet.
+    #[default]
+    Shared,
+    /// The interface only reaches the host and the other interfaces in host mode.
+    Host,
+    /// The interface is bridged to the physical interface of the given name, e.g. `en0`.
+    Bridged(String),
+}
+
+/// Returns the error of the vmnet status `status`.
+fn error(status: u32) -> io::Error {
+    match status {
+        // Creating vmnet interfaces requires root or the `com.apple.vm.networking` entitlement
+        VMNET_INVALID_ACCESS => io::Error::from(io::ErrorKind::PermissionDenied),
+        _ => io::Error::other(format!("vmnet failed with status {status}")),
+    }
+}
+
+/// Represents the handles of a started vmnet interface, shared by its event callback and the
+/// thread writing its packets.
+struct Handle {
+    iface: InterfaceRef,
+    queue: DispatchQueue,
+    /// End of the socket pair which is not given to the device, closed once the last callback
+    /// of vmnet which forwards packets to it is gone.
+    fd: OwnedFd,
+    max_packet_size: usize,
+}
+
+// The interface and the queue are thread-safe objects of vmnet and libdispatch
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Handle {
+    /// Moves the packets available on the interface to the socket pair. Packets which do not fit
+    /// in the socket buffer are dropped, as a network interface would.
+    fn forward_available(&self) {
+        let stride = HEADER.len() + self.max_packet_size;
+        let mut buf = vec![0; READ_BATCH * stride];
+        let mut iovs: Vec<libc::iovec> = Vec::with_capacity(READ_BATCH);
+        let mut descs: Vec<VmPktDesc> = Vec::with_capacity(READ_BATCH);
+        loop {
+            iovs.clear();
+            descs.clear();
+            for packet in buf.chunks_exact_mut(stride) {
+                packet[..HEADER.len()].copy_from_slice(&HEADER);
+                iovs.push(libc::iovec {
+                    iov_base: packet[HEADER.len()..].as_mut_ptr().cast(),
+                    iov_len: self.max_packet_size,
+                });
+            }
+            for iov in &mut iovs {
+                descs.push(VmPktDesc {
+                    vm_pkt_size: self.max_packet_size,
+                    vm_pkt_iov: iov,
+                    vm_pkt_iovcnt: 1,
+                    vm_flags: 0,
+                });
+            }
+            let mut count = READ_BATCH as c_int;
+            let status = unsafe { vmnet_read(self.iface, descs.as_mut_ptr(), &mut count) };
+            if status != VMNET_SUCCESS || count <= 0 {
+                return;
+            }
+            for (desc, packet) in descs
+                .iter()
+                .zip(buf.chunks_exact(stride))
+                .take(count as usize)
+            {
+                let len = HEADER.len() + desc.vm_pkt_size;
+                unsafe {
+                    libc::send(
+                        self.fd.as_raw_fd(),
+                        packet.as_ptr().cast(),
+                        len,
+                        libc::MSG_DONTWAIT,
+                    )
+                };
+            }
+            if (count as usize) < READ_BATCH {
+                return;
+            }
+        }
+    }
+
+    /// Moves the packets written to the socket pair to the interface, until the device closes
+    /// its end.
+    fn forward_written(&self) {
+        let mut buf = vec![0u8; HEADER.len() + self.max_packet_size];
+        loop {
+            let n =
+                unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n <= 0 {
+                if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            let n = n as usize;
+            if n <= HEADER.len() {
+                continue;
+            }
+            let mut iov = libc::iovec {
+                iov_base: buf[HEADER.len()..].as_mut_ptr().cast(),
+                iov_len: n - HEADER.len(),
+            };
+            let mut desc = VmPktDesc {
+                vm_pkt_size: n - HEADER.len(),
+                vm_pkt_iov: &mut iov,
+                vm_pkt_iovcnt: 1,
+                vm_flags: 0,
+            };
+            let mut count = 1;
+            unsafe { vmnet_write(self.iface, &mut desc, &mut count) };
+        }
+    }
+}
+
+/// Represents a started vmnet interface, whose packets are exchanged through a socket pair.
+pub struct Vmnet {
+    handle: Arc<Handle>,
+    mtu: i32,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Vmnet {
+    /// Starts a vmnet interface in mode `mode`. Returns it, along with the end of the socket
+    /// pair which carries its packets.
+    pub fn start(mode: &VmnetMode) -> Result<(Self, OwnedFd)> {
+        let bridged = match mode {
+            VmnetMode::Bridged(name) => {
+                Some(CString::new(name.as_str()).map_err(io::Error::other)?)
+            }
+            _ => None,
+        };
+        let desc = unsafe { xpc_dictionary_create(ptr::null(), ptr::null(), 0) };
+        let mode = match mode {
+            VmnetMode::Shared => VMNET_SHARED_MODE,
+            VmnetMode::Host => VMNET_HOST_MODE,
+            VmnetMode::Bridged(_) => VMNET_BRIDGED_MODE,
+        };
+        unsafe { xpc_dictionary_set_uint64(desc, vmnet_operation_mode_key, mode) };
+        if let Some(bridged) = &bridged {
+            unsafe {
+                xpc_dictionary_set_string(desc, vmnet_shared_interface_name_key, bridged.as_ptr())
+            };
+        }
+
+        let label = c"tokio-tun.vmnet";
+        let queue = unsafe { dispatch_queue_create(label.as_ptr(), ptr::null_mut()) };
+        let (tx, rx) = mpsc::channel();
+        let started = RcBlock::new(move |status: u32, params: XpcObject| {
+            // The parameters are only valid during the call
+            let res = if status == VMNET_SUCCESS {
+                Ok(unsafe {
+                    (
+                        xpc_dictionary_get_uint64(params, vmnet_mtu_key),
+                        xpc_dictionary_get_uint64(params, vmnet_max_packet_size_key),
+                    )
+                })
+            } else {
+                Err(status)
+            };
+            let _ = tx.send(res);
+        });
+        let iface = unsafe { vmnet_start_interface(desc, queue, &started) };
+        unsafe { xpc_release(desc) };
+        let started = if iface.is_null() {
+            Err(io::Error::other("vmnet could not start the interface"))
+        } else {
+            rx.recv()
+                .map_err(io::Error::other)
+                .and_then(|res| res.map_err(error))
+        };
+        let (mtu, max_packet_size) = match started {
+            Ok(res) => res,
+            Err(err) => {
+                unsafe { dispatch_release(queue) };
+                return Err(err.into());
+            }
+        };
+
+        let mut fds = [0; 2];
+        if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) } < 0 {
+            let err = io::Error::last_os_error();
+            stop(iface, queue);
+            return Err(err.into());
+        }
+        let (device, fd) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let handle = Arc::new(Handle {
+            iface,
+            queue,
+            fd,
+            max_packet_size: max_packet_size as usize,
+        });
+        // From now on, dropping `vmnet` stops the interface
+        let mut vmnet = Self {
+            handle: handle.clone(),
+            mtu: mtu as i32,
+            writer: None,
+        };
+        let available = handle.clone();
+        let callback = RcBlock::new(move |_event: u32, _params: XpcObject| {
+            available.forward_available();
+        });
+        let status = unsafe {
+            vmnet_interface_set_event_callback(
+                iface,
+                VMNET_INTERFACE_PACKETS_AVAILABLE,
+                queue,
+                Some(&callback),
+            )
+        };
+        if status != VMNET_SUCCESS {
+            return Err(error(status).into());
+        }
+        match thread::Builder::new()
+            .name("tokio-tun-vmnet".into())
+            .spawn(move || handle.forward_written())
+        {
+            Ok(writer) => vmnet.writer = Some(writer),
+            Err(err) => return Err(err.into()),
+        }
+        Ok((vmnet, device))
+    }
+
+    /// Returns the MTU of the interface.
+    pub fn mtu(&self) -> i32 {
+        self.mtu
+    }
+}
+
+/// Stops the interface `iface`, waiting for vmnet to release it, and releases `queue`.
+fn stop(iface: InterfaceRef, queue: DispatchQueue) {
+    let (tx, rx) = mpsc::channel();
+    let stopped = RcBlock::new(move |_status: u32| {
+        let _ = tx.send(());
+    });
+    unsafe {
+        vmnet_interface_set_event_callback(iface, VMNET_INTERFACE_PACKETS_AVAILABLE, queue, None);
+        if vmnet_stop_interface(iface, queue, &stopped) == VMNET_SUCCESS {
+            let _ = rx.recv();
+        }
+        dispatch_release(queue);
+    }
+}
+
+impl Drop for Vmnet {
+    fn drop(&mut self) {
+        // Wakes the writing thread up, in case the device still holds its end
+        unsafe { libc::shutdown(self.handle.fd.as_raw_fd(), libc::SHUT_RDWR) };
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        stop(self.handle.iface, self.handle.queue);
+    }
+}
+
+// Keeps the size of the descriptors in line with `struct vmpktdesc`
+const _: () = assert!(mem::size_of::<VmPktDesc>() == 24);
@@ -0,0 +1,204 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::pcap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+
+/// Number of copies waiting to be written to the mirror, beyond which the copies are dropped.
+const QUEUE_LEN: usize = 1024;
+
+/// Represents a device whose received and sent packets are copied to a mirror, another device
+/// or a pcap file, for out-of-band monitoring (e.g. by an IDS listening on the mirror device).
+///
+/// The copies are queued to a background task writing them to the mirror, and are dropped when
+/// 1024 copies are already waiting, so that a slow or stuck mirror never delays the wrapped
+/// device; [`dropped`](#method.dropped) counts them. An error writing to the mirror stops
+/// mirroring, and is returned by [`take_error`](#method.take_error).
+///
+/// ```no_run
+/// use tokio_tun::{Mirrored, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().name("tun0").up().build()?.pop().unwrap();
+/// let monitor = TunBuilder::new().name("monitor0").up().build()?.pop().unwrap();
+/// let tun = Mirrored::new(tun, monitor);
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Mirrored<T> {
+    inner: T,
+    tx: mpsc::Sender<Vec<u8>>,
+    dropped: AtomicU64,
+    error: Arc<Mutex<Option<io::Error>>>,
+    task: JoinHandle<()>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mirrored<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirrored")
+            .field("inner", &self.inner)
+            .field("dropped", &self.dropped.load(Ordering::Relaxed))
+            .field("mirroring", &!self.task.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice> Mirrored<T> {
+    /// Wraps `inner`, and spawns the task sending the copies of its packets to `mirror`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn new<M: AsyncTunDevice + 'static>(inner: T, mirror: M) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(QUEUE_LEN);
+        let error = Arc::new(Mutex::new(None));
+        let task = {
+            let error = error.clone();
+            tokio::spawn(async move {
+                while let Some(packet) = rx.recv().await {
+                    if let Err(err) = mirror.send_all(&packet).await {
+                        *error.lock().unwrap() = Some(err);
+                        return;
+                    }
+                }
+            })
+        };
+        Self::with_task(inner, tx, error, task)
+    }
+
+    /// Wraps `inner`, and spawns the task recording the copies of its packets into `writer` in
+    /// the pcap format. The copies are written on the blocking thread pool of tokio, and
+    /// `writer` is flushed when the wrapper is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn pcap(inner: T, mut writer: impl Write + Send + 'static) -> Result<Self> {
+        pcap::write_header(&mut writer, pcap::linktype(&inner.config()?))?;
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(QUEUE_LEN);
+        let error = Arc::new(Mutex::new(None));
+        let task = {
+            let error = error.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut record = || {
+                    while let Some(packet) = rx.blocking_recv() {
+                        pcap::write_record(&mut writer, &packet)?;
+                    }
+                    writer.flush()
+                };
+                if let Err(err) = record() {
+                    *error.lock().unwrap() = Some(err);
+                }
+            })
+        };
+        Ok(Self::with_task(inner, tx, error, task))
+    }
+
+    fn with_task(
+        inner: T,
+        tx: mpsc::Sender<Vec<u8>>,
+        error: Arc<Mutex<Option<io::Error>>>,
+        task: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            inner,
+            tx,
+            dropped: AtomicU64::new(0),
+            error,
+            task,
+        }
+    }
+
+    /// Receives a packet from the device and mirrors it.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.recv(buf).await?;
+        self.mirror(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Sends a buffer to the device and mirrors the part which was sent.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.send(buf).await?;
+        self.mirror(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Sends all of a buffer to the device and mirrors it.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.inner.send_all(buf).await?;
+        self.mirror(buf);
+        Ok(())
+    }
+
+    /// Returns the number of copies dropped because the mirror did not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` until an error stops mirroring.
+    pub fn is_mirroring(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Returns the error which stopped mirroring, if any.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.lock().unwrap().take()
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops mirroring and returns the wrapped device. The copies already queued are still
+    /// written to the mirror.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Queues a copy of `packet` for the mirror, or drops it if the queue is full.
+    fn mirror(&self, packet: &[u8]) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(packet.to_vec()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for Mirrored<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        Mirrored::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        Mirrored::send(self, buf).await
+    }
+
+    async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        Mirrored::send_all(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
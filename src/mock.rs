@@ -0,0 +1,386 @@
+use crate::AsyncTunDevice;
+use crate::Error;
+use crate::Result;
+use crate::TunConfig;
+use std::collections::VecDeque;
+use std::io;
+use std::net::Ipv4Addr;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Represents a queue of packets in one direction.
+#[derive(Default)]
+struct Queue {
+    packets: Mutex<VecDeque<Vec<u8>>>,
+    notify: Notify,
+}
+
+impl Queue {
+    fn push(&self, packet: Vec<u8>) {
+        self.packets.lock().unwrap().push_back(packet);
+        self.notify.notify_one();
+    }
+
+    fn try_pop(&self) -> Option<Vec<u8>> {
+        self.packets.lock().unwrap().pop_front()
+    }
+
+    /// Waits for the next packet, or returns `None` once `removed` is set and the queue is empty.
+    async fn pop(&self, removed: &AtomicBool) -> Option<Vec<u8>> {
+        loop {
+            let mut notified = pin!(self.notify.notified());
+            // Registers the waiter before checking, so that no notification is missed
+            notified.as_mut().enable();
+            if let Some(packet) = self.try_pop() {
+                return Some(packet);
+            }
+            if removed.load(Ordering::Acquire) {
+                return None;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Represents the queues of a [`TestTun`], shared with its handles or with the other device of
+/// its pair.
+#[derive(Clone, Default)]
+struct Link {
+    inbound: Arc<Queue>,
+    outbound: Arc<Queue>,
+    removed: Arc<AtomicBool>,
+    echo: Arc<AtomicBool>,
+}
+
+impl Link {
+    /// Returns the link of the other side, which receives what this side sends.
+    fn reversed(&self) -> Self {
+        Self {
+            inbound: self.outbound.clone(),
+            outbound: self.inbound.clone(),
+            removed: self.removed.clone(),
+            echo: self.echo.clone(),
+        }
+    }
+
+    fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+}
+
+fn removed() -> io::Error {
+    Error::DeviceRemoved.into()
+}
+
+/// Represents an in-memory device, which offers the surface of [`Tun`](struct.Tun.html) without
+/// a kernel device nor root privileges, to unit-test code written against a device.
+///
+/// The packets injected by its [`TestTunHandle`] are received from the device, and the packets
+/// sent to the device are collected by the handle. Once the handle
+/// [removes](struct.TestTunHandle.html#method.remove) the device, receiving and sending fail
+/// with [`Error::DeviceRemoved`](enum.Error.html#variant.DeviceRemoved), as they do on a real
+/// device deleted by `ip link del`.
+///
+/// The device implements [`AsyncTunDevice`](trait.AsyncTunDevice.html), and does not depend on a
+/// tokio runtime.
+///
+/// ```
+/// use tokio_tun::TestTun;
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let (tun, handle) = TestTun::new("tun0");
+/// handle.inject(b"ping");
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// tun.send(&buf[..n]).await?;
+/// assert_eq!(handle.try_sent().as_deref(), Some(&b"ping"[..]));
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestTun {
+    config: TunConfig,
+    link: Link,
+}
+
+impl std::fmt::Debug for TestTun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestTun")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TestTun {
+    /// Creates a Tun device named `name`, which is up with an MTU of 1500 and no address. Returns
+    /// it along with its handle.
+    pub fn new(name: &str) -> (Self, TestTunHandle) {
+        Self::with_config(TunConfig {
+            name: name.to_owned(),
+            tap: false,
+            mtu: 1500,
+            flags: (libc::IFF_UP | libc::IFF_RUNNING) as i16,
+            address: None,
+            destination: None,
+            broadcast: None,
+            netmask: None,
+            queues: 1,
+        })
+    }
+
+    /// Creates a device whose getters return `config`. Returns it along with its handle.
+    pub fn with_config(config: TunConfig) -> (Self, TestTunHandle) {
+        let link = Link::default();
+        let handle = TestTunHandle {
+            name: config.name.clone(),
+            link: link.reversed(),
+        };
+        (Self { config, link }, handle)
+    }
+
+    /// Creates two connected Tun devices named `a` and `b`, configured as by
+    /// [`new`](#method.new): the packets sent to one are received from the other, in order, so
+    /// that forwarding logic between two devices can be tested deterministically.
+    ///
+    /// ```
+    /// use tokio_tun::TestTun;
+    ///
+    /// # async fn run() -> std::io::Result<()> {
+    /// let (a, b) = TestTun::pair("tun0", "tun1");
+    /// a.send(b"ping").await?;
+    /// let mut buf = [0u8; 1500];
+    /// let n = b.recv(&mut buf).await?;
+    /// assert_eq!(&buf[..n], b"ping");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pair(a: &str, b: &str) -> (Self, Self) {
+        let (a, handle) = Self::new(a);
+        let (b, _) = Self::new(b);
+        let b = Self {
+            link: handle.link,
+            ..b
+        };
+        (a, b)
+    }
+
+    /// Receives the next injected packet. A packet larger than `buf` is truncated, as a real
+    /// device does.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.link.inbound.pop(&self.link.removed).await {
+            Some(packet) => Ok(copy(&packet, buf)),
+            None => Err(removed()),
+        }
+    }
+
+    /// Sends a packet, which the handle collects, or which is received back in echo mode (see
+    /// [`TestTunHandle::set_echo`](struct.TestTunHandle.html#method.set_echo)). Returns the
+    /// number of bytes sent.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.try_send(buf)
+    }
+
+    /// Sends all of a buffer.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.try_send(buf).map(drop)
+    }
+
+    /// Tries to receive an injected packet.
+    ///
+    /// When there is no pending packet, `Err(io::ErrorKind::WouldBlock)` is returned.
+    pub fn try_recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.link.inbound.try_pop() {
+            Some(packet) => Ok(copy(&packet, buf)),
+            None if self.link.is_removed() => Err(removed()),
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    /// Tries to send a packet, which never blocks. In echo mode, the packet is received back
+    /// instead of being collected by the handle.
+    pub fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        if self.link.is_removed() {
+            return Err(removed());
+        }
+        if self.link.echo.load(Ordering::Acquire) {
+            self.link.inbound.push(buf.to_vec());
+        } else {
+            self.link.outbound.push(buf.to_vec());
+        }
+        Ok(buf.len())
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Returns the value of MTU.
+    pub fn mtu(&self) -> Result<i32> {
+        Ok(self.config.mtu)
+    }
+
+    /// Returns the minimum size of a receive buffer which can hold any packet of the device
+    /// without truncation, accounting for the Ethernet framing of TAP devices.
+    pub fn recommended_buffer_size(&self) -> Result<usize> {
+        let config = &self.config;
+        let framing = if config.tap { 14 } else { 0 };
+        Ok(config.mtu.max(0) as usize + framing + 1)
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    pub fn config(&self) -> Result<TunConfig> {
+        Ok(self.config.clone())
+    }
+
+    /// Returns the IPv4 address of device.
+    pub fn address(&self) -> Result<Ipv4Addr> {
+        unassigned(self.config.address)
+    }
+
+    /// Returns the IPv4 destination address of device.
+    pub fn destination(&self) -> Result<Ipv4Addr> {
+        unassigned(self.config.destination)
+    }
+
+    /// Returns the IPv4 broadcast address of device.
+    pub fn broadcast(&self) -> Result<Ipv4Addr> {
+        unassigned(self.config.broadcast)
+    }
+
+    /// Returns the IPv4 netmask address of device.
+    pub fn netmask(&self) -> Result<Ipv4Addr> {
+        unassigned(self.config.netmask)
+    }
+
+    /// Returns the flags of device.
+    pub fn flags(&self) -> Result<i16> {
+        Ok(self.config.flags)
+    }
+}
+
+/// Copies `packet` into `buf`, truncating it. Returns the number of bytes copied.
+fn copy(packet: &[u8], buf: &mut [u8]) -> usize {
+    let n = packet.len().min(buf.len());
+    buf[..n].copy_from_slice(&packet[..n]);
+    n
+}
+
+/// Returns `addr`, failing with `EADDRNOTAVAIL` as the kernel does if it is not assigned.
+fn unassigned(addr: Option<Ipv4Addr>) -> Result<Ipv4Addr> {
+    addr.ok_or_else(|| io::Error::from_raw_os_error(libc::EADDRNOTAVAIL).into())
+}
+
+impl AsyncTunDevice for TestTun {
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        TestTun::recv(self, buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        TestTun::send(self, buf)
+    }
+
+    fn send_all(&self, buf: &[u8]) -> impl Future<Output = io::Result<()>> + Send {
+        TestTun::send_all(self, buf)
+    }
+
+    fn name(&self) -> &str {
+        TestTun::name(self)
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        TestTun::mtu(self)
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        TestTun::config(self)
+    }
+}
+
+/// Represents the other side of a [`TestTun`], which plays the part of the kernel: it injects
+/// the packets the device receives, and collects the packets sent to the device.
+///
+/// Handles can be cloned, e.g. to inject packets from one task and collect them from another.
+#[derive(Clone)]
+pub struct TestTunHandle {
+    name: String,
+    /// Link of the device, reversed: its inbound queue holds the packets sent to the device.
+    link: Link,
+}
+
+impl std::fmt::Debug for TestTunHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestTunHandle")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TestTunHandle {
+    /// Injects a packet, which the device receives next, after the packets injected before.
+    pub fn inject(&self, packet: impl Into<Vec<u8>>) {
+        self.link.outbound.push(packet.into());
+    }
+
+    /// Returns the number of injected packets which were not received yet.
+    pub fn pending(&self) -> usize {
+        self.link.outbound.packets.lock().unwrap().len()
+    }
+
+    /// Waits for the next packet sent to the device. Returns `None` once the device is removed
+    /// and all the packets sent before were collected.
+    pub async fn sent(&self) -> Option<Vec<u8>> {
+        self.link.inbound.pop(&self.link.removed).await
+    }
+
+    /// Returns the next packet sent to the device, if any.
+    pub fn try_sent(&self) -> Option<Vec<u8>> {
+        self.link.inbound.try_pop()
+    }
+
+    /// Returns all the packets sent to the device which were not collected yet.
+    pub fn drain_sent(&self) -> Vec<Vec<u8>> {
+        self.link
+            .inbound
+            .packets
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect()
+    }
+
+    /// Enables or disables echo mode, in which the packets sent to the device are received back
+    /// from it right away, after the packets already pending, instead of being collected. Round
+    /// trips of a protocol can then be tested without a peer.
+    ///
+    /// ```
+    /// use tokio_tun::TestTun;
+    ///
+    /// # async fn run() -> std::io::Result<()> {
+    /// let (tun, handle) = TestTun::new("tun0");
+    /// handle.set_echo(true);
+    /// tun.send(b"ping").await?;
+    /// let mut buf = [0u8; 1500];
+    /// let n = tun.recv(&mut buf).await?;
+    /// assert_eq!(&buf[..n], b"ping");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_echo(&self, echo: bool) {
+        self.link.echo.store(echo, Ordering::Release);
+    }
+
+    /// Removes the device: once the injected packets are received, receiving fails with
+    /// [`Error::DeviceRemoved`](enum.Error.html#variant.DeviceRemoved), and sending fails
+    /// immediately.
+    pub fn remove(&self) {
+        self.link.removed.store(true, Ordering::Release);
+        self.link.inbound.notify.notify_waiters();
+        self.link.outbound.notify.notify_waiters();
+    }
+}
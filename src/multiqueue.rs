@@ -0,0 +1,138 @@
+use crate::Tun;
+use crate::classify;
+use std::future;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Poll;
+
+/// Selects the queue of a [`TunPool`](struct.TunPool.html) which sends a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dispatch {
+    /// Sends the packets through each queue in turn.
+    #[default]
+    RoundRobin,
+    /// Sends all the packets of a flow (IP addresses, protocol and ports) through the same
+    /// queue, which preserves their order. Packets which are not IP are sent through the first
+    /// queue.
+    FlowHash,
+}
+
+/// Represents the queues of a multi-queue Tun/Tap device behind a single `recv` and `send`.
+///
+/// It is created by [`TunBuilder::build_pool`](struct.TunBuilder.html#method.build_pool) or from
+/// the `Vec<Tun>` returned by [`TunBuilder::build`](struct.TunBuilder.html#method.build), for
+/// applications which do not want a worker per queue.
+pub struct TunPool {
+    queues: Vec<Tun>,
+    dispatch: Dispatch,
+    // Offset of the IP header in a packet, i.e. past the packet information, virtio-net and
+    // Ethernet headers
+    offset: usize,
+    next_recv: AtomicUsize,
+    next_send: AtomicUsize,
+}
+
+impl From<Vec<Tun>> for TunPool {
+    fn from(queues: Vec<Tun>) -> Self {
+        Self::new(queues)
+    }
+}
+
+impl TunPool {
+    /// Creates a new pool of the queues of a device, dispatching the packets sent in a
+    /// round-robin fashion.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `queues` is empty.
+    pub fn new(queues: Vec<Tun>) -> Self {
+        assert!(!queues.is_empty(), "TunPool requires at least one queue");
+        let iface = &queues[0].iface;
+        let offset = match iface.is_tap() {
+            // The overhead of TAP devices accounts for a VLAN tag, which is checked per packet
+            Ok(true) => iface.overhead().unwrap_or_default().saturating_sub(4),
+            _ => iface.overhead().unwrap_or_default(),
+        };
+        Self {
+            queues,
+            dispatch: Dispatch::default(),
+            offset,
+            next_recv: AtomicUsize::new(0),
+            next_send: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the way the queue which sends a packet is selected.
+    pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+        self.dispatch = dispatch;
+        self
+    }
+
+    /// Receives a packet from whichever queue has one first.
+    ///
+    /// The queues are polled starting from a different one on each call, so that a busy queue
+    /// does not starve the others. Only a single task should receive from a pool at a time, as
+    /// each queue only wakes the last task which polled it.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_from(buf).await.map(|(_, n)| n)
+    }
+
+    /// Receives a packet from whichever queue has one first. Returns the index of the queue and
+    /// the number of bytes received.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, usize)> {
+        let start = self.next_recv.fetch_add(1, Ordering::Relaxed);
+        future::poll_fn(|cx| {
+            for i in 0..self.queues.len() {
+                let queue = (start + i) % self.queues.len();
+                if let Poll::Ready(res) = self.queues[queue].poll_recv(cx, buf) {
+                    return Poll::Ready(res.map(|n| (queue, n)));
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Sends a buffer through the queue selected by the dispatch policy. Returns the number of
+    /// bytes written to the device.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.queues[self.select(buf)].send(buf).await
+    }
+
+    /// Sends all of a buffer through the queue selected by the dispatch policy.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.queues[self.select(buf)].send_all(buf).await
+    }
+
+    /// Returns the queues of the pool.
+    pub fn queues(&self) -> &[Tun] {
+        &self.queues
+    }
+
+    /// Returns the queues of the pool, consuming it.
+    pub fn into_inner(self) -> Vec<Tun> {
+        self.queues
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.queues[0].name()
+    }
+
+    fn select(&self, buf: &[u8]) -> usize {
+        match self.dispatch {
+            Dispatch::RoundRobin => self.next_send.fetch_add(1, Ordering::Relaxed),
+            Dispatch::FlowHash => flow_hash(buf, self.offset).unwrap_or_default() as usize,
+        }
+        .wrapping_rem(self.queues.len())
+    }
+}
+
+/// Hashes the addresses, protocol and ports of the IP packet at `offset` in `buf`.
+fn flow_hash(buf: &[u8], mut offset: usize) -> Option<u64> {
+    // Skip the VLAN tag of TAP frames
+    if offset >= 2 && buf.get(offset - 2..offset) == Some(&[0x81, 0x00]) {
+        offset += 4;
+    }
+    classify(buf.get(offset..)?).map(|class| class.flow_hash())
+}
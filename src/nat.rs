@@ -0,0 +1,240 @@
+//! Helpers rewriting the addresses and the ports of IPv4 and IPv6 packets in place, for
+//! userspace NAT and port forwarding.
+//!
+//! Each rewrite updates the checksums which cover the rewritten field incrementally (see
+//! [`checksum::update`](../checksum/fn.update.html)): the IPv4 header checksum, and the
+//! checksum of the TCP, UDP or ICMPv6 packet carried, whose pseudo header covers the addresses.
+//! IPv6 packets with extension headers are not supported. The ports of an IPv4 fragment are
+//! only found in the first fragment.
+//!
+//! ```
+//! use std::net::SocketAddr;
+//! use tokio_tun::{checksum, nat};
+//!
+//! // A UDP packet from 10.0.0.1:1234 to 10.0.0.2:53
+//! let mut packet = [
+//!     0x45, 0, 0, 28, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0x04, 0xd2, 0, 53,
+//!     0, 8, 0, 0,
+//! ];
+//! checksum::fill(&mut packet);
+//!
+//! nat::set_source(&mut packet, "192.168.1.1:40000".parse()?)?;
+//! assert_eq!(nat::source(&packet)?, "192.168.1.1:40000".parse::<SocketAddr>()?);
+//! assert!(checksum::verify(&packet));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::checksum;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Offset of the checksum in the IPv4 header.
+const IPV4_CHECKSUM: usize = 10;
+
+/// Represents the layout of an IP packet.
+struct Layout {
+    v4: bool,
+    protocol: u8,
+    /// Offset of the transport header, or `None` if the packet is a fragment which does not
+    /// carry it.
+    transport: Option<usize>,
+}
+
+impl Layout {
+    /// Returns the offset of the source address, and of the destination address, and their
+    /// length.
+    fn addresses(&self) -> (usize, usize, usize) {
+        if self.v4 { (12, 16, 4) } else { (8, 24, 16) }
+    }
+
+    /// Returns the offset of the checksum of the transport packet, if it is carried and its
+    /// checksum covers the addresses.
+    fn transport_checksum(&self, packet: &[u8]) -> Option<usize> {
+        let offset = match self.protocol {
+            IPPROTO_TCP => 16,
+            IPPROTO_UDP => 6,
+            IPPROTO_ICMPV6 if !self.v4 => 2,
+            _ => return None,
+        };
+        let at = self.transport? + offset;
+        (packet.len() >= at + 2).then_some(at)
+    }
+
+    /// Returns the offset of the ports of the TCP or UDP packet.
+    fn ports(&self, packet: &[u8]) -> io::Result<usize> {
+        if !matches!(self.protocol, IPPROTO_TCP | IPPROTO_UDP) {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "packet is neither a TCP nor a UDP packet",
+            ));
+        }
+        match self.transport {
+            Some(start) if packet.len() >= start + 4 => Ok(start),
+            Some(_) => Err(invalid("packet is shorter than its ports")),
+            None => Err(invalid("packet is a fragment without ports")),
+        }
+    }
+}
+
+/// Returns the source address and port of the TCP or UDP packet `packet`.
+pub fn source(packet: &[u8]) -> io::Result<SocketAddr> {
+    socket_addr(packet, 0)
+}
+
+/// Returns the destination address and port of the TCP or UDP packet `packet`.
+pub fn destination(packet: &[u8]) -> io::Result<SocketAddr> {
+    socket_addr(packet, 1)
+}
+
+/// Rewrites the source address and port of the TCP or UDP packet `packet`, updating its
+/// checksums.
+pub fn set_source(packet: &mut [u8], addr: SocketAddr) -> io::Result<()> {
+    // Fails before rewriting anything if either cannot be rewritten
+    layout(packet)?.ports(packet)?;
+    set_source_addr(packet, addr.ip())?;
+    set_source_port(packet, addr.port())
+}
+
+/// Rewrites the destination address and port of the TCP or UDP packet `packet`, updating its
+/// checksums.
+pub fn set_destination(packet: &mut [u8], addr: SocketAddr) -> io::Result<()> {
+    // Fails before rewriting anything if either cannot be rewritten
+    layout(packet)?.ports(packet)?;
+    set_destination_addr(packet, addr.ip())?;
+    set_destination_port(packet, addr.port())
+}
+
+/// Rewrites the source address of the IP packet `packet`, updating its checksums. Fails with
+/// `io::ErrorKind::InvalidInput` if `addr` is not of the version of the packet.
+pub fn set_source_addr(packet: &mut [u8], addr: IpAddr) -> io::Result<()> {
+    set_addr(packet, addr, 0)
+}
+
+/// Rewrites the destination address of the IP packet `packet`, updating its checksums. Fails
+/// with `io::ErrorKind::InvalidInput` if `addr` is not of the version of the packet.
+pub fn set_destination_addr(packet: &mut [u8], addr: IpAddr) -> io::Result<()> {
+    set_addr(packet, addr, 1)
+}
+
+/// Rewrites the source port of the TCP or UDP packet `packet`, updating its checksum.
+pub fn set_source_port(packet: &mut [u8], port: u16) -> io::Result<()> {
+    set_port(packet, port, 0)
+}
+
+/// Rewrites the destination port of the TCP or UDP packet `packet`, updating its checksum.
+pub fn set_destination_port(packet: &mut [u8], port: u16) -> io::Result<()> {
+    set_port(packet, port, 1)
+}
+
+/// Returns the source (`index` 0) or destination (`index` 1) address and port of `packet`.
+fn socket_addr(packet: &[u8], index: usize) -> io::Result<SocketAddr> {
+    let layout = layout(packet)?;
+    let (source, destination, len) = layout.addresses();
+    let at = [source, destination][index];
+    let addr: IpAddr = if layout.v4 {
+        Ipv4Addr::from(<[u8; 4]>::try_from(&packet[at..at + len]).unwrap()).into()
+    } else {
+        Ipv6Addr::from(<[u8; 16]>::try_from(&packet[at..at + len]).unwrap()).into()
+    };
+    let at = layout.ports(packet)? + 2 * index;
+    Ok(SocketAddr::new(
+        addr,
+        u16::from_be_bytes([packet[at], packet[at + 1]]),
+    ))
+}
+
+/// Rewrites the source (`index` 0) or destination (`index` 1) address of `packet`.
+fn set_addr(packet: &mut [u8], addr: IpAddr, index: usize) -> io::Result<()> {
+    let layout = layout(packet)?;
+    let mut new = [0u8; 16];
+    match (addr, layout.v4) {
+        (IpAddr::V4(addr), true) => new[..4].copy_from_slice(&addr.octets()),
+        (IpAddr::V6(addr), false) => new.copy_from_slice(&addr.octets()),
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "address is not of the IP version of the packet",
+            ));
+        }
+    };
+    let (source, destination, len) = layout.addresses();
+    let at = [source, destination][index];
+    let (mut old, new) = ([0u8; 16], &new[..len]);
+    old[..len].copy_from_slice(&packet[at..at + len]);
+    let old = &old[..len];
+    packet[at..at + len].copy_from_slice(new);
+    if layout.v4 {
+        update(packet, IPV4_CHECKSUM, old, new, false);
+    }
+    if let Some(checksum) = layout.transport_checksum(packet) {
+        let optional = layout.v4 && layout.protocol == IPPROTO_UDP;
+        update(packet, checksum, old, new, optional);
+    }
+    Ok(())
+}
+
+/// Rewrites the source (`index` 0) or destination (`index` 1) port of `packet`.
+fn set_port(packet: &mut [u8], port: u16, index: usize) -> io::Result<()> {
+    let layout = layout(packet)?;
+    let at = layout.ports(packet)? + 2 * index;
+    let old = [packet[at], packet[at + 1]];
+    let new = port.to_be_bytes();
+    packet[at..at + 2].copy_from_slice(&new);
+    if let Some(checksum) = layout.transport_checksum(packet) {
+        let optional = layout.v4 && layout.protocol == IPPROTO_UDP;
+        update(packet, checksum, &old, &new, optional);
+    }
+    Ok(())
+}
+
+/// Updates the checksum at `at` for the replacement of `old` by `new`. An `optional` checksum
+/// (UDP over IPv4) which is zero is absent, and stays so.
+fn update(packet: &mut [u8], at: usize, old: &[u8], new: &[u8], optional: bool) {
+    let current = u16::from_be_bytes([packet[at], packet[at + 1]]);
+    if optional && current == 0 {
+        return;
+    }
+    let mut updated = checksum::update(current, old, new);
+    if optional && updated == 0 {
+        updated = 0xffff;
+    }
+    packet[at..at + 2].copy_from_slice(&updated.to_be_bytes());
+}
+
+/// Returns the layout of the IP packet `packet`.
+fn layout(packet: &[u8]) -> io::Result<Layout> {
+    match packet.first().map(|byte| byte >> 4) {
+        Some(4) => {
+            let len = (packet[0] & 0x0f) as usize * 4;
+            if len < 20 || packet.len() < len {
+                return Err(invalid("packet is shorter than its IPv4 header"));
+            }
+            // Only the first fragment carries the transport header
+            let offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff;
+            Ok(Layout {
+                v4: true,
+                protocol: packet[9],
+                transport: (offset == 0).then_some(len),
+            })
+        }
+        Some(6) => {
+            if packet.len() < 40 {
+                return Err(invalid("packet is shorter than its IPv6 header"));
+            }
+            Ok(Layout {
+                v4: false,
+                protocol: packet[6],
+                transport: Some(40),
+            })
+        }
+        _ => Err(invalid("packet is not an IP packet")),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,447 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::checksum;
+use crate::dot1q;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::Ipv6Addr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Hop limit of the NDP messages, which the receivers check to make sure that they were not
+/// forwarded.
+const HOP_LIMIT: u8 = 255;
+
+const ROUTER_SOLICITATION: u8 = 133;
+const ROUTER_ADVERTISEMENT: u8 = 134;
+const NEIGHBOR_SOLICITATION: u8 = 135;
+const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+const OPTION_SOURCE_LINK_LAYER: u8 = 1;
+const OPTION_TARGET_LINK_LAYER: u8 = 2;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+
+const FLAG_ROUTER: u8 = 0x80;
+const FLAG_SOLICITED: u8 = 0x40;
+const FLAG_OVERRIDE: u8 = 0x20;
+
+/// On-link and autonomous address configuration flags of a prefix.
+const PREFIX_FLAGS: u8 = 0xc0;
+/// Lifetimes of the advertised prefixes, the default ones of RFC 4861.
+const VALID_LIFETIME: u32 = 30 * 24 * 3600;
+const PREFERRED_LIFETIME: u32 = 7 * 24 * 3600;
+
+/// All-nodes multicast address.
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// Represents the router advertised by an [`NdpResponder`](struct.NdpResponder.html).
+#[derive(Debug, Clone)]
+struct Router {
+    mac: [u8; 6],
+    /// Link-local address of the router, derived from its MAC address.
+    ip: Ipv6Addr,
+    lifetime: Duration,
+    prefixes: Vec<(Ipv6Addr, u8)>,
+}
+
+/// Represents a TAP device which answers the Neighbor Solicitations for a set of IPv6
+/// addresses with their MAC addresses, the NDP counterpart of an
+/// [`ArpResponder`](struct.ArpResponder.html), and which optionally acts as the router of the
+/// link, advertising prefixes for the hosts to configure their addresses from (SLAAC), so that
+/// an IPv6-only network is usable without radvd.
+///
+/// The solicitations it answers are not returned by [`recv`](#method.recv); the other frames
+/// are. A solicitation tagged with VLAN tags is answered with the same tags. Adding or changing
+/// a mapping with [`insert`](#method.insert) announces it with an unsolicited Neighbor
+/// Advertisement, so that the neighbors update their caches.
+///
+/// Once a router is set with [`router`](#method.router), the Router Solicitations are answered
+/// with a Router Advertisement of the prefixes set with [`prefix`](#method.prefix), sent to all
+/// the nodes, and [`advertise`](#method.advertise) sends one unsolicited, e.g. periodically.
+/// The router answers as the link-local address derived from its MAC address.
+///
+/// The device must be created without packet information and offload, whose headers would not
+/// be understood.
+///
+/// ```no_run
+/// use tokio_tun::{NdpResponder, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tap = TunBuilder::new().tap().up().build()?.pop().unwrap();
+/// let tap = NdpResponder::new(tap)?
+///     .router([0x02, 0, 0, 0, 0, 1])
+///     .prefix("2001:db8::".parse()?, 64);
+/// tap.advertise().await?;
+/// let mut buf = [0u8; 1514];
+/// let n = tap.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NdpResponder<T> {
+    inner: T,
+    entries: Mutex<HashMap<Ipv6Addr, [u8; 6]>>,
+    router: Option<Router>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for NdpResponder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NdpResponder")
+            .field("inner", &self.inner)
+            .field("entries", &self.entries.lock().unwrap())
+            .field("router", &self.router)
+            .finish()
+    }
+}
+
+impl<T: AsyncTunDevice> NdpResponder<T> {
+    /// Wraps `inner`, without mappings nor router. Fails with `io::ErrorKind::InvalidInput` if
+    /// it is not a TAP device.
+    pub fn new(inner: T) -> Result<Self> {
+        if !inner.config()?.tap {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "answering Neighbor Solicitations needs a TAP device",
+            )
+            .into());
+        }
+        Ok(Self {
+            inner,
+            entries: Mutex::default(),
+            router: None,
+        })
+    }
+
+    /// Adds the mapping of `ip` to `mac`, without announcing it.
+    pub fn entry(self, ip: Ipv6Addr, mac: [u8; 6]) -> Self {
+        self.entries.lock().unwrap().insert(ip, mac);
+        self
+    }
+
+    /// Acts as the router of the link at the MAC address `mac`, and as the default router of
+    /// the hosts for 30 minutes after each advertisement.
+    pub fn router(mut self, mac: [u8; 6]) -> Self {
+        let prefixes = self.router.take().map_or_else(Vec::new, |r| r.prefixes);
+        self.router = Some(Router {
+            mac,
+            ip: link_local(mac),
+            lifetime: Duration::from_secs(1800),
+            prefixes,
+        });
+        self
+    }
+
+    /// Sets the time for which the hosts use the router as their default router after an
+    /// advertisement, up to 9000 seconds. Zero advertises the prefixes without offering a
+    /// default route.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no router is set.
+    pub fn router_lifetime(mut self, lifetime: Duration) -> Self {
+        let router = self.router.as_mut().expect("no router is set");
+        router.lifetime = lifetime;
+        self
+    }
+
+    /// Adds the prefix `prefix`/`len` to the Router Advertisements, on-link and for the hosts to
+    /// configure their addresses from, which needs `len` to be 64.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no router is set, or if `len` is larger than 128.
+    pub fn prefix(mut self, prefix: Ipv6Addr, len: u8) -> Self {
+        assert!(len <= 128, "prefix length {} is larger than 128", len);
+        let router = self.router.as_mut().expect("no router is set");
+        router.prefixes.push((prefix, len));
+        self
+    }
+
+    /// Returns the link-local address of the router, if any.
+    pub fn router_ip(&self) -> Option<Ipv6Addr> {
+        self.router.as_ref().map(|router| router.ip)
+    }
+
+    /// Adds or changes the mapping of `ip` to `mac`, and announces it with an unsolicited
+    /// Neighbor Advertisement if it is new. Returns the previous MAC address of `ip`, if any.
+    pub async fn insert(&self, ip: Ipv6Addr, mac: [u8; 6]) -> io::Result<Option<[u8; 6]>> {
+        let previous = self.entries.lock().unwrap().insert(ip, mac);
+        if previous != Some(mac) {
+            self.inner.send_all(&announcement(ip, mac)).await?;
+        }
+        Ok(previous)
+    }
+
+    /// Removes the mapping of `ip`. Returns its MAC address, if any.
+    pub fn remove(&self, ip: Ipv6Addr) -> Option<[u8; 6]> {
+        self.entries.lock().unwrap().remove(&ip)
+    }
+
+    /// Returns the MAC address which `ip` is mapped to, if any.
+    pub fn get(&self, ip: Ipv6Addr) -> Option<[u8; 6]> {
+        self.entries.lock().unwrap().get(&ip).copied()
+    }
+
+    /// Announces every mapping with an unsolicited Neighbor Advertisement.
+    pub async fn announce_all(&self) -> io::Result<()> {
+        let entries: Vec<_> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&ip, &mac)| (ip, mac))
+            .collect();
+        for (ip, mac) in entries {
+            self.inner.send_all(&announcement(ip, mac)).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends an unsolicited Router Advertisement to all the nodes. Does nothing if no router
+    /// is set.
+    pub async fn advertise(&self) -> io::Result<()> {
+        let Some(router) = &self.router else {
+            return Ok(());
+        };
+        let frame = ndp_frame(
+            multicast_mac(ALL_NODES),
+            router.mac,
+            &ETHERTYPE_IPV6.to_be_bytes(),
+            router.ip,
+            ALL_NODES,
+            &advertisement(router),
+        );
+        self.inner.send_all(&frame).await
+    }
+
+    /// Receives a frame from the device, answering the Neighbor Solicitations for the mapped
+    /// addresses and the Router Solicitations in the meantime.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.recv(buf).await?;
+            match self.reply(&buf[..n]) {
+                Some(reply) => self.inner.send_all(&reply).await?,
+                None => return Ok(n),
+            }
+        }
+    }
+
+    /// Sends a frame to the device.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf).await
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the reply to `frame` if it is a Neighbor Solicitation for a mapped address or
+    /// for the router, or a Router Solicitation while a router is set.
+    fn reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let tags = dot1q::parse(frame).ok()?;
+        let packet = frame.get(tags.payload..)?;
+        let is_ndp = tags.ether_type == ETHERTYPE_IPV6
+            && packet.len() >= 40 + 8
+            && packet[0] >> 4 == 6
+            && packet[6] == IPPROTO_ICMPV6
+            && packet[7] == HOP_LIMIT;
+        if !is_ndp {
+            return None;
+        }
+        let source = Ipv6Addr::from(<[u8; 16]>::try_from(&packet[8..24]).unwrap());
+        let icmp = &packet[40..];
+        let source_mac = source_link_layer(icmp).unwrap_or(frame[6..12].try_into().unwrap());
+        // The tags and the EtherType of the solicitation
+        let tags = &frame[12..tags.payload];
+
+        match icmp[0] {
+            NEIGHBOR_SOLICITATION if icmp.len() >= 24 => {
+                let target = Ipv6Addr::from(<[u8; 16]>::try_from(&icmp[8..24]).unwrap());
+                let (mac, mut flags) = match &self.router {
+                    Some(router) if router.ip == target => (router.mac, FLAG_ROUTER),
+                    _ => (self.get(target)?, 0),
+                };
+                flags |= FLAG_OVERRIDE;
+                // A solicitation from the unspecified address checks whether the target is
+                // in use (DAD), and is answered to all the nodes
+                let (destination, destination_mac) = if source.is_unspecified() {
+                    (ALL_NODES, multicast_mac(ALL_NODES))
+                } else {
+                    flags |= FLAG_SOLICITED;
+                    (source, source_mac)
+                };
+                let icmp = neighbor_advertisement(target, mac, flags);
+                Some(ndp_frame(
+                    destination_mac,
+                    mac,
+                    tags,
+                    target,
+                    destination,
+                    &icmp,
+                ))
+            }
+            ROUTER_SOLICITATION => {
+                let router = self.router.as_ref()?;
+                Some(ndp_frame(
+                    multicast_mac(ALL_NODES),
+                    router.mac,
+                    tags,
+                    router.ip,
+                    ALL_NODES,
+                    &advertisement(router),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for NdpResponder<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        NdpResponder::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        NdpResponder::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
+
+/// Returns the link-local address of the interface of MAC address `mac`, from its modified
+/// EUI-64 identifier (RFC 4291).
+fn link_local(mac: [u8; 6]) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets[0..2].copy_from_slice(&[0xfe, 0x80]);
+    octets[8..16].copy_from_slice(&[
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xff,
+        0xfe,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]);
+    Ipv6Addr::from(octets)
+}
+
+/// Returns the MAC address of the IPv6 multicast group `group`.
+fn multicast_mac(group: Ipv6Addr) -> [u8; 6] {
+    let octets = group.octets();
+    [0x33, 0x33, octets[12], octets[13], octets[14], octets[15]]
+}
+
+/// Returns the source link-layer address option of the solicitation `icmp`, if any.
+fn source_link_layer(icmp: &[u8]) -> Option<[u8; 6]> {
+    let start = match icmp[0] {
+        NEIGHBOR_SOLICITATION => 24,
+        _ => 8,
+    };
+    let mut options = icmp.get(start..)?;
+    while let [kind, len, ..] = *options {
+        let len = len as usize * 8;
+        if len == 0 || options.len() < len {
+            return None;
+        }
+        if kind == OPTION_SOURCE_LINK_LAYER && len == 8 {
+            return options[2..8].try_into().ok();
+        }
+        options = &options[len..];
+    }
+    None
+}
+
+/// Returns the frame of the unsolicited Neighbor Advertisement announcing that `ip` is at
+/// `mac`.
+fn announcement(ip: Ipv6Addr, mac: [u8; 6]) -> Vec<u8> {
+    ndp_frame(
+        multicast_mac(ALL_NODES),
+        mac,
+        &ETHERTYPE_IPV6.to_be_bytes(),
+        ip,
+        ALL_NODES,
+        &neighbor_advertisement(ip, mac, FLAG_OVERRIDE),
+    )
+}
+
+/// Returns the ICMPv6 message of the Neighbor Advertisement that `target` is at `mac`.
+fn neighbor_advertisement(target: Ipv6Addr, mac: [u8; 6], flags: u8) -> Vec<u8> {
+    let mut icmp = vec![NEIGHBOR_ADVERTISEMENT, 0, 0, 0, flags, 0, 0, 0];
+    icmp.extend_from_slice(&target.octets());
+    icmp.extend_from_slice(&[OPTION_TARGET_LINK_LAYER, 1]);
+    icmp.extend_from_slice(&mac);
+    icmp
+}
+
+/// Returns the ICMPv6 message of the Router Advertisement of `router`.
+fn advertisement(router: &Router) -> Vec<u8> {
+    let lifetime = router.lifetime.as_secs().min(9000) as u16;
+    let mut icmp = vec![ROUTER_ADVERTISEMENT, 0, 0, 0, 64, 0];
+    icmp.extend_from_slice(&lifetime.to_be_bytes());
+    // Reachable time and retransmission timer, left to the hosts
+    icmp.extend_from_slice(&[0; 8]);
+    icmp.extend_from_slice(&[OPTION_SOURCE_LINK_LAYER, 1]);
+    icmp.extend_from_slice(&router.mac);
+    for &(prefix, len) in &router.prefixes {
+        icmp.extend_from_slice(&[OPTION_PREFIX_INFORMATION, 4, len, PREFIX_FLAGS]);
+        icmp.extend_from_slice(&VALID_LIFETIME.to_be_bytes());
+        icmp.extend_from_slice(&PREFERRED_LIFETIME.to_be_bytes());
+        icmp.extend_from_slice(&[0; 4]);
+        icmp.extend_from_slice(&prefix.octets());
+    }
+    icmp
+}
+
+/// Returns the Ethernet frame of the NDP message `icmp` from `source` to `destination`, with
+/// the VLAN tags and EtherType `tags`.
+fn ndp_frame(
+    destination_mac: [u8; 6],
+    source_mac: [u8; 6],
+    tags: &[u8],
+    source: Ipv6Addr,
+    destination: Ipv6Addr,
+    icmp: &[u8],
+) -> Vec<u8> {
+    let mut packet = vec![0u8; 40];
+    packet[0] = 0x60;
+    packet[4..6].copy_from_slice(&(icmp.len() as u16).to_be_bytes());
+    packet[6] = IPPROTO_ICMPV6;
+    packet[7] = HOP_LIMIT;
+    packet[8..24].copy_from_slice(&source.octets());
+    packet[24..40].copy_from_slice(&destination.octets());
+    packet.extend_from_slice(icmp);
+    checksum::fill(&mut packet);
+
+    let mut frame = Vec::with_capacity(12 + tags.len() + packet.len());
+    frame.extend_from_slice(&destination_mac);
+    frame.extend_from_slice(&source_mac);
+    frame.extend_from_slice(tags);
+    frame.extend_from_slice(&packet);
+    frame
+}
@@ -0,0 +1,395 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::faulty::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+/// Number of packets held by a direction of the link when no limit is set, as in `tc-netem`.
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Size of the buffer receiving the packets of the wrapped device, which fits any packet.
+const RECV_BUFFER_SIZE: usize = 65536;
+
+/// Represents the impairments of the link, applied to both directions.
+#[derive(Debug, Clone, Copy)]
+struct Impairments {
+    delay: Duration,
+    jitter: Duration,
+    loss: f64,
+    duplicate: f64,
+    reorder: f64,
+    limit: usize,
+}
+
+/// Represents the impairments and the random generator drawing them.
+#[derive(Debug)]
+struct Link {
+    impairments: Impairments,
+    rng: Rng,
+}
+
+impl Link {
+    /// Returns the delays of the copies of a packet: none if it is lost, two if it is
+    /// duplicated.
+    fn delays(&mut self) -> [Option<Duration>; 2] {
+        if self.rng.happens(self.impairments.loss) {
+            return [None, None];
+        }
+        let first = Some(self.delay());
+        let second = self
+            .rng
+            .happens(self.impairments.duplicate)
+            .then(|| self.delay());
+        [first, second]
+    }
+
+    /// Returns the delay of a copy of a packet, which is zero if it is reordered.
+    fn delay(&mut self) -> Duration {
+        if self.rng.happens(self.impairments.reorder) {
+            return Duration::ZERO;
+        }
+        let Impairments { delay, jitter, .. } = self.impairments;
+        let offset = (self.rng.uniform() * 2.0 - 1.0) * jitter.as_secs_f64();
+        Duration::from_secs_f64((delay.as_secs_f64() + offset).max(0.0))
+    }
+}
+
+/// Represents a device which emulates a bad link in front of the device it wraps, with
+/// configurable latency, jitter, loss, duplication and reordering in both directions, like
+/// `tc-netem` without root privileges.
+///
+/// Each direction is a queue driven by tokio timers in background tasks: a packet is delayed by
+/// the latency plus or minus a uniformly drawn jitter, so that jitter larger than the gap
+/// between two packets reorders them. A reordered packet skips the delay instead, as in
+/// `tc-netem`, so reordering needs a latency to have an effect. A direction holds at most
+/// [`limit`](#method.limit) packets, and drops the packets beyond it. The received packets
+/// which wait to be read also count against the limit: packets arriving while `limit` of them
+/// wait are dropped.
+///
+/// Sending returns once the packet is queued, and waits while the wrapped device does not
+/// accept the packets due and `limit` packets wait for it; if sending a delayed packet to the wrapped
+/// device fails, the error is returned by the next send, and the following ones fail with
+/// `io::ErrorKind::BrokenPipe`. Likewise, an error receiving from the wrapped device is
+/// returned by `recv`, after which it fails with `io::ErrorKind::BrokenPipe`. The packets in
+/// flight are dropped with this value.
+///
+/// The impairments are drawn from a pseudo-random generator, whose [`seed`](#method.seed) makes
+/// a run reproducible.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tokio_tun::{NetEmu, TestTun};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let (tun, handle) = TestTun::new("tun0");
+/// let tun = NetEmu::new(tun)
+///     .delay(Duration::from_millis(50))
+///     .jitter(Duration::from_millis(10))
+///     .loss(0.01)
+///     .reorder(0.05);
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NetEmu<T> {
+    inner: Arc<T>,
+    link: Arc<Mutex<Link>>,
+    tx: mpsc::Sender<Vec<u8>>,
+    rx: tokio::sync::Mutex<mpsc::Receiver<io::Result<Vec<u8>>>>,
+    write_error: Arc<Mutex<Option<io::Error>>>,
+    tasks: [JoinHandle<()>; 3],
+}
+
+impl<T: fmt::Debug> fmt::Debug for NetEmu<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetEmu")
+            .field("inner", &self.inner)
+            .field("impairments", &self.link.lock().unwrap().impairments)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice + 'static> NetEmu<T> {
+    /// Wraps `inner`, without impairments, and spawns the tasks driving the link.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn new(inner: T) -> Self {
+        let inner = Arc::new(inner);
+        let link = Arc::new(Mutex::new(Link {
+            impairments: Impairments {
+                delay: Duration::ZERO,
+                jitter: Duration::ZERO,
+                loss: 0.0,
+                duplicate: 0.0,
+                reorder: 0.0,
+                limit: DEFAULT_LIMIT,
+            },
+            rng: Rng::default(),
+        }));
+        let write_error = Arc::new(Mutex::new(None));
+        let (tx, rx, tasks) = Self::spawn(&inner, &link, &write_error, DEFAULT_LIMIT);
+        Self {
+            inner,
+            link,
+            tx,
+            rx: tokio::sync::Mutex::new(rx),
+            write_error,
+            tasks,
+        }
+    }
+
+    /// Spawns the tasks driving the link, whose channels hold at most `limit` packets, and
+    /// returns the ends of the channels of the application along with the tasks.
+    #[allow(clippy::type_complexity)]
+    fn spawn(
+        inner: &Arc<T>,
+        link: &Arc<Mutex<Link>>,
+        write_error: &Arc<Mutex<Option<io::Error>>>,
+        limit: usize,
+    ) -> (
+        mpsc::Sender<Vec<u8>>,
+        mpsc::Receiver<io::Result<Vec<u8>>>,
+        [JoinHandle<()>; 3],
+    ) {
+        // A channel cannot be empty
+        let capacity = limit.max(1);
+
+        let (tx, outbound) = mpsc::channel::<Vec<u8>>(capacity);
+        let sender = {
+            let inner = inner.clone();
+            let write_error = write_error.clone();
+            tokio::spawn(run_link(link.clone(), outbound, move |packet| {
+                let inner = inner.clone();
+                let write_error = write_error.clone();
+                async move {
+                    match inner.send(&packet).await {
+                        Ok(_) => true,
+                        Err(err) => {
+                            *write_error.lock().unwrap() = Some(err);
+                            false
+                        }
+                    }
+                }
+            }))
+        };
+
+        let (inbound_tx, inbound) = mpsc::channel(capacity);
+        let (received_tx, rx) = mpsc::channel(capacity);
+        let reader = tokio::spawn(read(inner.clone(), inbound_tx, received_tx.clone()));
+        let receiver = tokio::spawn(run_link(link.clone(), inbound, move |packet| {
+            // The packets which do not fit are dropped, as the application reads too slowly
+            let sent = !matches!(
+                received_tx.try_send(Ok(packet)),
+                Err(mpsc::error::TrySendError::Closed(_))
+            );
+            async move { sent }
+        }));
+        (tx, rx, [sender, reader, receiver])
+    }
+
+    /// Sets the seed of the impairments, so that different runs draw different impairments.
+    pub fn seed(self, seed: u64) -> Self {
+        self.link.lock().unwrap().rng = Rng::new(seed);
+        self
+    }
+
+    /// Sets the latency added to each packet.
+    pub fn delay(self, delay: Duration) -> Self {
+        self.link.lock().unwrap().impairments.delay = delay;
+        self
+    }
+
+    /// Sets the largest variation of the latency, drawn uniformly above or below it.
+    pub fn jitter(self, jitter: Duration) -> Self {
+        self.link.lock().unwrap().impairments.jitter = jitter;
+        self
+    }
+
+    /// Sets the probability that a packet is lost.
+    pub fn loss(self, p: f64) -> Self {
+        self.link.lock().unwrap().impairments.loss = p;
+        self
+    }
+
+    /// Sets the probability that a packet is delivered twice, each copy with its own delay.
+    pub fn duplicate(self, p: f64) -> Self {
+        self.link.lock().unwrap().impairments.duplicate = p;
+        self
+    }
+
+    /// Sets the probability that a packet skips the latency, overtaking the packets in flight.
+    pub fn reorder(self, p: f64) -> Self {
+        self.link.lock().unwrap().impairments.reorder = p;
+        self
+    }
+
+    /// Sets the number of packets held by each direction of the link, beyond which the packets
+    /// are dropped.
+    ///
+    /// The channels of the link are sized from the limit, so the link is restarted, dropping the
+    /// packets in flight.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.link.lock().unwrap().impairments.limit = limit;
+        self.abort();
+        let (tx, rx, tasks) = Self::spawn(&self.inner, &self.link, &self.write_error, limit);
+        self.tx = tx;
+        self.rx = tokio::sync::Mutex::new(rx);
+        self.tasks = tasks;
+        self
+    }
+
+    /// Receives a packet from the device once it has crossed the link. A packet larger than
+    /// `buf` is truncated.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let packet = match self.rx.lock().await.recv().await {
+            Some(res) => res?,
+            None => return Err(ErrorKind::BrokenPipe.into()),
+        };
+        let n = packet.len().min(buf.len());
+        buf[..n].copy_from_slice(&packet[..n]);
+        Ok(n)
+    }
+
+    /// Queues a packet to cross the link to the device. Returns the length of the packet, which
+    /// is reported as sent even if it is lost on the way.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        if self.tx.send(buf.to_vec()).await.is_ok() {
+            return Ok(buf.len());
+        }
+        let err = self.write_error.lock().unwrap().take();
+        Err(err.unwrap_or_else(|| ErrorKind::BrokenPipe.into()))
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> NetEmu<T> {
+    /// Stops the tasks driving the link.
+    fn abort(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl<T> Drop for NetEmu<T> {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+/// Receives the packets of `inner` into the link `inbound`, until receiving fails, in which
+/// case the error skips the link to `received`.
+async fn read<T: AsyncTunDevice>(
+    inner: Arc<T>,
+    inbound: mpsc::Sender<Vec<u8>>,
+    received: mpsc::Sender<io::Result<Vec<u8>>>,
+) {
+    let mut buf = vec![0u8; RECV_BUFFER_SIZE];
+    loop {
+        match inner.recv(&mut buf).await {
+            Ok(n) => {
+                if inbound.send(buf[..n].to_vec()).await.is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                let _ = received.send(Err(err)).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Runs a direction of the link: delays the packets of `input` according to the impairments,
+/// and passes them to `deliver` in order of their deadlines, until `input` is closed or
+/// `deliver` returns `false`.
+async fn run_link<F, Fut>(
+    link: Arc<Mutex<Link>>,
+    mut input: mpsc::Receiver<Vec<u8>>,
+    mut deliver: F,
+) where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    // The sequence number keeps the order of the packets due at the same time
+    let mut queue = BinaryHeap::<Reverse<(Instant, u64, Vec<u8>)>>::new();
+    let mut seq = 0u64;
+    loop {
+        let deadline = queue.peek().map(|Reverse((deadline, _, _))| *deadline);
+        tokio::select! {
+            packet = input.recv() => {
+                let Some(packet) = packet else {
+                    return;
+                };
+                let (delays, limit) = {
+                    let mut link = link.lock().unwrap();
+                    (link.delays(), link.impairments.limit)
+                };
+                let now = Instant::now();
+                let mut push = |delay, packet| {
+                    if queue.len() < limit {
+                        queue.push(Reverse((now + delay, seq, packet)));
+                        seq += 1;
+                    }
+                };
+                match delays {
+                    [Some(first), Some(second)] => {
+                        push(first, packet.clone());
+                        push(second, packet);
+                    }
+                    [Some(first), None] => push(first, packet),
+                    _ => {}
+                }
+            }
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                let Reverse((_, _, packet)) = queue.pop().unwrap();
+                if !deliver(packet).await {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl<T: AsyncTunDevice + 'static> AsyncTunDevice for NetEmu<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        NetEmu::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        NetEmu::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::{self, ErrorKind};
+
+/// Length of [`PacketInfo`] on the wire.
+pub const PACKET_INFO_LEN: usize = 4;
+
+/// The packet was truncated because the buffer was too small.
+pub const TUN_PKT_STRIP: u16 = 0x0001;
+
+/// Represents the `tun_pi` structure which precedes the packets of devices created with
+/// [`TunBuilder::packet_info`](struct.TunBuilder.html#method.packet_info) on Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketInfo {
+    /// Flags of the packet, e.g. [`TUN_PKT_STRIP`].
+    pub flags: u16,
+    /// EtherType of the packet, e.g. `0x0800` for IPv4 and `0x86dd` for IPv6.
+    pub proto: u16,
+}
+
+impl PacketInfo {
+    /// Parses the header from the beginning of `buf`.
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        if buf.len() < PACKET_INFO_LEN {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "packet is shorter than the packet information header",
+            ));
+        }
+        Ok(Self {
+            flags: u16::from_ne_bytes([buf[0], buf[1]]),
+            proto: u16::from_be_bytes([buf[2], buf[3]]),
+        })
+    }
+
+    /// Encodes the header into the beginning of `buf`, which must be at least
+    /// [`PACKET_INFO_LEN`] bytes long.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&self.flags.to_ne_bytes());
+        buf[2..4].copy_from_slice(&self.proto.to_be_bytes());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::dot1q::{self, VlanTags};
+use etherparse::err::packet::SliceError;
+use etherparse::{NetSlice, SlicedPacket, TransportSlice};
+use std::cell::OnceCell;
+use std::fmt;
+
+/// Represents a packet received by [`Tun::recv_parsed`](struct.Tun.html#method.recv_parsed), with
+/// a view of its headers parsed by [`etherparse`] on first use (feature `etherparse`).
+///
+/// The headers are those of Ethernet on a Tap device, and start at the IP header on a Tun device.
+/// The device must be created without packet information and offload, whose headers would not be
+/// understood.
+pub struct ParsedPacket<'a> {
+    data: &'a [u8],
+    tap: bool,
+    headers: OnceCell<Result<SlicedPacket<'a>, SliceError>>,
+}
+
+impl<'a> ParsedPacket<'a> {
+    pub(crate) fn new(data: &'a [u8], tap: bool) -> Self {
+        Self {
+            data,
+            tap,
+            headers: OnceCell::new(),
+        }
+    }
+
+    /// Returns the bytes of the packet.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the length of the packet.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the packet is empty.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the headers of the packet, which are parsed by the first call.
+    pub fn headers(&self) -> Result<&SlicedPacket<'a>, &SliceError> {
+        self.headers
+            .get_or_init(|| {
+                if self.tap {
+                    SlicedPacket::from_ethernet(self.data)
+                } else {
+                    SlicedPacket::from_ip(self.data)
+                }
+            })
+            .as_ref()
+    }
+
+    /// Returns the IP (or ARP) header of the packet, if it could be parsed.
+    pub fn net(&self) -> Option<&NetSlice<'a>> {
+        self.headers().ok()?.net.as_ref()
+    }
+
+    /// Returns the VLAN tags of the frame of a Tap device, if it could be parsed, which tell the
+    /// service tag from the customer tag of a double-tagged frame (see the
+    /// [`dot1q`](dot1q/index.html) module).
+    pub fn vlan_tags(&self) -> Option<VlanTags> {
+        if !self.tap {
+            return None;
+        }
+        dot1q::parse(self.data).ok()
+    }
+
+    /// Returns the TCP, UDP or ICMP header of the packet, if it could be parsed.
+    pub fn transport(&self) -> Option<&TransportSlice<'a>> {
+        self.headers().ok()?.transport.as_ref()
+    }
+}
+
+impl AsRef<[u8]> for ParsedPacket<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl fmt::Debug for ParsedPacket<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParsedPacket")
+            .field("len", &self.data.len())
+            .field("tap", &self.tap)
+            .field("headers", &self.headers.get())
+            .finish()
+    }
+}
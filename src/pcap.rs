@@ -0,0 +1,335 @@
+use crate::AsyncTunDevice;
+use crate::Result;
+use crate::TunConfig;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const SNAPLEN: u32 = 65535;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_EPB: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+const EPB_FLAGS_INBOUND: u32 = 1;
+const EPB_FLAGS_OUTBOUND: u32 = 2;
+
+/// Represents the format of a recording.
+#[derive(Clone, Copy)]
+enum Format {
+    Pcap,
+    Pcapng,
+}
+
+/// Represents the direction of a packet, from the point of view of the device: the packets
+/// received from the device were sent by the host through it.
+#[derive(Clone, Copy)]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Represents a device whose received and sent packets are recorded into a pcap or pcapng
+/// file, which tcpdump and Wireshark can read.
+///
+/// The pcapng format also records the name and the MTU of the device, nanosecond timestamps,
+/// and the direction of each packet as tcpdump sees it on the device: the packets received from
+/// the device are outbound (sent by the host), and the packets sent to the device are inbound.
+///
+/// The packets of a Tun device are recorded as raw IP packets, and the frames of a Tap device as
+/// Ethernet frames. The device must not be created with packet information or offload, whose
+/// headers would be recorded as part of the packets.
+///
+/// Recording can be started and stopped at any time, from any task. The packets are written
+/// synchronously to a buffer which is flushed when recording stops (or the recorder is dropped),
+/// so this is meant for debugging rather than for capturing heavy traffic.
+///
+/// ```no_run
+/// use tokio_tun::{PcapRecorder, TunBuilder};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+/// let tun = PcapRecorder::new(tun)?;
+/// tun.record("tun.pcap")?;
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// tun.stop()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PcapRecorder<T> {
+    inner: T,
+    linktype: u32,
+    sink: Mutex<Sink>,
+}
+
+/// Represents the current recording, if any, and the error which stopped it early.
+#[derive(Default)]
+struct Sink {
+    writer: Option<(Box<dyn Write + Send>, Format)>,
+    error: Option<io::Error>,
+}
+
+impl Sink {
+    /// Records a packet. An error stops the recording; it is reported by `stop`.
+    fn write(&mut self, packet: &[u8], direction: Direction) {
+        let Some((writer, format)) = &mut self.writer else {
+            return;
+        };
+        let res = match format {
+            Format::Pcap => write_record(writer, packet),
+            Format::Pcapng => write_block(writer, packet, direction),
+        };
+        if let Err(err) = res {
+            self.writer = None;
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<T: AsyncTunDevice> PcapRecorder<T> {
+    /// Wraps `inner`, without recording yet.
+    pub fn new(inner: T) -> Result<Self> {
+        Ok(Self {
+            linktype: linktype(&inner.config()?),
+            inner,
+            sink: Mutex::default(),
+        })
+    }
+
+    /// Starts recording into the file at `path`, which is created or truncated. A recording in
+    /// progress is stopped first.
+    pub fn record(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.record_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Starts recording into `writer`. A recording in progress is stopped first.
+    pub fn record_to(&self, mut writer: impl Write + Send + 'static) -> io::Result<()> {
+        self.stop()?;
+        write_header(&mut writer, self.linktype)?;
+        self.sink.lock().unwrap().writer = Some((Box::new(writer), Format::Pcap));
+        Ok(())
+    }
+
+    /// Starts recording into the pcapng file at `path`, which is created or truncated. A
+    /// recording in progress is stopped first.
+    pub fn record_pcapng(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.record_pcapng_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Starts recording into `writer` in the pcapng format. A recording in progress is stopped
+    /// first.
+    pub fn record_pcapng_to(&self, mut writer: impl Write + Send + 'static) -> io::Result<()> {
+        self.stop()?;
+        let mtu = self.inner.mtu()?;
+        write_section(&mut writer, self.linktype, self.inner.name(), mtu)?;
+        self.sink.lock().unwrap().writer = Some((Box::new(writer), Format::Pcapng));
+        Ok(())
+    }
+
+    /// Stops recording and flushes the recorded packets. Returns the error which stopped the
+    /// recording early, if any.
+    pub fn stop(&self) -> io::Result<()> {
+        let mut sink = self.sink.lock().unwrap();
+        if let Some(err) = sink.error.take() {
+            sink.writer = None;
+            return Err(err);
+        }
+        match sink.writer.take() {
+            Some((mut writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` if the packets are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.sink.lock().unwrap().writer.is_some()
+    }
+
+    /// Receives a packet from the device and records it.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.recv(buf).await?;
+        self.sink
+            .lock()
+            .unwrap()
+            .write(&buf[..n], Direction::Outbound);
+        Ok(n)
+    }
+
+    /// Sends a buffer to the device and records the part which was sent.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.send(buf).await?;
+        self.sink
+            .lock()
+            .unwrap()
+            .write(&buf[..n], Direction::Inbound);
+        Ok(n)
+    }
+
+    /// Sends all of a buffer to the device and records it.
+    pub async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        self.inner.send_all(buf).await?;
+        self.sink.lock().unwrap().write(buf, Direction::Inbound);
+        Ok(())
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Stops recording and returns the wrapped device.
+    pub fn into_inner(self) -> T {
+        let _ = self.stop();
+        self.inner
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for PcapRecorder<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        PcapRecorder::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        PcapRecorder::send(self, buf).await
+    }
+
+    async fn send_all(&self, buf: &[u8]) -> io::Result<()> {
+        PcapRecorder::send_all(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+}
+
+/// Returns the link type of the packets of a device of configuration `config`.
+pub(crate) fn linktype(config: &TunConfig) -> u32 {
+    if config.tap {
+        LINKTYPE_ETHERNET
+    } else {
+        LINKTYPE_RAW
+    }
+}
+
+/// Writes the global header of a pcap file.
+pub(crate) fn write_header(writer: &mut impl Write, linktype: u32) -> io::Result<()> {
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&PCAP_MAGIC.to_ne_bytes());
+    header.extend_from_slice(&2u16.to_ne_bytes());
+    header.extend_from_slice(&4u16.to_ne_bytes());
+    header.extend_from_slice(&0i32.to_ne_bytes());
+    header.extend_from_slice(&0u32.to_ne_bytes());
+    header.extend_from_slice(&SNAPLEN.to_ne_bytes());
+    header.extend_from_slice(&linktype.to_ne_bytes());
+    writer.write_all(&header)
+}
+
+/// Writes a packet record, timestamped now.
+pub(crate) fn write_record(writer: &mut impl Write, packet: &[u8]) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let len = packet.len() as u32;
+    let captured = len.min(SNAPLEN);
+    let mut header = [0u8; 16];
+    header[0..4].copy_from_slice(&(now.as_secs() as u32).to_ne_bytes());
+    header[4..8].copy_from_slice(&now.subsec_micros().to_ne_bytes());
+    header[8..12].copy_from_slice(&captured.to_ne_bytes());
+    header[12..16].copy_from_slice(&len.to_ne_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(&packet[..captured as usize])
+}
+
+/// Writes the section header block and the interface description block of a pcapng file.
+fn write_section(writer: &mut impl Write, linktype: u32, name: &str, mtu: i32) -> io::Result<()> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_ne_bytes());
+    body.extend_from_slice(&1u16.to_ne_bytes());
+    body.extend_from_slice(&0u16.to_ne_bytes());
+    // The length of the section is unknown
+    body.extend_from_slice(&(-1i64).to_ne_bytes());
+    push_option(
+        &mut body,
+        OPT_SHB_USERAPPL,
+        env!("CARGO_PKG_NAME").as_bytes(),
+    );
+    push_option(&mut body, OPT_ENDOFOPT, &[]);
+    write_pcapng_block(writer, PCAPNG_SHB, &body)?;
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(linktype as u16).to_ne_bytes());
+    body.extend_from_slice(&0u16.to_ne_bytes());
+    body.extend_from_slice(&SNAPLEN.to_ne_bytes());
+    push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+    push_option(
+        &mut body,
+        OPT_IF_DESCRIPTION,
+        format!("mtu {mtu}").as_bytes(),
+    );
+    // Timestamps in nanoseconds
+    push_option(&mut body, OPT_IF_TSRESOL, &[9]);
+    push_option(&mut body, OPT_ENDOFOPT, &[]);
+    write_pcapng_block(writer, PCAPNG_IDB, &body)
+}
+
+/// Writes an enhanced packet block, timestamped now.
+fn write_block(writer: &mut impl Write, packet: &[u8], direction: Direction) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let len = packet.len() as u32;
+    let captured = len.min(SNAPLEN) as usize;
+    let mut body = Vec::with_capacity(32 + captured);
+    body.extend_from_slice(&0u32.to_ne_bytes());
+    body.extend_from_slice(&((now >> 32) as u32).to_ne_bytes());
+    body.extend_from_slice(&(now as u32).to_ne_bytes());
+    body.extend_from_slice(&(captured as u32).to_ne_bytes());
+    body.extend_from_slice(&len.to_ne_bytes());
+    body.extend_from_slice(&packet[..captured]);
+    body.resize(body.len().next_multiple_of(4), 0);
+    let flags = match direction {
+        Direction::Inbound => EPB_FLAGS_INBOUND,
+        Direction::Outbound => EPB_FLAGS_OUTBOUND,
+    };
+    push_option(&mut body, OPT_EPB_FLAGS, &flags.to_ne_bytes());
+    push_option(&mut body, OPT_ENDOFOPT, &[]);
+    write_pcapng_block(writer, PCAPNG_EPB, &body)
+}
+
+/// Writes a pcapng block of type `ty`, whose length is repeated after its (aligned) body.
+fn write_pcapng_block(writer: &mut impl Write, ty: u32, body: &[u8]) -> io::Result<()> {
+    let len = (12 + body.len()) as u32;
+    writer.write_all(&ty.to_ne_bytes())?;
+    writer.write_all(&len.to_ne_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&len.to_ne_bytes())
+}
+
+/// Appends an option to the body of a pcapng block, padded to 4 bytes.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_ne_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len().next_multiple_of(4), 0);
+}
@@ -0,0 +1,251 @@
+//! Support for thread-per-core runtimes built on `io_uring` (Linux only, feature `monoio`).
+//!
+//! Thread-per-core runtimes such as `monoio` run one single-threaded runtime per CPU and their
+//! I/O handles are bound to the ring of the thread which created them, so they are `!Send`.
+//! [`TunBuilder::build_per_core`](../struct.TunBuilder.html#method.build_per_core) therefore
+//! returns one `Send` [`TunQueue`](struct.TunQueue.html) per queue, which is moved to the thread
+//! of its core and [registered](struct.TunQueue.html#method.register) there with the local
+//! ring:
+//!
+//! ```no_run
+//! # fn main() -> tokio_tun::Result<()> {
+//! use tokio_tun::TunBuilder;
+//! use tokio_tun::affinity::pin_current_thread;
+//!
+//! let queues = TunBuilder::new().queues(4).up().build_per_core()?;
+//! let workers = queues
+//!     .into_iter()
+//!     .map(|queue| {
+//!         std::thread::spawn(move || {
+//!             pin_current_thread(queue.cpu()).unwrap();
+//!             monoio::RuntimeBuilder::<monoio::IoUringDriver>::new()
+//!                 .build()
+//!                 .unwrap()
+//!                 .block_on(async move {
+//!                     let tun = queue.register().unwrap();
+//!                     let mut buf = vec![0u8; tun.recommended_buffer_size().unwrap()];
+//!                     loop {
+//!                         let (res, b) = tun.recv(buf).await;
+//!                         let n = res.unwrap();
+//!                         buf = b;
+//!                         println!("cpu {}: {:?}", tun.cpu(), &buf[..n]);
+//!                     }
+//!                 })
+//!         })
+//!     })
+//!     .collect::<Vec<_>>();
+//! # drop(workers);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::CloseOptions;
+use crate::Result;
+use crate::TunConfig;
+use crate::device;
+use crate::linux::interface::Interface;
+use crate::linux::io::TunIo;
+use crate::linux::params::Params;
+use monoio::BufResult;
+use monoio::buf::{IoBuf, IoBufMut};
+use monoio::fs::File;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::sync::Arc;
+use std::thread;
+
+/// Represents a queue of a Tun/Tap device which is not yet registered with the ring of a
+/// thread-per-core runtime.
+///
+/// It is `Send`, so that it can be moved to the thread of the core which serves it.
+pub struct TunQueue {
+    iface: Arc<Interface>,
+    io: TunIo,
+    /// Index of the queue among the queues of device.
+    queue: usize,
+    cpu: usize,
+}
+
+impl AsRawFd for TunQueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.io.as_raw_fd()
+    }
+}
+
+impl TunQueue {
+    /// Creates new queues of Tun/Tap device, assigning queue `N` to CPU `N`.
+    pub(crate) fn new(params: Params, queues: usize) -> Result<Vec<Self>> {
+        let (iface, fds) = device::allocate(params, queues)?;
+        let iface = Arc::new(iface);
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut tuns = Vec::with_capacity(queues);
+        for (queue, fd) in fds.into_iter().enumerate() {
+            tuns.push(Self {
+                iface: iface.clone(),
+                io: TunIo::from(fd),
+                queue,
+                cpu: queue % cpus,
+            });
+        }
+        Ok(tuns)
+    }
+
+    /// Returns the index of this queue among the queues of device, in the order in which
+    /// [`TunBuilder::build_per_core`](struct.TunBuilder.html#method.build_per_core) returned
+    /// them, which is also the CPU of the queue on machines with enough CPUs.
+    pub fn queue_index(&self) -> usize {
+        self.queue
+    }
+
+    /// Returns the number of queues of device, as built.
+    pub fn total_queues(&self) -> usize {
+        self.iface.queues()
+    }
+
+    /// Returns the CPU whose thread is expected to serve this queue.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+
+    /// Steers the packets sent through a multi-queue device by the CPU which sends them, so that
+    /// queue `N` receives the packets of CPU `N`.
+    pub fn steer_by_cpu(&self) -> Result<()> {
+        self.iface.steer_by_cpu(self.io.as_fd())
+    }
+
+    /// Registers the queue with the ring of the current thread.
+    ///
+    /// It must be called inside a `monoio` runtime using the `io_uring` driver; the returned
+    /// handle can only be used on this thread.
+    pub fn register(self) -> io::Result<MonoioTun> {
+        let fd = self.io.as_raw_fd();
+        // io_uring arms a poll itself for blocking files, but fails non-blocking ones early
+        device::set_nonblocking(fd, false)?;
+        let file = unsafe { fs::File::from_raw_fd(self.io.into_raw_fd()) };
+        Ok(MonoioTun {
+            iface: self.iface,
+            file: File::from_std(file)?,
+            cpu: self.cpu,
+        })
+    }
+}
+
+/// Represents a queue of a Tun/Tap device registered with the ring of a `monoio` runtime.
+///
+/// It is `!Send` and bound to the thread which registered it. The buffers are passed by value
+/// and returned with the result, as is usual for completion-based I/O.
+pub struct MonoioTun {
+    iface: Arc<Interface>,
+    file: File,
+    cpu: usize,
+}
+
+impl AsRawFd for MonoioTun {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl MonoioTun {
+    /// Closes the Tun/Tap device and reports any error, unlike dropping it.
+    ///
+    /// The device keeps its state and its persistent flag, so that the kernel removes it once
+    /// its last file descriptor is closed unless it is persistent. See
+    /// [`close_with`](#method.close_with) to tear it down.
+    pub async fn close(self) -> Result<()> {
+        self.close_with(CloseOptions::default()).await
+    }
+
+    /// Closes the Tun/Tap device and reports any error, tearing it down as `options` tell if
+    /// this is the last handle of the device, before its file descriptor and control socket are
+    /// closed. Otherwise only the file descriptor of this queue is closed.
+    pub async fn close_with(self, options: CloseOptions) -> Result<()> {
+        match Arc::try_unwrap(self.iface) {
+            Ok(iface) => {
+                iface.shutdown(
+                    unsafe { BorrowedFd::borrow_raw(self.file.as_raw_fd()) },
+                    options,
+                )?;
+                self.file.close().await?;
+                iface.close()
+            }
+            Err(_) => Ok(self.file.close().await?),
+        }
+    }
+
+    /// Receives a packet from the Tun/Tap interface into `buf`.
+    pub async fn recv<T: IoBufMut>(&self, buf: T) -> BufResult<usize, T> {
+        // The offset is ignored by Tun/Tap devices
+        self.file.read_at(buf, 0).await
+    }
+
+    /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
+    pub async fn send<T: IoBuf>(&self, buf: T) -> BufResult<usize, T> {
+        self.file.write_at(buf, 0).await
+    }
+
+    /// Sends all of a buffer to the Tun/Tap interface.
+    pub async fn send_all<T: IoBuf>(&self, buf: T) -> BufResult<(), T> {
+        self.file.write_all_at(buf, 0).await
+    }
+
+    /// Returns the CPU whose thread is expected to serve this queue.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Returns the minimum size of a receive buffer which can hold any packet of the device
+    /// without truncation.
+    pub fn recommended_buffer_size(&self) -> Result<usize> {
+        device::recommended_buffer_size(&self.iface)
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    ///
+    /// Addresses which are not assigned to the device are reported as `None`.
+    pub fn config(&self) -> Result<TunConfig> {
+        device::config(&self.iface)
+    }
+
+    /// Returns the value of MTU.
+    pub fn mtu(&self) -> Result<i32> {
+        self.iface.mtu(None)
+    }
+
+    /// Returns the IPv4 address of device.
+    pub fn address(&self) -> Result<Ipv4Addr> {
+        self.iface.address(None)
+    }
+
+    /// Returns the IPv4 destination address of device.
+    pub fn destination(&self) -> Result<Ipv4Addr> {
+        self.iface.destination(None)
+    }
+
+    /// Returns the IPv4 broadcast address of device.
+    pub fn broadcast(&self) -> Result<Ipv4Addr> {
+        self.iface.broadcast(None)
+    }
+
+    /// Returns the IPv4 netmask address of device.
+    pub fn netmask(&self) -> Result<Ipv4Addr> {
+        self.iface.netmask(None)
+    }
+
+    /// Returns the flags of device.
+    pub fn flags(&self) -> Result<i16> {
+        self.iface.flags(None)
+    }
+}
@@ -0,0 +1,171 @@
+//! [`smoltcp`] integration (feature `smoltcp`).
+//!
+//! [`Tun`](../struct.Tun.html) and [`SyncTun`](../struct.SyncTun.html) implement
+//! [`smoltcp::phy::Device`], so that a userspace TCP/IP stack can run directly on a device: Tun
+//! devices use the IP medium, and Tap devices the Ethernet medium.
+//!
+//! The devices must be created without packet information and offload, whose headers smoltcp
+//! would not understand. [`Device::receive`] does not wait: it returns `None` when no packet is
+//! available, so a `SyncTun` must be set non-blocking and the stack polled once the device is
+//! readable, e.g. with [`Tun::readable`](../struct.Tun.html#method.readable), or once
+//! [`smoltcp::iface::Interface::poll_delay`] elapsed.
+//!
+//! ```no_run
+//! use smoltcp::iface::{Config, Interface, SocketSet};
+//! use smoltcp::time::Instant;
+//! use smoltcp::wire::HardwareAddress;
+//! use tokio_tun::TunBuilder;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut tun = TunBuilder::new().up().build()?.pop().unwrap();
+//! let config = Config::new(HardwareAddress::Ip);
+//! let mut iface = Interface::new(config, &mut tun, Instant::now());
+//! let mut sockets = SocketSet::new(vec![]);
+//! loop {
+//!     iface.poll(Instant::now(), &mut tun, &mut sockets);
+//!     // Also wake up after `iface.poll_delay` to serve the timers of the sockets
+//!     tun.readable().await?;
+//! }
+//! # }
+//! ```
+
+use crate::SyncTun;
+use crate::device;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(feature = "tokio")]
+use crate::{PooledPacket, Tun};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+#[cfg(feature = "tokio")]
+use tokio::io::Interest;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// Returns the capabilities of the device `iface`: its medium and its MTU.
+///
+/// The checksums are computed and verified by smoltcp, since the packets of a device carry
+/// complete checksums.
+fn capabilities(iface: &Interface) -> DeviceCapabilities {
+    let tap = iface.is_tap().unwrap_or(false);
+    let mtu = iface.mtu(None).map_or(1500, |mtu| mtu as usize);
+    let mut caps = DeviceCapabilities::default();
+    if tap {
+        // The MTU of smoltcp includes the Ethernet header
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = mtu + ETHERNET_HEADER_LEN;
+    } else {
+        caps.medium = Medium::Ip;
+        caps.max_transmission_unit = mtu;
+    }
+    caps
+}
+
+/// Represents a packet received by [`Device::receive`] on a [`Tun`](../struct.Tun.html).
+#[cfg(feature = "tokio")]
+pub struct TunRxToken(PooledPacket);
+
+#[cfg(feature = "tokio")]
+impl phy::RxToken for TunRxToken {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(&self.0)
+    }
+}
+
+/// Represents a packet to send through a [`Tun`](../struct.Tun.html).
+///
+/// The packet is dropped if the device is not writable, as a network interface would.
+#[cfg(feature = "tokio")]
+pub struct TunTxToken<'a>(&'a Tun);
+
+#[cfg(feature = "tokio")]
+impl phy::TxToken for TunTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0; len];
+        let res = f(&mut buf);
+        let _ = self.0.try_send(&buf);
+        res
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Device for Tun {
+    type RxToken<'a> = TunRxToken;
+    type TxToken<'a> = TunTxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        // A read which would block clears the readiness awaited by `Tun::readable`
+        let packet = self
+            .io
+            .try_io(Interest::READABLE, |io| self.recv_pooled_from(io))
+            .ok()?;
+        Some((TunRxToken(packet), TunTxToken(self)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TunTxToken(self))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        capabilities(&self.iface)
+    }
+}
+
+/// Represents a packet received by [`Device::receive`] on a
+/// [`SyncTun`](../struct.SyncTun.html).
+pub struct SyncRxToken(Vec<u8>);
+
+impl phy::RxToken for SyncRxToken {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(&self.0)
+    }
+}
+
+/// Represents a packet to send through a [`SyncTun`](../struct.SyncTun.html).
+///
+/// The packet is dropped if the device is not writable, as a network interface would.
+pub struct SyncTxToken<'a>(&'a SyncTun);
+
+impl phy::TxToken for SyncTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0; len];
+        let res = f(&mut buf);
+        let _ = self.0.send(&buf);
+        res
+    }
+}
+
+impl Device for SyncTun {
+    type RxToken<'a> = SyncRxToken;
+    type TxToken<'a> = SyncTxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let size = device::recommended_buffer_size(&self.iface).unwrap_or(device::MAX_MTU);
+        let mut buf = vec![0; size + ETHERNET_HEADER_LEN];
+        let n = self.recv(&mut buf).ok()?;
+        buf.truncate(n);
+        Some((SyncRxToken(buf), SyncTxToken(self)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(SyncTxToken(self))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        capabilities(&self.iface)
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of idle buffers kept by a pool.
+const MAX_IDLE: usize = 64;
+
+/// Represents a pool of reusable receive buffers.
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    /// Size of the buffers, shared by the pools of the queues of a device so that resizing one
+    /// resizes them all.
+    size: Arc<AtomicUsize>,
+}
+
+impl BufferPool {
+    pub fn new(size: Arc<AtomicUsize>) -> Arc<Self> {
+        Arc::new(Self {
+            buffers: Mutex::new(Vec::new()),
+            size,
+        })
+    }
+
+    /// Returns the size of the buffers of the pool.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Changes the size of the buffers of the pool and of the pools sharing its size. Idle
+    /// buffers of another size are freed as they are taken or returned.
+    pub fn resize(&self, size: usize) {
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    /// Takes a buffer out of the pool, allocating a new one if the pool is empty.
+    pub fn take(&self) -> Vec<u8> {
+        let size = self.size();
+        let mut buffers = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+        while let Some(buf) = buffers.pop() {
+            if buf.len() == size {
+                return buf;
+            }
+        }
+        vec![0u8; size]
+    }
+
+    /// Returns a buffer to the pool, or frees it if the pool is full or it has another size.
+    pub fn put(&self, buf: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+        if buffers.len() < MAX_IDLE && buf.len() == self.size() {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// Represents a packet received by [`Tun::recv_pooled`](struct.Tun.html#method.recv_pooled).
+///
+/// It dereferences to the bytes of the packet and returns its buffer to the pool of the device
+/// on drop, so that no allocation is needed to receive subsequent packets.
+pub struct PooledPacket {
+    buf: Vec<u8>,
+    len: usize,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledPacket {
+    pub(crate) fn new(buf: Vec<u8>, len: usize, pool: Arc<BufferPool>) -> Self {
+        Self { buf, len, pool }
+    }
+}
+
+impl Deref for PooledPacket {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for PooledPacket {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PooledPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PooledPacket").field(&&**self).finish()
+    }
+}
+
+impl Drop for PooledPacket {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
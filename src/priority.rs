@@ -0,0 +1,239 @@
+use crate::AsyncTunDevice;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Number of packets each lane holds when no capacity is set.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// Represents the queues of the lanes and the state of the scheduler.
+#[derive(Debug)]
+struct Lanes {
+    queues: Vec<VecDeque<Vec<u8>>>,
+    capacity: usize,
+    /// Packets sent in turn by each lane in weighted round-robin, or `None` for strict priority.
+    weights: Option<Vec<u32>>,
+    /// Lane whose turn it is in weighted round-robin, and the packets it may still send.
+    turn: usize,
+    credit: u32,
+    /// Set once sending to the device failed; the error is returned by the next send.
+    closed: bool,
+    write_error: Option<io::Error>,
+}
+
+impl Lanes {
+    /// Takes the next packet to send, if any.
+    fn pick(&mut self) -> Option<Vec<u8>> {
+        let Some(weights) = &self.weights else {
+            return self.queues.iter_mut().find_map(VecDeque::pop_front);
+        };
+        // Visits every lane once after the current one, which may have run out of credit
+        for _ in 0..=self.queues.len() {
+            if self.credit > 0
+                && let Some(packet) = self.queues[self.turn].pop_front()
+            {
+                self.credit -= 1;
+                return Some(packet);
+            }
+            self.turn = (self.turn + 1) % self.queues.len();
+            self.credit = weights[self.turn];
+        }
+        None
+    }
+}
+
+/// Represents the lanes shared with the task draining them.
+struct Shared {
+    lanes: Mutex<Lanes>,
+    /// Notified when a packet is queued.
+    queued: Notify,
+    /// Notified when a packet leaves its lane, or when the task stops.
+    space: Notify,
+}
+
+/// Represents a sender queueing the packets for a device in several lanes of priority (e.g.
+/// keepalives and control messages ahead of bulk transfers), drained by a background task in
+/// order of priority, so that latency-critical packets are not stuck behind bulk ones when the
+/// device is congested.
+///
+/// Lane 0 has the highest priority. By default, the scheduling is strict: a packet is sent only
+/// when the lanes of higher priority are empty, so a busy lane starves the ones below it. With
+/// [`weights`](#method.weights), the lanes take turns instead, each sending up to its weight in
+/// packets per turn (weighted round-robin), so that every lane progresses.
+///
+/// Each lane holds up to [`capacity`](#method.capacity) packets; [`send`](#method.send) waits
+/// for room in its lane, while [`try_send`](#method.try_send) fails with
+/// `io::ErrorKind::WouldBlock`. If sending a packet to the device fails, the error is returned
+/// by the next send, and the following ones fail with `io::ErrorKind::BrokenPipe`. The queued
+/// packets are dropped with this value.
+///
+/// ```no_run
+/// use tokio_tun::{PrioritizedSender, TunBuilder};
+///
+/// const CONTROL: usize = 0;
+/// const BULK: usize = 1;
+///
+/// # async fn run(keepalive: &[u8], chunk: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+/// let sender = PrioritizedSender::new(tun, 2).weights([4, 1]);
+/// sender.send(BULK, chunk).await?;
+/// sender.send(CONTROL, keepalive).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PrioritizedSender<T> {
+    inner: Arc<T>,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for PrioritizedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lanes = self.shared.lanes.lock().unwrap();
+        f.debug_struct("PrioritizedSender")
+            .field("inner", &self.inner)
+            .field("lanes", &lanes.queues.len())
+            .field("capacity", &lanes.capacity)
+            .field("weights", &lanes.weights)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsyncTunDevice + 'static> PrioritizedSender<T> {
+    /// Wraps `inner` with `lanes` lanes of strict priority, and spawns the task sending their
+    /// packets to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lanes` is zero, or if called outside of a tokio runtime.
+    pub fn new(inner: T, lanes: usize) -> Self {
+        assert!(lanes > 0, "a sender needs at least one lane");
+        let inner = Arc::new(inner);
+        let shared = Arc::new(Shared {
+            lanes: Mutex::new(Lanes {
+                queues: (0..lanes).map(|_| VecDeque::new()).collect(),
+                capacity: DEFAULT_CAPACITY,
+                weights: None,
+                turn: 0,
+                credit: 0,
+                closed: false,
+                write_error: None,
+            }),
+            queued: Notify::new(),
+            space: Notify::new(),
+        });
+        let task = tokio::spawn(run(inner.clone(), shared.clone()));
+        Self {
+            inner,
+            shared,
+            task,
+        }
+    }
+
+    /// Sets the number of packets each lane holds.
+    pub fn capacity(self, capacity: usize) -> Self {
+        self.shared.lanes.lock().unwrap().capacity = capacity;
+        self
+    }
+
+    /// Schedules the lanes in weighted round-robin, the lane of index `i` sending up to
+    /// `weights[i]` packets per turn, instead of in strict priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one weight per lane, or if a weight is zero.
+    pub fn weights(self, weights: impl IntoIterator<Item = u32>) -> Self {
+        let weights: Vec<u32> = weights.into_iter().collect();
+        {
+            let mut lanes = self.shared.lanes.lock().unwrap();
+            assert_eq!(weights.len(), lanes.queues.len(), "one weight per lane");
+            assert!(weights.iter().all(|&w| w > 0), "weights must be positive");
+            lanes.turn = 0;
+            lanes.credit = weights[0];
+            lanes.weights = Some(weights);
+        }
+        self
+    }
+
+    /// Queues a packet in the lane of index `lane`, waiting for room in it if it is full.
+    /// Returns once the packet is queued.
+    pub async fn send(&self, lane: usize, buf: &[u8]) -> io::Result<()> {
+        loop {
+            let space = self.shared.space.notified();
+            tokio::pin!(space);
+            // Registers before trying, so that a packet leaving the lane meanwhile wakes it
+            space.as_mut().enable();
+            match self.try_send(lane, buf) {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => space.await,
+                res => return res,
+            }
+        }
+    }
+
+    /// Queues a packet in the lane of index `lane`, or fails with `io::ErrorKind::WouldBlock`
+    /// if it is full.
+    pub fn try_send(&self, lane: usize, buf: &[u8]) -> io::Result<()> {
+        let mut lanes = self.shared.lanes.lock().unwrap();
+        if lanes.closed {
+            let err = lanes.write_error.take();
+            return Err(err.unwrap_or_else(|| ErrorKind::BrokenPipe.into()));
+        }
+        let capacity = lanes.capacity;
+        let Some(queue) = lanes.queues.get_mut(lane) else {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "no such lane"));
+        };
+        if queue.len() >= capacity {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        queue.push_back(buf.to_vec());
+        self.shared.queued.notify_one();
+        Ok(())
+    }
+
+    /// Returns the number of packets waiting in the lane of index `lane`.
+    pub fn queued(&self, lane: usize) -> usize {
+        let lanes = self.shared.lanes.lock().unwrap();
+        lanes.queues.get(lane).map_or(0, VecDeque::len)
+    }
+
+    /// Receives a packet from the device, bypassing the lanes.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf).await
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Drop for PrioritizedSender<T> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Sends the packets of the lanes to `inner` in the order of the scheduler, until sending
+/// fails.
+async fn run<T: AsyncTunDevice>(inner: Arc<T>, shared: Arc<Shared>) {
+    loop {
+        let packet = shared.lanes.lock().unwrap().pick();
+        let Some(packet) = packet else {
+            shared.queued.notified().await;
+            continue;
+        };
+        shared.space.notify_waiters();
+        if let Err(err) = inner.send_all(&packet).await {
+            let mut lanes = shared.lanes.lock().unwrap();
+            lanes.closed = true;
+            lanes.write_error = Some(err);
+            lanes.queues.iter_mut().for_each(VecDeque::clear);
+            drop(lanes);
+            shared.space.notify_waiters();
+            return;
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::Tun;
+use bytes::{Bytes, BytesMut};
+use std::io;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Moves packets between `tun` and a pair of channels until both directions are closed or
+/// `cancel` is cancelled.
+///
+/// A slot of `inbound` is reserved before each read, so that a full channel stops reading the
+/// device instead of dropping packets which were already read: the kernel queue of the device
+/// absorbs (and eventually drops) the excess as it does for any slow reader.
+///
+/// On cancellation, reading stops immediately while the packets already queued in `outbound`
+/// are still sent.
+pub(crate) async fn channels(
+    tun: Arc<Tun>,
+    inbound: mpsc::Sender<Bytes>,
+    mut outbound: mpsc::Receiver<Bytes>,
+    buffer_size: usize,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let reader = async {
+        loop {
+            let permit = tokio::select! {
+                permit = inbound.reserve() => match permit {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                },
+                _ = cancel.cancelled() => break,
+            };
+            let mut buf = BytesMut::zeroed(buffer_size);
+            let n = tokio::select! {
+                res = tun.recv(&mut buf) => res?,
+                _ = inbound.closed() => break,
+                _ = cancel.cancelled() => break,
+            };
+            buf.truncate(n);
+            permit.send(buf.freeze());
+        }
+        Ok::<_, io::Error>(())
+    };
+    let writer = async {
+        loop {
+            let packet = tokio::select! {
+                packet = outbound.recv() => packet,
+                _ = cancel.cancelled() => {
+                    outbound.close();
+                    outbound.recv().await
+                }
+            };
+            match packet {
+                Some(packet) => tun.send_all(&packet).await?,
+                None => break,
+            }
+        }
+        Ok::<_, io::Error>(())
+    };
+    tokio::try_join!(reader, writer).map(|_| ())
+}
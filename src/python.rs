@@ -0,0 +1,152 @@
+//! Python bindings (feature `python`).
+//!
+//! The `tokio_tun` Python module provides the `TokioTun` class, whose `recv` and `send` methods
+//! return awaitables driven by a tokio runtime in the background, so that a Python script can
+//! run devices from `asyncio`. Errors are raised as `OSError`. The module is built with:
+//!
+//! ```sh
+//! cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib
+//! ```
+//!
+//! and by renaming the library to `tokio_tun.so` (`libtokio_tun.so` on Linux and
+//! `libtokio_tun.dylib` on macOS).
+//!
+//! ```python
+//! import asyncio
+//! from tokio_tun import TokioTun
+//!
+//! async def main():
+//!     tun = TokioTun(name="tun0", address="10.0.0.1", netmask="255.255.255.0", up=True)
+//!     while True:
+//!         packet = await tun.recv()
+//!         print(f"{tun.name}: {len(packet)} bytes")
+//!
+//! asyncio.run(main())
+//! ```
+
+use crate::Tun;
+use crate::TunBuilder;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+
+/// Represents a Tun/Tap device driven from Python.
+#[pyclass(name = "TokioTun", module = "tokio_tun", frozen)]
+pub struct PyTun {
+    tun: Arc<Tun>,
+    buffer_size: usize,
+}
+
+fn parse_ipv4(addr: Option<&str>) -> PyResult<Option<Ipv4Addr>> {
+    addr.map(|addr| {
+        addr.parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid IPv4 address: {addr}")))
+    })
+    .transpose()
+}
+
+#[pymethods]
+impl PyTun {
+    /// Creates a device with the given parameters, which are those of `TunBuilder`.
+    #[new]
+    #[pyo3(signature = (
+        name = "",
+        tap = false,
+        mtu = None,
+        address = None,
+        netmask = None,
+        destination = None,
+        persist = false,
+        up = false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: &str,
+        tap: bool,
+        mtu: Option<i32>,
+        address: Option<&str>,
+        netmask: Option<&str>,
+        destination: Option<&str>,
+        persist: bool,
+        up: bool,
+    ) -> PyResult<Self> {
+        let mut builder = TunBuilder::new().name(name);
+        if tap {
+            builder = builder.tap();
+        }
+        if let Some(mtu) = mtu {
+            builder = builder.mtu(mtu);
+        }
+        if let Some(address) = parse_ipv4(address)? {
+            builder = builder.address(address);
+        }
+        if let Some(netmask) = parse_ipv4(netmask)? {
+            builder = builder.netmask(netmask);
+        }
+        if let Some(destination) = parse_ipv4(destination)? {
+            builder = builder.destination(destination);
+        }
+        if persist {
+            builder = builder.persist();
+        }
+        if up {
+            builder = builder.up();
+        }
+        // The device is registered with the reactor of the runtime which drives the awaitables
+        let _guard = pyo3_async_runtimes::tokio::get_runtime().enter();
+        let tun = builder.build().map_err(io::Error::from)?.remove(0);
+        let buffer_size = tun.recommended_buffer_size().map_err(io::Error::from)?;
+        Ok(Self {
+            tun: Arc::new(tun),
+            buffer_size,
+        })
+    }
+
+    /// The name of the device.
+    #[getter]
+    fn name(&self) -> &str {
+        self.tun.name()
+    }
+
+    /// The MTU of the device.
+    #[getter]
+    fn mtu(&self) -> PyResult<i32> {
+        Ok(self.tun.mtu().map_err(io::Error::from)?)
+    }
+
+    /// Returns the file descriptor of the device.
+    fn fileno(&self) -> i32 {
+        self.tun.as_raw_fd()
+    }
+
+    /// Returns an awaitable of the next packet received from the device, as `bytes`.
+    fn recv<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let tun = self.tun.clone();
+        let mut buf = vec![0; self.buffer_size];
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let n = tun.recv(&mut buf).await?;
+            buf.truncate(n);
+            Ok(buf)
+        })
+    }
+
+    /// Returns an awaitable which sends `packet` to the device, and results in the number of
+    /// bytes sent.
+    fn send<'py>(&self, py: Python<'py>, packet: Vec<u8>) -> PyResult<Bound<'py, PyAny>> {
+        let tun = self.tun.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move { Ok(tun.send(&packet).await?) })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TokioTun(name={:?})", self.tun.name())
+    }
+}
+
+/// The `tokio_tun` Python module.
+#[pymodule]
+fn tokio_tun(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTun>()
+}
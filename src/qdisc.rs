@@ -0,0 +1,27 @@
+/// Represents the queueing discipline of a device, set by
+/// [`TunBuilder::qdisc`](struct.TunBuilder.html#method.qdisc) or
+/// [`Tun::set_qdisc`](struct.Tun.html#method.set_qdisc).
+///
+/// Tun/Tap devices get `pfifo_fast` by default, whose long queue adds latency under load
+/// (bufferbloat) when the reader of the device is slower than its writers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Qdisc {
+    /// No queue: packets are handed to the device directly, and dropped when its own queue
+    /// is full.
+    NoQueue,
+    /// Fair queueing (`fq`), which paces the flows of the host.
+    Fq,
+    /// Fair queueing with controlled delay (`fq_codel`), which keeps the queueing delay low.
+    FqCodel,
+}
+
+impl Qdisc {
+    /// Returns the kernel name of the queueing discipline.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NoQueue => "noqueue",
+            Self::Fq => "fq",
+            Self::FqCodel => "fq_codel",
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::Result;
+use crate::SyncTun;
+use std::io;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::Arc;
+
+/// Represents the queues of a TAP device handed over to a QEMU process, built by
+/// [`TunBuilder::build_qemu`](struct.TunBuilder.html#method.build_qemu).
+///
+/// The file descriptors of the queues are close-on-exec, so that no other child process inherits
+/// them: [`inherit_by`](#method.inherit_by) clears the flag in the QEMU process only, between
+/// `fork` and `exec`. QEMU then serves the device with the `fds` (or `fd`) option of its tap
+/// netdev, which [`netdev`](#method.netdev) renders.
+///
+/// The file descriptors of the parent are closed once the `QemuTap` and the commands it was
+/// passed to are dropped, so that the device is removed when QEMU exits, unless it is persistent.
+///
+/// ```no_run
+/// use std::process::Command;
+/// use tokio_tun::TunBuilder;
+///
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tap = TunBuilder::new().queues(4).up().build_qemu()?;
+/// let mut qemu = Command::new("qemu-system-x86_64");
+/// qemu.args(["-netdev", &tap.netdev("net0"), "-device", &tap.device("net0")]);
+/// tap.inherit_by(&mut qemu);
+/// let child = qemu.spawn()?;
+/// drop((tap, qemu));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct QemuTap {
+    name: String,
+    fds: Arc<[OwnedFd]>,
+}
+
+impl QemuTap {
+    /// Takes over the queues of a TAP device, in blocking mode as QEMU expects them, and marks
+    /// them close-on-exec.
+    pub(crate) fn new(tuns: Vec<SyncTun>) -> Result<Self> {
+        let name = tuns
+            .first()
+            .map(|tun| tun.name().to_owned())
+            .unwrap_or_default();
+        let fds = tuns.into_iter().map(OwnedFd::from).collect::<Arc<[_]>>();
+        for fd in fds.iter() {
+            set_cloexec(fd.as_raw_fd(), true)?;
+        }
+        Ok(Self { name, fds })
+    }
+
+    /// Returns the name of the device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of queues of the device.
+    pub fn queues(&self) -> usize {
+        self.fds.len()
+    }
+
+    /// Returns the file descriptors of the queues, which are those QEMU inherits.
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.fds.iter().map(AsRawFd::as_raw_fd).collect()
+    }
+
+    /// Makes the process spawned by `command` inherit the file descriptors of the queues, and
+    /// only them: the flag close-on-exec is cleared in the child process after `fork`, so
+    /// concurrently spawned processes do not inherit them.
+    ///
+    /// `command` holds the file descriptors until it is dropped, so that they stay open until the
+    /// process is spawned.
+    pub fn inherit_by<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        let fds = Arc::clone(&self.fds);
+        // Only async-signal-safe functions may be called between `fork` and `exec`
+        unsafe {
+            command.pre_exec(move || {
+                for fd in fds.iter() {
+                    set_cloexec(fd.as_raw_fd(), false)?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the value of the QEMU option `-netdev` serving the device with the inherited file
+    /// descriptors, e.g. `tap,id=net0,fds=3:4` for two queues.
+    pub fn netdev(&self, id: &str) -> String {
+        let fds = self.fds().iter().map(RawFd::to_string).collect::<Vec<_>>();
+        match fds.as_slice() {
+            [fd] => format!("tap,id={id},fd={fd}"),
+            fds => format!("tap,id={id},fds={}", fds.join(":")),
+        }
+    }
+
+    /// Returns the value of the QEMU option `-device` of a virtio-net NIC attached to the netdev
+    /// `id`, which enables multi-queue when the device has several queues: a pair of interrupt
+    /// vectors per queue, plus one for configuration changes and one for the control queue.
+    pub fn device(&self, id: &str) -> String {
+        match self.queues() {
+            0 | 1 => format!("virtio-net-pci,netdev={id}"),
+            queues => format!(
+                "virtio-net-pci,netdev={id},mq=on,vectors={}",
+                2 * queues + 2
+            ),
+        }
+    }
+}
+
+/// Sets or clears the flag close-on-exec of `fd`.
+fn set_cloexec(fd: RawFd, cloexec: bool) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let flags = if cloexec {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Share of a second of traffic which the bucket holds when no burst is set.
+const DEFAULT_BURST_DIVISOR: u64 = 10;
+
+/// Smallest burst of bytes when none is set, so that a packet of the largest usual MTU passes
+/// without waiting on an idle bucket.
+const MIN_DEFAULT_BURST_BYTES: u64 = 1500;
+
+/// Represents a token bucket, refilled with `rate` tokens per second up to `burst` tokens.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    rate: u64,
+    burst: u64,
+    /// Tokens left, negative while a packet larger than the burst is paid off.
+    tokens: f64,
+}
+
+impl Bucket {
+    fn new(rate: u64, burst: u64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst as f64,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.burst as f64);
+    }
+
+    /// Returns how long to wait before `cost` tokens can be taken. A cost larger than the burst
+    /// only waits for a full bucket, and leaves it in debt.
+    fn wait(&self, cost: u64) -> Duration {
+        let needed = cost.min(self.burst) as f64 - self.tokens;
+        if needed <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(needed / self.rate as f64)
+        }
+    }
+}
+
+/// Represents the buckets of both limits, refilled at `refilled`.
+#[derive(Debug)]
+struct State {
+    bytes: Option<Bucket>,
+    packets: Option<Bucket>,
+    refilled: Instant,
+}
+
+/// Represents a device which shapes the packets sent to the device it wraps with token buckets,
+/// limiting the bytes and the packets per second, to cap the bandwidth of a tunnel without
+/// setting up `tc`.
+///
+/// Each limit allows a burst, the number of bytes or packets sent at once after an idle period,
+/// which defaults to a tenth of a second of traffic. A send exceeding a limit waits for the
+/// bucket to refill, which applies backpressure to the sender instead of dropping the packet.
+/// A packet larger than the burst of bytes waits for a full bucket, and the packets after it
+/// wait until it is paid off. The packets received from the device are not limited.
+///
+/// ```no_run
+/// use tokio_tun::{RateLimited, TunBuilder};
+///
+/// # async fn run() -> tokio_tun::Result<()> {
+/// let tun = TunBuilder::new().name("tun0").up().build()?.pop().unwrap();
+/// // 10 Mbit/s, in bursts of up to 64 KiB
+/// let tun = RateLimited::new(tun)
+///     .bytes_per_second(10_000_000 / 8)
+///     .burst_bytes(64 * 1024);
+/// tun.send(&[0x45, 0, 0, 20]).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RateLimited<T> {
+    inner: T,
+    state: Mutex<State>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for RateLimited<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("RateLimited")
+            .field("inner", &self.inner)
+            .field("bytes", &state.bytes)
+            .field("packets", &state.packets)
+            .finish()
+    }
+}
+
+impl<T: AsyncTunDevice> RateLimited<T> {
+    /// Wraps `inner`, without limits.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(State {
+                bytes: None,
+                packets: None,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Limits the bytes sent per second to `rate`, or removes the limit if `rate` is zero.
+    pub fn bytes_per_second(self, rate: u64) -> Self {
+        let burst = (rate / DEFAULT_BURST_DIVISOR).max(MIN_DEFAULT_BURST_BYTES);
+        self.state.lock().unwrap().bytes = (rate > 0).then(|| Bucket::new(rate, burst));
+        self
+    }
+
+    /// Limits the packets sent per second to `rate`, or removes the limit if `rate` is zero.
+    pub fn packets_per_second(self, rate: u64) -> Self {
+        let burst = (rate / DEFAULT_BURST_DIVISOR).max(1);
+        self.state.lock().unwrap().packets = (rate > 0).then(|| Bucket::new(rate, burst));
+        self
+    }
+
+    /// Sets the burst of the limit of bytes, i.e. the bytes sent at once after an idle period.
+    /// Has no effect without a limit of bytes.
+    pub fn burst_bytes(self, burst: u64) -> Self {
+        if let Some(bucket) = &mut self.state.lock().unwrap().bytes {
+            *bucket = Bucket::new(bucket.rate, burst.max(1));
+        }
+        self
+    }
+
+    /// Sets the burst of the limit of packets, i.e. the packets sent at once after an idle
+    /// period. Has no effect without a limit of packets.
+    pub fn burst_packets(self, burst: u64) -> Self {
+        if let Some(bucket) = &mut self.state.lock().unwrap().packets {
+            *bucket = Bucket::new(bucket.rate, burst.max(1));
+        }
+        self
+    }
+
+    /// Receives a packet from the device, which is not limited.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.recv(buf).await
+    }
+
+    /// Sends a packet to the device, waiting until the limits allow it. The tokens are taken
+    /// before sending, and are not given back if sending fails.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let wait = self.take(buf.len() as u64);
+            if wait.is_zero() {
+                break;
+            }
+            time::sleep(wait).await;
+        }
+        self.inner.send(buf).await
+    }
+
+    /// Returns the time until a packet of `len` bytes is allowed by the limits, or takes its
+    /// tokens and returns zero if it already is.
+    fn take(&self, len: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(state.refilled);
+        state.refilled = now;
+        let State { bytes, packets, .. } = &mut *state;
+        let mut wait = Duration::ZERO;
+        for (bucket, cost) in [(&mut *bytes, len), (&mut *packets, 1)] {
+            if let Some(bucket) = bucket {
+                bucket.refill(elapsed);
+                wait = wait.max(bucket.wait(cost));
+            }
+        }
+        if wait.is_zero() {
+            for (bucket, cost) in [(bytes, len), (packets, 1)] {
+                if let Some(bucket) = bucket {
+                    bucket.tokens -= cost as f64;
+                }
+            }
+        }
+        wait
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for RateLimited<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        RateLimited::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        RateLimited::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
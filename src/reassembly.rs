@@ -0,0 +1,659 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Result;
+use crate::TunConfig;
+use crate::checksum;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+/// Time after which an incomplete datagram is dropped by default, as `ipfrag_time` of Linux.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bytes of fragments held at most by default.
+const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Incomplete datagrams held at most by default.
+const DEFAULT_MAX_DATAGRAMS: usize = 256;
+
+/// Fragments of a datagram held at most, which bounds the work per fragment.
+const MAX_FRAGMENTS: usize = 64;
+
+/// Largest reassembled datagram.
+const MAX_DATAGRAM: usize = 65535;
+
+/// Identifies the fragments of a datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    V4 {
+        source: [u8; 4],
+        destination: [u8; 4],
+        protocol: u8,
+        id: u16,
+    },
+    V6 {
+        source: [u8; 16],
+        destination: [u8; 16],
+        id: u32,
+    },
+}
+
+/// Represents a fragment parsed from a packet.
+struct Fragment<'a> {
+    key: Key,
+    /// Headers which precede the fragmentable part, as sent in the first fragment.
+    header: &'a [u8],
+    /// Offset in `header` of the field holding the protocol of the fragmentable part (IPv6).
+    next_header: usize,
+    /// Protocol of the fragmentable part (IPv6).
+    protocol: u8,
+    offset: usize,
+    more: bool,
+    data: &'a [u8],
+}
+
+/// Represents an incomplete datagram.
+struct Datagram {
+    started: Instant,
+    /// Headers of the first fragment, and the offset and value of the field to rewrite.
+    header: Option<(Vec<u8>, usize, u8)>,
+    /// Fragments, as their offset and data.
+    fragments: Vec<(usize, Vec<u8>)>,
+    /// Length of the fragmentable part, known from the last fragment.
+    len: Option<usize>,
+    bytes: usize,
+}
+
+/// Represents an engine reassembling IPv4 fragments and IPv6 packets with a fragment header
+/// into whole datagrams, with bounded memory.
+///
+/// An incomplete datagram is dropped after a [`timeout`](#method.timeout), and the fragments
+/// beyond the [`max_bytes`](#method.max_bytes) and [`max_datagrams`](#method.max_datagrams)
+/// limits are dropped, so that floods of fragments which never complete cannot exhaust the
+/// memory. Fragments overlapping other fragments of their datagram drop the whole datagram,
+/// as RFC 5722 requires for IPv6; exact duplicates are ignored.
+///
+/// See [`Reassembled`] to reassemble the packets received from a device.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use tokio_tun::Reassembler;
+///
+/// let mut reassembler = Reassembler::new();
+/// // Packets which are not fragments pass through unchanged
+/// let packet = [0x45, 0, 0, 20, 0, 0, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+/// assert!(matches!(reassembler.process(&packet), Some(Cow::Borrowed(_))));
+/// ```
+pub struct Reassembler {
+    timeout: Duration,
+    max_bytes: usize,
+    max_datagrams: usize,
+    datagrams: HashMap<Key, Datagram>,
+    /// Datagrams by age, to expire them in order.
+    expiry: VecDeque<(Instant, Key)>,
+    bytes: usize,
+}
+
+impl fmt::Debug for Reassembler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reassembler")
+            .field("timeout", &self.timeout)
+            .field("max_bytes", &self.max_bytes)
+            .field("max_datagrams", &self.max_datagrams)
+            .field("pending", &self.datagrams.len())
+            .field("bytes", &self.bytes)
+            .finish()
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reassembler {
+    /// Creates a new engine, dropping the datagrams incomplete after 30 seconds, and holding
+    /// up to 4 MiB of fragments of up to 256 datagrams.
+    pub fn new() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_datagrams: DEFAULT_MAX_DATAGRAMS,
+            datagrams: HashMap::new(),
+            expiry: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Sets the time after which an incomplete datagram is dropped.
+    ///
+    /// The identification of an expired datagram may be reused by a new one, which is
+    /// reassembled from its own fragments only:
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use tokio_tun::Reassembler;
+    ///
+    /// # fn fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+    /// #     let flags = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    /// #     let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0];
+    /// #     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    /// #     packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    /// #     packet[4..6].copy_from_slice(&id.to_be_bytes());
+    /// #     packet[6..8].copy_from_slice(&flags.to_be_bytes());
+    /// #     packet.extend_from_slice(data);
+    /// #     packet
+    /// # }
+    /// let mut reassembler = Reassembler::new().timeout(Duration::from_millis(20));
+    /// assert!(reassembler.process(&fragment(7, 0, true, &[1; 8])).is_none());
+    /// thread::sleep(Duration::from_millis(30));
+    /// // The first fragment of the new datagram expires the old one
+    /// assert!(reassembler.process(&fragment(7, 8, false, &[2; 8])).is_none());
+    /// assert_eq!(reassembler.pending(), 1);
+    /// let first = fragment(7, 0, true, &[3; 8]);
+    /// let datagram = reassembler.process(&first).unwrap();
+    /// assert_eq!(datagram[20..], [[3; 8], [2; 8]].concat());
+    /// assert_eq!(reassembler.pending(), 0);
+    /// ```
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of bytes of fragments held at most, beyond which new fragments are
+    /// dropped. A fragment costs its data and its headers.
+    ///
+    /// ```
+    /// use tokio_tun::Reassembler;
+    ///
+    /// # fn fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+    /// #     let flags = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    /// #     let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0];
+    /// #     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    /// #     packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    /// #     packet[4..6].copy_from_slice(&id.to_be_bytes());
+    /// #     packet[6..8].copy_from_slice(&flags.to_be_bytes());
+    /// #     packet.extend_from_slice(data);
+    /// #     packet
+    /// # }
+    /// let mut reassembler = Reassembler::new().max_bytes(2 * (20 + 8));
+    /// assert!(reassembler.process(&fragment(1, 0, true, &[1; 8])).is_none());
+    /// assert!(reassembler.process(&fragment(1, 8, true, &[2; 8])).is_none());
+    /// // Beyond the limit: the fragment is dropped, so the datagram never completes
+    /// assert!(reassembler.process(&fragment(1, 16, false, &[3; 8])).is_none());
+    /// assert_eq!(reassembler.pending(), 1);
+    /// ```
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Sets the number of incomplete datagrams held at most, beyond which the fragments of new
+    /// datagrams are dropped.
+    ///
+    /// ```
+    /// use tokio_tun::Reassembler;
+    ///
+    /// # fn fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+    /// #     let flags = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    /// #     let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0];
+    /// #     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    /// #     packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    /// #     packet[4..6].copy_from_slice(&id.to_be_bytes());
+    /// #     packet[6..8].copy_from_slice(&flags.to_be_bytes());
+    /// #     packet.extend_from_slice(data);
+    /// #     packet
+    /// # }
+    /// let mut reassembler = Reassembler::new().max_datagrams(1);
+    /// assert!(reassembler.process(&fragment(1, 0, true, &[1; 8])).is_none());
+    /// assert!(reassembler.process(&fragment(2, 0, true, &[2; 8])).is_none());
+    /// assert!(reassembler.process(&fragment(2, 8, false, &[2; 8])).is_none());
+    /// assert_eq!(reassembler.pending(), 1);
+    /// // The datagram held still completes
+    /// assert!(reassembler.process(&fragment(1, 8, false, &[1; 8])).is_some());
+    /// ```
+    pub fn max_datagrams(mut self, max_datagrams: usize) -> Self {
+        self.max_datagrams = max_datagrams;
+        self
+    }
+
+    /// Returns the number of incomplete datagrams held.
+    pub fn pending(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    /// Processes the IP packet `packet`: returns it unchanged if it is not a fragment, the
+    /// reassembled datagram if it is the fragment which completes one, and `None` if it is
+    /// held, or dropped because it is invalid or beyond the limits.
+    ///
+    /// The fragments may arrive in any order. The headers of the datagram are those of its first
+    /// fragment: with IPv4, its total length, fragmentation fields and checksum are rewritten,
+    /// and an exact duplicate of a fragment is ignored.
+    ///
+    /// ```
+    /// use tokio_tun::Reassembler;
+    ///
+    /// # fn fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+    /// #     let flags = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    /// #     let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0];
+    /// #     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    /// #     packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    /// #     packet[4..6].copy_from_slice(&id.to_be_bytes());
+    /// #     packet[6..8].copy_from_slice(&flags.to_be_bytes());
+    /// #     packet.extend_from_slice(data);
+    /// #     packet
+    /// # }
+    /// let mut reassembler = Reassembler::new();
+    /// assert!(reassembler.process(&fragment(1, 16, false, &[3; 4])).is_none());
+    /// assert!(reassembler.process(&fragment(1, 0, true, &[1; 8])).is_none());
+    /// assert!(reassembler.process(&fragment(1, 0, true, &[1; 8])).is_none());
+    /// let middle = fragment(1, 8, true, &[2; 8]);
+    /// let datagram = reassembler.process(&middle).unwrap();
+    /// assert_eq!(datagram[20..], [&[1; 8][..], &[2; 8], &[3; 4]].concat());
+    /// // Total length, then no more fragments flag nor offset
+    /// assert_eq!(datagram[2..4], 40u16.to_be_bytes());
+    /// assert_eq!(datagram[6..8], [0, 0]);
+    /// let sum = datagram[..20]
+    ///     .chunks(2)
+    ///     .map(|word| u16::from_be_bytes([word[0], word[1]]) as u32)
+    ///     .sum::<u32>();
+    /// assert_eq!((sum & 0xffff) + (sum >> 16), 0xffff);
+    /// ```
+    ///
+    /// A fragment which overlaps another one without duplicating it drops its whole datagram, as
+    /// RFC 5722 requires for IPv6, as do a last fragment inconsistent with the others and more
+    /// than 64 fragments:
+    ///
+    /// ```
+    /// use tokio_tun::Reassembler;
+    ///
+    /// # fn fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+    /// #     let flags = (offset / 8) as u16 | if more { 0x2000 } else { 0 };
+    /// #     let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0];
+    /// #     packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    /// #     packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    /// #     packet[4..6].copy_from_slice(&id.to_be_bytes());
+    /// #     packet[6..8].copy_from_slice(&flags.to_be_bytes());
+    /// #     packet.extend_from_slice(data);
+    /// #     packet
+    /// # }
+    /// let mut reassembler = Reassembler::new();
+    /// assert!(reassembler.process(&fragment(1, 0, true, &[1; 16])).is_none());
+    /// assert!(reassembler.process(&fragment(1, 8, true, &[2; 16])).is_none());
+    /// assert_eq!(reassembler.pending(), 0);
+    ///
+    /// // The datagram ends before the data already received
+    /// assert!(reassembler.process(&fragment(2, 16, true, &[1; 8])).is_none());
+    /// assert!(reassembler.process(&fragment(2, 8, false, &[2; 8])).is_none());
+    /// assert_eq!(reassembler.pending(), 0);
+    ///
+    /// for i in 0..64 {
+    ///     assert!(reassembler.process(&fragment(3, 8 + i * 8, true, &[1; 8])).is_none());
+    /// }
+    /// assert_eq!(reassembler.pending(), 1);
+    /// assert!(reassembler.process(&fragment(3, 0, true, &[1; 8])).is_none());
+    /// assert_eq!(reassembler.pending(), 0);
+    /// ```
+    ///
+    /// With IPv6, the fragment header is removed, and the field which pointed to it, here in a
+    /// hop-by-hop options header, is patched with the protocol of the datagram:
+    ///
+    /// ```
+    /// use tokio_tun::Reassembler;
+    ///
+    /// fn fragment(offset: u16, more: bool, data: &[u8]) -> Vec<u8> {
+    ///     let mut packet = vec![0x60, 0, 0, 0, 0, 0, 0, 64];
+    ///     packet[4..6].copy_from_slice(&((8 + 8 + data.len()) as u16).to_be_bytes());
+    ///     packet.extend_from_slice(&[0xfd; 32]);
+    ///     // Hop-by-hop options, then the fragment header of a UDP datagram
+    ///     packet.extend_from_slice(&[44, 0, 1, 4, 0, 0, 0, 0]);
+    ///     packet.extend_from_slice(&[17, 0]);
+    ///     packet.extend_from_slice(&(offset | more as u16).to_be_bytes());
+    ///     packet.extend_from_slice(&0x1234u32.to_be_bytes());
+    ///     packet.extend_from_slice(data);
+    ///     packet
+    /// }
+    ///
+    /// let mut reassembler = Reassembler::new();
+    /// assert!(reassembler.process(&fragment(8, false, &[2; 8])).is_none());
+    /// let first = fragment(0, true, &[1; 8]);
+    /// let datagram = reassembler.process(&first).unwrap();
+    /// assert_eq!(datagram.len(), 48 + 16);
+    /// assert_eq!(datagram[4..6], (8 + 16u16).to_be_bytes());
+    /// assert_eq!(datagram[6], 0);
+    /// assert_eq!(datagram[40], 17);
+    /// assert_eq!(datagram[48..], [[1; 8], [2; 8]].concat());
+    /// ```
+    pub fn process<'a>(&mut self, packet: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let Some(fragment) = parse(packet) else {
+            return Some(Cow::Borrowed(packet));
+        };
+        let now = Instant::now();
+        self.expire(now);
+        self.insert(fragment, now).map(Cow::Owned)
+    }
+
+    /// Drops the datagrams incomplete for longer than the timeout.
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(started, key)) = self.expiry.front() {
+            if now.duration_since(started) < self.timeout {
+                break;
+            }
+            self.expiry.pop_front();
+            // The datagram may have completed, and its key been reused since
+            if self
+                .datagrams
+                .get(&key)
+                .is_some_and(|d| d.started == started)
+            {
+                self.remove(&key);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(datagram) = self.datagrams.remove(key) {
+            self.bytes -= datagram.bytes;
+        }
+    }
+
+    /// Adds `fragment` to its datagram, and returns the datagram if it is complete.
+    fn insert(&mut self, fragment: Fragment<'_>, now: Instant) -> Option<Vec<u8>> {
+        let end = fragment.offset + fragment.data.len();
+        // All fragments but the last carry a multiple of 8 bytes
+        if (fragment.more && !fragment.data.len().is_multiple_of(8)) || end > MAX_DATAGRAM {
+            return None;
+        }
+        let cost = fragment.data.len() + fragment.header.len();
+        if self.bytes + cost > self.max_bytes {
+            return None;
+        }
+        if !self.datagrams.contains_key(&fragment.key) {
+            if self.datagrams.len() >= self.max_datagrams {
+                return None;
+            }
+            // Drops the entries of the datagrams which are gone, so that completing datagrams
+            // faster than they expire does not grow the queue
+            if self.expiry.len() >= 2 * self.max_datagrams.max(1) {
+                let datagrams = &self.datagrams;
+                self.expiry.retain(|(started, key)| {
+                    datagrams.get(key).is_some_and(|d| d.started == *started)
+                });
+            }
+            self.expiry.push_back((now, fragment.key));
+            self.datagrams.insert(
+                fragment.key,
+                Datagram {
+                    started: now,
+                    header: None,
+                    fragments: Vec::new(),
+                    len: None,
+                    bytes: 0,
+                },
+            );
+        }
+        let datagram = self.datagrams.get_mut(&fragment.key).unwrap();
+
+        let mut overlaps = false;
+        for (offset, data) in &datagram.fragments {
+            if *offset == fragment.offset && data.as_slice() == fragment.data {
+                // Exact duplicate
+                return None;
+            }
+            overlaps |= *offset < end && fragment.offset < offset + data.len();
+        }
+        let inconsistent = match (datagram.len, fragment.more) {
+            (Some(len), false) => len != end,
+            (Some(len), true) => end > len,
+            (None, false) => datagram.fragments.iter().any(|(o, d)| o + d.len() > end),
+            (None, true) => false,
+        };
+        if overlaps || inconsistent || datagram.fragments.len() >= MAX_FRAGMENTS {
+            self.remove(&fragment.key);
+            return None;
+        }
+
+        if !fragment.more {
+            datagram.len = Some(end);
+        }
+        if fragment.offset == 0 {
+            datagram.header = Some((
+                fragment.header.to_vec(),
+                fragment.next_header,
+                fragment.protocol,
+            ));
+        }
+        datagram
+            .fragments
+            .push((fragment.offset, fragment.data.to_vec()));
+        datagram.bytes += cost;
+        self.bytes += cost;
+
+        let len = datagram.len?;
+        let received = datagram
+            .fragments
+            .iter()
+            .map(|(_, d)| d.len())
+            .sum::<usize>();
+        if datagram.header.is_none() || received != len {
+            return None;
+        }
+        let mut datagram = self.datagrams.remove(&fragment.key).unwrap();
+        self.bytes -= datagram.bytes;
+        let (header, next_header, protocol) = datagram.header.take().unwrap();
+        build(header, next_header, protocol, datagram.fragments, len)
+    }
+}
+
+/// Returns the fragment of `packet`, or `None` if it is not a fragment.
+fn parse(packet: &[u8]) -> Option<Fragment<'_>> {
+    match packet.first()? >> 4 {
+        4 => {
+            let ihl = (packet[0] & 0x0f) as usize * 4;
+            if ihl < 20 || packet.len() < ihl {
+                return None;
+            }
+            let flags = u16::from_be_bytes([packet[6], packet[7]]);
+            let more = flags & 0x2000 != 0;
+            let offset = (flags & 0x1fff) as usize * 8;
+            if !more && offset == 0 {
+                return None;
+            }
+            let total = (u16::from_be_bytes([packet[2], packet[3]]) as usize).min(packet.len());
+            Some(Fragment {
+                key: Key::V4 {
+                    source: packet[12..16].try_into().unwrap(),
+                    destination: packet[16..20].try_into().unwrap(),
+                    protocol: packet[9],
+                    id: u16::from_be_bytes([packet[4], packet[5]]),
+                },
+                header: &packet[..ihl],
+                next_header: 9,
+                protocol: packet[9],
+                offset,
+                more,
+                data: packet.get(ihl..total)?,
+            })
+        }
+        6 => {
+            if packet.len() < 40 {
+                return None;
+            }
+            // Only the extension headers which precede the fragment header may be before it
+            let mut next_header = 6;
+            let mut start = 40;
+            loop {
+                match packet[next_header] {
+                    IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                        let len = (*packet.get(start + 1)? as usize + 1) * 8;
+                        next_header = start;
+                        start += len;
+                    }
+                    IPPROTO_FRAGMENT => break,
+                    _ => return None,
+                }
+            }
+            let header = packet.get(start..start + 8)?;
+            let flags = u16::from_be_bytes([header[2], header[3]]);
+            let end = (40 + u16::from_be_bytes([packet[4], packet[5]]) as usize).min(packet.len());
+            Some(Fragment {
+                key: Key::V6 {
+                    source: packet[8..24].try_into().unwrap(),
+                    destination: packet[24..40].try_into().unwrap(),
+                    id: u32::from_be_bytes(header[4..8].try_into().unwrap()),
+                },
+                header: &packet[..start],
+                next_header,
+                protocol: header[0],
+                offset: (flags & 0xfff8) as usize,
+                more: flags & 1 != 0,
+                data: packet.get(start + 8..end)?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Builds the datagram of `len` bytes from the headers of its first fragment and its
+/// fragments, which cover it without overlapping.
+fn build(
+    mut header: Vec<u8>,
+    next_header: usize,
+    protocol: u8,
+    mut fragments: Vec<(usize, Vec<u8>)>,
+    len: usize,
+) -> Option<Vec<u8>> {
+    let total = header.len() + len;
+    if header[0] >> 4 == 4 {
+        if total > MAX_DATAGRAM {
+            return None;
+        }
+        header[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+        // Clears the more fragments flag and the offset, keeping don't fragment
+        header[6] &= 0x40;
+        header[7] = 0;
+        header[10..12].copy_from_slice(&[0, 0]);
+        let csum = checksum::compute(&header);
+        header[10..12].copy_from_slice(&csum.to_be_bytes());
+    } else {
+        if total - 40 > MAX_DATAGRAM {
+            return None;
+        }
+        header[4..6].copy_from_slice(&((total - 40) as u16).to_be_bytes());
+        header[next_header] = protocol;
+    }
+    fragments.sort_unstable_by_key(|(offset, _)| *offset);
+    let mut datagram = header;
+    datagram.reserve(len);
+    for (_, data) in fragments {
+        datagram.extend_from_slice(&data);
+    }
+    Some(datagram)
+}
+
+/// Represents a device whose received fragments are reassembled by a [`Reassembler`], so that
+/// the application only receives whole datagrams. The packets sent are not changed.
+///
+/// It expects IP packets, i.e. a Tun device without packet information.
+///
+/// ```no_run
+/// use tokio_tun::{Reassembled, Reassembler, TunBuilder};
+///
+/// # async fn run() -> tokio_tun::Result<()> {
+/// let tun = TunBuilder::new().name("tun0").up().build()?.pop().unwrap();
+/// let tun = Reassembled::new(tun, Reassembler::new().max_bytes(1024 * 1024));
+/// let mut buf = [0u8; 65535];
+/// let n = tun.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Reassembled<T> {
+    inner: T,
+    reassembler: Mutex<Reassembler>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Reassembled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reassembled")
+            .field("inner", &self.inner)
+            .field("reassembler", &self.reassembler)
+            .finish()
+    }
+}
+
+impl<T: AsyncTunDevice> Reassembled<T> {
+    /// Wraps `inner`, reassembling its fragments with `reassembler`.
+    pub fn new(inner: T, reassembler: Reassembler) -> Self {
+        Self {
+            inner,
+            reassembler: Mutex::new(reassembler),
+        }
+    }
+
+    /// Receives a packet which is not a fragment, or a reassembled datagram, from the device. A
+    /// datagram larger than `buf` is truncated.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.recv(buf).await?;
+            let datagram = match self.reassembler.lock().unwrap().process(&buf[..n]) {
+                Some(Cow::Borrowed(_)) => return Ok(n),
+                Some(Cow::Owned(datagram)) => datagram,
+                None => continue,
+            };
+            let n = datagram.len().min(buf.len());
+            buf[..n].copy_from_slice(&datagram[..n]);
+            return Ok(n);
+        }
+    }
+
+    /// Sends a packet to the device.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.send(buf).await
+    }
+
+    /// Returns a reference to the wrapped device.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped device, dropping the incomplete datagrams.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncTunDevice> AsyncTunDevice for Reassembled<T> {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        Reassembled::recv(self, buf).await
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        Reassembled::send(self, buf).await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        self.inner.mtu()
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        self.inner.config()
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        self.inner.device_type()
+    }
+}
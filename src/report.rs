@@ -0,0 +1,121 @@
+use crate::Error;
+use crate::Result;
+use std::fmt;
+
+/// Represents how the platform handles an option which it does not honor as requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptionStatus {
+    /// The option has no effect on the device.
+    Ignored,
+    /// The option is honored in part, or by a close substitute.
+    Approximated,
+    /// The option has no equivalent on the platform.
+    Unsupported,
+}
+
+impl fmt::Display for OptionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OptionStatus::Ignored => "ignored",
+            OptionStatus::Approximated => "approximated",
+            OptionStatus::Unsupported => "unsupported",
+        })
+    }
+}
+
+/// Represents an option of [`TunBuilder`](struct.TunBuilder.html) which the platform does not
+/// honor as requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedOption {
+    /// Name of the method of the builder which set the option, e.g. `"owner"`.
+    pub option: &'static str,
+    /// How the option is handled.
+    pub status: OptionStatus,
+    /// Why, and what is done instead if anything.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for SkippedOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.option, self.status, self.reason)
+    }
+}
+
+/// Represents the options of a [`TunBuilder`](struct.TunBuilder.html) which the platform skips,
+/// approximates or does not support, returned by
+/// [`TunBuilder::report`](struct.TunBuilder.html#method.report) and
+/// [`TunBuilder::build_with_report`](struct.TunBuilder.html#method.build_with_report).
+///
+/// A cross-platform application can log them, or fail on them with
+/// [`ensure_honored`](#method.ensure_honored):
+///
+/// ```no_run
+/// use tokio_tun::TunBuilder;
+///
+/// # async fn run() -> tokio_tun::Result<()> {
+/// let (tuns, report) = TunBuilder::new().owner(1000).persist().build_with_report()?;
+/// for skipped in report.options() {
+///     eprintln!("warning: {}", skipped);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    options: Vec<SkippedOption>,
+}
+
+impl BuildReport {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn push(
+        &mut self,
+        option: &'static str,
+        status: OptionStatus,
+        reason: &'static str,
+    ) {
+        self.options.push(SkippedOption {
+            option,
+            status,
+            reason,
+        });
+    }
+
+    /// Returns `true` if every requested option is honored.
+    pub fn is_empty(&self) -> bool {
+        self.options.is_empty()
+    }
+
+    /// Returns the options which are not honored as requested.
+    pub fn options(&self) -> &[SkippedOption] {
+        &self.options
+    }
+
+    /// Returns the entry of the option `option`, if it is not honored as requested.
+    pub fn get(&self, option: &str) -> Option<&SkippedOption> {
+        self.options.iter().find(|skipped| skipped.option == option)
+    }
+
+    /// Fails with [`Error::Unsupported`](enum.Error.html#variant.Unsupported) for the first
+    /// option which is not honored as requested, if any.
+    pub fn ensure_honored(&self) -> Result<()> {
+        match self.options.first() {
+            Some(skipped) => Err(Error::Unsupported {
+                feature: skipped.option,
+                platform: std::env::consts::OS,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, skipped) in self.options.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", skipped)?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,167 @@
+use std::io;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Represents the errors of the crate.
+///
+/// Errors whose cause callers commonly handle have their own variant: failures of the system
+/// are classified by their `errno` when they are converted from `io::Error` or `nix::Error`,
+/// and the other failures are kept as [`Error::IoError`] or [`Error::NixError`].
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("{0}")]
-    NixError(#[from] nix::Error),
+    NixError(nix::Error),
 
     #[error("{0}")]
-    IoError(#[from] std::io::Error),
+    IoError(std::io::Error),
+
+    /// The device was removed while it was open, e.g. by `ip link del`; it has to be created
+    /// again.
+    #[error("the device was removed")]
+    DeviceRemoved,
+
+    /// The process is not allowed to create or configure the device (`EPERM` or `EACCES`).
+    #[error("permission denied")]
+    PermissionDenied,
+
+    /// The process is not allowed to open the device node or to create the device (Linux): it
+    /// lacks `capability`, unless it owns the persistent device it attaches to.
+    #[error("{}", missing_capability(capability, *device_node_exists))]
+    MissingCapability {
+        /// Capability which the process lacks, `CAP_NET_ADMIN`.
+        capability: &'static str,
+        /// Whether the device node (`/dev/net/tun`) exists, which is not the case in containers
+        /// without access to it.
+        device_node_exists: bool,
+    },
+
+    /// The device does not exist (`ENODEV`).
+    #[error("the device was not found")]
+    DeviceNotFound,
+
+    /// The name of the device is used by a device which cannot be attached to, e.g. a device of
+    /// another type or a busy device (see
+    /// [`TunBuilder::auto_suffix`](struct.TunBuilder.html#method.auto_suffix)).
+    #[error("the name `{0}` is already in use by a device which cannot be attached to")]
+    NameInUse(String),
+
+    /// The parameter `field` is invalid.
+    #[error("invalid parameter `{field}`")]
+    InvalidParam { field: &'static str },
+
+    /// The packet of `len` bytes is larger than the `max` bytes which the device accepts, and
+    /// was not sent (see [`TunBuilder::strict`](struct.TunBuilder.html#method.strict)).
+    #[error("packet of {len} bytes is larger than the {max} bytes the device accepts")]
+    PacketTooLarge { len: usize, max: usize },
+
+    /// The feature `feature` is not supported on the platform `platform`.
+    #[error("{feature} is not supported on {platform}")]
+    Unsupported {
+        feature: &'static str,
+        platform: &'static str,
+    },
+}
+
+fn missing_capability(capability: &str, device_node_exists: bool) -> String {
+    let mut msg = format!(
+        "permission denied: creating a device requires the {capability} capability (e.g. running \
+         as root), and attaching to a persistent device requires it or owning the device"
+    );
+    if !device_node_exists {
+        msg.push_str("; the device node /dev/net/tun does not exist either");
+    }
+    msg
+}
+
+impl Error {
+    /// Returns `true` if the I/O error `err` of a read or a write means that the device was
+    /// removed, in which case it converts into [`Error::DeviceRemoved`].
+    pub fn is_device_removed(err: &io::Error) -> bool {
+        matches!(
+            err.get_ref().and_then(|err| err.downcast_ref::<Error>()),
+            Some(Error::DeviceRemoved)
+        )
+    }
+
+    /// Returns the last OS error of a read or a write on the device, which reports a removed
+    /// device as [`Error::DeviceRemoved`].
+    ///
+    /// Linux fails with `EBADFD` once the device of a descriptor was deleted, and macOS with
+    /// `ENXIO` once its control socket was disconnected.
+    pub(crate) fn last_io_error() -> io::Error {
+        let err = io::Error::last_os_error();
+        #[cfg(target_os = "linux")]
+        let removed = libc::EBADFD;
+        #[cfg(not(target_os = "linux"))]
+        let removed = libc::ENXIO;
+        if err.raw_os_error() == Some(removed) {
+            return io::Error::new(io::ErrorKind::NotConnected, Error::DeviceRemoved);
+        }
+        err
+    }
+
+    /// Calls the system call `f` until it is not interrupted by a signal, and returns its result
+    /// as a count of bytes, or [`last_io_error`](#method.last_io_error) if it fails.
+    pub(crate) fn retry_io(mut f: impl FnMut() -> libc::ssize_t) -> io::Result<usize> {
+        loop {
+            let n = f();
+            if n >= 0 {
+                return Ok(n as usize);
+            }
+            let err = Self::last_io_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Returns the variant of the failures of the system with `errno`, if it has one.
+    fn from_errno(errno: i32) -> Option<Self> {
+        match errno {
+            libc::EPERM | libc::EACCES => Some(Error::PermissionDenied),
+            libc::ENODEV => Some(Error::DeviceNotFound),
+            _ => None,
+        }
+    }
+}
+
+impl From<nix::Error> for Error {
+    fn from(err: nix::Error) -> Self {
+        Self::from_errno(err as i32).unwrap_or(Error::NixError(err))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // Errors of the crate which were converted into `io::Error` are converted back
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        match err.raw_os_error().and_then(Self::from_errno) {
+            Some(classified) => classified,
+            None => Error::IoError(err),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::NixError(err) => return err.into(),
+            Error::IoError(err) => return err,
+            Error::DeviceRemoved => io::ErrorKind::NotConnected,
+            Error::PermissionDenied | Error::MissingCapability { .. } => {
+                io::ErrorKind::PermissionDenied
+            }
+            Error::DeviceNotFound => io::ErrorKind::NotFound,
+            Error::NameInUse(_) => io::ErrorKind::ResourceBusy,
+            Error::InvalidParam { .. } | Error::PacketTooLarge { .. } => {
+                io::ErrorKind::InvalidInput
+            }
+            Error::Unsupported { .. } => io::ErrorKind::Unsupported,
+        };
+        io::Error::new(kind, err)
+    }
 }
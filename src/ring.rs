@@ -0,0 +1,63 @@
+use std::ops::Range;
+
+/// Represents a reusable arena receiving several packets at once, filled by
+/// [`Tun::recv_ring`](struct.Tun.html#method.recv_ring).
+///
+/// Packets are read back to back into one large allocation and handed out as slices of it, so
+/// receiving does not allocate nor copy once the ring is created.
+pub struct PacketRing {
+    arena: Vec<u8>,
+    packets: Vec<Range<usize>>,
+}
+
+impl PacketRing {
+    /// Creates a new ring of `capacity` bytes.
+    ///
+    /// The capacity should be a multiple of the
+    /// [recommended buffer size](struct.Tun.html#method.recommended_buffer_size) of the device,
+    /// as a packet is only read if a whole buffer of that size is left.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            arena: vec![0u8; capacity],
+            packets: Vec::new(),
+        }
+    }
+
+    /// Returns the number of packets in the ring.
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Returns whether the ring holds no packet.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Returns the packet at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.packets
+            .get(index)
+            .map(|range| &self.arena[range.clone()])
+    }
+
+    /// Returns an iterator over the packets in the ring.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.packets.iter().map(|range| &self.arena[range.clone()])
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.packets.clear();
+    }
+
+    /// Returns the free space of the arena.
+    pub(crate) fn free(&mut self) -> &mut [u8] {
+        let offset = self.packets.last().map_or(0, |range| range.end);
+        &mut self.arena[offset..]
+    }
+
+    /// Records a packet of `len` bytes written at the beginning of the free space.
+    pub(crate) fn push(&mut self, len: usize) {
+        let offset = self.packets.last().map_or(0, |range| range.end);
+        self.packets.push(offset..offset + len);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::Error;
+use crate::Result;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::netlink::Netlink;
+#[cfg(target_os = "macos")]
+use crate::macos;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+use ipnet::IpNet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Represents the options of a route added by [`Tun::add_route`](struct.Tun.html#method.add_route).
+///
+/// By default, the route sends the packets directly through the device, in the main routing
+/// table, with the default metric of the system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteOptions {
+    pub(crate) gateway: Option<IpAddr>,
+    pub(crate) metric: Option<u32>,
+    pub(crate) table: Option<u32>,
+}
+
+impl RouteOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the packets of the route to `gateway`, a peer reachable through the device.
+    pub fn gateway(mut self, gateway: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Sets the metric (priority) of the route; lower metrics are preferred (Linux only).
+    pub fn metric(mut self, metric: u32) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Adds the route to routing table `table` instead of the main one (Linux only).
+    pub fn table(mut self, table: u32) -> Self {
+        self.table = Some(table);
+        self
+    }
+}
+
+/// Returns the network of `prefix` bits at `dest`.
+pub(crate) fn net(dest: IpAddr, prefix: u8) -> Result<IpNet> {
+    IpNet::new(dest, prefix).map_err(|_| Error::InvalidParam { field: "prefix" })
+}
+
+/// Returns the networks covered by a default route through a device.
+///
+/// On macOS, the default routes of the system cannot be shadowed by another default route, so
+/// each family is covered by its two halves instead, which are more specific.
+fn default_nets() -> Vec<IpNet> {
+    if cfg!(target_os = "macos") {
+        half_nets()
+    } else {
+        vec![
+            IpNet::new(Ipv4Addr::UNSPECIFIED.into(), 0).unwrap(),
+            IpNet::new(Ipv6Addr::UNSPECIFIED.into(), 0).unwrap(),
+        ]
+    }
+}
+
+/// Returns the two halves of the IPv4 and IPv6 address spaces, which together take precedence
+/// over any default route.
+pub(crate) fn half_nets() -> Vec<IpNet> {
+    vec![
+        IpNet::new(Ipv4Addr::UNSPECIFIED.into(), 1).unwrap(),
+        IpNet::new(Ipv4Addr::new(128, 0, 0, 0).into(), 1).unwrap(),
+        IpNet::new(Ipv6Addr::UNSPECIFIED.into(), 1).unwrap(),
+        IpNet::new(Ipv6Addr::new(0x8000, 0, 0, 0, 0, 0, 0, 0).into(), 1).unwrap(),
+    ]
+}
+
+/// Adds a route to `dest` through the interface of index `index`.
+pub(crate) fn add_at(index: u32, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return Ok(Netlink::new()?.add_route(index, dest, opts)?);
+    #[cfg(target_os = "macos")]
+    return Ok(macos::route::add(index, dest, opts)?);
+}
+
+/// Removes the route to `dest` through the interface of index `index`.
+pub(crate) fn del_at(index: u32, dest: IpNet, opts: &RouteOptions) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return Ok(Netlink::new()?.del_route(index, dest, opts)?);
+    #[cfg(target_os = "macos")]
+    return Ok(macos::route::delete(index, dest, opts)?);
+}
+
+/// Returns the index of the interface and the gateway, if any, of the route to `dest`.
+pub(crate) fn lookup(dest: IpAddr) -> Result<(u32, Option<IpAddr>)> {
+    #[cfg(target_os = "linux")]
+    return Ok(Netlink::new()?.get_route(dest)?);
+    #[cfg(target_os = "macos")]
+    return Ok(macos::route::get(dest)?);
+}
+
+/// Returns the options of the default route to `net`: the gateway only applies to its family.
+pub(crate) fn default_opts(net: &IpNet, opts: &RouteOptions) -> RouteOptions {
+    let mut opts = *opts;
+    if opts
+        .gateway
+        .is_some_and(|gateway| gateway.is_ipv4() != net.addr().is_ipv4())
+    {
+        opts.gateway = None;
+    }
+    opts
+}
+
+/// Adds the IPv4 and IPv6 default routes through `iface`, or none of them.
+pub(crate) fn set_default(iface: &Interface, opts: &RouteOptions) -> Result<()> {
+    let nets = default_nets();
+    for (i, net) in nets.iter().enumerate() {
+        if let Err(err) = iface.add_route(*net, &default_opts(net, opts)) {
+            for net in &nets[..i] {
+                let _ = iface.del_route(*net, &default_opts(net, opts));
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the IPv4 and IPv6 default routes through `iface`. The routes which do not exist are
+/// skipped.
+pub(crate) fn remove_default(iface: &Interface, opts: &RouteOptions) -> Result<()> {
+    let mut res = Ok(());
+    for net in default_nets() {
+        match iface.del_route(net, &default_opts(&net, opts)) {
+            Err(Error::IoError(err)) if err.raw_os_error() == Some(libc::ESRCH) => {}
+            Err(err) if res.is_ok() => res = Err(err),
+            _ => {}
+        }
+    }
+    res
+}
@@ -0,0 +1,256 @@
+use crate::Counters;
+use crate::counters::QueueCounters;
+use std::io;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+/// Weight of the latest rate in the moving averages, which thus mostly reflect the last ten
+/// samples or so.
+const EWMA_WEIGHT: f64 = 0.2;
+
+/// Represents the statistics of a network interface kept by the kernel, from its point of view:
+/// on a Tun/Tap device, the received packets are the ones the application sent to it, and the
+/// transmitted packets are the ones the application received from it.
+///
+/// The counters of macOS are 32-bit, and wrap around at 4 GiB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    /// Number of packets received by the interface.
+    pub rx_packets: u64,
+    /// Number of bytes received by the interface.
+    pub rx_bytes: u64,
+    /// Number of packets transmitted by the interface.
+    pub tx_packets: u64,
+    /// Number of bytes transmitted by the interface.
+    pub tx_bytes: u64,
+    /// Number of receive errors.
+    pub rx_errors: u64,
+    /// Number of transmit errors.
+    pub tx_errors: u64,
+    /// Number of received packets dropped, e.g. for lack of memory.
+    pub rx_dropped: u64,
+    /// Number of packets dropped before being transmitted, e.g. when no application reads the
+    /// device (Linux only).
+    pub tx_dropped: u64,
+}
+
+impl InterfaceCounters {
+    /// Reads the counters of the interface `name`.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn read(name: &str) -> io::Result<Self> {
+        let read = |counter: &str| -> io::Result<u64> {
+            let path = format!("/sys/class/net/{}/statistics/{}", name, counter);
+            std::fs::read_to_string(path)?
+                .trim()
+                .parse()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        };
+        Ok(Self {
+            rx_packets: read("rx_packets")?,
+            rx_bytes: read("rx_bytes")?,
+            tx_packets: read("tx_packets")?,
+            tx_bytes: read("tx_bytes")?,
+            rx_errors: read("rx_errors")?,
+            tx_errors: read("tx_errors")?,
+            rx_dropped: read("rx_dropped")?,
+            tx_dropped: read("tx_dropped")?,
+        })
+    }
+
+    /// Reads the counters of the interface `name`, from the link entry of `getifaddrs`.
+    #[cfg(target_os = "macos")]
+    pub(crate) fn read(name: &str) -> io::Result<Self> {
+        let mut ifaddrs = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut ifaddrs) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut counters = None;
+        let mut ifaddr = ifaddrs;
+        while let Some(entry) = unsafe { ifaddr.as_ref() } {
+            let is_link = unsafe { entry.ifa_addr.as_ref() }
+                .is_some_and(|addr| addr.sa_family as i32 == libc::AF_LINK);
+            let entry_name = unsafe { std::ffi::CStr::from_ptr(entry.ifa_name) };
+            if is_link && !entry.ifa_data.is_null() && entry_name.to_bytes() == name.as_bytes() {
+                let data = unsafe { &*(entry.ifa_data as *const libc::if_data) };
+                counters = Some(Self {
+                    rx_packets: data.ifi_ipackets.into(),
+                    rx_bytes: data.ifi_ibytes.into(),
+                    tx_packets: data.ifi_opackets.into(),
+                    tx_bytes: data.ifi_obytes.into(),
+                    rx_errors: data.ifi_ierrors.into(),
+                    tx_errors: data.ifi_oerrors.into(),
+                    rx_dropped: data.ifi_iqdrops.into(),
+                    tx_dropped: 0,
+                });
+                break;
+            }
+            ifaddr = entry.ifa_next;
+        }
+        unsafe { libc::freeifaddrs(ifaddrs) };
+        counters.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such interface"))
+    }
+}
+
+/// Represents the rates of traffic in each direction, in packets and bits per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rates {
+    /// Received packets per second.
+    pub rx_pps: f64,
+    /// Received bits per second.
+    pub rx_bps: f64,
+    /// Transmitted packets per second.
+    pub tx_pps: f64,
+    /// Transmitted bits per second.
+    pub tx_bps: f64,
+}
+
+impl Rates {
+    /// Returns the rates between the totals `before` and `after`, taken `secs` seconds apart.
+    /// A counter which went backwards (a device created again, or a 32-bit counter wrapping
+    /// around) counts as idle.
+    fn between(before: [u64; 4], after: [u64; 4], secs: f64) -> Self {
+        let rate = |i: usize| after[i].saturating_sub(before[i]) as f64 / secs;
+        Self {
+            rx_pps: rate(0),
+            rx_bps: rate(1) * 8.0,
+            tx_pps: rate(2),
+            tx_bps: rate(3) * 8.0,
+        }
+    }
+
+    /// Returns the moving average `self` updated with the latest rates `rates`.
+    fn smooth(self, rates: Self) -> Self {
+        let ewma = |average: f64, rate: f64| average + EWMA_WEIGHT * (rate - average);
+        Self {
+            rx_pps: ewma(self.rx_pps, rates.rx_pps),
+            rx_bps: ewma(self.rx_bps, rates.rx_bps),
+            tx_pps: ewma(self.tx_pps, rates.tx_pps),
+            tx_bps: ewma(self.tx_bps, rates.tx_bps),
+        }
+    }
+}
+
+/// Represents a sample of the statistics of a queue, published by a
+/// [`StatsSampler`](struct.StatsSampler.html).
+///
+/// The rates are `None` until two samples were taken, and the counters are `None` when they
+/// are not available: the software counters unless they were enabled with
+/// [`TunBuilder::counters`](struct.TunBuilder.html#method.counters), the interface counters
+/// when reading them failed (e.g. the device was removed).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsSnapshot {
+    /// Time at which the sample was taken.
+    pub at: std::time::Instant,
+    /// Counters of the interface, shared by all its queues.
+    pub interface: Option<InterfaceCounters>,
+    /// Rates of the interface over the last interval.
+    pub interface_rates: Option<Rates>,
+    /// Exponentially weighted moving average of the rates of the interface.
+    pub interface_ewma: Option<Rates>,
+    /// Software counters of the queue.
+    pub software: Option<Counters>,
+    /// Rates of the queue over the last interval.
+    pub software_rates: Option<Rates>,
+    /// Exponentially weighted moving average of the rates of the queue.
+    pub software_ewma: Option<Rates>,
+}
+
+impl StatsSnapshot {
+    /// Takes a sample, and derives its rates from the previous sample `previous`, if any.
+    fn take(name: &str, counters: &QueueCounters, previous: Option<&Self>) -> Self {
+        let at = std::time::Instant::now();
+        let interface = InterfaceCounters::read(name).ok();
+        let software = counters.snapshot();
+        let secs = previous.map(|previous| (at - previous.at).as_secs_f64());
+        let interface_totals =
+            |c: InterfaceCounters| [c.rx_packets, c.rx_bytes, c.tx_packets, c.tx_bytes];
+        let software_totals = |c: Counters| [c.rx_packets, c.rx_bytes, c.tx_packets, c.tx_bytes];
+        let (interface_rates, interface_ewma) = derive(
+            interface.map(interface_totals),
+            previous.and_then(|p| Some((interface_totals(p.interface?), p.interface_ewma))),
+            secs,
+        );
+        let (software_rates, software_ewma) = derive(
+            software.map(software_totals),
+            previous.and_then(|p| Some((software_totals(p.software?), p.software_ewma))),
+            secs,
+        );
+        Self {
+            at,
+            interface,
+            interface_rates,
+            interface_ewma,
+            software,
+            software_rates,
+            software_ewma,
+        }
+    }
+}
+
+/// Returns the rates and their moving average from the totals `totals` and the totals and
+/// average of the previous sample `previous`, taken `secs` seconds before. The average starts
+/// at the first rate.
+fn derive(
+    totals: Option<[u64; 4]>,
+    previous: Option<([u64; 4], Option<Rates>)>,
+    secs: Option<f64>,
+) -> (Option<Rates>, Option<Rates>) {
+    let (Some(totals), Some((before, ewma)), Some(secs)) = (totals, previous, secs) else {
+        return (None, None);
+    };
+    if secs <= 0.0 {
+        return (None, ewma);
+    }
+    let rates = Rates::between(before, totals, secs);
+    (
+        Some(rates),
+        Some(ewma.map_or(rates, |ewma| ewma.smooth(rates))),
+    )
+}
+
+/// Represents a background task sampling the statistics of a queue periodically, returned by
+/// [`Tun::start_stats_sampler`](struct.Tun.html#method.start_stats_sampler). The task stops
+/// when this value is dropped.
+#[derive(Debug)]
+pub struct StatsSampler {
+    rx: watch::Receiver<StatsSnapshot>,
+    task: JoinHandle<()>,
+}
+
+impl StatsSampler {
+    /// Takes a first sample, and spawns the task taking the next ones every `interval`.
+    pub(crate) fn start(name: String, counters: QueueCounters, interval: Duration) -> Self {
+        let (tx, rx) = watch::channel(StatsSnapshot::take(&name, &counters, None));
+        let task = tokio::spawn(async move {
+            let mut ticks = time::interval_at(Instant::now() + interval, interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let snapshot = StatsSnapshot::take(&name, &counters, Some(&tx.borrow()));
+                if tx.send(snapshot).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { rx, task }
+    }
+
+    /// Returns the latest sample.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        *self.rx.borrow()
+    }
+
+    /// Returns a receiver of the samples, notified of each new one, e.g. to feed a dashboard
+    /// from another task.
+    pub fn subscribe(&self) -> watch::Receiver<StatsSnapshot> {
+        self.rx.clone()
+    }
+}
+
+impl Drop for StatsSampler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,426 @@
+use crate::AsyncTunDevice;
+use crate::CloseOptions;
+use crate::DeviceType;
+#[cfg(feature = "etherparse")]
+use crate::ParsedPacket;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::Qdisc;
+use crate::Result;
+#[cfg(feature = "netlink")]
+use crate::RouteOptions;
+use crate::TunBuilder;
+use crate::TunConfig;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::VlanInterface;
+#[cfg(feature = "netlink")]
+use crate::VpnRoutes;
+use crate::device;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::io::TunIo;
+#[cfg(target_os = "linux")]
+use crate::linux::params::Params;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::io::TunIo;
+#[cfg(target_os = "macos")]
+use crate::macos::params::Params;
+#[cfg(feature = "netlink")]
+use crate::route;
+#[cfg(target_os = "linux")]
+use crate::sysctl::{self, AcceptRa, RpFilter};
+#[cfg(feature = "netlink")]
+use ipnet::IpNet;
+use std::io::{self, IoSlice};
+#[cfg(feature = "netlink")]
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+/// Represents a Tun/Tap device performing blocking I/O, without any async runtime.
+///
+/// Use [`TunBuilder::build_sync`](struct.TunBuilder.html#method.build_sync) to create a new
+/// instance. It is available without the `tokio` feature, for applications which only need to
+/// manage devices or run their own event loop.
+pub struct SyncTun {
+    pub(crate) iface: Arc<Interface>,
+    io: TunIo,
+    /// Index of the queue among the queues of device.
+    queue: usize,
+}
+
+impl AsRawFd for SyncTun {
+    fn as_raw_fd(&self) -> RawFd {
+        self.io.as_raw_fd()
+    }
+}
+
+/// Creates a Tun/Tap device from the file descriptor of a device opened elsewhere, e.g. by the
+/// `tun` or `tun-tap` crates, so that its I/O can be moved to this crate incrementally.
+///
+/// The file descriptor is switched to blocking mode and the name of device is queried from
+/// the kernel. The file descriptor is closed if it is not one of a Tun/Tap device.
+impl TryFrom<OwnedFd> for SyncTun {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> io::Result<Self> {
+        device::set_nonblocking(fd.as_raw_fd(), false)?;
+        let iface = Interface::from_fd(fd.as_fd())?;
+        Ok(Self {
+            iface: Arc::new(iface),
+            io: TunIo::from(fd),
+            queue: 0,
+        })
+    }
+}
+
+/// Hands the file descriptor of the queue over to another owner, e.g. the `tun` crate with
+/// `tun::Configuration::raw_fd`, without closing it nor setting the device down.
+///
+/// A device which is not persistent is removed once all of its file descriptors are closed.
+impl From<SyncTun> for OwnedFd {
+    fn from(tun: SyncTun) -> Self {
+        unsafe { OwnedFd::from_raw_fd(tun.into_raw_fd()) }
+    }
+}
+
+impl IntoRawFd for SyncTun {
+    fn into_raw_fd(self) -> RawFd {
+        self.io.into_raw_fd()
+    }
+}
+
+impl SyncTun {
+    /// Creates new instances of Tun/Tap device, one per queue.
+    pub(crate) fn new(params: Params, queues: usize) -> Result<Vec<Self>> {
+        let (iface, fds) = device::allocate(params, queues)?;
+        let iface = Arc::new(iface);
+        let mut tuns = Vec::with_capacity(queues);
+        for (queue, fd) in fds.into_iter().enumerate() {
+            let tun = Self {
+                iface: iface.clone(),
+                io: TunIo::from(fd),
+                queue,
+            };
+            device::set_nonblocking(tun.as_raw_fd(), false)?;
+            tuns.push(tun);
+        }
+        Ok(tuns)
+    }
+
+    /// Creates a new instance of Tun/Tap device from an already configured file descriptor.
+    ///
+    /// The file descriptor is switched to blocking mode and the name of device is queried from
+    /// the kernel.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor of a Tun/Tap device (a `utun` control socket on
+    /// macOS). The ownership of `fd` is transferred to the returned
+    /// [`SyncTun`](struct.SyncTun.html), which closes it on drop.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        Self::try_from(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Switches the file descriptor between blocking (the default) and non-blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        device::set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    /// Closes the Tun/Tap device and reports any error, unlike dropping it.
+    ///
+    /// The device keeps its state and its persistent flag, so that the kernel removes it once
+    /// its last file descriptor is closed unless it is persistent. See
+    /// [`close_with`](#method.close_with) to tear it down.
+    pub fn close(self) -> Result<()> {
+        self.close_with(CloseOptions::default())
+    }
+
+    /// Closes the Tun/Tap device and reports any error, tearing it down as `options` tell if
+    /// this is the last handle of the device, before its file descriptor and control socket are
+    /// closed. Otherwise only the file descriptor of this queue is closed.
+    pub fn close_with(self, options: CloseOptions) -> Result<()> {
+        device::close(self.iface, self.io, options)
+    }
+
+    /// Receives a packet from the Tun/Tap interface, blocking until one is available.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.recv(buf)
+    }
+
+    /// Receives a packet from the Tun/Tap interface into `buf`, blocking until one is available,
+    /// along with a view of its headers which are parsed on first use (feature `etherparse`).
+    #[cfg(feature = "etherparse")]
+    pub fn recv_parsed<'a>(&self, buf: &'a mut [u8]) -> io::Result<ParsedPacket<'a>> {
+        let n = self.recv(buf)?;
+        Ok(ParsedPacket::new(&buf[..n], self.iface.is_tap()?))
+    }
+
+    /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.send(buf)
+    }
+
+    /// Sends several different buffers to the Tun/Tap interface. Returns the number of bytes written to the device.
+    pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.io.sendv(bufs)
+    }
+
+    /// Returns the minimum size of a receive buffer which can hold any packet of the device
+    /// without truncation.
+    pub fn recommended_buffer_size(&self) -> Result<usize> {
+        device::recommended_buffer_size(&self.iface)
+    }
+
+    /// Applies the options of `builder` which differ from the current configuration of device,
+    /// and returns the names of the options which changed (see
+    /// [`Tun::reconfigure`](struct.Tun.html#method.reconfigure)).
+    pub fn reconfigure(&self, builder: TunBuilder) -> Result<Vec<&'static str>> {
+        device::reconfigure(&self.iface, builder.params()?)
+    }
+
+    /// Steers the packets sent through a multi-queue device by the CPU which sends them, so that
+    /// queue `N` receives the packets of CPU `N` (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn steer_by_cpu(&self) -> Result<()> {
+        self.iface.steer_by_cpu(self.io.as_fd())
+    }
+
+    /// Sets the reverse path filtering mode of the device (Linux only).
+    ///
+    /// Tunnels whose peers send packets from addresses which are not routed through the device
+    /// need [`RpFilter::Loose`](enum.RpFilter.html) or `Off`.
+    #[cfg(target_os = "linux")]
+    pub fn set_rp_filter(&self, mode: RpFilter) -> Result<()> {
+        sysctl::set_rp_filter(self.name(), mode)
+    }
+
+    /// Enables or disables IPv4 and IPv6 forwarding on the device (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn set_forwarding(&self, enabled: bool) -> Result<()> {
+        sysctl::set_forwarding(self.name(), enabled)
+    }
+
+    /// Sets whether the device accepts IPv6 router advertisements (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn set_accept_ra(&self, accept: AcceptRa) -> Result<()> {
+        sysctl::set_accept_ra(self.name(), accept)
+    }
+
+    /// Adds an IPv4 or IPv6 address to the device while it is running (feature `netlink`).
+    ///
+    /// Unlike [`TunBuilder::address`](struct.TunBuilder.html#method.address), it keeps the
+    /// existing addresses, so it can add secondary addresses or renumber the device without
+    /// recreating it.
+    #[cfg(feature = "netlink")]
+    pub fn add_address(&self, net: IpNet) -> Result<()> {
+        self.iface.add_address(net)
+    }
+
+    /// Removes an IPv4 or IPv6 address of the device (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn remove_address(&self, net: IpNet) -> Result<()> {
+        self.iface.del_address(net)
+    }
+
+    /// Adds (or replaces) a static neighbor (ARP or NDP) entry mapping `addr` to `mac` on a
+    /// Tap device, so that the kernel does not need to resolve it (feature `netlink`, Linux
+    /// only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn add_neighbor(&self, addr: IpAddr, mac: [u8; 6]) -> Result<()> {
+        self.iface.add_neighbor(addr, mac)
+    }
+
+    /// Removes the neighbor entry of `addr` on a Tap device (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn remove_neighbor(&self, addr: IpAddr) -> Result<()> {
+        self.iface.del_neighbor(addr)
+    }
+
+    /// Adds the (Tap) device to the existing bridge `bridge`, like `ip link set master`
+    /// (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn attach_to_bridge(&self, bridge: &str) -> Result<()> {
+        self.iface.set_bridge(Some(bridge))
+    }
+
+    /// Removes the device from its bridge, if any (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn detach_from_bridge(&self) -> Result<()> {
+        self.iface.set_bridge(None)
+    }
+
+    /// Creates the 802.1Q VLAN sub-interface `<name>.<id>` of a Tap device (feature `netlink`,
+    /// Linux only).
+    ///
+    /// The sub-interface is deleted when the returned
+    /// [`VlanInterface`](struct.VlanInterface.html) is dropped, or along with the device.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn create_vlan(&self, id: u16) -> Result<VlanInterface> {
+        VlanInterface::create(&self.iface, id)
+    }
+
+    /// Replaces the queueing discipline of the device (feature `netlink`, Linux only).
+    ///
+    /// Fails with an `io::ErrorKind::NotFound` error if the kernel does not provide `qdisc`.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn set_qdisc(&self, qdisc: Qdisc) -> Result<()> {
+        self.iface.set_qdisc(qdisc)
+    }
+
+    /// Adds a route to `dest`/`prefix` through the device (feature `netlink`).
+    ///
+    /// The route is added over netlink on Linux and through a `PF_ROUTE` socket on macOS, where
+    /// the metric and the table of `opts` are ignored. It fails if the route already exists.
+    #[cfg(feature = "netlink")]
+    pub fn add_route(&self, dest: IpAddr, prefix: u8, opts: RouteOptions) -> Result<()> {
+        self.iface.add_route(route::net(dest, prefix)?, &opts)
+    }
+
+    /// Removes a route to `dest`/`prefix` through the device, added by
+    /// [`add_route`](#method.add_route) (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn del_route(&self, dest: IpAddr, prefix: u8, opts: RouteOptions) -> Result<()> {
+        self.iface.del_route(route::net(dest, prefix)?, &opts)
+    }
+
+    /// Routes all the IPv4 and IPv6 traffic through the device (feature `netlink`), as a
+    /// full-tunnel VPN does.
+    ///
+    /// A default route of each family is added with the metric and in the table of `opts`, and
+    /// through its gateway for the family of the gateway, if any. Either both routes are added or
+    /// none is. On macOS, each default route is made of the two halves of the address space, so
+    /// that it takes precedence over the default route of the system.
+    #[cfg(feature = "netlink")]
+    pub fn set_default_route(&self, opts: RouteOptions) -> Result<()> {
+        route::set_default(&self.iface, &opts)
+    }
+
+    /// Removes the default routes added by [`set_default_route`](#method.set_default_route)
+    /// with the same `opts` (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn remove_default_route(&self, opts: RouteOptions) -> Result<()> {
+        route::remove_default(&self.iface, &opts)
+    }
+
+    /// Routes all the traffic of the host through the device, except the traffic to the VPN
+    /// `server`, which keeps its current route (feature `netlink`).
+    ///
+    /// A host route to `server` is pinned through its current interface and gateway, so that
+    /// the tunnel does not route its own packets, then the two halves of the IPv4 and IPv6
+    /// address spaces are routed through the device, through the gateway of `opts` for its
+    /// family, if any. The halves take precedence over the default routes of the system, which
+    /// are left untouched. The routes are removed when the returned
+    /// [`VpnRoutes`](struct.VpnRoutes.html) is dropped.
+    #[cfg(feature = "netlink")]
+    pub fn setup_vpn_routes(&self, server: IpAddr, opts: RouteOptions) -> Result<VpnRoutes> {
+        VpnRoutes::setup(self.iface.index()?, server, &opts)
+    }
+
+    /// Returns the index of this queue among the queues of device, in the order in which
+    /// [`TunBuilder::build_sync`](struct.TunBuilder.html#method.build_sync) returned them. A device
+    /// created from a file descriptor is a single queue, of index `0`.
+    pub fn queue_index(&self) -> usize {
+        self.queue
+    }
+
+    /// Returns the number of queues of device, as built.
+    pub fn total_queues(&self) -> usize {
+        self.iface.queues()
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    ///
+    /// Addresses which are not assigned to the device are reported as `None`.
+    pub fn config(&self) -> Result<TunConfig> {
+        device::config(&self.iface)
+    }
+
+    /// Returns the value of MTU.
+    pub fn mtu(&self) -> Result<i32> {
+        self.iface.mtu(None)
+    }
+
+    /// Returns the IPv4 address of device.
+    pub fn address(&self) -> Result<Ipv4Addr> {
+        self.iface.address(None)
+    }
+
+    /// Returns the IPv4 destination address of device.
+    pub fn destination(&self) -> Result<Ipv4Addr> {
+        self.iface.destination(None)
+    }
+
+    /// Returns the IPv4 broadcast address of device.
+    pub fn broadcast(&self) -> Result<Ipv4Addr> {
+        self.iface.broadcast(None)
+    }
+
+    /// Returns the IPv4 netmask address of device.
+    pub fn netmask(&self) -> Result<Ipv4Addr> {
+        self.iface.netmask(None)
+    }
+
+    /// Returns the type of device, known since its creation and checked against the kernel on
+    /// Linux, to pick the framing of its packets.
+    pub fn device_type(&self) -> Result<DeviceType> {
+        device::device_type(&self.iface, self.io.as_fd())
+    }
+
+    /// Returns `true` if the device is administratively up.
+    pub fn is_up(&self) -> Result<bool> {
+        device::is_up(&self.iface)
+    }
+
+    /// Brings the device up, so that it services incoming and outgoing packets.
+    pub fn set_up(&self) -> Result<()> {
+        device::set_up(&self.iface, true)
+    }
+
+    /// Brings the device down. Its configuration is kept, and the packets sent to it are
+    /// dropped until it is brought up again.
+    pub fn set_down(&self) -> Result<()> {
+        device::set_up(&self.iface, false)
+    }
+
+    /// Returns the flags of device.
+    pub fn flags(&self) -> Result<i16> {
+        self.iface.flags(None)
+    }
+}
+
+/// The futures returned by this implementation perform blocking I/O and complete on their
+/// first poll.
+impl AsyncTunDevice for SyncTun {
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        SyncTun::recv(self, buf)
+    }
+
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        SyncTun::send(self, buf)
+    }
+
+    fn name(&self) -> &str {
+        SyncTun::name(self)
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        SyncTun::mtu(self)
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        SyncTun::config(self)
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        SyncTun::device_type(self)
+    }
+}
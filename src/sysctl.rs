@@ -0,0 +1,82 @@
+use crate::Result;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Represents the reverse path filtering mode of a device, set by
+/// [`Tun::set_rp_filter`](struct.Tun.html#method.set_rp_filter).
+///
+/// The kernel applies the maximum of the mode of the device and the one of `all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpFilter {
+    /// No source validation.
+    Off,
+    /// Drops the packets whose source is not routed back through the device.
+    Strict,
+    /// Drops the packets whose source is not routed back through any device.
+    Loose,
+}
+
+/// Represents whether a device accepts IPv6 router advertisements, set by
+/// [`Tun::set_accept_ra`](struct.Tun.html#method.set_accept_ra).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptRa {
+    /// Ignores the router advertisements.
+    Never,
+    /// Accepts the router advertisements, unless forwarding is enabled on the device.
+    Default,
+    /// Accepts the router advertisements, even if forwarding is enabled on the device.
+    Always,
+}
+
+impl RpFilter {
+    fn value(self) -> u8 {
+        match self {
+            Self::Off => 0,
+            Self::Strict => 1,
+            Self::Loose => 2,
+        }
+    }
+}
+
+impl AcceptRa {
+    fn value(self) -> u8 {
+        match self {
+            Self::Never => 0,
+            Self::Default => 1,
+            Self::Always => 2,
+        }
+    }
+}
+
+/// Sets the IPv4 reverse path filtering mode of device `name`.
+pub(crate) fn set_rp_filter(name: &str, mode: RpFilter) -> Result<()> {
+    write("ipv4", name, "rp_filter", mode.value())
+}
+
+/// Enables or disables forwarding on device `name`, for IPv4 and, if the kernel supports it,
+/// IPv6.
+pub(crate) fn set_forwarding(name: &str, enabled: bool) -> Result<()> {
+    write("ipv4", name, "forwarding", enabled as u8)?;
+    if Path::new("/proc/sys/net/ipv6").exists() {
+        write("ipv6", name, "forwarding", enabled as u8)?;
+    }
+    Ok(())
+}
+
+/// Sets whether device `name` accepts IPv6 router advertisements.
+pub(crate) fn set_accept_ra(name: &str, accept: AcceptRa) -> Result<()> {
+    write("ipv6", name, "accept_ra", accept.value())
+}
+
+/// Writes `value` to `/proc/sys/net/<family>/conf/<name>/<key>`.
+///
+/// The errors are reported with the path, since a missing entry (an unknown device, or IPv6
+/// disabled) and a read-only `/proc/sys` (a container) are otherwise indistinguishable.
+fn write(family: &str, name: &str, key: &str, value: u8) -> Result<()> {
+    // The dots of the device names are slashes in the sysctl paths, like `eth0/100`
+    let name = name.replace('.', "/");
+    let path = format!("/proc/sys/net/{family}/conf/{name}/{key}");
+    fs::write(&path, value.to_string())
+        .map_err(|err| io::Error::new(err.kind(), format!("{path}: {err}")).into())
+}
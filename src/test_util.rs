@@ -0,0 +1,238 @@
+//! Builders of packets for tests (feature `test-util`).
+//!
+//! [`PacketBuilder`] builds IPv4 and IPv6 packets for Tun devices, with correct lengths and
+//! checksums, and [`ethernet`] frames them for Tap devices, along with the ARP requests and
+//! replies of [`arp_request`] and [`arp_reply`].
+//!
+//! ```
+//! use std::net::Ipv4Addr;
+//! use tokio_tun::test_util::PacketBuilder;
+//!
+//! let packet = PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1))
+//!     .udp(5000, 53, b"query");
+//! assert_eq!(packet.len(), 20 + 8 + 5);
+//! ```
+//!
+//! On Linux, [`testns`] runs a test with a device inside a throwaway network namespace.
+
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+mod testns;
+
+use crate::checksum;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[cfg(all(target_os = "linux", feature = "tokio"))]
+pub use self::testns::testns;
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// Broadcast MAC address.
+pub const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// Represents the IP header of the packets to build: their source and destination addresses,
+/// and their TTL (or hop limit), which defaults to 64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketBuilder {
+    source: IpAddr,
+    destination: IpAddr,
+    ttl: u8,
+}
+
+impl PacketBuilder {
+    /// Builds IPv4 packets from `source` to `destination`.
+    pub fn ipv4(source: Ipv4Addr, destination: Ipv4Addr) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            ttl: 64,
+        }
+    }
+
+    /// Builds IPv6 packets from `source` to `destination`.
+    pub fn ipv6(source: Ipv6Addr, destination: Ipv6Addr) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            ttl: 64,
+        }
+    }
+
+    /// Sets the TTL of IPv4 packets, or the hop limit of IPv6 packets.
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the same builder with the source and destination addresses swapped, to build
+    /// replies.
+    pub fn reversed(self) -> Self {
+        Self {
+            source: self.destination,
+            destination: self.source,
+            ..self
+        }
+    }
+
+    /// Builds an ICMP (or ICMPv6) echo request.
+    pub fn icmp_echo_request(&self, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+        let kind = if self.is_ipv4() { 8 } else { 128 };
+        self.icmp_echo(kind, id, seq, payload)
+    }
+
+    /// Builds an ICMP (or ICMPv6) echo reply.
+    pub fn icmp_echo_reply(&self, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+        let kind = if self.is_ipv4() { 0 } else { 129 };
+        self.icmp_echo(kind, id, seq, payload)
+    }
+
+    /// Builds a UDP datagram.
+    pub fn udp(&self, source_port: u16, destination_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut segment = Vec::with_capacity(8 + payload.len());
+        segment.extend_from_slice(&source_port.to_be_bytes());
+        segment.extend_from_slice(&destination_port.to_be_bytes());
+        segment.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        segment.extend_from_slice(&[0, 0]);
+        segment.extend_from_slice(payload);
+        self.packet(IPPROTO_UDP, &segment, Some(6))
+    }
+
+    /// Builds a TCP segment opening a connection, with the SYN flag and the initial sequence
+    /// number `seq`.
+    pub fn tcp_syn(&self, source_port: u16, destination_port: u16, seq: u32) -> Vec<u8> {
+        let mut segment = vec![0; 20];
+        segment[0..2].copy_from_slice(&source_port.to_be_bytes());
+        segment[2..4].copy_from_slice(&destination_port.to_be_bytes());
+        segment[4..8].copy_from_slice(&seq.to_be_bytes());
+        segment[12] = 5 << 4; // Data offset, in 32-bit words
+        segment[13] = 0x02; // SYN
+        segment[14..16].copy_from_slice(&64240u16.to_be_bytes());
+        self.packet(IPPROTO_TCP, &segment, Some(16))
+    }
+
+    fn is_ipv4(&self) -> bool {
+        self.source.is_ipv4()
+    }
+
+    fn icmp_echo(&self, kind: u8, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+        let mut message = Vec::with_capacity(8 + payload.len());
+        message.extend_from_slice(&[kind, 0, 0, 0]);
+        message.extend_from_slice(&id.to_be_bytes());
+        message.extend_from_slice(&seq.to_be_bytes());
+        message.extend_from_slice(payload);
+        if self.is_ipv4() {
+            // The checksum of ICMP only covers the message
+            let csum = checksum::fold(checksum::sum(&message, 0));
+            message[2..4].copy_from_slice(&csum.to_be_bytes());
+            self.packet(IPPROTO_ICMP, &message, None)
+        } else {
+            self.packet(IPPROTO_ICMPV6, &message, Some(2))
+        }
+    }
+
+    /// Prepends the IP header of protocol `protocol` to `payload`, and fills the checksum at
+    /// `csum_offset` of the payload, which covers the pseudo header.
+    fn packet(&self, protocol: u8, payload: &[u8], csum_offset: Option<usize>) -> Vec<u8> {
+        let mut packet = match (self.source, self.destination) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                let mut header = vec![0; 20];
+                header[0] = 0x45;
+                header[2..4].copy_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+                header[6] = 0x40; // Don't fragment
+                header[8] = self.ttl;
+                header[9] = protocol;
+                header[12..16].copy_from_slice(&source.octets());
+                header[16..20].copy_from_slice(&destination.octets());
+                let csum = checksum::fold(checksum::sum(&header, 0));
+                header[10..12].copy_from_slice(&csum.to_be_bytes());
+                header
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                let mut header = vec![0; 40];
+                header[0] = 0x60;
+                header[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+                header[6] = protocol;
+                header[7] = self.ttl;
+                header[8..24].copy_from_slice(&source.octets());
+                header[24..40].copy_from_slice(&destination.octets());
+                header
+            }
+            // The constructors only pair addresses of the same family
+            _ => unreachable!(),
+        };
+        let l4 = packet.len();
+        packet.extend_from_slice(payload);
+        if let Some(offset) = csum_offset {
+            let pseudo = checksum::pseudo_header(&packet, protocol, payload.len());
+            let mut csum = checksum::fold(checksum::sum(&packet[l4..], pseudo));
+            if protocol == IPPROTO_UDP && csum == 0 {
+                csum = 0xffff;
+            }
+            packet[l4 + offset..l4 + offset + 2].copy_from_slice(&csum.to_be_bytes());
+        }
+        packet
+    }
+}
+
+/// Frames the IP packet `packet` in an Ethernet frame from `source` to `destination`, for Tap
+/// devices. Its EtherType is taken from the version of the packet.
+pub fn ethernet(destination: [u8; 6], source: [u8; 6], packet: &[u8]) -> Vec<u8> {
+    let ethertype = match packet.first().map(|b| b >> 4) {
+        Some(6) => ETHERTYPE_IPV6,
+        _ => ETHERTYPE_IPV4,
+    };
+    frame(destination, source, ethertype, packet)
+}
+
+/// Builds the Ethernet frame of an ARP request, broadcast by `sender_mac` at `sender_ip` to
+/// resolve `target_ip`.
+pub fn arp_request(sender_mac: [u8; 6], sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Vec<u8> {
+    let arp = arp(1, sender_mac, sender_ip, [0; 6], target_ip);
+    frame(BROADCAST_MAC, sender_mac, ETHERTYPE_ARP, &arp)
+}
+
+/// Builds the Ethernet frame of an ARP reply, sent by `sender_mac` at `sender_ip` to
+/// `target_mac` at `target_ip`.
+pub fn arp_reply(
+    sender_mac: [u8; 6],
+    sender_ip: Ipv4Addr,
+    target_mac: [u8; 6],
+    target_ip: Ipv4Addr,
+) -> Vec<u8> {
+    let arp = arp(2, sender_mac, sender_ip, target_mac, target_ip);
+    frame(target_mac, sender_mac, ETHERTYPE_ARP, &arp)
+}
+
+fn arp(
+    operation: u16,
+    sender_mac: [u8; 6],
+    sender_ip: Ipv4Addr,
+    target_mac: [u8; 6],
+    target_ip: Ipv4Addr,
+) -> Vec<u8> {
+    let mut arp = Vec::with_capacity(28);
+    arp.extend_from_slice(&1u16.to_be_bytes()); // Ethernet
+    arp.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    arp.extend_from_slice(&[6, 4]);
+    arp.extend_from_slice(&operation.to_be_bytes());
+    arp.extend_from_slice(&sender_mac);
+    arp.extend_from_slice(&sender_ip.octets());
+    arp.extend_from_slice(&target_mac);
+    arp.extend_from_slice(&target_ip.octets());
+    arp
+}
+
+fn frame(destination: [u8; 6], source: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + payload.len());
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
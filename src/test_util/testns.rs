@@ -0,0 +1,68 @@
+use crate::Result;
+use crate::Tun;
+use crate::TunBuilder;
+use crate::linux::interface::Interface;
+use std::future::Future;
+use std::io;
+use std::panic;
+use std::thread;
+
+/// Runs `f` with a device built by `builder` inside a throwaway network namespace, and returns
+/// its output (Linux and feature `tokio`).
+///
+/// The namespace is created on a dedicated thread, which runs `f` on a current-thread tokio
+/// runtime: the device, and any socket `f` opens, live in the namespace, isolated from the
+/// network of the host, along with a loopback interface which is up. The namespace and the
+/// device are destroyed by the kernel once the thread exits, even if `f` panics, in which case
+/// the panic is resumed on the calling thread.
+///
+/// Creating a network namespace requires the `CAP_SYS_ADMIN` capability (e.g. root), or an
+/// unprivileged user namespace entered beforehand (e.g. with `unshare -rn`).
+///
+/// ```no_run
+/// use std::net::Ipv4Addr;
+/// use tokio_tun::TunBuilder;
+/// use tokio_tun::test_util::{PacketBuilder, testns};
+///
+/// # fn run() -> tokio_tun::Result<()> {
+/// let builder = TunBuilder::new()
+///     .address(Ipv4Addr::new(10, 0, 0, 1))
+///     .netmask(Ipv4Addr::new(255, 255, 255, 0))
+///     .up();
+/// testns(builder, |tun| async move {
+///     let ping = PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1))
+///         .icmp_echo_request(1, 1, b"ping");
+///     tun.send(&ping).await.unwrap();
+///     let mut buf = [0u8; 1500];
+///     let n = tun.recv(&mut buf).await.unwrap();
+///     assert_eq!(buf[20], 0); // Echo reply
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn testns<F, Fut>(builder: TunBuilder, f: F) -> Result<Fut::Output>
+where
+    F: FnOnce(Tun) -> Fut + Send + 'static,
+    Fut: Future,
+    Fut::Output: Send + 'static,
+{
+    let thread = thread::Builder::new()
+        .name("tokio-tun-testns".into())
+        .spawn(move || -> Result<Fut::Output> {
+            // The namespace of the thread only, which is left along with the thread
+            if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Interface::new(&[], "lo", 0)?.flags(Some(libc::IFF_UP as i16))?;
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async move {
+                let tun = builder.build()?.remove(0);
+                Ok(f(tun).await)
+            })
+        })?;
+    thread
+        .join()
+        .unwrap_or_else(|panic| panic::resume_unwind(panic))
+}
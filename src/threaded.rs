@@ -0,0 +1,158 @@
+use crate::device;
+#[cfg(target_os = "linux")]
+use crate::linux::interface::Interface;
+#[cfg(target_os = "linux")]
+use crate::linux::io::TunIo;
+#[cfg(target_os = "macos")]
+use crate::macos::interface::Interface;
+#[cfg(target_os = "macos")]
+use crate::macos::io::TunIo;
+use std::io::{self, ErrorKind, PipeReader, PipeWriter};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::mpsc;
+
+/// Represents a Tun/Tap queue served by dedicated OS threads, created by
+/// [`Tun::into_threaded`](struct.Tun.html#method.into_threaded).
+///
+/// A reader thread and a writer thread perform blocking I/O on the file descriptor and exchange
+/// packets with async consumers through bounded channels. This avoids the reactor entirely and
+/// usually yields a higher throughput for gateways which saturate a queue.
+///
+/// Both threads exit once this value is dropped, even on an idle device: the reader thread
+/// waits for a packet and for the drop at once. The file descriptor is then closed.
+pub struct ThreadedTun {
+    iface: Arc<Interface>,
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    tx: mpsc::Sender<Vec<u8>>,
+    write_error: Arc<Mutex<Option<io::Error>>>,
+    /// Write end of the pipe awaited by the reader thread, whose closing on drop stops it.
+    _stop: PipeWriter,
+}
+
+impl ThreadedTun {
+    pub(crate) fn new(
+        iface: Arc<Interface>,
+        io: TunIo,
+        buffer_size: usize,
+        capacity: usize,
+    ) -> io::Result<Self> {
+        device::set_nonblocking(io.as_raw_fd(), false)?;
+
+        let io = Arc::new(io);
+        let (read_tx, rx) = mpsc::channel(capacity);
+        let (tx, mut write_rx) = mpsc::channel::<Vec<u8>>(capacity);
+        let write_error = Arc::new(Mutex::new(None));
+        let (stopped, stop) = io::pipe()?;
+
+        let reader = io.clone();
+        thread::Builder::new()
+            .name(format!("{}-reader", iface.name()))
+            .spawn(move || {
+                loop {
+                    match wait_readable(reader.as_raw_fd(), &stopped) {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(err) => {
+                            let _ = read_tx.blocking_send(Err(err));
+                            break;
+                        }
+                    }
+                    let mut buf = vec![0u8; buffer_size];
+                    let res = reader.recv(&mut buf).map(|n| {
+                        buf.truncate(n);
+                        buf
+                    });
+                    let failed = res.is_err();
+                    if read_tx.blocking_send(res).is_err() || failed {
+                        break;
+                    }
+                }
+            })?;
+
+        let writer_error = write_error.clone();
+        thread::Builder::new()
+            .name(format!("{}-writer", iface.name()))
+            .spawn(move || {
+                while let Some(packet) = write_rx.blocking_recv() {
+                    if let Err(err) = io.send(&packet) {
+                        *writer_error.lock().unwrap_or_else(|err| err.into_inner()) = Some(err);
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            iface,
+            rx,
+            tx,
+            write_error,
+            _stop: stop,
+        })
+    }
+
+    /// Receives a packet read by the reader thread.
+    ///
+    /// Once the reader thread has failed, its error is returned and subsequent calls fail with
+    /// `io::ErrorKind::BrokenPipe`.
+    pub async fn recv(&mut self) -> io::Result<Vec<u8>> {
+        match self.rx.recv().await {
+            Some(res) => res,
+            None => Err(ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    /// Queues a packet to be written by the writer thread, waiting while the queue is full.
+    ///
+    /// If the writer thread has failed, its error is returned, then `io::ErrorKind::BrokenPipe`.
+    pub async fn send(&self, packet: Vec<u8>) -> io::Result<()> {
+        if self.tx.send(packet).await.is_ok() {
+            return Ok(());
+        }
+        let err = self
+            .write_error
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        Err(err.unwrap_or_else(|| ErrorKind::BrokenPipe.into()))
+    }
+
+    /// Returns the name of Tun/Tap device.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+}
+
+/// Waits until `fd` is readable or reports an error, returning `true`, or until the write end of
+/// `stopped` is closed, returning `false`.
+fn wait_readable(fd: RawFd, stopped: &PipeReader) -> io::Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: stopped.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        // The write end is only ever closed, so any event of the pipe is its closing
+        if fds[1].revents != 0 {
+            return Ok(false);
+        }
+        if fds[0].revents != 0 {
+            return Ok(true);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::time::{Instant, SystemTime};
+
+/// Represents the time at which a packet was received from a Tun/Tap device.
+///
+/// Both clocks are sampled right after the read system call returns. Tun/Tap devices do not
+/// support kernel timestamps (`SIOCGSTAMP` only applies to sockets), so this is the closest
+/// available approximation of the arrival time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Monotonic time, suitable for measuring latencies.
+    pub monotonic: Instant,
+    /// Wall-clock time, suitable for packet captures.
+    pub wall: SystemTime,
+}
+
+impl Timestamp {
+    pub(crate) fn now() -> Self {
+        Self {
+            monotonic: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+}
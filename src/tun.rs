@@ -1,5 +1,36 @@
+use crate::AddressChanges;
+use crate::AsyncTunDevice;
+use crate::CloseOptions;
+use crate::Counters;
+use crate::DeviceType;
+use crate::Error;
+use crate::InterfaceCounters;
+use crate::MtuChanges;
+use crate::OwnedReadyGuard;
+use crate::PacketRing;
+#[cfg(feature = "etherparse")]
+use crate::ParsedPacket;
+use crate::PooledPacket;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::Qdisc;
 use crate::Result;
+#[cfg(feature = "netlink")]
+use crate::RouteOptions;
+use crate::StatsSampler;
+use crate::ThreadedTun;
+use crate::Timestamp;
 use crate::TunBuilder;
+use crate::TunConfig;
+use crate::TunHandle;
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+use crate::VlanInterface;
+#[cfg(feature = "netlink")]
+use crate::VpnRoutes;
+use crate::builder::DEFAULT_BUDGET;
+use crate::counters::QueueCounters;
+use crate::device;
+use crate::gso;
+use crate::handle;
 #[cfg(target_os = "linux")]
 use crate::linux::interface::Interface;
 #[cfg(target_os = "linux")]
@@ -12,20 +43,44 @@ use crate::macos::interface::Interface;
 use crate::macos::io::TunIo;
 #[cfg(target_os = "macos")]
 use crate::macos::params::Params;
-use std::io::{self, ErrorKind, IoSlice, Read, Write};
+use crate::pool::BufferPool;
+use crate::pump;
+#[cfg(feature = "netlink")]
+use crate::route;
+#[cfg(target_os = "linux")]
+use crate::sysctl::{self, AcceptRa, RpFilter};
+use bytes::{Bytes, BytesMut};
+#[cfg(feature = "netlink")]
+use ipnet::IpNet;
+use std::fmt;
+use std::future::{self, Future};
+use std::io::{self, ErrorKind, IoSlice};
 use std::mem;
+#[cfg(feature = "netlink")]
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
-#[cfg(target_os = "linux")]
-use std::os::raw::c_char;
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{self, Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::io::unix::AsyncFd;
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, coop};
+use tokio_util::sync::CancellationToken;
 
-#[cfg(target_os = "linux")]
-static TUN: &[u8] = b"/dev/net/tun\0";
+/// The readiness awaited by reads, which includes errors: a removed device only reports an error
+/// readiness, and its reads would otherwise wait forever instead of failing.
+const READABLE: Interest = Interest::READABLE.add(Interest::ERROR);
+
+/// Pending wait for [`READABLE`] of the poll-based reads, kept across polls since `AsyncFd` only
+/// offers `poll_read_ready`, which ignores the error readiness.
+type ReadReady = Pin<Box<dyn Future<Output = io::Result<Ready>> + Send>>;
+
+/// Interval between the checks of [`Tun::wait_for_up`].
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 // Taken from the `futures` crate
 macro_rules! ready {
@@ -37,10 +92,53 @@ macro_rules! ready {
     };
 }
 
+/// Returns the result of an I/O operation attempted without waiting for readiness, unless it
+/// would block.
+///
+/// Tun/Tap devices are writable almost all the time, so writing first saves polling readiness
+/// for nearly every packet. If the write would block, the readiness is awaited as usual.
+fn optimistic<T>(res: io::Result<T>) -> Option<io::Result<T>> {
+    match res {
+        Err(err) if err.kind() == ErrorKind::WouldBlock => None,
+        res => Some(res),
+    }
+}
+
+/// Attempts a write without waiting for readiness like [`optimistic`], once the task has coop
+/// budget left.
+///
+/// A successful write consumes a unit of the budget, like a write which waited for readiness,
+/// so that a task writing in a loop to an always writable device still yields to the others.
+/// Without budget left, the task yields before writing.
+fn poll_optimistic<T>(
+    cx: &mut Context<'_>,
+    f: impl FnOnce() -> io::Result<T>,
+) -> Poll<Option<io::Result<T>>> {
+    let coop = ready!(coop::poll_proceed(cx));
+    let res = optimistic(f());
+    if res.is_some() {
+        coop.made_progress();
+    }
+    Poll::Ready(res)
+}
+
 /// Represents a Tun/Tap device. Use [`TunBuilder`](struct.TunBuilder.html) to create a new instance of [`Tun`](struct.Tun.html).
 pub struct Tun {
-    iface: Arc<Interface>,
-    io: AsyncFd<TunIo>,
+    pub(crate) iface: Arc<Interface>,
+    pub(crate) io: Arc<AsyncFd<TunIo>>,
+    read_ready: Mutex<Option<ReadReady>>,
+    /// Index of the queue among the queues of device.
+    queue: usize,
+    pool: Arc<BufferPool>,
+    budget: usize,
+    counters: QueueCounters,
+    busy_poll: Option<Duration>,
+    /// Largest packet which send methods accept, in strict mode, shared by the queues of device
+    /// so that [`reconfigure`](#method.reconfigure) updates it for all of them.
+    max_send: Option<Arc<AtomicUsize>>,
+    /// Error which ended a batch of packets after some were received, returned by the next
+    /// batch.
+    deferred: Mutex<Option<io::Error>>,
 }
 
 impl AsRawFd for Tun {
@@ -49,23 +147,117 @@ impl AsRawFd for Tun {
     }
 }
 
+/// Creates a Tun/Tap device from the file descriptor of a device opened elsewhere, e.g. by the
+/// `tun` or `tun-tap` crates, so that its I/O can be moved to this crate incrementally.
+///
+/// The file descriptor is switched to non-blocking mode and the name of device is queried from
+/// the kernel. The file descriptor is closed if it is not one of a Tun/Tap device.
+impl TryFrom<OwnedFd> for Tun {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> io::Result<Self> {
+        device::set_nonblocking(fd.as_raw_fd(), true)?;
+        let iface = Interface::from_fd(fd.as_fd())?;
+        let buffer_size = Arc::new(AtomicUsize::new(Self::buffer_size(&iface)));
+        Ok(Self {
+            iface: Arc::new(iface),
+            io: Arc::new(AsyncFd::new(TunIo::from(fd))?),
+            read_ready: Mutex::default(),
+            queue: 0,
+            pool: BufferPool::new(buffer_size),
+            budget: DEFAULT_BUDGET,
+            counters: QueueCounters::new(false),
+            busy_poll: None,
+            max_send: None,
+            deferred: Mutex::default(),
+        })
+    }
+}
+
+/// Hands the file descriptor of the queue over to another owner, e.g. the `tun` crate with
+/// `tun::Configuration::raw_fd`, without closing it nor setting the device down.
+///
+/// A device which is not persistent is removed once all of its file descriptors are closed.
+impl From<Tun> for OwnedFd {
+    fn from(tun: Tun) -> Self {
+        unsafe { OwnedFd::from_raw_fd(tun.into_raw_fd()) }
+    }
+}
+
+impl IntoRawFd for Tun {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_parts().1.into_raw_fd()
+    }
+}
+
+impl fmt::Debug for Tun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tun")
+            .field("name", &self.name())
+            .field("fd", &self.as_raw_fd())
+            .field("tap", &self.iface.is_tap().ok())
+            .field("mtu", &self.mtu().ok())
+            .field("flags", &self.flags().ok())
+            .field("address", &self.address().ok())
+            .field("destination", &self.destination().ok())
+            .field("broadcast", &self.broadcast().ok())
+            .field("netmask", &self.netmask().ok())
+            .finish()
+    }
+}
+
+impl fmt::Display for Tun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_unset<T: fmt::Display>(value: Result<T>) -> String {
+            value.map_or_else(|_| "-".into(), |value| value.to_string())
+        }
+
+        let mode = match self.iface.is_tap() {
+            Ok(true) => "tap",
+            Ok(false) => "tun",
+            Err(_) => "-",
+        };
+        writeln!(f, "┌ name: {}", self.name())?;
+        writeln!(f, "├ fd: {}", self.as_raw_fd())?;
+        writeln!(f, "├ mode: {}", mode)?;
+        writeln!(f, "├ mtu: {}", or_unset(self.mtu()))?;
+        writeln!(f, "├ flags: {}", or_unset(self.flags()))?;
+        writeln!(f, "├ address: {}", or_unset(self.address()))?;
+        writeln!(f, "├ destination: {}", or_unset(self.destination()))?;
+        writeln!(f, "├ broadcast: {}", or_unset(self.broadcast()))?;
+        write!(f, "└ netmask: {}", or_unset(self.netmask()))
+    }
+}
+
 impl AsyncRead for Tun {
+    /// Reads a packet into the unfilled part of `buf`, after what it already holds, without
+    /// initializing it first. A packet larger than the unfilled part is truncated to its length.
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> task::Poll<io::Result<()>> {
-        let self_mut = self.get_mut();
+        // A read into no room would consume a packet and lose it
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let this = self.get_mut();
         loop {
-            let mut guard = ready!(self_mut.io.poll_read_ready_mut(cx))?;
+            let ready = ready!(this.poll_read_ready(cx))?;
 
-            match guard.try_io(|inner| inner.get_mut().read(buf.initialize_unfilled())) {
-                Ok(Ok(n)) => {
-                    buf.set_filled(buf.filled().len() + n);
+            // SAFETY: `recv_uninit` only writes initialized bytes into the unfilled part, and
+            // reports how many of them it wrote
+            let unfilled = unsafe { buf.unfilled_mut() };
+            match this.try_read(ready, |inner| {
+                this.counters.recv(inner.recv_uninit(unfilled))
+            }) {
+                Ok(n) => {
+                    unsafe { buf.assume_init(n) };
+                    buf.advance(n);
                     return Poll::Ready(Ok(()));
                 }
-                Ok(Err(err)) => return Poll::Ready(Err(err)),
-                Err(_) => continue,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                Err(err) => return Poll::Ready(Err(err)),
             }
         }
     }
@@ -78,10 +270,18 @@ impl AsyncWrite for Tun {
         buf: &[u8],
     ) -> task::Poll<io::Result<usize>> {
         let self_mut = self.get_mut();
+        self_mut.check_size(buf.len())?;
+        let counters = &self_mut.counters;
+        let io = &self_mut.io;
+        if let Some(res) =
+            ready!(poll_optimistic(cx, || counters.send(buf.len(), io.get_ref().send(buf))))
+        {
+            return Poll::Ready(res);
+        }
         loop {
-            let mut guard = ready!(self_mut.io.poll_write_ready_mut(cx))?;
+            let mut guard = ready!(self_mut.io.poll_write_ready(cx))?;
 
-            match guard.try_io(|inner| inner.get_mut().write(buf)) {
+            match guard.try_io(|inner| counters.send(buf.len(), inner.get_ref().send(buf))) {
                 Ok(result) => return Poll::Ready(result),
                 Err(_would_block) => continue,
             }
@@ -94,10 +294,19 @@ impl AsyncWrite for Tun {
         bufs: &[IoSlice<'_>],
     ) -> Poll<std::result::Result<usize, io::Error>> {
         let self_mut = self.get_mut();
+        let counters = &self_mut.counters;
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        self_mut.check_size(len)?;
+        let io = &self_mut.io;
+        if let Some(res) =
+            ready!(poll_optimistic(cx, || counters.send(len, io.get_ref().sendv(bufs))))
+        {
+            return Poll::Ready(res);
+        }
         loop {
-            let mut guard = ready!(self_mut.io.poll_write_ready_mut(cx))?;
+            let mut guard = ready!(self_mut.io.poll_write_ready(cx))?;
 
-            match guard.try_io(|inner| inner.get_mut().write_vectored(bufs)) {
+            match guard.try_io(|inner| counters.send(len, inner.get_ref().sendv(bufs))) {
                 Ok(result) => return Poll::Ready(result),
                 Err(_would_block) => continue,
             }
@@ -108,16 +317,9 @@ impl AsyncWrite for Tun {
         true
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> task::Poll<io::Result<()>> {
-        let self_mut = self.get_mut();
-        loop {
-            let mut guard = ready!(self_mut.io.poll_write_ready_mut(cx))?;
-
-            match guard.try_io(|inner| inner.get_mut().flush()) {
-                Ok(result) => return Poll::Ready(result),
-                Err(_) => continue,
-            }
-        }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> task::Poll<io::Result<()>> {
+        // Packets are handed to the kernel as a whole on write, there is nothing to flush
+        Poll::Ready(Ok(()))
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> task::Poll<io::Result<()>> {
@@ -132,132 +334,425 @@ impl Tun {
 
     /// Creates a new instance of Tun/Tap device.
     pub(crate) fn new(params: Params) -> Result<Self> {
-        let iface = Self::allocate(params, 1)?;
-        let fd = iface.files()[0];
+        let budget = params.budget;
+        let counters = params.counters;
+        let busy_poll = params.busy_poll;
+        let strict = params.strict;
+        let (iface, mut fds) = device::allocate(params, 1)?;
+        let fd = fds.remove(0);
+        let buffer_size = Arc::new(AtomicUsize::new(Self::buffer_size(&iface)));
+        let max_send = Self::max_send(&iface, strict)?;
         Ok(Self {
             iface: Arc::new(iface),
-            io: AsyncFd::new(TunIo::from(fd))?,
+            io: Arc::new(AsyncFd::new(TunIo::from(fd))?),
+            read_ready: Mutex::default(),
+            queue: 0,
+            pool: BufferPool::new(buffer_size),
+            budget,
+            counters: QueueCounters::new(counters),
+            busy_poll,
+            max_send,
+            deferred: Mutex::default(),
         })
     }
 
     /// Creates a new instance of Tun/Tap device.
     pub(crate) fn new_mq(params: Params, queues: usize) -> Result<Vec<Self>> {
-        let iface = Self::allocate(params, queues)?;
+        let budget = params.budget;
+        let counters = params.counters;
+        let busy_poll = params.busy_poll;
+        let strict = params.strict;
+        let (iface, fds) = device::allocate(params, queues)?;
         let mut tuns = Vec::with_capacity(queues);
+        let buffer_size = Arc::new(AtomicUsize::new(Self::buffer_size(&iface)));
+        let max_send = Self::max_send(&iface, strict)?;
         let iface = Arc::new(iface);
-        for &fd in iface.files() {
+        for (queue, fd) in fds.into_iter().enumerate() {
             tuns.push(Self {
                 iface: iface.clone(),
-                io: AsyncFd::new(TunIo::from(fd))?,
+                io: Arc::new(AsyncFd::new(TunIo::from(fd))?),
+                read_ready: Mutex::default(),
+                queue,
+                pool: BufferPool::new(buffer_size.clone()),
+                budget,
+                counters: QueueCounters::new(counters),
+                busy_poll,
+                max_send: max_send.clone(),
+                deferred: Mutex::default(),
             })
         }
         Ok(tuns)
     }
 
-    #[cfg(target_os = "linux")]
-    fn allocate(params: Params, queues: usize) -> Result<Interface> {
-        let fds = (0..queues)
-            .map(|_| unsafe {
-                match libc::open(
-                    TUN.as_ptr().cast::<c_char>(),
-                    libc::O_RDWR | libc::O_NONBLOCK,
-                ) {
-                    fd if fd >= 0 => Ok(fd),
-                    _ => Err(io::Error::last_os_error().into()),
-                }
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        let iface = Interface::new(
-            fds,
-            params.name.as_deref().unwrap_or_default(),
-            params.flags,
-        )?;
-        iface.init(params)?;
-        Ok(iface)
-    }
-
-    #[cfg(target_os = "macos")]
-    fn allocate(params: Params, queues: usize) -> Result<Interface> {
-        // In macOS, we use the utun interface
-        let mut fds = Vec::with_capacity(queues);
-        let specified_unit = if let Some(name) = &params.name {
-            // Check if name is in utun format
-            if name.starts_with("utun") {
-                name[4..].parse::<i32>().ok()
-            } else {
-                None
-            }
-        } else {
-            None
+    /// Creates a new instance of Tun/Tap device from an already configured file descriptor.
+    ///
+    /// The file descriptor is switched to non-blocking mode and the name of device is queried
+    /// from the kernel. This is useful when the device is allocated by a privileged process and
+    /// its file descriptor is passed to (or inherited by) an unprivileged one.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor of a Tun/Tap device (a `utun` control socket on
+    /// macOS). The ownership of `fd` is transferred to the returned [`Tun`](struct.Tun.html),
+    /// which closes it on drop.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        Self::try_from(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Returns the size of internally allocated receive buffers, derived from the configured MTU.
+    ///
+    /// One extra byte is reserved so that a packet which does not fit is detected as truncated.
+    pub(crate) fn buffer_size(iface: &Interface) -> usize {
+        let mtu = match iface.vnet_hdr() {
+            Ok(true) => device::MAX_MTU,
+            _ => iface
+                .mtu(None)
+                .map_or(device::MAX_MTU, |mtu| (mtu as usize).min(device::MAX_MTU)),
         };
+        mtu + iface.overhead().unwrap_or_default() + 1
+    }
+
+    /// Returns the largest packet which send methods accept, if `strict`.
+    fn max_send(iface: &Interface, strict: bool) -> Result<Option<Arc<AtomicUsize>>> {
+        if !strict {
+            return Ok(None);
+        }
+        device::recommended_buffer_size(iface).map(|max| Some(Arc::new(AtomicUsize::new(max))))
+    }
+
+    /// Fails with [`Error::PacketTooLarge`] in strict mode if a packet of `len` bytes is larger
+    /// than the device accepts.
+    fn check_size(&self, len: usize) -> io::Result<()> {
+        match self
+            .max_send
+            .as_ref()
+            .map(|max| max.load(Ordering::Relaxed))
+        {
+            Some(max) if len > max => Err(Error::PacketTooLarge { len, max }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with `io::ErrorKind::InvalidInput` unless the packets of the device are an IP packet
+    /// preceded by a virtio-net header only, as [`gso`](gso/index.html) expects: no Ethernet
+    /// header, no packet information.
+    fn check_offload(&self) -> io::Result<()> {
+        let plain = !self.iface.is_tap()?
+            && self.iface.vnet_hdr()?
+            && self.iface.overhead()? == gso::VIRTIO_NET_HDR_LEN;
+        if !plain {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "GSO needs a TUN device with offloads and without packet information",
+            ));
+        }
+        Ok(())
+    }
 
-        // If a specific utun name was requested, try to open it
-        if let Some(unit) = specified_unit {
-            let (fd, name) = Interface::open_utun(unit)?;
-            fds.push(fd);
+    /// Receives a packet from the Tun/Tap interface.
+    ///
+    /// A packet larger than `buf` is truncated to its length, on Linux and macOS alike. With a
+    /// `buf` one byte longer than [`recommended_buffer_size`](#method.recommended_buffer_size), a
+    /// return value equal to `buf.len()` reliably indicates truncation.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(res) = self.busy_poll(|| self.io.get_ref().recv(buf)) {
+            return self.counters.recv(res);
+        }
+        self.io
+            .async_io(READABLE, |inner| self.counters.recv(inner.recv(buf)))
+            .await
+    }
+
+    /// Receives a packet from the Tun/Tap interface into `buf`, along with a view of its headers
+    /// which are parsed on first use (feature `etherparse`).
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    #[cfg(feature = "etherparse")]
+    pub async fn recv_parsed<'a>(&self, buf: &'a mut [u8]) -> io::Result<ParsedPacket<'a>> {
+        let n = self.recv(buf).await?;
+        Ok(ParsedPacket::new(&buf[..n], self.iface.is_tap()?))
+    }
+
+    /// Attempts to receive a packet from the Tun/Tap interface, registering the waker of `cx` if
+    /// none is available.
+    pub(crate) fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let ready = ready!(self.poll_read_ready(cx))?;
+            match self.try_read(ready, |inner| self.counters.recv(inner.recv(buf))) {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    /// Polls the readiness of the interface for [`READABLE`], registering the waker of `cx` if it
+    /// is not ready. The readiness is retained until a read which would block clears it in
+    /// [`try_read`](#method.try_read).
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<Ready>> {
+        let mut pending = self.read_ready.lock().unwrap();
+        let ready = pending.get_or_insert_with(|| {
+            let io = self.io.clone();
+            Box::pin(async move {
+                let mut guard = io.ready(READABLE).await?;
+                let ready = guard.ready();
+                guard.retain_ready();
+                Ok(ready)
+            })
+        });
+        let res = ready!(ready.as_mut().poll(cx));
+        *pending = None;
+        Poll::Ready(res)
+    }
 
-            // Create Interface instance
-            let iface = Interface::new(fds, &name, params.flags)?;
-            iface.init(params)?;
-            return Ok(iface);
+    /// Performs the read `f` once the interface reported the readiness `ready` for
+    /// [`READABLE`].
+    ///
+    /// `AsyncFd::try_io` does not support combined interests: the read readiness is cleared
+    /// through it if `f` would block, while an error readiness alone lets `f` return the error of
+    /// the removed device.
+    pub(crate) fn try_read<R>(
+        &self,
+        ready: Ready,
+        f: impl FnOnce(&TunIo) -> io::Result<R>,
+    ) -> io::Result<R> {
+        if ready.is_readable() {
+            self.io.try_io(Interest::READABLE, f)
         } else {
-            // Otherwise, try to open the next available utun device
-            for i in 0..16 {
-                // Try to open utun devices from 0 to 15
-                match Interface::open_utun(i) {
-                    Ok((fd, name)) => {
-                        fds.push(fd);
-
-                        // Set fd to non-blocking mode
-                        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
-                        if flags < 0 {
-                            return Err(io::Error::last_os_error().into());
-                        }
+            f(self.io.get_ref())
+        }
+    }
+
+    /// Returns the interface and the I/O of the queue, dropping the pending wait for readiness,
+    /// if any, which shares the latter.
+    fn into_parts(self) -> (Arc<Interface>, TunIo) {
+        let Self {
+            iface,
+            io,
+            read_ready,
+            ..
+        } = self;
+        drop(read_ready);
+        let io = Arc::into_inner(io).expect("the I/O of the queue is not shared anymore");
+        (iface, io.into_inner())
+    }
+
+    /// Receives a packet from the Tun/Tap interface into a buffer taken from the internal pool
+    /// of this queue.
+    ///
+    /// The buffers are sized from the MTU of device, and resized when
+    /// [`reconfigure`](#method.reconfigure) changes it. The buffer is returned to the pool when
+    /// the packet is dropped, so no allocation happens in steady state. A packet larger than the
+    /// buffers results in an `io::ErrorKind::InvalidData` error: if the MTU was raised by other
+    /// means (e.g. `ip link`), the buffers are then resized for the next packets.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv_pooled(&self) -> io::Result<PooledPacket> {
+        if self.busy_poll.is_some() {
+            let mut buf = self.pool.take();
+            match self.busy_poll(|| self.io.get_ref().recv(&mut buf)) {
+                Some(res) => return self.pooled(buf, self.counters.recv(res)),
+                None => self.pool.put(buf),
+            }
+        }
+        self.io
+            .async_io(READABLE, |inner| self.recv_pooled_from(inner))
+            .await
+    }
 
-                        if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
-                            return Err(io::Error::last_os_error().into());
+    /// Receives as many packets as are queued on the Tun/Tap interface, up to the budget set by
+    /// [`TunBuilder::budget`](struct.TunBuilder.html#method.budget), into buffers taken from the
+    /// internal pool of this queue. The packets are appended to `packets` and their number is
+    /// returned.
+    ///
+    /// It waits for a single readiness event and reads until the queue is empty, instead of
+    /// returning to the reactor after every packet. The budget prevents a busy queue from
+    /// starving the other tasks of the runtime. An error which occurs once packets were received,
+    /// e.g. a truncated packet, ends the batch and is returned by the next call.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv_many(&self, packets: &mut Vec<PooledPacket>) -> io::Result<usize> {
+        self.take_deferred()?;
+        self.io
+            .async_io(READABLE, |inner| {
+                let mut count = 0;
+                while count < self.budget {
+                    match self.recv_pooled_from(inner) {
+                        Ok(packet) => {
+                            packets.push(packet);
+                            count += 1;
                         }
+                        Err(err) if count > 0 => {
+                            self.defer(err);
+                            break;
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(count)
+            })
+            .await
+    }
 
-                        // Create Interface instance
-                        let iface = Interface::new(fds, &name, params.flags)?;
-                        iface.init(params)?;
-                        return Ok(iface);
+    /// Receives as many packets as are queued on the Tun/Tap interface, up to the budget set by
+    /// [`TunBuilder::budget`](struct.TunBuilder.html#method.budget) and the capacity of `ring`.
+    /// Returns the number of received packets.
+    ///
+    /// The previous content of `ring` is discarded. The packets are read directly into the
+    /// arena of the ring, so no allocation nor copy happens. An error which occurs once packets
+    /// were received, e.g. a truncated packet, ends the batch and is returned by the next call.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv_ring(&self, ring: &mut PacketRing) -> io::Result<usize> {
+        let packet_size = self.pool.size();
+        ring.clear();
+        self.take_deferred()?;
+        if ring.free().len() < packet_size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "ring is smaller than the MTU of device",
+            ));
+        }
+        self.io
+            .async_io(READABLE, |inner| {
+                while ring.len() < self.budget && ring.free().len() >= packet_size {
+                    let free = ring.free();
+                    let len = free.len();
+                    let res = match self.counters.recv(inner.recv(free)) {
+                        Ok(n) if n < len => Ok(n),
+                        Ok(_) => Err(self.truncated()),
+                        Err(err) => Err(err),
+                    };
+                    match res {
+                        Ok(n) => ring.push(n),
+                        Err(err) if !ring.is_empty() => {
+                            self.defer(err);
+                            break;
+                        }
+                        Err(err) => return Err(err),
                     }
-                    Err(_) => continue,
                 }
+                Ok(ring.len())
+            })
+            .await
+    }
+
+    /// Returns the error which ended the previous batch, if any.
+    fn take_deferred(&self) -> io::Result<()> {
+        match self.deferred.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps `err`, which ended a batch of packets, for the next batch unless the queue is
+    /// merely empty.
+    fn defer(&self, err: io::Error) {
+        if err.kind() != ErrorKind::WouldBlock {
+            *self.deferred.lock().unwrap() = Some(err);
+        }
+    }
+
+    /// Retries `f` until it does not block or the busy-poll duration set by
+    /// [`TunBuilder::busy_poll`](struct.TunBuilder.html#method.busy_poll) elapses. Only the
+    /// result which ends the spin is meant to be recorded by the counters, not every retry.
+    fn busy_poll<R>(&self, mut f: impl FnMut() -> io::Result<R>) -> Option<io::Result<R>> {
+        let duration = self.busy_poll?;
+        let start = Instant::now();
+        loop {
+            if let Some(res) = optimistic(f()) {
+                return Some(res);
+            }
+            if start.elapsed() >= duration {
+                return None;
             }
+            std::hint::spin_loop();
+        }
+    }
 
-            return Err(
-                io::Error::new(io::ErrorKind::NotFound, "No available utun device found").into(),
-            );
+    /// Resizes the buffers of the pool for the current MTU, which is larger than when they were
+    /// sized if a packet did not fit, and returns the error reporting the truncated packet.
+    fn truncated(&self) -> io::Error {
+        self.pool.resize(Self::buffer_size(&self.iface));
+        io::Error::new(
+            ErrorKind::InvalidData,
+            "packet is larger than the MTU of device and was truncated",
+        )
+    }
+
+    pub(crate) fn recv_pooled_from(&self, io: &TunIo) -> io::Result<PooledPacket> {
+        let mut buf = self.pool.take();
+        let res = self.counters.recv(io.recv(&mut buf));
+        self.pooled(buf, res)
+    }
+
+    /// Returns the packet which `res` read into `buf`, or returns `buf` to the pool.
+    fn pooled(&self, buf: Vec<u8>, res: io::Result<usize>) -> io::Result<PooledPacket> {
+        match res {
+            Ok(n) if n < buf.len() => Ok(PooledPacket::new(buf, n, self.pool.clone())),
+            res => {
+                self.pool.put(buf);
+                match res {
+                    Ok(_) => Err(self.truncated()),
+                    Err(err) => Err(err),
+                }
+            }
         }
     }
 
-    /// Receives a packet from the Tun/Tap interface.
+    /// Receives a packet from a Tun/Tap interface with offloads enabled and splits it into
+    /// regular packets, which are appended to `segments`. Returns the number of appended packets.
+    ///
+    /// `buf` must be large enough for coalesced packets, see
+    /// [`recommended_buffer_size`](#method.recommended_buffer_size). Fails with
+    /// `io::ErrorKind::InvalidInput` unless the device is a TUN device with offloads and without
+    /// packet information, whose IP header directly follows the virtio-net header.
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
-    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        loop {
-            let mut guard = self.io.readable().await?;
-            match guard.try_io(|inner| inner.get_ref().recv(buf)) {
-                Ok(res) => return res,
-                Err(_) => continue,
-            }
+    pub async fn recv_gso(&self, buf: &mut [u8], segments: &mut Vec<Vec<u8>>) -> io::Result<usize> {
+        self.check_offload()?;
+        let n = self.recv(buf).await?;
+        gso::split(&buf[..n], segments)
+    }
+
+    /// Receives a packet from the Tun/Tap interface along with the time it was read.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn recv_with_timestamp(&self, buf: &mut [u8]) -> io::Result<(usize, Timestamp)> {
+        if let Some(res) = self.busy_poll(|| self.io.get_ref().recv(buf)) {
+            return self.counters.recv(res).map(|n| (n, Timestamp::now()));
         }
+        self.io
+            .async_io(READABLE, |inner| {
+                let n = self.counters.recv(inner.recv(buf))?;
+                Ok((n, Timestamp::now()))
+            })
+            .await
     }
 
     /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
     pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
-        loop {
-            let mut guard = self.io.writable().await?;
-            match guard.try_io(|inner| inner.get_ref().send(buf)) {
-                Ok(res) => return res,
-                Err(_) => continue,
-            }
+        self.check_size(buf.len())?;
+        let attempt = future::poll_fn(|cx| {
+            poll_optimistic(cx, || {
+                self.counters.send(buf.len(), self.io.get_ref().send(buf))
+            })
+        });
+        if let Some(res) = attempt.await {
+            return res;
         }
+        self.io
+            .async_io(Interest::WRITABLE, |inner| {
+                self.counters.send(buf.len(), inner.send(buf))
+            })
+            .await
     }
 
     /// Sends all of a buffer to the Tun/Tap interface.
@@ -277,26 +772,136 @@ impl Tun {
         Ok(())
     }
 
+    /// Sends packets to a Tun/Tap interface with offloads enabled, coalescing consecutive TCP
+    /// segments of the same flow into single writes. Returns the number of writes.
+    ///
+    /// `packets` are regular IP packets, without virtio-net header. Fails with
+    /// `io::ErrorKind::InvalidInput` unless the device is a TUN device with offloads and without
+    /// packet information.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn send_coalesced(&self, packets: &[&[u8]]) -> io::Result<usize> {
+        self.check_offload()?;
+        let mut coalesced = Vec::new();
+        gso::coalesce(packets, &mut coalesced);
+        for packet in &coalesced {
+            self.send(packet).await?;
+        }
+        Ok(coalesced.len())
+    }
+
     /// Sends several different buffers to the Tun/Tap interface. Returns the number of bytes written to the device.
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
     pub async fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        loop {
-            let mut guard = self.io.writable().await?;
-            match guard.try_io(|inner| inner.get_ref().sendv(bufs)) {
-                Ok(res) => return res,
-                Err(_) => continue,
-            }
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        self.check_size(len)?;
+        let attempt = future::poll_fn(|cx| {
+            poll_optimistic(cx, || {
+                self.counters.send(len, self.io.get_ref().sendv(bufs))
+            })
+        });
+        if let Some(res) = attempt.await {
+            return res;
+        }
+        self.io
+            .async_io(Interest::WRITABLE, |inner| {
+                self.counters.send(len, inner.sendv(bufs))
+            })
+            .await
+    }
+
+    /// Waits for the Tun/Tap interface to become readable.
+    ///
+    /// The readiness is only cleared by a read which would block, so this returns immediately
+    /// until the pending packets are read.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.io.ready(READABLE).await?.retain_ready();
+        Ok(())
+    }
+
+    /// Waits for the Tun/Tap interface to become readable.
+    ///
+    /// The returned future owns the `Arc`, so it is `'static` and can be spawned into a task.
+    pub async fn readable_owned(self: Arc<Self>) -> io::Result<OwnedReadyGuard> {
+        let mut guard = self.io.ready(READABLE).await?;
+        let ready = guard.ready();
+        guard.retain_ready();
+        Ok(OwnedReadyGuard::new(self, READABLE, ready))
+    }
+
+    /// Waits for the Tun/Tap interface to become writable.
+    ///
+    /// The returned future owns the `Arc`, so it is `'static` and can be spawned into a task.
+    pub async fn writable_owned(self: Arc<Self>) -> io::Result<OwnedReadyGuard> {
+        let mut guard = self.io.writable().await?;
+        let ready = guard.ready();
+        guard.retain_ready();
+        Ok(OwnedReadyGuard::new(self, Interest::WRITABLE, ready))
+    }
+
+    /// Receives a packet into the spare capacity of `buf`, returning the buffer along with the
+    /// result.
+    ///
+    /// The returned future owns both the `Arc` and the buffer, so it is `'static` and can be
+    /// spawned or stored in collections of futures. The received bytes are appended to the
+    /// current length of `buf`, whose spare capacity is first grown to hold a packet of the MTU
+    /// of device if needed.
+    pub fn recv_owned(
+        self: &Arc<Self>,
+        mut buf: BytesMut,
+    ) -> impl Future<Output = (BytesMut, io::Result<usize>)> + Send + 'static {
+        let tun = self.clone();
+        async move {
+            buf.reserve(tun.pool.size());
+            let len = buf.len();
+            buf.resize(buf.capacity(), 0);
+            let res = tun.recv(&mut buf[len..]).await;
+            buf.truncate(len + *res.as_ref().unwrap_or(&0));
+            (buf, res)
         }
     }
 
+    /// Sends a buffer to the Tun/Tap interface. Returns the number of bytes written to the device.
+    ///
+    /// The returned future owns both the `Arc` and the buffer, so it is `'static` and can be
+    /// spawned or stored in collections of futures.
+    pub fn send_owned(
+        self: &Arc<Self>,
+        buf: Bytes,
+    ) -> impl Future<Output = io::Result<usize>> + Send + 'static {
+        let tun = self.clone();
+        async move { tun.send(&buf).await }
+    }
+
+    /// Reads and discards all the packets currently queued on the Tun/Tap interface without
+    /// waiting. Returns the number of discarded packets.
+    ///
+    /// The packets are read into a buffer of the internal pool, so no allocation happens in
+    /// steady state.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub fn drain(&self) -> io::Result<usize> {
+        let mut buf = self.pool.take();
+        let mut count = 0;
+        let res = loop {
+            match self.counters.recv(self.io.get_ref().recv(&mut buf)) {
+                Ok(_) => count += 1,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break Ok(count),
+                Err(err) => break Err(err),
+            }
+        };
+        self.pool.put(buf);
+        res
+    }
+
     /// Tries to receive a buffer from the Tun/Tap interface.
     ///
     /// When there is no pending data, `Err(io::ErrorKind::WouldBlock)` is returned.
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
     pub fn try_recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        self.io.get_ref().recv(buf)
+        self.counters.recv(self.io.get_ref().recv(buf))
     }
 
     /// Tries to send a packet to the Tun/Tap interface.
@@ -305,7 +910,8 @@ impl Tun {
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
     pub fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
-        self.io.get_ref().send(buf)
+        self.check_size(buf.len())?;
+        self.counters.send(buf.len(), self.io.get_ref().send(buf))
     }
 
     /// Tries to send several different buffers to the Tun/Tap interface.
@@ -314,7 +920,425 @@ impl Tun {
     ///
     /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
     pub fn try_send_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.io.get_ref().sendv(bufs)
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        self.check_size(len)?;
+        self.counters.send(len, self.io.get_ref().sendv(bufs))
+    }
+
+    /// Closes the Tun/Tap device and reports any error, unlike dropping it.
+    ///
+    /// The device keeps its state and its persistent flag, so that the kernel removes it once
+    /// its last file descriptor is closed unless it is persistent. See
+    /// [`close_with`](#method.close_with) to tear it down.
+    pub async fn close(self) -> Result<()> {
+        self.close_with(CloseOptions::default()).await
+    }
+
+    /// Closes the Tun/Tap device and reports any error, tearing it down as `options` tell if
+    /// this is the last handle of the device (i.e. the other queues have already been dropped
+    /// or closed): it is set down, its persistent flag is cleared, then its file descriptor and
+    /// control socket are closed. Otherwise only the file descriptor of this queue is closed.
+    ///
+    /// ```no_run
+    /// use tokio_tun::{CloseOptions, TunBuilder};
+    ///
+    /// # async fn run() -> tokio_tun::Result<()> {
+    /// let tun = TunBuilder::new().persist().build()?.pop().unwrap();
+    /// tun.close_with(CloseOptions::new().down().clear_persist()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn close_with(self, options: CloseOptions) -> Result<()> {
+        let (iface, io) = self.into_parts();
+        device::close(iface, io, options)
+    }
+
+    /// Tries to perform a custom I/O operation on the file descriptor of the Tun/Tap interface.
+    ///
+    /// If the interface is ready for `interest`, `f` is called, otherwise `Err(io::ErrorKind::WouldBlock)`
+    /// is returned. `f` should perform the system call on [`as_raw_fd`](#method.as_raw_fd) and
+    /// return `Err(io::ErrorKind::WouldBlock)` only if the system call itself did, which clears
+    /// the readiness of the interface.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub fn try_io<R>(
+        &self,
+        interest: Interest,
+        f: impl FnOnce() -> io::Result<R>,
+    ) -> io::Result<R> {
+        self.io.try_io(interest, |_| f())
+    }
+
+    /// Performs a custom I/O operation on the file descriptor of the Tun/Tap interface, waiting
+    /// for readiness as needed.
+    ///
+    /// `f` is called whenever the interface is ready for `interest` and retried as long as it
+    /// returns `Err(io::ErrorKind::WouldBlock)`. See [`try_io`](#method.try_io) for the
+    /// requirements on `f`.
+    ///
+    /// This method takes &self, so it is possible to call this method concurrently with other methods on this struct.
+    pub async fn async_io<R>(
+        &self,
+        interest: Interest,
+        mut f: impl FnMut() -> io::Result<R>,
+    ) -> io::Result<R> {
+        self.io.async_io(interest, |_| f()).await
+    }
+
+    /// Converts this queue into a [`ThreadedTun`](struct.ThreadedTun.html), whose I/O is performed
+    /// by dedicated OS threads in blocking mode.
+    ///
+    /// `capacity` is the number of packets buffered in each direction.
+    pub fn into_threaded(self, capacity: usize) -> io::Result<ThreadedTun> {
+        let buffer_size = Self::buffer_size(&self.iface);
+        let (iface, io) = self.into_parts();
+        ThreadedTun::new(iface, io, buffer_size, capacity)
+    }
+
+    /// Converts this queue into a pair of channels served by a background pump task.
+    ///
+    /// Packets written to the returned sender are sent to the device, and packets received from
+    /// the device are delivered to the returned receiver. `capacity` is the number of packets
+    /// buffered in each direction; once the receiver is full, the pump stops reading the device
+    /// until there is room again, so no packet is lost in between.
+    ///
+    /// The pump stops once the receiver and all the senders are dropped, or on the first I/O
+    /// error, which is returned by the join handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn into_channels(
+        self,
+        capacity: usize,
+    ) -> (
+        mpsc::Sender<Bytes>,
+        mpsc::Receiver<Bytes>,
+        JoinHandle<io::Result<()>>,
+    ) {
+        self.into_channels_with_cancel(capacity, CancellationToken::new())
+    }
+
+    /// Converts this queue into a pair of channels like
+    /// [`into_channels`](#method.into_channels), whose pump also stops once `cancel` is
+    /// cancelled.
+    ///
+    /// On cancellation, the pump stops reading the device, sends the packets which are already
+    /// queued in the sender and then stops.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn into_channels_with_cancel(
+        self,
+        capacity: usize,
+        cancel: CancellationToken,
+    ) -> (
+        mpsc::Sender<Bytes>,
+        mpsc::Receiver<Bytes>,
+        JoinHandle<io::Result<()>>,
+    ) {
+        let buffer_size = Self::buffer_size(&self.iface);
+        let (inbound_tx, inbound_rx) = mpsc::channel(capacity);
+        let (outbound_tx, outbound_rx) = mpsc::channel(capacity);
+        let pump = tokio::spawn(pump::channels(
+            Arc::new(self),
+            inbound_tx,
+            outbound_rx,
+            buffer_size,
+            cancel,
+        ));
+        (outbound_tx, inbound_rx, pump)
+    }
+
+    /// Moves this queue into a background owner task and returns a cloneable handle to it.
+    ///
+    /// The packets sent and the configuration changes requested through the clones of the
+    /// [`TunHandle`](struct.TunHandle.html) are executed one at a time by the owner task, which
+    /// also delivers the received packets to the returned receiver. `capacity` is the number of
+    /// commands and packets buffered in each direction.
+    ///
+    /// The owner task stops once the receiver and all the handles are dropped, or on the first
+    /// read error, which is returned by the join handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn into_handle(
+        self,
+        capacity: usize,
+    ) -> (TunHandle, mpsc::Receiver<Bytes>, JoinHandle<io::Result<()>>) {
+        self.into_handle_with_cancel(capacity, CancellationToken::new())
+    }
+
+    /// Moves this queue into a background owner task like [`into_handle`](#method.into_handle),
+    /// which also stops once `cancel` is cancelled.
+    ///
+    /// On cancellation, the owner task stops reading the device, executes the commands which are
+    /// already queued and then stops.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn into_handle_with_cancel(
+        self,
+        capacity: usize,
+        cancel: CancellationToken,
+    ) -> (TunHandle, mpsc::Receiver<Bytes>, JoinHandle<io::Result<()>>) {
+        let buffer_size = Self::buffer_size(&self.iface);
+        let (handle, commands) = TunHandle::channel(capacity);
+        let (inbound_tx, inbound_rx) = mpsc::channel(capacity);
+        let owner = tokio::spawn(handle::run(self, commands, inbound_tx, buffer_size, cancel));
+        (handle, inbound_rx, owner)
+    }
+
+    /// Steers the packets sent through a multi-queue device by the CPU which sends them, so that
+    /// queue `N` receives the packets of CPU `N` (Linux only).
+    ///
+    /// It attaches an eBPF steering program to the device, which requires `CAP_BPF` (or
+    /// `CAP_SYS_ADMIN`). See the [`affinity`](affinity/index.html) module to pin the workers of
+    /// the queues accordingly.
+    #[cfg(target_os = "linux")]
+    pub fn steer_by_cpu(&self) -> Result<()> {
+        self.iface.steer_by_cpu(self.io.as_fd())
+    }
+
+    /// Detaches this queue from its multi-queue device, which stops steering packets to it and
+    /// drops the packets waiting in it, until [`attach_queue`](#method.attach_queue) attaches
+    /// it again (Linux only). Sending through a detached queue fails.
+    #[cfg(target_os = "linux")]
+    pub fn detach_queue(&self) -> Result<()> {
+        self.iface.set_queue_attached(self.io.as_fd(), false)
+    }
+
+    /// Attaches this queue to its device again after
+    /// [`detach_queue`](#method.detach_queue) (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn attach_queue(&self) -> Result<()> {
+        self.iface.set_queue_attached(self.io.as_fd(), true)
+    }
+
+    /// Sets the reverse path filtering mode of the device (Linux only).
+    ///
+    /// Tunnels whose peers send packets from addresses which are not routed through the device
+    /// need [`RpFilter::Loose`](enum.RpFilter.html) or `Off`.
+    #[cfg(target_os = "linux")]
+    pub fn set_rp_filter(&self, mode: RpFilter) -> Result<()> {
+        sysctl::set_rp_filter(self.name(), mode)
+    }
+
+    /// Enables or disables IPv4 and IPv6 forwarding on the device (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn set_forwarding(&self, enabled: bool) -> Result<()> {
+        sysctl::set_forwarding(self.name(), enabled)
+    }
+
+    /// Sets whether the device accepts IPv6 router advertisements (Linux only).
+    #[cfg(target_os = "linux")]
+    pub fn set_accept_ra(&self, accept: AcceptRa) -> Result<()> {
+        sysctl::set_accept_ra(self.name(), accept)
+    }
+
+    /// Adds an IPv4 or IPv6 address to the device while it is running (feature `netlink`).
+    ///
+    /// Unlike [`TunBuilder::address`](struct.TunBuilder.html#method.address), it keeps the
+    /// existing addresses, so it can add secondary addresses or renumber the device without
+    /// recreating it.
+    #[cfg(feature = "netlink")]
+    pub fn add_address(&self, net: IpNet) -> Result<()> {
+        self.iface.add_address(net)
+    }
+
+    /// Removes an IPv4 or IPv6 address of the device (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn remove_address(&self, net: IpNet) -> Result<()> {
+        self.iface.del_address(net)
+    }
+
+    /// Adds (or replaces) a static neighbor (ARP or NDP) entry mapping `addr` to `mac` on a
+    /// Tap device, so that the kernel does not need to resolve it (feature `netlink`, Linux
+    /// only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn add_neighbor(&self, addr: IpAddr, mac: [u8; 6]) -> Result<()> {
+        self.iface.add_neighbor(addr, mac)
+    }
+
+    /// Removes the neighbor entry of `addr` on a Tap device (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn remove_neighbor(&self, addr: IpAddr) -> Result<()> {
+        self.iface.del_neighbor(addr)
+    }
+
+    /// Adds the (Tap) device to the existing bridge `bridge`, like `ip link set master`
+    /// (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn attach_to_bridge(&self, bridge: &str) -> Result<()> {
+        self.iface.set_bridge(Some(bridge))
+    }
+
+    /// Removes the device from its bridge, if any (feature `netlink`, Linux only).
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn detach_from_bridge(&self) -> Result<()> {
+        self.iface.set_bridge(None)
+    }
+
+    /// Creates the 802.1Q VLAN sub-interface `<name>.<id>` of a Tap device (feature `netlink`,
+    /// Linux only).
+    ///
+    /// The sub-interface is deleted when the returned
+    /// [`VlanInterface`](struct.VlanInterface.html) is dropped, or along with the device.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn create_vlan(&self, id: u16) -> Result<VlanInterface> {
+        VlanInterface::create(&self.iface, id)
+    }
+
+    /// Replaces the queueing discipline of the device (feature `netlink`, Linux only).
+    ///
+    /// Fails with an `io::ErrorKind::NotFound` error if the kernel does not provide `qdisc`.
+    #[cfg(all(feature = "netlink", target_os = "linux"))]
+    pub fn set_qdisc(&self, qdisc: Qdisc) -> Result<()> {
+        self.iface.set_qdisc(qdisc)
+    }
+
+    /// Adds a route to `dest`/`prefix` through the device (feature `netlink`).
+    ///
+    /// The route is added over netlink on Linux and through a `PF_ROUTE` socket on macOS, where
+    /// the metric and the table of `opts` are ignored. It fails if the route already exists.
+    #[cfg(feature = "netlink")]
+    pub fn add_route(&self, dest: IpAddr, prefix: u8, opts: RouteOptions) -> Result<()> {
+        self.iface.add_route(route::net(dest, prefix)?, &opts)
+    }
+
+    /// Removes a route to `dest`/`prefix` through the device, added by
+    /// [`add_route`](#method.add_route) (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn del_route(&self, dest: IpAddr, prefix: u8, opts: RouteOptions) -> Result<()> {
+        self.iface.del_route(route::net(dest, prefix)?, &opts)
+    }
+
+    /// Routes all the IPv4 and IPv6 traffic through the device (feature `netlink`), as a
+    /// full-tunnel VPN does.
+    ///
+    /// A default route of each family is added with the metric and in the table of `opts`, and
+    /// through its gateway for the family of the gateway, if any. Either both routes are added or
+    /// none is. On macOS, each default route is made of the two halves of the address space, so
+    /// that it takes precedence over the default route of the system.
+    #[cfg(feature = "netlink")]
+    pub fn set_default_route(&self, opts: RouteOptions) -> Result<()> {
+        route::set_default(&self.iface, &opts)
+    }
+
+    /// Removes the default routes added by [`set_default_route`](#method.set_default_route)
+    /// with the same `opts` (feature `netlink`).
+    #[cfg(feature = "netlink")]
+    pub fn remove_default_route(&self, opts: RouteOptions) -> Result<()> {
+        route::remove_default(&self.iface, &opts)
+    }
+
+    /// Routes all the traffic of the host through the device, except the traffic to the VPN
+    /// `server`, which keeps its current route (feature `netlink`).
+    ///
+    /// A host route to `server` is pinned through its current interface and gateway, so that
+    /// the tunnel does not route its own packets, then the two halves of the IPv4 and IPv6
+    /// address spaces are routed through the device, through the gateway of `opts` for its
+    /// family, if any. The halves take precedence over the default routes of the system, which
+    /// are left untouched. The routes are removed when the returned
+    /// [`VpnRoutes`](struct.VpnRoutes.html) is dropped.
+    #[cfg(feature = "netlink")]
+    pub fn setup_vpn_routes(&self, server: IpAddr, opts: RouteOptions) -> Result<VpnRoutes> {
+        VpnRoutes::setup(self.iface.index()?, server, &opts)
+    }
+
+    /// Returns a snapshot of the software counters of this queue, if they were enabled with
+    /// [`TunBuilder::counters`](struct.TunBuilder.html#method.counters).
+    ///
+    /// The counters only account for the I/O performed through this crate, including the
+    /// `WouldBlock` retries and short writes which the kernel statistics of the interface do not
+    /// show.
+    pub fn counters(&self) -> Option<Counters> {
+        self.counters.snapshot()
+    }
+
+    /// Returns the statistics of the interface kept by the kernel, shared by all its queues.
+    pub fn interface_counters(&self) -> Result<InterfaceCounters> {
+        Ok(InterfaceCounters::read(self.name())?)
+    }
+
+    /// Spawns a task sampling the statistics of this queue every `interval`, the interface
+    /// counters and the software counters if enabled, and deriving their rates in packets and
+    /// bits per second, over the last interval and as a moving average. The task stops when
+    /// the returned [`StatsSampler`](struct.StatsSampler.html) is dropped.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use tokio_tun::TunBuilder;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let tun = TunBuilder::new().counters().up().build()?.pop().unwrap();
+    /// let sampler = tun.start_stats_sampler(Duration::from_secs(1));
+    /// let mut samples = sampler.subscribe();
+    /// while samples.changed().await.is_ok() {
+    ///     if let Some(rates) = samples.borrow().software_ewma {
+    ///         println!("rx {:.0} pps, tx {:.0} pps", rates.rx_pps, rates.tx_pps);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero, or if called outside of a tokio runtime.
+    pub fn start_stats_sampler(&self, interval: Duration) -> StatsSampler {
+        StatsSampler::start(self.name().to_owned(), self.counters.clone(), interval)
+    }
+
+    /// Spawns a task watching the MTU of the device, e.g. to resize the buffers when a tool
+    /// such as `ip link set mtu` changes it underneath the application. The changes are
+    /// notified by the kernel on Linux with the `netlink` feature, and polled every second
+    /// otherwise. The task stops when the returned [`MtuChanges`](struct.MtuChanges.html) is
+    /// dropped.
+    ///
+    /// ```no_run
+    /// # use tokio_tun::TunBuilder;
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+    /// let mut changes = tun.mtu_changes()?;
+    /// while let Some(mtu) = changes.next().await {
+    ///     println!("MTU is now {}", mtu);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn mtu_changes(&self) -> Result<MtuChanges> {
+        MtuChanges::start(self.iface.clone())
+    }
+
+    /// Spawns a task watching the IPv4 and IPv6 addresses of the device, e.g. to rebind the
+    /// sockets of the application when a network manager reconfigures it, like
+    /// [`mtu_changes`](#method.mtu_changes). The task stops when the returned
+    /// [`AddressChanges`](struct.AddressChanges.html) is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn address_changes(&self) -> Result<AddressChanges> {
+        AddressChanges::start(self.iface.clone())
+    }
+
+    /// Returns the index of this queue among the queues of device, in the order in which
+    /// [`TunBuilder::build`](struct.TunBuilder.html#method.build) returned them. A device
+    /// created from a file descriptor is a single queue, of index `0`.
+    pub fn queue_index(&self) -> usize {
+        self.queue
+    }
+
+    /// Returns the number of queues of device, as built.
+    pub fn total_queues(&self) -> usize {
+        self.iface.queues()
     }
 
     /// Returns the name of Tun/Tap device.
@@ -327,6 +1351,78 @@ impl Tun {
         self.iface.mtu(None)
     }
 
+    /// Returns the minimum size of a receive buffer which can hold any packet of the device
+    /// without truncation.
+    ///
+    /// It accounts for the current MTU (or the maximum size of coalesced packets if offloads are
+    /// enabled) as well as the packet information header, the virtio-net header and the Ethernet
+    /// framing of TAP devices, when applicable.
+    pub fn recommended_buffer_size(&self) -> Result<usize> {
+        device::recommended_buffer_size(&self.iface)
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    ///
+    /// Addresses which are not assigned to the device are reported as `None`.
+    pub fn config(&self) -> Result<TunConfig> {
+        device::config(&self.iface)
+    }
+
+    /// Waits until the device is administratively and operationally up and, on Linux, until its
+    /// IPv6 addresses have completed duplicate address detection, so that packets sent right
+    /// after [`build`](struct.TunBuilder.html#method.build) are not lost to the link setup.
+    ///
+    /// The device is not brought up: it has to be, by [`TunBuilder::up`](struct.TunBuilder.html#method.up)
+    /// or by another process. Fails with an I/O error of kind `TimedOut` if the device is not
+    /// ready within `timeout`. The runtime must have its time driver enabled.
+    pub async fn wait_for_up(&self, timeout: Duration) -> Result<()> {
+        let ready = async {
+            while !device::is_ready(&self.iface)? {
+                tokio::time::sleep(READY_POLL_INTERVAL).await;
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, ready)
+            .await
+            .map_err(|_| Error::IoError(io::ErrorKind::TimedOut.into()))?
+    }
+
+    /// Applies the options of `builder` which differ from the current configuration of device,
+    /// e.g. to reload the configuration of a daemon on `SIGHUP` without recreating the device.
+    /// Returns the names of the options which changed (the methods of the builder setting them).
+    ///
+    /// The MTU, the IPv4 addresses, the additional addresses (which are added, not replaced), the
+    /// link flags (`multicast`, `arp`, `promisc`) and the up state (`up` or `down`) are applied
+    /// in either direction, the other options are ignored. The options which the builder does
+    /// not set are left as they are. A name or mode other than the
+    /// ones of device fails with [`Error::InvalidParam`] before anything is applied. If an
+    /// option fails to apply, the ones already applied are restored as far as possible before
+    /// the error is returned. The buffers of [`recv_pooled`](#method.recv_pooled) and, in strict
+    /// mode, the size of the packets the queues of device accept follow the new MTU.
+    ///
+    /// ```no_run
+    /// # async fn run(tun: tokio_tun::Tun) -> tokio_tun::Result<()> {
+    /// use tokio_tun::TunBuilder;
+    ///
+    /// let changed = tun.reconfigure(TunBuilder::new().mtu(1400).up())?;
+    /// println!("changed: {:?}", changed);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reconfigure(&self, builder: TunBuilder) -> Result<Vec<&'static str>> {
+        let changed = device::reconfigure(&self.iface, builder.params()?)?;
+        if changed.contains(&"mtu") {
+            self.pool.resize(Self::buffer_size(&self.iface));
+        }
+        if let Some(max_send) = self.max_send.as_ref().filter(|_| changed.contains(&"mtu")) {
+            max_send.store(
+                device::recommended_buffer_size(&self.iface)?,
+                Ordering::Relaxed,
+            );
+        }
+        Ok(changed)
+    }
+
     /// Returns the IPv4 address of MTU.
     pub fn address(&self) -> Result<Ipv4Addr> {
         self.iface.address(None)
@@ -347,8 +1443,60 @@ impl Tun {
         self.iface.netmask(None)
     }
 
+    /// Returns the type of device, known since its creation and checked against the kernel on
+    /// Linux, to pick the framing of its packets.
+    pub fn device_type(&self) -> Result<DeviceType> {
+        device::device_type(&self.iface, self.io.as_fd())
+    }
+
+    /// Returns `true` if the device is administratively up.
+    pub fn is_up(&self) -> Result<bool> {
+        device::is_up(&self.iface)
+    }
+
+    /// Brings the device up, so that it services incoming and outgoing packets.
+    pub fn set_up(&self) -> Result<()> {
+        device::set_up(&self.iface, true)
+    }
+
+    /// Brings the device down. Its configuration is kept, and the packets sent to it are
+    /// dropped until it is brought up again.
+    pub fn set_down(&self) -> Result<()> {
+        device::set_up(&self.iface, false)
+    }
+
     /// Returns the flags of MTU.
     pub fn flags(&self) -> Result<i16> {
         self.iface.flags(None)
     }
 }
+
+impl AsyncTunDevice for Tun {
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        Tun::recv(self, buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        Tun::send(self, buf)
+    }
+
+    fn send_all(&self, buf: &[u8]) -> impl Future<Output = io::Result<()>> + Send {
+        Tun::send_all(self, buf)
+    }
+
+    fn name(&self) -> &str {
+        Tun::name(self)
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        Tun::mtu(self)
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        Tun::config(self)
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        Tun::device_type(self)
+    }
+}
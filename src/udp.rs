@@ -0,0 +1,201 @@
+use crate::Result;
+use crate::Tun;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+/// Length of the UDP header.
+const UDP_HEADER_LEN: usize = 8;
+/// Length of the Ethernet header, which is not counted in the MTU of Tap devices.
+const ETHERNET_HEADER_LEN: usize = 14;
+
+type Transform = Box<dyn Fn(&mut Vec<u8>) -> io::Result<()> + Send + Sync>;
+
+/// Represents a tunnel which encapsulates the packets of a device in UDP datagrams exchanged
+/// with a peer, one packet per datagram.
+///
+/// The packets can be transformed on their way out by a [`seal`](#method.seal) callback (e.g. to
+/// encrypt them) and on their way in by an [`open`](#method.open) callback; a packet is dropped
+/// if its callback fails. Datagrams which do not come from the peer are ignored, as are the
+/// transient errors of the socket (e.g. an ICMP port unreachable message while the peer is not
+/// listening yet) and the packets which the device rejects.
+///
+/// ```no_run
+/// use tokio::net::UdpSocket;
+/// use tokio_tun::{TunBuilder, UdpTunnel};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let tun = TunBuilder::new().up().build()?.pop().unwrap();
+/// let socket = UdpSocket::bind("0.0.0.0:51820").await?;
+/// let tunnel = UdpTunnel::new(tun, socket, "192.0.2.1:51820".parse()?);
+/// tunnel.clamp_mtu(1500)?;
+/// tunnel.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct UdpTunnel {
+    tun: Tun,
+    socket: UdpSocket,
+    peer: SocketAddr,
+    overhead: usize,
+    seal: Option<Transform>,
+    open: Option<Transform>,
+}
+
+impl fmt::Debug for UdpTunnel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpTunnel")
+            .field("tun", &self.tun)
+            .field("socket", &self.socket)
+            .field("peer", &self.peer)
+            .field("overhead", &self.overhead)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UdpTunnel {
+    /// Creates a tunnel between `tun` and `peer`, reached through `socket`.
+    pub fn new(tun: Tun, socket: UdpSocket, peer: SocketAddr) -> Self {
+        Self {
+            tun,
+            socket,
+            peer,
+            overhead: 0,
+            seal: None,
+            open: None,
+        }
+    }
+
+    /// Sets the callback which transforms each packet received from the device before it is sent
+    /// to the peer, in place.
+    pub fn seal(
+        mut self,
+        seal: impl Fn(&mut Vec<u8>) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.seal = Some(Box::new(seal));
+        self
+    }
+
+    /// Sets the callback which transforms each datagram received from the peer before it is sent
+    /// to the device, in place.
+    pub fn open(
+        mut self,
+        open: impl Fn(&mut Vec<u8>) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.open = Some(Box::new(open));
+        self
+    }
+
+    /// Sets the maximum number of bytes which [`seal`](#method.seal) adds to a packet (e.g. a
+    /// nonce and an authentication tag), which is accounted for by
+    /// [`clamp_mtu`](#method.clamp_mtu). Default value is `0`.
+    pub fn overhead(mut self, overhead: usize) -> Self {
+        self.overhead = overhead;
+        self
+    }
+
+    /// Lowers the MTU of the device so that its packets, once sealed and encapsulated, fit in a
+    /// single datagram on a path of MTU `path_mtu`: the kernel then fragments or rejects larger
+    /// packets itself, instead of the datagrams being fragmented on the way. Returns the new MTU.
+    pub fn clamp_mtu(&self, path_mtu: usize) -> Result<i32> {
+        let ip_header_len = if self.peer.is_ipv4() { 20 } else { 40 };
+        let mut overhead = ip_header_len + UDP_HEADER_LEN + self.overhead;
+        if self.tun.iface.is_tap()? {
+            overhead += ETHERNET_HEADER_LEN;
+        }
+        let mtu = path_mtu
+            .checked_sub(overhead)
+            .and_then(|mtu| i32::try_from(mtu).ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "the path MTU is smaller than the encapsulation overhead",
+                )
+            })?;
+        self.tun.iface.mtu(Some(mtu))
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Returns a reference to the device.
+    pub fn tun(&self) -> &Tun {
+        &self.tun
+    }
+
+    /// Returns a reference to the socket.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Forwards packets in both directions until an error which is not transient occurs.
+    /// Dropping the returned future stops the tunnel.
+    pub async fn run(&self) -> io::Result<()> {
+        let size = self.tun.recommended_buffer_size()?;
+        let outbound = async {
+            let mut buf = Vec::with_capacity(size + self.overhead);
+            loop {
+                buf.resize(size, 0);
+                let n = self.tun.recv(&mut buf).await?;
+                buf.truncate(n);
+                if let Some(seal) = &self.seal
+                    && seal(&mut buf).is_err()
+                {
+                    continue;
+                }
+                match self.socket.send_to(&buf, self.peer).await {
+                    Err(err) if !is_transient(&err) => return Err(err),
+                    _ => {}
+                }
+            }
+        };
+        let inbound = async {
+            let mut buf = vec![0; u16::MAX as usize];
+            let mut opened = Vec::new();
+            loop {
+                let (n, from) = match self.socket.recv_from(&mut buf).await {
+                    Ok(res) => res,
+                    Err(err) if is_transient(&err) => continue,
+                    Err(err) => return Err(err),
+                };
+                if from != self.peer {
+                    continue;
+                }
+                let packet = match &self.open {
+                    Some(open) => {
+                        opened.clear();
+                        opened.extend_from_slice(&buf[..n]);
+                        if open(&mut opened).is_err() {
+                            continue;
+                        }
+                        &opened[..]
+                    }
+                    None => &buf[..n],
+                };
+                match self.tun.send(packet).await {
+                    // The device rejects malformed packets
+                    Err(err) if err.kind() != ErrorKind::InvalidInput => return Err(err),
+                    _ => {}
+                }
+            }
+        };
+        tokio::select! {
+            res = outbound => res,
+            res = inbound => res,
+        }
+    }
+}
+
+/// Returns `true` if `err` only concerns the datagram being sent or received, which is dropped.
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    ) || matches!(err.raw_os_error(), Some(libc::EMSGSIZE | libc::ENOBUFS))
+}
@@ -0,0 +1,47 @@
+use crate::IpVersion;
+use std::io;
+
+/// Length of the header which utun devices prepend to each packet: the address family of the
+/// packet, in network byte order.
+pub(crate) const UTUN_HEADER_LEN: usize = 4;
+
+/// `AF_INET` of macOS.
+const AF_INET: u8 = 2;
+
+/// `AF_INET6` of macOS, which differs from the one of Linux.
+const AF_INET6: u8 = 30;
+
+/// Returns the header of `packet`, written to utun devices: the address family of its IP
+/// version. A packet which is not an IP packet is sent as `AF_INET`, which the kernel drops.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn header(packet: &[u8]) -> [u8; UTUN_HEADER_LEN] {
+    match IpVersion::of(packet) {
+        Some(IpVersion::V6) => [0, 0, 0, AF_INET6],
+        _ => [0, 0, 0, AF_INET],
+    }
+}
+
+/// Copies the packet of `frame`, as read from a utun device, into `buf` without its header.
+/// Returns the number of bytes copied.
+///
+/// As on Linux, a packet which does not fit in `buf` is truncated to its length, so a return
+/// value equal to `buf.len()` means that the packet may have been truncated. A frame shorter
+/// than the header is not a packet, and results in an `io::ErrorKind::InvalidData` error rather
+/// than `Ok(0)`, which would be mistaken for the end of the stream.
+pub(crate) fn strip_header(frame: &[u8], buf: &mut [u8]) -> io::Result<usize> {
+    let packet = packet(frame, buf.len())?;
+    buf[..packet.len()].copy_from_slice(packet);
+    Ok(packet.len())
+}
+
+/// Returns the packet of `frame` without its header, truncated to `len` bytes, with the
+/// semantics of [`strip_header`].
+pub(crate) fn packet(frame: &[u8], len: usize) -> io::Result<&[u8]> {
+    let Some(packet) = frame.get(UTUN_HEADER_LEN..) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame is shorter than the utun header",
+        ));
+    };
+    Ok(&packet[..packet.len().min(len)])
+}
@@ -0,0 +1,82 @@
+use crate::Error;
+use crate::Result;
+use crate::linux::interface::{self, Interface};
+use crate::linux::netlink::Netlink;
+use std::io::{self, ErrorKind};
+
+/// Represents an 802.1Q VLAN sub-interface of a Tap device, created by
+/// [`Tun::create_vlan`](struct.Tun.html#method.create_vlan).
+///
+/// The sub-interface is deleted when it is dropped, and by the kernel along with its parent
+/// device: frames tagged with its VLAN id which are written to the parent device are received
+/// by the sub-interface, and the packets sent through the sub-interface are read from the
+/// parent device as tagged frames.
+#[derive(Debug)]
+pub struct VlanInterface {
+    name: String,
+    id: u16,
+    index: u32,
+}
+
+impl VlanInterface {
+    /// Creates the sub-interface of VLAN `id` on `parent`, named `<parent>.<id>`.
+    pub(crate) fn create(parent: &Interface, id: u16) -> Result<Self> {
+        if !(1..4095).contains(&id) {
+            return Err(Error::InvalidParam { field: "id" });
+        }
+        let name = format!("{}.{}", parent.name(), id);
+        if name.len() >= libc::IFNAMSIZ {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("the name of the VLAN interface {name:?} is too long"),
+            )
+            .into());
+        }
+        Netlink::new()?.add_vlan(parent.index()?, &name, id)?;
+        let index = interface::index_of(&name)?;
+        Ok(Self { name, id, index })
+    }
+
+    /// Returns the name of the sub-interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the VLAN id of the sub-interface.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the index of the sub-interface.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Sets the sub-interface up or down.
+    pub fn set_up(&self, up: bool) -> Result<()> {
+        Ok(Netlink::new()?.set_link(self.index, None, Some(up))?)
+    }
+
+    /// Deletes the sub-interface and reports any error, unlike dropping it. A sub-interface
+    /// already deleted along with its parent device is not an error.
+    pub fn delete(mut self) -> Result<()> {
+        self.remove()
+    }
+
+    fn remove(&mut self) -> Result<()> {
+        let index = std::mem::take(&mut self.index);
+        if index == 0 {
+            return Ok(());
+        }
+        match Netlink::new()?.del_link(index) {
+            Err(err) if err.raw_os_error() == Some(libc::ENODEV) => Ok(()),
+            res => Ok(res?),
+        }
+    }
+}
+
+impl Drop for VlanInterface {
+    fn drop(&mut self) {
+        let _ = self.remove();
+    }
+}
@@ -0,0 +1,91 @@
+use crate::Error;
+use crate::Result;
+use crate::RouteOptions;
+use crate::route;
+use ipnet::IpNet;
+use std::io::{self, ErrorKind};
+use std::net::IpAddr;
+
+/// Represents the routes which send all the traffic of the host through a VPN device, added by
+/// [`Tun::setup_vpn_routes`](struct.Tun.html#method.setup_vpn_routes).
+///
+/// The routes are removed when it is dropped, or by [`teardown`](#method.teardown) which
+/// reports the errors.
+#[derive(Debug)]
+pub struct VpnRoutes {
+    server: IpAddr,
+    gateway: Option<IpAddr>,
+    routes: Vec<(u32, IpNet, RouteOptions)>,
+}
+
+impl VpnRoutes {
+    /// Adds the routes through the device of index `index`, keeping `server` reachable through
+    /// its current route.
+    pub(crate) fn setup(index: u32, server: IpAddr, opts: &RouteOptions) -> Result<Self> {
+        let (server_index, gateway) = route::lookup(server)?;
+        if server_index == index {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "the server is already routed through the device",
+            )
+            .into());
+        }
+        let mut vpn = Self {
+            server,
+            gateway,
+            routes: Vec::new(),
+        };
+        // The host route to the server goes first, so that the tunnel never routes itself
+        let server_opts = RouteOptions {
+            gateway,
+            ..RouteOptions::default()
+        };
+        vpn.add(server_index, IpNet::from(server), server_opts)?;
+        for net in route::half_nets() {
+            vpn.add(index, net, route::default_opts(&net, opts))?;
+        }
+        Ok(vpn)
+    }
+
+    /// Adds a route, which is removed on teardown. If it fails, dropping `self` removes the routes
+    /// added so far.
+    fn add(&mut self, index: u32, dest: IpNet, opts: RouteOptions) -> Result<()> {
+        route::add_at(index, dest, &opts)?;
+        self.routes.push((index, dest, opts));
+        Ok(())
+    }
+
+    /// Returns the address of the VPN server.
+    pub fn server(&self) -> IpAddr {
+        self.server
+    }
+
+    /// Returns the gateway through which the server is reached, if it is not on-link.
+    pub fn gateway(&self) -> Option<IpAddr> {
+        self.gateway
+    }
+
+    /// Removes the routes, in the reverse order. The routes which no longer exist are skipped.
+    /// Returns the first error, if any.
+    pub fn teardown(mut self) -> Result<()> {
+        self.remove()
+    }
+
+    fn remove(&mut self) -> Result<()> {
+        let mut res = Ok(());
+        while let Some((index, dest, opts)) = self.routes.pop() {
+            match route::del_at(index, dest, &opts) {
+                Err(Error::IoError(err)) if err.raw_os_error() == Some(libc::ESRCH) => {}
+                Err(err) if res.is_ok() => res = Err(err),
+                _ => {}
+            }
+        }
+        res
+    }
+}
+
+impl Drop for VpnRoutes {
+    fn drop(&mut self) {
+        let _ = self.remove();
+    }
+}
@@ -0,0 +1,203 @@
+use crate::AsyncTunDevice;
+use crate::DeviceType;
+use crate::Error;
+use crate::Result;
+use crate::Tun;
+use crate::TunBuilder;
+use crate::TunConfig;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// Number of events which a subscriber lagging behind may miss.
+const EVENTS_CAPACITY: usize = 16;
+
+/// Represents an event of the watchdog of a [`WatchedTun`].
+#[derive(Debug, Clone)]
+pub enum WatchdogEvent {
+    /// The device `name` was removed, or its file descriptor failed permanently.
+    Lost { name: String },
+    /// The device `name` was created again, and replaced the lost one.
+    Reopened { name: String },
+    /// Creating the device again failed with `error`; the next I/O tries again.
+    ReopenFailed { error: Arc<Error> },
+}
+
+impl fmt::Display for WatchdogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchdogEvent::Lost { name } => write!(f, "device {} was lost", name),
+            WatchdogEvent::Reopened { name } => write!(f, "device {} was reopened", name),
+            WatchdogEvent::ReopenFailed { error } => write!(f, "reopening failed: {}", error),
+        }
+    }
+}
+
+/// Represents a Tun/Tap device which is created again, from the options of its builder, when it
+/// is removed (e.g. by `ip link del` or a network manager) or when its file descriptor fails
+/// permanently. It is created by
+/// [`TunBuilder::build_watched`](struct.TunBuilder.html#method.build_watched).
+///
+/// The I/O which detects the loss of the device creates it again, under the same name, and is
+/// then performed on the new device: a receive waits for the packets of the new device, and a
+/// send is retried once. The packets in flight on the lost device are lost with it. Each
+/// transition is emitted as a [`WatchdogEvent`] to the [`subscribe`](#method.subscribe)rs.
+///
+/// ```no_run
+/// use tokio_tun::TunBuilder;
+///
+/// # async fn run() -> tokio_tun::Result<()> {
+/// let tun = TunBuilder::new().name("tun0").up().build_watched()?;
+/// let mut events = tun.subscribe();
+/// tokio::spawn(async move {
+///     while let Ok(event) = events.recv().await {
+///         eprintln!("{}", event);
+///     }
+/// });
+/// let mut buf = [0u8; 1500];
+/// let n = tun.recv(&mut buf).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct WatchedTun {
+    builder: TunBuilder,
+    name: String,
+    tun: RwLock<Arc<Tun>>,
+    events: broadcast::Sender<WatchdogEvent>,
+}
+
+impl fmt::Debug for WatchedTun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchedTun")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WatchedTun {
+    /// Creates a new watched device from `tun`, which is created again from `builder`.
+    pub(crate) fn new(builder: TunBuilder, tun: Tun) -> Self {
+        Self {
+            builder,
+            name: tun.name().to_owned(),
+            tun: RwLock::new(Arc::new(tun)),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    /// Returns a receiver of the events of the watchdog.
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchdogEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns the current device, e.g. to configure it. It is replaced, not updated, when the
+    /// device is created again.
+    pub fn tun(&self) -> Arc<Tun> {
+        self.tun.read().unwrap().clone()
+    }
+
+    /// Receives a packet from the Tun/Tap interface, creating the device again if it is lost.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let tun = self.tun();
+            match tun.recv(buf).await {
+                Err(err) if is_lost(&err) => self.reopen(&tun)?,
+                res => return res,
+            }
+        }
+    }
+
+    /// Sends a packet to the Tun/Tap interface, creating the device again and sending the packet
+    /// to the new device if it is lost.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let tun = self.tun();
+        match tun.send(buf).await {
+            Err(err) if is_lost(&err) => {
+                self.reopen(&tun)?;
+                self.tun().send(buf).await
+            }
+            res => res,
+        }
+    }
+
+    /// Returns the name of Tun/Tap device, which the device keeps when it is created again.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of MTU.
+    pub fn mtu(&self) -> Result<i32> {
+        self.tun().mtu()
+    }
+
+    /// Returns a snapshot of the whole configuration of device.
+    pub fn config(&self) -> Result<TunConfig> {
+        self.tun().config()
+    }
+
+    /// Returns the type of device.
+    pub fn device_type(&self) -> Result<DeviceType> {
+        self.tun().device_type()
+    }
+
+    /// Creates the device again in place of `lost`, unless another I/O already did.
+    fn reopen(&self, lost: &Arc<Tun>) -> io::Result<()> {
+        let mut tun = self.tun.write().unwrap();
+        if !Arc::ptr_eq(&*tun, lost) {
+            return Ok(());
+        }
+        let _ = self.events.send(WatchdogEvent::Lost {
+            name: self.name.clone(),
+        });
+        match self.builder.clone().params().and_then(Tun::new) {
+            Ok(new) => {
+                *tun = Arc::new(new);
+                let _ = self.events.send(WatchdogEvent::Reopened {
+                    name: self.name.clone(),
+                });
+                Ok(())
+            }
+            Err(err) => {
+                let err = Arc::new(err);
+                let _ = self
+                    .events
+                    .send(WatchdogEvent::ReopenFailed { error: err.clone() });
+                Err(io::Error::other(err))
+            }
+        }
+    }
+}
+
+/// Returns `true` if the I/O error `err` means that the device is lost: it was removed, or its
+/// file descriptor is no longer usable.
+fn is_lost(err: &io::Error) -> bool {
+    Error::is_device_removed(err) || matches!(err.raw_os_error(), Some(libc::EBADF | libc::EIO))
+}
+
+impl AsyncTunDevice for WatchedTun {
+    fn recv(&self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        WatchedTun::recv(self, buf)
+    }
+
+    fn send(&self, buf: &[u8]) -> impl Future<Output = io::Result<usize>> + Send {
+        WatchedTun::send(self, buf)
+    }
+
+    fn name(&self) -> &str {
+        WatchedTun::name(self)
+    }
+
+    fn mtu(&self) -> Result<i32> {
+        WatchedTun::mtu(self)
+    }
+
+    fn config(&self) -> Result<TunConfig> {
+        WatchedTun::config(self)
+    }
+
+    fn device_type(&self) -> Result<DeviceType> {
+        WatchedTun::device_type(self)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::Tun;
+use bytes::{Bytes, BytesMut};
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Represents the workers spawned by [`spawn_workers`](fn.spawn_workers.html), one per queue.
+///
+/// Dropping it detaches the workers; use [`shutdown`](#method.shutdown) to stop them.
+pub struct Workers {
+    pub(crate) handles: Vec<JoinHandle<io::Result<()>>>,
+    pub(crate) cancel: CancellationToken,
+}
+
+impl Workers {
+    /// Returns the number of workers.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if there is no worker.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Stops the workers and waits for them to finish.
+    ///
+    /// A worker stops as soon as it is waiting for a packet; a packet being handled is handled
+    /// to completion and its reply is sent. Returns the first error of a worker, if any.
+    pub async fn shutdown(self) -> io::Result<()> {
+        self.cancel.cancel();
+        self.join().await
+    }
+
+    /// Waits for the workers to finish, which happens once they fail or their cancellation token
+    /// is cancelled. Returns the first error of a worker, if any.
+    pub async fn join(self) -> io::Result<()> {
+        let mut res = Ok(());
+        for handle in self.handles {
+            let worker = handle
+                .await
+                .unwrap_or_else(|err| Err(io::Error::other(err)));
+            if res.is_ok() {
+                res = worker;
+            }
+        }
+        res
+    }
+}
+
+/// Spawns a worker per queue of a (multi-queue) device, which receives each packet, passes it
+/// to `handler` and sends back the reply of `handler`, if any.
+///
+/// The handler of a worker is called for one packet at a time, so the packets of a queue are
+/// handled in order, while the queues are served concurrently.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+pub fn spawn_workers<F, Fut>(queues: Vec<Tun>, handler: F) -> Workers
+where
+    F: Fn(Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<Bytes>> + Send,
+{
+    spawn_workers_with_cancel(queues, CancellationToken::new(), handler)
+}
+
+/// Spawns a worker per queue of a (multi-queue) device like
+/// [`spawn_workers`](fn.spawn_workers.html), which also stops once `cancel` is cancelled.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+pub fn spawn_workers_with_cancel<F, Fut>(
+    queues: Vec<Tun>,
+    cancel: CancellationToken,
+    handler: F,
+) -> Workers
+where
+    F: Fn(Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<Bytes>> + Send,
+{
+    let handler = Arc::new(handler);
+    // A child token, so that shutting the workers down does not cancel the caller's token
+    let cancel = cancel.child_token();
+    let handles = queues
+        .into_iter()
+        .map(|tun| {
+            let handler = handler.clone();
+            let cancel = cancel.clone();
+            tokio::spawn(async move {
+                let buffer_size = Tun::buffer_size(&tun.iface);
+                loop {
+                    let mut buf = BytesMut::zeroed(buffer_size);
+                    let n = tokio::select! {
+                        res = tun.recv(&mut buf) => res?,
+                        _ = cancel.cancelled() => return Ok(()),
+                    };
+                    buf.truncate(n);
+                    if let Some(reply) = handler(buf.freeze()).await {
+                        tun.send_all(&reply).await?;
+                    }
+                }
+            })
+        })
+        .collect();
+    Workers { handles, cancel }
+}